@@ -18,6 +18,68 @@ pub fn bitmap_bench(c: &mut Criterion) {
     });
 }
 
+pub fn large_block_map_bench(c: &mut Criterion) {
+    // A KeyBytes3 sized bitmap with every block allocated, requiring the
+    // set bits of the full block map to be counted to find the last block.
+    const MAX_KEY: usize = 1 << 24;
+
+    let mut dense = VecBitmap::new_with_capacity(MAX_KEY);
+    for i in (0..MAX_KEY).step_by(64) {
+        dense.set(i, true);
+    }
+    let mut bloom = CompressedBitmap::from(dense);
+
+    c.bench_function("bitmap_lookup_hit_large_block_map", |b| {
+        b.iter(|| black_box(bloom.get(MAX_KEY - 64)))
+    });
+    c.bench_function("bitmap_insert_true_large_block_map", |b| {
+        b.iter(|| bloom.set(MAX_KEY - 63, true))
+    });
+    c.bench_function("bitmap_count_ones_large_block_map", |b| {
+        b.iter(|| black_box(bloom.count_ones()))
+    });
+}
+
+pub fn locate_bench(c: &mut Criterion) {
+    // Name the benchmarks after the popcount kernel, allowing the results of
+    // `cargo bench` and `cargo bench --features simd` to be compared.
+    let kernel = if cfg!(feature = "simd") {
+        "simd"
+    } else {
+        "scalar"
+    };
+
+    // KeyBytes2 and KeyBytes3 sized bitmaps with every block allocated.
+    for (name, max_key) in [("small", 1_usize << 16), ("large", 1 << 24)] {
+        let mut dense = VecBitmap::new_with_capacity(max_key);
+        for i in (0..max_key).step_by(64) {
+            dense.set(i, true);
+        }
+        let mut bloom = CompressedBitmap::from(dense);
+
+        // Keys in distinct blocks spread across the bitmap, defeating the
+        // cache of the most recently written block.
+        let keys = (0..64)
+            .map(|i| i * (max_key / 64) + 1 + i % 63)
+            .collect::<Vec<_>>();
+
+        c.bench_function(&format!("bitmap_get_{}_{}", name, kernel), |b| {
+            b.iter(|| {
+                for &k in &keys {
+                    black_box(bloom.get(k));
+                }
+            })
+        });
+        c.bench_function(&format!("bitmap_set_{}_{}", name, kernel), |b| {
+            b.iter(|| {
+                for &k in &keys {
+                    bloom.set(k, true);
+                }
+            })
+        });
+    }
+}
+
 #[cfg(feature = "bytes")]
 pub fn bytes_bitmap_bench(c: &mut Criterion) {
     let mut bloom = BytesBitmap::new_with_capacity(1024);
@@ -38,6 +100,66 @@ pub fn bytes_bitmap_bench(c: &mut Criterion) {
     c.bench_function("bytes_bitmap_lookup miss, same block", |b| {
         b.iter(|| black_box(bloom.get(43)))
     });
+
+    // Every key of a KeyBytes2 sized bitmap, against the same access pattern
+    // of a VecBitmap as a baseline.
+    const MAX_KEY: usize = 1 << 16;
+    let mut bytes = BytesBitmap::new_with_capacity(MAX_KEY);
+    let mut vec = VecBitmap::new_with_capacity(MAX_KEY);
+
+    c.bench_function("bytes_bitmap_set_keybytes2", |b| {
+        b.iter(|| (0..MAX_KEY).step_by(3).for_each(|i| bytes.set(i, true)))
+    });
+    c.bench_function("vec_bitmap_set_keybytes2", |b| {
+        b.iter(|| (0..MAX_KEY).step_by(3).for_each(|i| vec.set(i, true)))
+    });
+    c.bench_function("bytes_bitmap_get_keybytes2", |b| {
+        b.iter(|| (0..MAX_KEY).filter(|&i| bytes.get(i)).count())
+    });
+    c.bench_function("vec_bitmap_get_keybytes2", |b| {
+        b.iter(|| (0..MAX_KEY).filter(|&i| vec.get(i)).count())
+    });
+}
+
+pub fn or_bench(c: &mut Criterion) {
+    // A KeyBytes3 sized dense bitmap.
+    const MAX_KEY: usize = 1 << 24;
+
+    let mut a = VecBitmap::new_with_capacity(MAX_KEY);
+    let mut b = VecBitmap::new_with_capacity(MAX_KEY);
+    for i in (0..MAX_KEY).step_by(7) {
+        a.set(i, true);
+        b.set(i + 3, true);
+    }
+
+    c.bench_function("vec_bitmap_or_keybytes3", |bench| {
+        bench.iter(|| black_box(a.or(&b)))
+    });
+
+    let a = CompressedBitmap::from(a);
+    let b = CompressedBitmap::from(b);
+    c.bench_function("compressed_bitmap_or_keybytes3", |bench| {
+        bench.iter(|| black_box(a.or(&b)))
+    });
+
+    let mut out = CompressedBitmap::new(MAX_KEY);
+    c.bench_function("compressed_bitmap_or_into_keybytes3", |bench| {
+        bench.iter(|| a.or_into(&b, black_box(&mut out)))
+    });
+
+    #[cfg(feature = "bytes")]
+    {
+        let mut a = BytesBitmap::new_with_capacity(MAX_KEY);
+        let mut b = BytesBitmap::new_with_capacity(MAX_KEY);
+        for i in (0..MAX_KEY).step_by(7) {
+            a.set(i, true);
+            b.set(i + 3, true);
+        }
+
+        c.bench_function("bytes_bitmap_or_keybytes3", |bench| {
+            bench.iter(|| black_box(a.or(&b)))
+        });
+    }
 }
 
 pub fn basic_bench(c: &mut Criterion) {
@@ -60,6 +182,27 @@ pub fn basic_bench(c: &mut Criterion) {
     c.bench_function("bloom_lookup_miss_same_block", |b| {
         b.iter(|| black_box(bloom.contains(&[1, 3])))
     });
+
+    let items = (0..1000).map(|v| [v, v + 1]).collect::<Vec<_>>();
+    let mut out = vec![false; items.len()];
+    c.bench_function("bloom_contains_batch_1000", |b| {
+        b.iter(|| bloom.contains_batch(black_box(&items), &mut out))
+    });
+}
+
+pub fn small_bench(c: &mut Criterion) {
+    let mut bloom =
+        BloomFilterBuilder::small(std::collections::hash_map::RandomState::default()).build();
+
+    c.bench_function("small_bloom_insert", |b| b.iter(|| bloom.insert(&[1, 2])));
+
+    c.bench_function("small_bloom_lookup_hit", |b| {
+        b.iter(|| black_box(bloom.contains(&[1, 2])))
+    });
+
+    c.bench_function("small_bloom_lookup_miss", |b| {
+        b.iter(|| black_box(bloom.contains(&[13, 42])))
+    });
 }
 
 pub fn insert_bench(c: &mut Criterion) {
@@ -172,6 +315,26 @@ pub fn insert_bench(c: &mut Criterion) {
         )
     });
 
+    c.bench_function("bloom_build_from_hashes_4_000_000", |b| {
+        let hasher = std::collections::hash_map::RandomState::default();
+        let hashes = (0..4_000_000)
+            .map(|v| std::hash::BuildHasher::hash_one(&hasher, v))
+            .collect::<Vec<_>>();
+
+        b.iter_batched(
+            || hashes.clone(),
+            |hashes| {
+                let bloom: Bloom2<_, CompressedBitmap, i32> = Bloom2::build_from_hashes(
+                    hasher.clone(),
+                    bloom2::FilterSize::KeyBytes4,
+                    hashes,
+                );
+                black_box(bloom)
+            },
+            BatchSize::NumBatches(1),
+        )
+    });
+
     c.bench_function("bloom_vec_convert_4_000_000", |b| {
         let mut bloom = BloomFilterBuilder::default()
             .with_bitmap::<VecBitmap>()
@@ -212,12 +375,25 @@ pub fn insert_bench(c: &mut Criterion) {
 criterion_group!(
     benches,
     basic_bench,
+    small_bench,
     insert_bench,
     bitmap_bench,
-    bytes_bitmap_bench
+    bytes_bitmap_bench,
+    large_block_map_bench,
+    locate_bench,
+    or_bench
 );
 
 #[cfg(not(feature = "bytes"))]
-criterion_group!(benches, basic_bench, insert_bench, bitmap_bench,);
+criterion_group!(
+    benches,
+    basic_bench,
+    small_bench,
+    insert_bench,
+    bitmap_bench,
+    large_block_map_bench,
+    locate_bench,
+    or_bench
+);
 
 criterion_main!(benches);
@@ -0,0 +1,410 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    bloom::{capacity_for_fpp, estimated_entries, estimated_fpp, key_size_to_bits},
+    math, Bloom2, CompressedBitmap, FilterSize, MatchMode, ProbeScheme,
+};
+
+/// The target false positive probability used by [`Bloom2::analyze()`].
+const DEFAULT_TARGET_FPP: f64 = 0.01;
+
+/// The fraction of allocated blocks above which decompressing is recommended.
+const DECOMPRESS_THRESHOLD: f64 = 0.9;
+
+/// The [skew](FilterAnalysis::skew) above which the hash is considered poorly
+/// distributed.
+const SKEW_THRESHOLD: f64 = 2.0;
+
+/// A change recommended by a [`FilterAnalysis`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recommendation {
+    /// Rebuild the filter from the source items at `size` (see
+    /// [`Bloom2::rebuild_from()`]), either because the filter exceeds the
+    /// target false positive probability, or because a smaller filter would
+    /// meet it.
+    Rebuild { size: FilterSize },
+
+    /// Shrink the filter to `size` with [`Bloom2::fold_to()`], without the
+    /// source items.
+    Fold { size: FilterSize },
+
+    /// Use [`ProbeScheme::DoubleHashing`] with `probes` probes per item, the
+    /// [optimal](crate::math::optimal_k) number for the estimated entries of a
+    /// filter using [`MatchMode::All`](crate::MatchMode::All).
+    ProbeCount { probes: u8 },
+
+    /// Most blocks of the bitmap are allocated, so a [`VecBitmap`] uses a
+    /// similar amount of memory and is faster to query (see
+    /// [`Bloom2::decompress()`]).
+    ///
+    /// [`VecBitmap`]: crate::VecBitmap
+    Decompress,
+
+    /// The set bits are unevenly distributed across the bitmap, suggesting a
+    /// poorly distributed hash - consider a different hasher.
+    CheckHasher,
+}
+
+/// A report of the state of a [`Bloom2`] filter, and the configuration
+/// changes recommended to meet a target false positive probability.
+///
+/// See [`Bloom2::analyze()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterAnalysis {
+    /// The [`FilterSize`] of the filter.
+    pub key_size: FilterSize,
+
+    /// The [`ProbeScheme`] of the filter.
+    pub probe_scheme: ProbeScheme,
+
+    /// The target false positive probability the recommendations are made
+    /// for.
+    pub target_fpp: f64,
+
+    /// The estimated false positive probability at the current load.
+    pub fpp: f64,
+
+    /// The estimated number of distinct entries in the filter.
+    pub estimated_entries: usize,
+
+    /// The fraction of bits set (see [`CompressedBitmap::density()`]).
+    pub utilisation: f64,
+
+    /// The fraction of blocks allocated (see
+    /// [`CompressedBitmap::allocated_fraction()`]).
+    pub allocated_fraction: f64,
+
+    /// The variance of the number of bits set in each block, relative to the
+    /// variance expected for a well distributed hash.
+    ///
+    /// A value near 1 indicates the bits are distributed as expected, while
+    /// larger values indicate the bits are clustered in fewer blocks.
+    pub skew: f64,
+
+    /// The recommended configuration changes, if any.
+    pub recommendations: Vec<Recommendation>,
+}
+
+impl std::fmt::Display for FilterAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "filter size {}, probe scheme {:?}",
+            self.key_size, self.probe_scheme
+        )?;
+        writeln!(
+            f,
+            "estimated fpp: {:.7} (target {}), estimated entries: {}",
+            self.fpp, self.target_fpp, self.estimated_entries
+        )?;
+        write!(
+            f,
+            "utilisation: {:.7}, allocated blocks: {:.4}, skew: {:.2}",
+            self.utilisation, self.allocated_fraction, self.skew
+        )?;
+
+        for r in &self.recommendations {
+            write!(f, "\nrecommendation: ")?;
+            match r {
+                Recommendation::Rebuild { size } => write!(f, "rebuild at size {}", size)?,
+                Recommendation::Fold { size } => write!(f, "fold to size {}", size)?,
+                Recommendation::ProbeCount { probes } => {
+                    write!(f, "use double hashing with {} probes", probes)?
+                }
+                Recommendation::Decompress => write!(f, "decompress the bitmap")?,
+                Recommendation::CheckHasher => {
+                    write!(f, "bits are unevenly distributed, check the hasher")?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<H, T> Bloom2<H, CompressedBitmap, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    /// Analyse the load and layout of the filter, recommending changes to its
+    /// configuration for a target false positive probability of 1%.
+    ///
+    /// ```rust
+    /// use bloom2::{BloomFilterBuilder, FilterSize, Recommendation};
+    ///
+    /// let mut b = BloomFilterBuilder::default()
+    ///     .size(FilterSize::KeyBytes1)
+    ///     .build();
+    /// for i in 0..100 {
+    ///     b.insert(&i);
+    /// }
+    ///
+    /// let report = b.analyze();
+    /// println!("{}", report);
+    ///
+    /// // The filter is overloaded, and should be rebuilt with a larger size.
+    /// assert!(report.fpp > 0.01);
+    /// assert!(matches!(
+    ///     report.recommendations[0],
+    ///     Recommendation::Rebuild { .. }
+    /// ));
+    /// ```
+    ///
+    /// See [`Bloom2::analyze_for_fpp()`].
+    pub fn analyze(&self) -> FilterAnalysis {
+        self.analyze_for_fpp(DEFAULT_TARGET_FPP)
+    }
+
+    /// Analyse the filter as [`Bloom2::analyze()`] does, recommending changes
+    /// for a false positive probability of `target_fpp`.
+    ///
+    /// A filter exceeding `target_fpp` is recommended to be rebuilt at the
+    /// smallest size that holds the estimated entries within `target_fpp`,
+    /// and a filter much larger than required is recommended to be folded
+    /// (or rebuilt, if it uses [`ProbeScheme::Split`]) to that size.
+    pub fn analyze_for_fpp(&self, target_fpp: f64) -> FilterAnalysis {
+        let key_size = self.key_size();
+        let scheme = self.probe_scheme();
+        let match_mode = self.match_mode();
+        let bitmap = self.bitmap();
+
+        let ones = bitmap.count_ones();
+        let entries = estimated_entries(key_size, scheme, ones);
+        let fpp = estimated_fpp(
+            key_size,
+            scheme,
+            match_mode,
+            ones as f64 / key_size_to_bits(key_size) as f64,
+        );
+
+        let mut recommendations = Vec::new();
+
+        // The smallest size holding the entries within the target.
+        let wanted = (6..=64)
+            .filter_map(FilterSize::from_key_bits)
+            .find(|&size| capacity_for_fpp(size, scheme, match_mode, target_fpp) >= entries);
+
+        match wanted {
+            Some(size) if size.key_bits() > key_size.key_bits() => {
+                recommendations.push(Recommendation::Rebuild { size });
+            }
+            Some(size) if entries > 0 && size.key_bits() < key_size.key_bits() => {
+                let r = match scheme {
+                    ProbeScheme::Split | ProbeScheme::SplitLsb => Recommendation::Rebuild { size },
+                    ProbeScheme::DoubleHashing { .. } => Recommendation::Fold { size },
+                };
+                recommendations.push(r);
+            }
+            _ => {}
+        }
+
+        // The optimal probe count assumes a lookup matches only if all of its
+        // probes are set - when matching any probe, fewer is always better.
+        if let (ProbeScheme::DoubleHashing { .. }, MatchMode::All) = (scheme, match_mode) {
+            let size = wanted.unwrap_or(key_size);
+            let optimal = math::optimal_k(key_size_to_bits(size), entries).min(u8::MAX as usize);
+            if entries > 0 && optimal != scheme.probes(size) {
+                recommendations.push(Recommendation::ProbeCount {
+                    probes: optimal as u8,
+                });
+            }
+        }
+
+        let allocated_fraction = bitmap.allocated_fraction();
+        if allocated_fraction > DECOMPRESS_THRESHOLD {
+            recommendations.push(Recommendation::Decompress);
+        }
+
+        let skew = skew(bitmap, key_size, scheme, entries);
+        if skew > SKEW_THRESHOLD {
+            recommendations.push(Recommendation::CheckHasher);
+        }
+
+        FilterAnalysis {
+            key_size,
+            probe_scheme: scheme,
+            target_fpp,
+            fpp,
+            estimated_entries: entries,
+            utilisation: bitmap.density(),
+            allocated_fraction,
+            skew,
+            recommendations,
+        }
+    }
+}
+
+/// Return the variance of the number of bits set per block of `bitmap`,
+/// relative to the variance expected after inserting `entries` well
+/// distributed hashes.
+fn skew(
+    bitmap: &CompressedBitmap,
+    key_size: FilterSize,
+    scheme: ProbeScheme,
+    entries: usize,
+) -> f64 {
+    let block_bits = u64::BITS as f64;
+    let bits = key_size_to_bits(key_size) as f64;
+    let blocks = (bits / block_bits).ceil();
+
+    // The observed variance, counting unallocated blocks as empty.
+    let histogram = bitmap.occupancy_histogram();
+    let mean = bitmap.count_ones() as f64 / blocks;
+    let allocated = histogram.iter().sum::<usize>() as f64;
+    let observed = (histogram
+        .iter()
+        .enumerate()
+        .map(|(n, &count)| count as f64 * (n as f64 - mean).powi(2))
+        .sum::<f64>()
+        + (blocks - allocated) * mean * mean)
+        / blocks;
+
+    // The keys of a split filter include a shorter last chunk
+    // (if the key size does not divide 64) that only reaches the lowest
+    // 2^rem bits, so those blocks are expected to be more heavily populated.
+    let n = entries as f64;
+    let (uniform, rem) = match scheme {
+        ProbeScheme::Split | ProbeScheme::SplitLsb => {
+            let key_bits = key_size.key_bits();
+            ((64 / key_bits) as f64 * n, 64 % key_bits)
+        }
+        ProbeScheme::DoubleHashing { .. } => (scheme.probes(key_size) as f64 * n, 0),
+    };
+    let (short, short_bits) = match rem {
+        0 => (0.0, bits),
+        rem => (n, (1_u64 << rem) as f64),
+    };
+
+    // The probability of a bit being set in, and the fraction of blocks
+    // within, each region.
+    let p_short = -(-uniform / bits - short / short_bits).exp_m1();
+    let p_rest = -(-uniform / bits).exp_m1();
+    let frac_short = (short_bits / bits).min(1.0);
+
+    // Each region is binomially distributed, plus the variance between the
+    // means of the two regions.
+    let binomial = |p: f64| block_bits * p * (1.0 - p);
+    let expected = frac_short * binomial(p_short)
+        + (1.0 - frac_short) * binomial(p_rest)
+        + frac_short * (1.0 - frac_short) * (block_bits * (p_short - p_rest)).powi(2);
+
+    if expected <= 0.0 {
+        return 1.0;
+    }
+    observed / expected
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use super::*;
+    use crate::BloomFilterBuilder;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    fn new_filter(
+        size: FilterSize,
+        scheme: ProbeScheme,
+    ) -> Bloom2<StableBuildHasher, CompressedBitmap, u32> {
+        BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .size(size)
+            .probe_scheme(scheme)
+            .build()
+    }
+
+    #[test]
+    fn test_analyze_overloaded() {
+        let mut b = new_filter(FilterSize::KeyBytes1, ProbeScheme::Split);
+        for v in 0..100 {
+            b.insert(&v);
+        }
+
+        let report = b.analyze();
+        assert!(report.fpp > report.target_fpp);
+        assert!(matches!(
+            report.recommendations[0],
+            Recommendation::Rebuild { size } if size.key_bits() > 8
+        ));
+        assert!(report.recommendations.contains(&Recommendation::Decompress));
+    }
+
+    #[test]
+    fn test_analyze_oversized() {
+        let mut b = new_filter(FilterSize::KeyBytes3, ProbeScheme::Split);
+        let mut d = new_filter(
+            FilterSize::KeyBytes3,
+            ProbeScheme::DoubleHashing { probes: 3 },
+        );
+        for v in 0..100 {
+            b.insert(&v);
+            d.insert(&v);
+        }
+
+        let report = b.analyze();
+        assert!(report.fpp < report.target_fpp);
+        assert!(matches!(
+            report.recommendations[..],
+            [Recommendation::Rebuild { size }] if size.key_bits() < 24
+        ));
+        assert!(report.skew < SKEW_THRESHOLD, "{}", report);
+
+        // A double hashing filter can be folded instead, and the probe count
+        // tuned.
+        let report = d.analyze();
+        let size = match report.recommendations[0] {
+            Recommendation::Fold { size } => size,
+            r => panic!("unexpected recommendation {:?}", r),
+        };
+        assert!(report.skew < SKEW_THRESHOLD, "{}", report);
+
+        // The probe count is only tuned when matching all probes.
+        assert_eq!(report.recommendations.len(), 1);
+        d.set_match_mode(MatchMode::All);
+        assert!(d
+            .analyze()
+            .recommendations
+            .iter()
+            .any(|r| matches!(r, Recommendation::ProbeCount { .. })));
+
+        assert!(d.fold_to(size).unwrap().fpp_after <= 0.01);
+    }
+
+    #[test]
+    fn test_analyze_empty() {
+        let b = new_filter(FilterSize::KeyBytes2, ProbeScheme::Split);
+        let report = b.analyze();
+
+        assert_eq!(report.fpp, 0.0);
+        assert_eq!(report.estimated_entries, 0);
+        assert_eq!(report.recommendations, vec![]);
+    }
+
+    #[test]
+    fn test_analyze_skew() {
+        for &size in &[
+            FilterSize::KeyBytes2,
+            FilterSize::KeyBytes3,
+            FilterSize::KeyBits(20),
+        ] {
+            let mut b = new_filter(size, ProbeScheme::Split);
+            for v in 0..5000 {
+                b.insert(&v);
+            }
+            let report = b.analyze();
+            assert!(report.skew < SKEW_THRESHOLD, "{}", report);
+        }
+
+        // Setting bits directly into a handful of blocks.
+        let mut b = new_filter(FilterSize::KeyBytes2, ProbeScheme::Split);
+        for key in 0..256 {
+            b.bitmap_mut().set(key, true);
+        }
+        let report = b.analyze();
+        assert!(report.skew > SKEW_THRESHOLD, "{}", report);
+        assert!(report
+            .recommendations
+            .contains(&Recommendation::CheckHasher));
+    }
+}
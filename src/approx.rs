@@ -0,0 +1,79 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::{ApproxHashSet, Bitmap, Bloom2, FilterCascade, RibbonFilter, ShardedFilterSet};
+
+/// A set answering approximate membership queries, implemented by the filter
+/// types of this crate.
+///
+/// A `contains` result of `false` means the item was **definitely not** added
+/// to the set, while `true` means it **probably** was - the probability of a
+/// false positive depends on the implementation and its configuration.
+///
+/// ```rust
+/// use bloom2::{ApproximateSet, Bloom2};
+///
+/// fn count_present<S: ApproximateSet<u32>>(set: &S, items: &[u32]) -> usize {
+///     items.iter().filter(|v| set.contains(v)).count()
+/// }
+///
+/// let mut b = Bloom2::default();
+/// b.insert(&1);
+/// assert_eq!(count_present(&b, &[1]), 1);
+/// ```
+pub trait ApproximateSet<T: ?Sized> {
+    /// Returns `true` if `item` is **probably** in the set, or `false` if it
+    /// is **definitely not**.
+    fn contains(&self, item: &T) -> bool;
+}
+
+impl<H, B, T> ApproximateSet<T> for Bloom2<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    fn contains(&self, item: &T) -> bool {
+        Bloom2::contains(self, item)
+    }
+}
+
+impl<T, S> ApproximateSet<T> for ApproxHashSet<T, S>
+where
+    T: Hash,
+    S: BuildHasher,
+{
+    fn contains(&self, item: &T) -> bool {
+        ApproxHashSet::contains(self, item)
+    }
+}
+
+impl<H, B, T> ApproximateSet<T> for ShardedFilterSet<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    fn contains(&self, item: &T) -> bool {
+        ShardedFilterSet::contains(self, item)
+    }
+}
+
+impl<H, T> ApproximateSet<T> for FilterCascade<H, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    fn contains(&self, item: &T) -> bool {
+        FilterCascade::contains(self, item)
+    }
+}
+
+impl<H, T> ApproximateSet<T> for RibbonFilter<H, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    fn contains(&self, item: &T) -> bool {
+        RibbonFilter::contains(self, item)
+    }
+}
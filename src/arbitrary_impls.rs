@@ -0,0 +1,114 @@
+//! [`Arbitrary`] implementations for generating random, valid filters and
+//! bitmaps in property tests and fuzzers.
+
+use std::hash::{BuildHasher, Hash};
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{Bitmap, Bloom2, CompressedBitmap, FilterSize, VecBitmap};
+
+/// The largest bitmap capacity generated for a [`CompressedBitmap`].
+///
+/// This covers the full range of a [`FilterSize::KeyBytes4`] filter.
+const MAX_COMPRESSED_KEY: usize = 1 << 32;
+
+/// The largest bitmap capacity generated for a dense [`VecBitmap`], bounding
+/// the size of the allocation.
+const MAX_DENSE_KEY: usize = 1 << 20;
+
+impl<'a> Arbitrary<'a> for FilterSize {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            FilterSize::KeyBytes1,
+            FilterSize::KeyBytes2,
+            FilterSize::KeyBytes3,
+            FilterSize::KeyBytes4,
+            FilterSize::KeyBytes5,
+        ])?)
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (1, Some(8))
+    }
+}
+
+/// Return an arbitrary `max_key` in the range `1..=limit` and a sorted set of
+/// keys within it.
+fn arbitrary_keys(u: &mut Unstructured<'_>, limit: usize) -> Result<(usize, Vec<usize>)> {
+    let max_key = u.int_in_range(1..=limit)?;
+
+    let mut keys = u
+        .arbitrary_iter::<usize>()?
+        .map(|v| v.map(|v| v % max_key))
+        .collect::<Result<Vec<_>>>()?;
+    keys.sort_unstable();
+    keys.dedup();
+
+    Ok((max_key, keys))
+}
+
+impl<'a> Arbitrary<'a> for CompressedBitmap {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let (max_key, keys) = arbitrary_keys(u, MAX_COMPRESSED_KEY)?;
+        Ok(CompressedBitmap::from_sorted_keys(max_key, keys))
+    }
+}
+
+impl<'a> Arbitrary<'a> for VecBitmap {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let (max_key, keys) = arbitrary_keys(u, MAX_DENSE_KEY)?;
+
+        let mut b = VecBitmap::new_with_capacity(max_key);
+        for key in keys {
+            b.set(key, true);
+        }
+        Ok(b)
+    }
+}
+
+/// Generates a filter of an arbitrary [`FilterSize`], populated with
+/// arbitrary hashes.
+impl<'a, H, T> Arbitrary<'a> for Bloom2<H, CompressedBitmap, T>
+where
+    H: BuildHasher + Default,
+    T: Hash,
+{
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let key_size = FilterSize::arbitrary(u)?;
+        let hashes = u.arbitrary_iter::<u64>()?.collect::<Result<Vec<_>>>()?;
+
+        Ok(Bloom2::build_from_hashes(H::default(), key_size, hashes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::RandomState;
+
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn prop_arbitrary_valid(data in prop::collection::vec(any::<u8>(), 0..1024)) {
+            let mut u = Unstructured::new(&data);
+            let b = CompressedBitmap::arbitrary(&mut u).unwrap();
+
+            let (max_key, keys) = arbitrary_keys(&mut Unstructured::new(&data), MAX_COMPRESSED_KEY).unwrap();
+            assert_eq!(b.verify_invariants(), Ok(()));
+            assert_eq!(b.max_key(), max_key);
+            for key in keys {
+                assert!(b.get(key));
+            }
+
+            let mut u = Unstructured::new(&data);
+            let b = Bloom2::<RandomState, CompressedBitmap, u32>::arbitrary(&mut u).unwrap();
+            assert_eq!(b.verify_invariants(), Ok(()));
+
+            let mut u = Unstructured::new(&data);
+            VecBitmap::arbitrary(&mut u).unwrap();
+        }
+    }
+}
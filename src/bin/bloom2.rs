@@ -0,0 +1,159 @@
+//! A command line tool to build, query, merge and inspect [`Bloom2`] filters.
+//!
+//! Filters are hashed with the [`XxHashBuildHasher`], and saved as
+//! [bincode] encoded files, so a filter built by one invocation can be queried
+//! by another.
+//!
+//! ```text
+//! bloom2 build [--size 1-5] [--output FILE] [INPUT]
+//! bloom2 query FILTER [INPUT]
+//! bloom2 merge --output FILE FILTER...
+//! bloom2 stats FILTER
+//! ```
+//!
+//! Lines are read from `INPUT`, or stdin if not provided. Filters are written
+//! to `--output`, or stdout if not provided.
+//!
+//! [bincode]: https://github.com/bincode-org/bincode
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    process::ExitCode,
+};
+
+use bloom2::{Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize, XxHashBuildHasher};
+
+type Filter = Bloom2<XxHashBuildHasher, CompressedBitmap, String>;
+
+const USAGE: &str = "\
+usage:
+    bloom2 build [--size 1-5] [--output FILE] [INPUT]
+    bloom2 query FILTER [INPUT]
+    bloom2 merge --output FILE FILTER...
+    bloom2 stats FILTER
+
+build   insert each line of INPUT (or stdin) into a new filter
+query   print each line of INPUT (or stdin) and whether it is probably in FILTER
+merge   write the union of all FILTER files to FILE
+stats   print a summary of FILTER";
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let (cmd, args) = args.split_first().ok_or("no command given")?;
+    let args = Args::parse(args)?;
+
+    match cmd.as_str() {
+        "build" => {
+            let size = match args.size.as_deref() {
+                Some(v) => parse_size(v)?,
+                None => FilterSize::KeyBytes2,
+            };
+            let mut filter: Filter = BloomFilterBuilder::hasher(XxHashBuildHasher::default())
+                .size(size)
+                .build();
+
+            filter.insert_lines(input(args.positional.first())?)?;
+            write_filter(&filter, args.output.as_deref())
+        }
+        "query" => {
+            let (path, rest) = args.positional.split_first().ok_or("no filter given")?;
+            let filter = read_filter(path)?;
+
+            let mut out = BufWriter::new(io::stdout().lock());
+            for line in input(rest.first())?.lines() {
+                let line = line?;
+                writeln!(out, "{}\t{}", line, filter.contains(&line))?;
+            }
+            Ok(out.flush()?)
+        }
+        "merge" => {
+            let output = args.output.as_deref().ok_or("merge requires --output")?;
+            let (first, rest) = args.positional.split_first().ok_or("no filters given")?;
+
+            let mut filter = read_filter(first)?;
+            for path in rest {
+                filter
+                    .try_union(&read_filter(path)?)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+            }
+            write_filter(&filter, Some(output))
+        }
+        "stats" => {
+            let path = args.positional.first().ok_or("no filter given")?;
+            println!("{}", read_filter(path)?);
+            Ok(())
+        }
+        _ => Err(format!("unknown command {:?}", cmd).into()),
+    }
+}
+
+/// The flags and positional arguments of a command.
+#[derive(Debug, Default)]
+struct Args {
+    size: Option<String>,
+    output: Option<String>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut out = Self::default();
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--size" | "-s" => out.size = Some(iter.next().ok_or("missing size")?.clone()),
+                "--output" | "-o" => {
+                    out.output = Some(iter.next().ok_or("missing output path")?.clone())
+                }
+                _ => out.positional.push(arg.clone()),
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+fn parse_size(v: &str) -> Result<FilterSize> {
+    match v.parse() {
+        Ok(size) if FilterSize::ALL[..5].contains(&size) => Ok(size),
+        _ => Err(format!("invalid size {:?}, expected 1 to 5", v).into()),
+    }
+}
+
+/// Open `path` for reading, or stdin if `None`.
+fn input(path: Option<&String>) -> Result<Box<dyn BufRead>> {
+    Ok(match path {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    })
+}
+
+fn read_filter(path: &str) -> Result<Filter> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(bincode::deserialize_from(BufReader::new(file))?)
+}
+
+fn write_filter(filter: &Filter, path: Option<&str>) -> Result<()> {
+    let mut out: Box<dyn Write> = match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    bincode::serialize_into(&mut out, filter)?;
+    Ok(out.flush()?)
+}
@@ -0,0 +1,338 @@
+use crate::Bitmap;
+
+use super::{
+    compressed_bitmap::CompressedBitmap, index_for_key, memory::MemoryBreakdown, vec::VecBitmap,
+};
+
+/// The number of writes over which the rate of block allocations is measured.
+const WINDOW: usize = 1024;
+
+/// Switch to the dense representation if more than this many writes within a
+/// [`WINDOW`] allocate a new block.
+const DENSE_ALLOCATIONS: usize = WINDOW / 8;
+
+/// Switch back to the compressed representation if no more than this many
+/// writes within a [`WINDOW`] allocate a new block.
+const QUIESCENT_ALLOCATIONS: usize = WINDOW / 128;
+
+/// The storage currently backing an [`AdaptiveBitmap`].
+#[derive(Debug, Clone)]
+enum Storage {
+    Compressed(CompressedBitmap),
+    Dense(VecBitmap),
+}
+
+/// A bitmap that switches between a sparse [`CompressedBitmap`] and a dense
+/// [`VecBitmap`] representation based on how it is used.
+///
+/// An `AdaptiveBitmap` starts out compressed, providing the same low memory
+/// footprint as a [`CompressedBitmap`] for lightly populated bitmaps. If the
+/// bitmap becomes heavily populated (more than half of the blocks are
+/// allocated), or the rate of writes that allocate new blocks becomes high
+/// (each of which may require moving the blocks of a `CompressedBitmap`), the
+/// bitmap is transparently decompressed to provide `O(1)` writes.
+///
+/// Once the bitmap is dense, if the writes quiesce (few writes allocate new
+/// blocks) and the bitmap is lightly populated (such as after clearing bits),
+/// it is transparently compressed again.
+///
+/// ```rust
+/// use bloom2::{AdaptiveBitmap, BloomFilterBuilder, FilterSize};
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .with_bitmap::<AdaptiveBitmap>()
+///     .size(FilterSize::KeyBytes2)
+///     .build();
+///
+/// filter.insert(&"no manual compression");
+/// assert!(filter.contains(&"no manual compression"));
+/// ```
+///
+/// Switching representation is an `O(n)` operation, performed at most once per
+/// 1024 writes, or when the load factor threshold is first crossed.
+#[derive(Debug, Clone)]
+pub struct AdaptiveBitmap {
+    storage: Storage,
+    max_key: usize,
+
+    /// The number of non-zero blocks in the bitmap.
+    blocks: usize,
+
+    /// The number of writes in the current window.
+    writes: usize,
+    /// The number of writes in the current window that allocated a block.
+    allocations: usize,
+}
+
+impl AdaptiveBitmap {
+    /// Returns true if the bitmap is currently using the dense
+    /// representation.
+    pub fn is_dense(&self) -> bool {
+        matches!(self.storage, Storage::Dense(_))
+    }
+
+    /// Return the number of blocks in the dense representation.
+    fn dense_blocks(&self) -> usize {
+        index_for_key(self.max_key) + 1
+    }
+
+    /// Returns true if half or more of the blocks are non-zero.
+    fn is_heavily_loaded(&self) -> bool {
+        self.blocks * 2 >= self.dense_blocks()
+    }
+
+    /// Returns true if less than an eighth of the blocks are non-zero.
+    fn is_lightly_loaded(&self) -> bool {
+        self.blocks * 8 < self.dense_blocks()
+    }
+
+    fn decompress(&mut self) {
+        if let Storage::Compressed(ref b) = self.storage {
+            let dense = b.decompress(self.max_key);
+            self.blocks = dense.block_count();
+            self.storage = Storage::Dense(dense);
+        }
+    }
+
+    fn compress(&mut self) {
+        // Swap the dense bitmap out of self, leaving an empty (unallocated)
+        // compressed bitmap in its place.
+        let placeholder = Storage::Compressed(CompressedBitmap::new(self.max_key));
+        match std::mem::replace(&mut self.storage, placeholder) {
+            Storage::Dense(b) => {
+                let compressed = CompressedBitmap::from(b);
+                self.blocks = compressed.block_count();
+                self.storage = Storage::Compressed(compressed);
+            }
+            v => self.storage = v,
+        }
+    }
+
+    /// Record a write, and switch the representation if the write pattern
+    /// warrants it.
+    fn record_write(&mut self, allocated: bool) {
+        self.writes += 1;
+        self.allocations += allocated as usize;
+
+        if !self.is_dense() && self.is_heavily_loaded() {
+            self.decompress();
+        }
+
+        if self.writes < WINDOW {
+            return;
+        }
+
+        match self.storage {
+            Storage::Compressed(_) if self.allocations > DENSE_ALLOCATIONS => self.decompress(),
+            Storage::Dense(_)
+                if self.allocations <= QUIESCENT_ALLOCATIONS && self.is_lightly_loaded() =>
+            {
+                self.compress()
+            }
+            _ => {}
+        }
+
+        self.writes = 0;
+        self.allocations = 0;
+    }
+}
+
+impl Bitmap for AdaptiveBitmap {
+    fn new_with_capacity(max_key: usize) -> Self {
+        Self {
+            storage: Storage::Compressed(CompressedBitmap::new(max_key)),
+            max_key,
+            blocks: 0,
+            writes: 0,
+            allocations: 0,
+        }
+    }
+
+    fn set(&mut self, key: usize, value: bool) {
+        let allocated = match self.storage {
+            Storage::Compressed(ref mut b) => {
+                let before = b.block_count();
+                b.set(key, value);
+                self.blocks = b.block_count();
+                self.blocks > before
+            }
+            Storage::Dense(ref mut b) => {
+                let before = b.block_for_key(key);
+                b.set(key, value);
+                let after = b.block_for_key(key);
+
+                match (before, after) {
+                    (0, 0) => false,
+                    (0, _) => {
+                        self.blocks += 1;
+                        true
+                    }
+                    (_, 0) => {
+                        self.blocks -= 1;
+                        false
+                    }
+                    _ => false,
+                }
+            }
+        };
+
+        self.record_write(allocated);
+    }
+
+    fn get(&self, key: usize) -> bool {
+        match self.storage {
+            Storage::Compressed(ref b) => b.get(key),
+            Storage::Dense(ref b) => b.get(key),
+        }
+    }
+
+    fn byte_size(&self) -> usize {
+        match self.storage {
+            Storage::Compressed(ref b) => b.size(),
+            Storage::Dense(ref b) => b.byte_size(),
+        }
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        let usage = match self.storage {
+            Storage::Compressed(ref b) => b.memory_breakdown(),
+            Storage::Dense(ref b) => b.memory_breakdown(),
+        };
+
+        // The overhead of the inner bitmap is included in the size of self.
+        MemoryBreakdown {
+            overhead_bytes: std::mem::size_of_val(self),
+            ..usage
+        }
+    }
+
+    fn warm(&self) {
+        match self.storage {
+            Storage::Compressed(ref b) => b.warm(),
+            Storage::Dense(ref b) => b.warm(),
+        }
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        let storage = match (&self.storage, &other.storage) {
+            (Storage::Compressed(a), Storage::Compressed(b)) => Storage::Compressed(a.or(b)),
+            (Storage::Dense(a), Storage::Dense(b)) => Storage::Dense(a.or(b)),
+            (Storage::Dense(a), Storage::Compressed(b))
+            | (Storage::Compressed(b), Storage::Dense(a)) => {
+                Storage::Dense(a.or(&b.decompress(self.max_key)))
+            }
+        };
+
+        let blocks = match storage {
+            Storage::Compressed(ref b) => b.block_count(),
+            Storage::Dense(ref b) => b.block_count(),
+        };
+
+        Self {
+            storage,
+            max_key: self.max_key,
+            blocks,
+            writes: 0,
+            allocations: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const MAX_KEY: usize = 1 << 16;
+
+    // A smaller bitmap that switches representation more frequently.
+    const PROP_MAX_KEY: usize = 1 << 12;
+
+    #[test]
+    fn test_transitions() {
+        let mut b = AdaptiveBitmap::new_with_capacity(MAX_KEY);
+        assert!(!b.is_dense());
+
+        // Setting a bit in every block crosses the load factor
+        // threshold.
+        for i in (0..MAX_KEY).step_by(64) {
+            b.set(i, true);
+        }
+        assert!(b.is_dense());
+
+        // Clearing the bits and then performing a window of non-allocating
+        // writes recompresses the bitmap.
+        for i in (0..MAX_KEY).step_by(64) {
+            b.set(i, false);
+        }
+        b.set(1, true);
+        for _ in 0..WINDOW {
+            b.set(1, true);
+        }
+        assert!(!b.is_dense());
+
+        for i in 0..MAX_KEY {
+            assert_eq!(b.get(i), i == 1);
+        }
+    }
+
+    #[test]
+    fn test_allocation_rate() {
+        let mut b = AdaptiveBitmap::new_with_capacity(MAX_KEY);
+
+        // A window of writes that mostly allocate new blocks decompresses the
+        // bitmap, despite the low load factor.
+        for i in 0..WINDOW {
+            b.set((i % 256) * 64, true);
+        }
+        assert!(b.is_dense());
+        assert!(!b.is_heavily_loaded());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_insert_contains(
+            values in prop::collection::vec((0..PROP_MAX_KEY, any::<bool>()), 0..3000),
+        ) {
+            let mut b = AdaptiveBitmap::new_with_capacity(PROP_MAX_KEY);
+            let mut want = VecBitmap::new_with_capacity(PROP_MAX_KEY);
+
+            for &(v, value) in &values {
+                b.set(v, value);
+                want.set(v, value);
+            }
+
+            // Ensure all values are equal in the test range.
+            for i in 0..PROP_MAX_KEY {
+                assert_eq!(b.get(i), want.get(i));
+            }
+        }
+
+        #[test]
+        fn prop_or(
+            a in prop::collection::vec(0..PROP_MAX_KEY, 0..3000),
+            b in prop::collection::vec(0..PROP_MAX_KEY, 0..20),
+        ) {
+            let mut a_bitmap = AdaptiveBitmap::new_with_capacity(PROP_MAX_KEY);
+            let mut b_bitmap = AdaptiveBitmap::new_with_capacity(PROP_MAX_KEY);
+
+            for v in a.iter() {
+                a_bitmap.set(*v, true);
+            }
+
+            for v in b.iter() {
+                b_bitmap.set(*v, true);
+            }
+
+            let union = a_bitmap.or(&b_bitmap);
+            let reverse = b_bitmap.or(&a_bitmap);
+
+            for i in 0..PROP_MAX_KEY {
+                let want = a_bitmap.get(i) || b_bitmap.get(i);
+                assert_eq!(union.get(i), want);
+                assert_eq!(reverse.get(i), want);
+            }
+        }
+    }
+}
@@ -1,12 +1,10 @@
 #![cfg(feature = "bytes")]
 
-use std::convert::TryInto;
-
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{Bytes, BytesMut};
 
 use crate::{
-    bitmap::{bitmask_for_key, index_for_key},
-    Bitmap,
+    bitmap::{index_for_key, saturate_key, simd, touch_pages, try_zeroed_vec, MemoryBreakdown},
+    Bitmap, Error, FilterSize,
 };
 
 /// A plain, heap-allocated, `O(1)` indexed bitmap using `bytes::BytesMut` for
@@ -19,6 +17,8 @@ use crate::{
 /// need for serialisation; the output of [BytesBitmap::freeze()] can be used to
 /// construct a new instance. [Serde] serialisation is also implemented as a
 /// conveinence to enable serialisation to various formats.
+///
+/// [Serde]: https://github.com/serde-rs/serde
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BytesBitmap {
@@ -35,6 +35,22 @@ impl BytesBitmap {
         self.max_key
     }
 
+    /// Return the length, in bytes, of the storage of a [`BytesBitmap`]
+    /// backing a filter of `key_size`.
+    ///
+    /// This is the length of the [`BytesBitmap::freeze()`] output of such a
+    /// filter, and can be used to validate a buffer before constructing a
+    /// filter from it.
+    ///
+    /// ```rust
+    /// use bloom2::{BytesBitmap, FilterSize};
+    ///
+    /// assert_eq!(BytesBitmap::byte_len(FilterSize::KeyBytes2), 8200);
+    /// ```
+    pub fn byte_len(key_size: FilterSize) -> usize {
+        (index_for_key(key_size.max_bits()) + 1) * size_of::<usize>()
+    }
+
     pub fn from_bytes(bitmap: impl Into<Bytes>) -> Self {
         let bitmap = bitmap.into();
         Self {
@@ -42,6 +58,11 @@ impl BytesBitmap {
             bitmap: BytesMut::from(bitmap),
         }
     }
+
+    /// Return the highest key addressable by the bitmap storage.
+    fn last_key(&self) -> usize {
+        (self.bitmap.len() * 8).saturating_sub(1)
+    }
 }
 
 impl Bitmap for BytesBitmap {
@@ -55,58 +76,205 @@ impl Bitmap for BytesBitmap {
         }
     }
 
-    fn set(&mut self, key: usize, value: bool) {
-        let offset = index_for_key(key);
-        let byte_offset = offset * size_of::<usize>();
+    fn try_new_with_capacity(max_key: usize) -> Result<Self, Error> {
+        let size = (index_for_key(max_key).saturating_add(1)).saturating_mul(size_of::<usize>());
 
-        let slice = &mut self.bitmap[byte_offset..byte_offset + size_of::<usize>()];
-        let mut num = usize::from_ne_bytes(slice.try_into().unwrap());
+        // SAFETY: a zeroed u8 is valid.
+        let bytes = unsafe { try_zeroed_vec::<u8>(size)? };
 
-        if value {
-            num |= bitmask_for_key(key);
-        } else {
-            num &= !bitmask_for_key(key);
-        }
+        // Converting a uniquely owned buffer does not copy.
+        Ok(Self {
+            bitmap: BytesMut::from(Bytes::from(bytes)),
+            max_key,
+        })
+    }
 
-        slice.copy_from_slice(&num.to_ne_bytes());
+    fn set(&mut self, key: usize, value: bool) {
+        let key = saturate_key(key, self.last_key());
+        set_bit(&mut self.bitmap, key, value);
     }
 
     fn get(&self, key: usize) -> bool {
-        let offset = index_for_key(key);
-        let byte_offset = offset * size_of::<usize>();
-        let slice = &self.bitmap[byte_offset..byte_offset + size_of::<usize>()];
-        let num = usize::from_ne_bytes(slice.try_into().unwrap());
-        num & bitmask_for_key(key) != 0
+        let key = saturate_key(key, self.last_key());
+        get_bit(&self.bitmap, key)
     }
 
     fn byte_size(&self) -> usize {
         self.bitmap.len()
     }
 
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        MemoryBreakdown {
+            bitmap_bytes: self.bitmap.len(),
+            spare_bytes: self.bitmap.capacity() - self.bitmap.len(),
+            overhead_bytes: std::mem::size_of_val(self),
+            ..Default::default()
+        }
+    }
+
+    fn warm(&self) {
+        touch_pages(&self.bitmap);
+    }
+
     fn or(&self, other: &Self) -> Self {
         assert_eq!(self.bitmap.len(), other.bitmap.len());
 
-        let mut result = BytesMut::with_capacity(self.bitmap.len());
-        let chunks = self
-            .bitmap
-            .chunks_exact(size_of::<usize>())
-            .zip(other.bitmap.chunks_exact(size_of::<usize>()));
+        let mut result = BytesMut::zeroed(self.bitmap.len());
+        simd::or_into(&mut result, &self.bitmap, &other.bitmap);
 
-        for (a_chunk, b_chunk) in chunks {
-            let a = usize::from_ne_bytes(a_chunk.try_into().unwrap());
-            let b = usize::from_ne_bytes(b_chunk.try_into().unwrap());
-            result.put_slice(&(a | b).to_ne_bytes());
+        Self {
+            bitmap: result,
+            max_key: self.max_key,
         }
+    }
+}
 
+/// An immutable, shared view of the frozen bytes of a [`BytesBitmap`].
+///
+/// A `FrozenBytesBitmap` wraps the [`Bytes`] returned by
+/// [`BytesBitmap::freeze()`] (or received from the network) without copying,
+/// allowing many filters to share a single buffer. Cloning a
+/// `FrozenBytesBitmap` is cheap, and reads are as fast as a [`BytesBitmap`].
+///
+/// Writes are copy-on-write: the first write to a shared buffer copies it,
+/// leaving other views unchanged.
+///
+/// The byte representation of a bitmap is platform-specific (it uses the
+/// native endianness), and should only be shared between hosts of the same
+/// architecture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrozenBytesBitmap {
+    max_key: usize,
+    bitmap: Bytes,
+}
+
+impl FrozenBytesBitmap {
+    pub fn from_bytes(bitmap: Bytes) -> Self {
         Self {
-            bitmap: result,
+            max_key: bitmap.len() * 8,
+            bitmap,
+        }
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.bitmap
+    }
+
+    pub fn max_key(&self) -> usize {
+        self.max_key
+    }
+
+    /// Return the highest key addressable by the bitmap storage.
+    fn last_key(&self) -> usize {
+        (self.bitmap.len() * 8).saturating_sub(1)
+    }
+}
+
+impl From<BytesBitmap> for FrozenBytesBitmap {
+    fn from(v: BytesBitmap) -> Self {
+        Self {
+            max_key: v.max_key,
+            bitmap: v.freeze(),
+        }
+    }
+}
+
+impl Bitmap for FrozenBytesBitmap {
+    fn new_with_capacity(max_key: usize) -> Self {
+        BytesBitmap::new_with_capacity(max_key).into()
+    }
+
+    fn try_new_with_capacity(max_key: usize) -> Result<Self, Error> {
+        BytesBitmap::try_new_with_capacity(max_key).map(Into::into)
+    }
+
+    fn set(&mut self, key: usize, value: bool) {
+        let key = saturate_key(key, self.last_key());
+
+        // Converting a uniquely owned buffer does not copy.
+        let mut bitmap = BytesMut::from(std::mem::take(&mut self.bitmap));
+        set_bit(&mut bitmap, key, value);
+        self.bitmap = bitmap.freeze();
+    }
+
+    fn get(&self, key: usize) -> bool {
+        let key = saturate_key(key, self.last_key());
+        get_bit(&self.bitmap, key)
+    }
+
+    fn byte_size(&self) -> usize {
+        self.bitmap.len()
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        MemoryBreakdown {
+            bitmap_bytes: self.bitmap.len(),
+            overhead_bytes: std::mem::size_of_val(self),
+            ..Default::default()
+        }
+    }
+
+    fn warm(&self) {
+        touch_pages(&self.bitmap);
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        assert_eq!(self.bitmap.len(), other.bitmap.len());
+
+        let mut result = BytesMut::zeroed(self.bitmap.len());
+        simd::or_into(&mut result, &self.bitmap, &other.bitmap);
+
+        Self {
+            bitmap: result.freeze(),
             max_key: self.max_key,
         }
     }
 }
 
+/// Return the offset of the byte containing `key`, and the mask of `key`
+/// within it.
+///
+/// The bitmap is laid out as a sequence of native endian words, so the byte
+/// holding a bit depends on the platform byte order; addressing the byte
+/// directly avoids reconstructing the whole word on every access.
+#[inline(always)]
+fn byte_for_key(key: usize) -> (usize, u8) {
+    let bit = key % (u64::BITS as usize);
+
+    let byte_in_word = if cfg!(target_endian = "little") {
+        bit / 8
+    } else {
+        size_of::<usize>() - 1 - bit / 8
+    };
+
+    (
+        index_for_key(key) * size_of::<usize>() + byte_in_word,
+        1 << (bit % 8),
+    )
+}
+
+#[inline(always)]
+fn get_bit(bitmap: &[u8], key: usize) -> bool {
+    let (offset, mask) = byte_for_key(key);
+    bitmap[offset] & mask != 0
+}
+
+#[inline(always)]
+fn set_bit(bitmap: &mut [u8], key: usize, value: bool) {
+    let (offset, mask) = byte_for_key(key);
+
+    if value {
+        bitmap[offset] |= mask;
+    } else {
+        bitmap[offset] &= !mask;
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::convert::TryInto;
+
     use proptest::prelude::*;
 
     use super::*;
@@ -163,5 +331,68 @@ mod tests {
                 assert_eq!(union.get(i), combined_bitmap.get(i));
             }
         }
+
+        #[test]
+        fn prop_frozen_matches_bytes(
+            values in prop::collection::hash_set(0..MAX_KEY, 0..20),
+        ) {
+            let mut b = BytesBitmap::new_with_capacity(MAX_KEY);
+            for v in &values {
+                b.set(*v, true);
+            }
+
+            let frozen = FrozenBytesBitmap::from(b.clone());
+            for i in 0..MAX_KEY {
+                assert_eq!(frozen.get(i), b.get(i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_new_with_capacity() {
+        let b = BytesBitmap::try_new_with_capacity(MAX_KEY).unwrap();
+        assert_eq!(b, BytesBitmap::new_with_capacity(MAX_KEY));
+
+        let b = FrozenBytesBitmap::try_new_with_capacity(MAX_KEY).unwrap();
+        assert_eq!(b, FrozenBytesBitmap::new_with_capacity(MAX_KEY));
+    }
+
+    #[test]
+    fn test_native_endian_word_layout() {
+        let mut b = BytesBitmap::new_with_capacity(MAX_KEY);
+        for key in [0, 7, 8, 42, 63, 64, 130, 1027] {
+            b.set(key, true);
+        }
+
+        // The byte layout must remain a sequence of native endian words, as
+        // produced by the previous word-at-a-time implementation.
+        let bytes = b.freeze();
+        for (i, chunk) in bytes.chunks_exact(size_of::<usize>()).enumerate() {
+            let word = usize::from_ne_bytes(chunk.try_into().unwrap());
+            for bit in 0..usize::BITS as usize {
+                let key = i * usize::BITS as usize + bit;
+                let want = [0, 7, 8, 42, 63, 64, 130, 1027].contains(&key);
+                assert_eq!(word & (1 << bit) != 0, want, "key {key}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_frozen_copy_on_write() {
+        let mut b = BytesBitmap::new_with_capacity(MAX_KEY);
+        b.set(42, true);
+
+        let shared = b.freeze();
+        let a = FrozenBytesBitmap::from_bytes(shared.clone());
+        let mut c = a.clone();
+
+        // Reads share the original buffer.
+        assert_eq!(a.clone().into_bytes().as_ptr(), shared.as_ptr());
+
+        // Writes do not modify other views of the buffer.
+        c.set(7, true);
+        assert!(c.get(7) && c.get(42));
+        assert!(!a.get(7));
+        assert!(!FrozenBytesBitmap::from_bytes(shared).get(7));
     }
 }
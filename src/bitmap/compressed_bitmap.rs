@@ -1,8 +1,22 @@
-use crate::Bitmap;
+use crate::{Bitmap, Error};
 
-use super::{bitmask_for_key, index_for_key, vec::VecBitmap};
+use super::{
+    bitmask_for_key, index_for_key,
+    memory::{vec_bytes, MemoryBreakdown},
+    pool::BufferPool,
+    saturate_key, simd, touch_pages,
+    vec::VecBitmap,
+};
 
-/// A sparse, 2-level bitmap with a low memory footprint, optimised for reads.
+/// The number of blocks located and prefetched at once before being read by
+/// [`CompressedBitmap::get_many()`].
+const PREFETCH_BATCH: usize = 8;
+
+/// The number of keys sorted at a time, in a buffer on the stack, by the
+/// batched lookups.
+const SORT_BUFFER_LEN: usize = 64;
+
+/// A sparse, 3-level bitmap with a low memory footprint, optimised for reads.
 ///
 /// A `CompressedBitmap` splits the bitmap up into blocks of `usize` bits, and
 /// uses a second bitmap to mark populated blocks, lazily allocating them as
@@ -27,6 +41,29 @@ use super::{bitmask_for_key, index_for_key, vec::VecBitmap};
 ///     └ ─ ┴ ─ ┴ ─ ┴ ─ ┘ └───┴───┴───┴───┘ └ ─ ┴ ─ ┴ ─ ┴ ─ ┘
 /// ```
 ///
+/// For very large key spaces the block map itself becomes large - a
+/// [`FilterSize::KeyBytes5`] filter requires 2GB of block map. To keep the
+/// fixed overhead proportional to the load factor, the block map is itself
+/// compressed in the same way: the block map is split into `usize` words, and
+/// a third "top map" bitmap marks the populated block map words:
+///
+/// ```text
+///                  ┌───┬───┐
+///         Top map: │ 1 │ 0 │
+///                  └─┬─┴───┘
+///                    └───┐
+///     ┌ ─ ┬ ─ ┬ ─ ┬ ─ ┐ ┌▼──┬───┬───┬───┐ ┌ ─ ┬ ─ ┬ ─ ┬ ─ ┐
+///       0   0   0   0   │ 0 │ 1 │ 0 │ 0 │   0   0   0   0     Block map
+///     └ ─ ┴ ─ ┴ ─ ┴ ─ ┘ └───┴─┬─┴───┴───┘ └ ─ ┴ ─ ┴ ─ ┴ ─ ┘
+///                             ▼
+///                           Blocks
+/// ```
+///
+/// The top map is itself sparse, storing only its non-zero words alongside
+/// their logical indexes, so the memory used by every level is proportional to
+/// the number of populated blocks regardless of the size of the key space, and
+/// an empty `CompressedBitmap` does not allocate at all.
+///
 /// This amortised `O(1)` insert operation takes ~4ns, while reading a value
 /// takes a constant time ~1ns on a Core i7 @ 2.60GHz.
 ///
@@ -37,56 +74,797 @@ use super::{bitmask_for_key, index_for_key, vec::VecBitmap};
 /// ## Features
 ///
 /// If the `serde` feature is enabled, a `CompressedBitmap` supports
-/// (de)serialisation with [serde].
+/// (de)serialisation with [serde]. Binary formats encode the bitmap words as
+/// contiguous byte slices, while human-readable formats encode them as a
+/// sequence of integers.
+///
+/// Bitmaps serialised by v0.5 (and earlier) of this crate with a
+/// self-describing format (such as JSON) store a single, uncompressed block
+/// map, and are converted to the current layout when deserialised. v0.5
+/// bitmaps serialised by a release build do not record their `max_key`, and
+/// cannot be deserialised - see [`LegacyBloom2`](crate::LegacyBloom2) to read a v0.5 filter.
 ///
 /// [serde]: https://github.com/serde-rs/serde
+/// [`FilterSize::KeyBytes5`]: crate::FilterSize::KeyBytes5
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Repr"))]
+#[cfg_attr(feature = "allocative", derive(allocative::Allocative))]
 pub struct CompressedBitmap {
+    /// The logical indexes of the words in top_map, in ascending order.
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_words"))]
+    top_index: Vec<usize>,
+    /// The non-zero words of a bitmap of populated (non-zero) words in the
+    /// logical block map, in order.
+    ///
+    /// LSB is 0.
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_words"))]
+    top_map: Vec<usize>,
+    /// The populated words of the logical block map, in order.
+    ///
     /// LSB is 0.
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_words"))]
     block_map: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_words"))]
     bitmap: Vec<usize>,
 
-    #[cfg(debug_assertions)]
+    /// The most recently written block, allowing consecutive writes to the
+    /// same block to skip the offset computation.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "allocative", allocative(skip))]
+    hot: HotBlock,
+
+    max_key: usize,
+}
+
+/// The serialised representation of a [`CompressedBitmap`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Repr {
+    /// Absent from the layout with a dense `top_map`.
+    #[serde(default, deserialize_with = "super::serde_words::deserialize_some")]
+    top_index: Option<Vec<usize>>,
+    /// Absent from the v0.5 layout, in which `block_map` covers every block
+    /// of the key space.
+    #[serde(default, deserialize_with = "super::serde_words::deserialize_some")]
+    top_map: Option<Vec<usize>>,
+    #[serde(with = "super::serde_words")]
+    block_map: Vec<usize>,
+    #[serde(with = "super::serde_words")]
+    bitmap: Vec<usize>,
     max_key: usize,
 }
 
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<Repr> for CompressedBitmap {
+    type Error = InvariantError;
+
+    fn try_from(v: Repr) -> Result<Self, Self::Error> {
+        let ((top_index, top_map), block_map) = match (v.top_index, v.top_map) {
+            (Some(top_index), top_map) => ((top_index, top_map.unwrap_or_default()), v.block_map),
+            (None, Some(top_map)) => (Self::sparse_top_map(&top_map), v.block_map),
+            (None, None) => {
+                // Index the populated words of the uncompressed block map.
+                let mut top_map = vec![0; v.block_map.len().div_ceil(u64::BITS as usize)];
+                for (i, _) in v.block_map.iter().enumerate().filter(|(_, &w)| w != 0) {
+                    top_map[index_for_key(i)] |= bitmask_for_key(i);
+                }
+                while top_map.last() == Some(&0) {
+                    top_map.pop();
+                }
+
+                let block_map = v.block_map.into_iter().filter(|&w| w != 0).collect();
+                (Self::sparse_top_map(&top_map), block_map)
+            }
+        };
+
+        let out = Self {
+            top_index,
+            top_map,
+            block_map,
+            bitmap: v.bitmap,
+            hot: HotBlock::default(),
+            max_key: v.max_key,
+        };
+
+        // Reject inconsistent levels, which would otherwise be indexed out of
+        // bounds when read.
+        out.verify_lengths()?;
+        Ok(out)
+    }
+}
+
 impl CompressedBitmap {
     /// Construct a `CompressedBitmap` for space to hold up to `max_key` number
     /// of bits.
+    ///
+    /// All levels of the bitmap are lazily allocated as keys are set, so an
+    /// empty `CompressedBitmap` does not allocate, regardless of `max_key`.
     pub fn new(max_key: usize) -> Self {
-        // Calculate how many instances of usize (blocks) are needed to hold
-        // max_key number of bits.
-        let blocks = index_for_key(max_key);
-
-        // Figure out how many usize elements are needed to represent blocks
-        // number of bitmaps.
-        let num_blocks = match blocks % (u64::BITS as usize) {
-            0 => index_for_key(blocks),
-            _ => index_for_key(blocks) + 1, // +1 to cover the remainder
-        };
+        CompressedBitmap {
+            top_index: Vec::new(),
+            top_map: Vec::new(),
+            block_map: Vec::new(),
+            bitmap: Vec::new(),
+            hot: HotBlock::default(),
 
-        // Allocate a block map.
-        //
-        // The block map contains bitmaps with 1 bits indicating the bitmap for
-        // that key has been allocated.
-        let block_map = vec![0; num_blocks];
+            max_key,
+        }
+    }
 
+    /// Construct a `CompressedBitmap` for space to hold up to `max_key` number
+    /// of bits, reusing buffers from `pool`.
+    ///
+    /// See [`BufferPool`].
+    pub fn new_in(max_key: usize, pool: &mut BufferPool) -> Self {
         CompressedBitmap {
-            bitmap: Vec::new(),
-            block_map,
+            top_index: pool.take(),
+            top_map: pool.take(),
+            block_map: pool.take(),
+            bitmap: pool.take(),
+            hot: HotBlock::default(),
 
-            #[cfg(debug_assertions)]
             max_key,
         }
     }
 
+    /// Compress the dense bitmap `words` into a `CompressedBitmap` holding up
+    /// to `max_key` number of bits, in a single pass.
+    ///
+    /// Word `n` of `words` holds the bits of keys `n * 64..(n + 1) * 64`, with
+    /// the lowest key in the least significant bit - the same layout as the
+    /// blocks yielded by [`CompressedBitmap::blocks()`]. Only the non-zero
+    /// words are retained, and `words` may be shorter than the key space, in
+    /// which case the remaining keys are unset:
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let b = CompressedBitmap::from_dense_words(&[1 << 1, 0, 1 << 2], 1024).unwrap();
+    ///
+    /// assert!(b.get(1));
+    /// assert!(b.get(130));
+    /// assert_eq!(b.count_ones(), 2);
+    /// ```
+    ///
+    /// Returns [`Error::KeyOutOfRange`] if `words` holds more bits than the
+    /// key space of `max_key`, or sets a bit above `max_key`.
+    pub fn from_dense_words(words: &[u64], max_key: usize) -> Result<Self, Error> {
+        if words.len() > index_for_key(max_key) + 1 {
+            return Err(Error::KeyOutOfRange {
+                key: words.len() * u64::BITS as usize - 1,
+                max_key,
+            });
+        }
+
+        // The last word may hold bits beyond max_key.
+        if let Some((index, word)) = words.iter().enumerate().rfind(|(_, &w)| w != 0) {
+            let key = index * u64::BITS as usize + (u64::BITS - 1 - word.leading_zeros()) as usize;
+            if key > max_key {
+                return Err(Error::KeyOutOfRange { key, max_key });
+            }
+        }
+
+        let mut out = Self::new(max_key);
+        for (block_index, &word) in words.iter().enumerate() {
+            if word != 0 {
+                out.push_block(block_index, word as usize);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Construct a `CompressedBitmap` holding up to `max_key` number of bits
+    /// from the `(logical block index, block)` pairs of `blocks`.
+    ///
+    /// # Panics
+    ///
+    /// `blocks` MUST be in strictly ascending block index order.
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_blocks<I>(max_key: usize, blocks: I) -> Self
+    where
+        I: IntoIterator<Item = (usize, usize)>,
+    {
+        let mut out = Self::new(max_key);
+        for (block_index, block) in blocks {
+            out.push_block(block_index, block);
+        }
+        out
+    }
+
+    /// Compress `bitmap` into a `CompressedBitmap` reusing buffers from
+    /// `pool` for the block maps.
+    ///
+    /// This is the pooled equivalent of [`CompressedBitmap::from()`]. The
+    /// blocks are compressed in place within the buffer of `bitmap`, which
+    /// retains its full capacity for reuse once the `CompressedBitmap` is
+    /// [recycled](CompressedBitmap::recycle).
+    pub fn from_vec_bitmap_in(bitmap: VecBitmap, pool: &mut BufferPool) -> Self {
+        let (bitmap, max_key) = bitmap.into_parts();
+
+        let mut compressed = Self::new_in(max_key, pool);
+        pool.put(std::mem::replace(&mut compressed.bitmap, bitmap));
+        compressed.compact_dense();
+
+        compressed
+    }
+
+    /// Return the buffers of this bitmap to `pool` for later reuse.
+    ///
+    /// See [`BufferPool`].
+    pub fn recycle(self, pool: &mut BufferPool) {
+        pool.put(self.top_index);
+        pool.put(self.top_map);
+        pool.put(self.block_map);
+        pool.put(self.bitmap);
+    }
+
+    /// Construct a `CompressedBitmap` for space to hold up to `max_key` number
+    /// of bits, with the bits in `keys` set to `true`.
+    ///
+    /// The bitmap is constructed in a single append-only pass over `keys`,
+    /// which is significantly faster than calling [`CompressedBitmap::set()`]
+    /// for each key, as no blocks are inserted into the middle of the
+    /// compressed bitmap.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let b = CompressedBitmap::from_sorted_keys(1024, [1, 2, 2, 42, 1000]);
+    ///
+    /// assert!(b.get(42));
+    /// assert!(!b.get(43));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `keys` are not sorted in ascending order
+    /// (duplicate keys are permitted).
+    ///
+    /// This method MAY panic if any key is more than `max_key`, as described
+    /// in [`CompressedBitmap::set()`].
+    pub fn from_sorted_keys<I>(max_key: usize, keys: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let keys = keys.into_iter().map(|key| {
+            let key = saturate_key(key, max_key);
+            debug_assert!(key <= max_key, "key {} > {} max", key, max_key);
+            key
+        });
+
+        let mut out = Self::new(max_key);
+        for (idx, block) in SortedKeyBlocks::new(keys) {
+            out.push_block(idx, block);
+        }
+
+        out
+    }
+
+    /// Sets the bits in `keys` to `true`.
+    ///
+    /// `keys` is sorted in place, and all the new blocks are merged into the
+    /// bitmap in a single pass, turning `N` calls to
+    /// [`CompressedBitmap::set()`] (each `O(n)` in the worst case) into a
+    /// single `O(n + N)` operation. This is significantly faster for bursts
+    /// of writes, but is slower than calling [`CompressedBitmap::set()`] for a
+    /// small number of keys that mostly land in existing blocks.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set_many(&mut [1000, 42, 1]);
+    ///
+    /// assert!(b.get(42));
+    /// assert!(!b.get(43));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method MAY panic if any key is more than the `max_key` value
+    /// provided when initialising the bitmap, as described in
+    /// [`CompressedBitmap::set()`].
+    pub fn set_many(&mut self, keys: &mut [usize]) {
+        #[cfg(feature = "saturating")]
+        for key in keys.iter_mut() {
+            *key = saturate_key(*key, self.max_key);
+        }
+
+        #[cfg(debug_assertions)]
+        for &key in keys.iter() {
+            debug_assert!(key <= self.max_key, "key {} > {} max", key, self.max_key);
+        }
+
+        if keys.is_empty() {
+            return;
+        }
+
+        keys.sort_unstable();
+
+        let mut out = self.empty_with_capacity(self.bitmap.len() + keys.len());
+        merge_blocks(
+            &mut out,
+            BlockIter::new(self),
+            SortedKeyBlocks::new(keys.iter().copied()),
+        );
+
+        *self = out;
+    }
+
     pub fn size(&self) -> usize {
-        (self.block_map.capacity() * std::mem::size_of::<usize>())
+        (self.top_index.capacity() * std::mem::size_of::<usize>())
+            + (self.top_map.capacity() * std::mem::size_of::<usize>())
+            + (self.block_map.capacity() * std::mem::size_of::<usize>())
             + (self.bitmap.capacity() * std::mem::size_of::<usize>())
             + std::mem::size_of_val(self)
     }
 
+    /// Verify the internal consistency of the bitmap, including that no bit
+    /// above the `max_key` it was constructed with is set.
+    ///
+    /// This is an `O(n)` check intended for debugging integrations that
+    /// construct or restore a `CompressedBitmap` outside of this crate's
+    /// control (such as deserialising persisted state, or FFI), and returns a
+    /// description of the first inconsistency found.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(42, true);
+    ///
+    /// assert!(b.verify_invariants().is_ok());
+    /// ```
+    ///
+    /// Blocks containing no set bits are permitted, as setting the last bit
+    /// in a block to `false` leaves the (now empty) block in place.
+    pub fn verify_invariants(&self) -> Result<(), InvariantError> {
+        let max_key = self.max_key;
+
+        // Block map words are only allocated to mark a block, so are never
+        // 0.
+        if let Some(index) = self.block_map.iter().position(|&v| v == 0) {
+            return Err(InvariantError::EmptyBlockMapWord { index });
+        }
+
+        self.verify_lengths()?;
+
+        // No bit above max_key can be set.
+        let highest = BlockIter::new(self)
+            .filter(|(_, block)| *block != 0)
+            .last()
+            .map(|(idx, block)| {
+                idx * u64::BITS as usize + (usize::BITS - 1 - block.leading_zeros()) as usize
+            });
+        if let Some(key) = highest.filter(|&v| v > max_key) {
+            return Err(InvariantError::KeyOutOfRange { key, max_key });
+        }
+
+        Ok(())
+    }
+
+    /// Verify that each level of the bitmap holds an entry for every bit set
+    /// in the level above it, and that the top map indexes are ordered and
+    /// within the key space.
+    ///
+    /// This is the subset of [`CompressedBitmap::verify_invariants()`]
+    /// required to read the bitmap without indexing out of bounds.
+    fn verify_lengths(&self) -> Result<(), InvariantError> {
+        // Each top map word has a logical index.
+        if self.top_index.len() != self.top_map.len() {
+            return Err(InvariantError::TopMapLength {
+                want: self.top_map.len(),
+                got: self.top_index.len(),
+            });
+        }
+
+        let top_words = index_for_key(index_for_key(index_for_key(self.max_key))) + 1;
+        if let Some(index) = (0..self.top_index.len()).position(|i| {
+            self.top_index[i] >= top_words || (i > 0 && self.top_index[i] <= self.top_index[i - 1])
+        }) {
+            return Err(InvariantError::InvalidTopMapIndex { index });
+        }
+
+        // Each set bit in the top map marks a block map word.
+        let want = simd::count_ones(&self.top_map);
+        if want != self.block_map.len() {
+            return Err(InvariantError::BlockMapLength {
+                want,
+                got: self.block_map.len(),
+            });
+        }
+
+        // Each set bit in the block map marks a block.
+        let want = simd::count_ones(&self.block_map);
+        if want != self.bitmap.len() {
+            return Err(InvariantError::BitmapLength {
+                want,
+                got: self.bitmap.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Verify the bitmap as [`CompressedBitmap::verify_invariants()`] does,
+    /// and that it was constructed to hold `max_key` number of bits.
+    pub(crate) fn verify_invariants_for(&self, max_key: usize) -> Result<(), InvariantError> {
+        if self.max_key != max_key {
+            return Err(InvariantError::MaxKeyMismatch {
+                want: max_key,
+                got: self.max_key,
+            });
+        }
+        self.verify_invariants()
+    }
+
+    /// Returns the number of allocated blocks in the bitmap.
+    pub(crate) fn block_count(&self) -> usize {
+        self.bitmap.len()
+    }
+
+    /// Returns the number of bytes used by the populated entries of all 3
+    /// levels, excluding any excess capacity.
+    pub(crate) fn used_bytes(&self) -> usize {
+        (self.top_index.len() + self.top_map.len() + self.block_map.len() + self.bitmap.len())
+            * std::mem::size_of::<usize>()
+    }
+
+    /// Returns the `max_key` the bitmap was initialised with.
+    pub(crate) fn max_key(&self) -> usize {
+        self.max_key
+    }
+
+    /// Returns the block (word) at `block_index`, or 0 if it is not
+    /// allocated.
+    pub(crate) fn block(&self, block_index: usize) -> usize {
+        let slot = Slot::locate(self, block_index);
+        self.bitmap.get(slot.offset).copied().unwrap_or_default() & slot.block_map_hit
+    }
+
+    /// Returns the top map indexes, top map, block map and bitmap words.
+    #[cfg(feature = "prost")]
+    pub(crate) fn raw_parts(&self) -> (&[usize], &[usize], &[usize], &[usize]) {
+        (
+            &self.top_index,
+            &self.top_map,
+            &self.block_map,
+            &self.bitmap,
+        )
+    }
+
+    /// Construct a `CompressedBitmap` from the words returned by
+    /// [`CompressedBitmap::raw_parts()`], without validating them.
+    ///
+    /// The caller should call [`CompressedBitmap::verify_invariants()`] before
+    /// use if the words are untrusted.
+    #[cfg(feature = "prost")]
+    pub(crate) fn from_raw_parts(
+        top_index: Vec<usize>,
+        top_map: Vec<usize>,
+        block_map: Vec<usize>,
+        bitmap: Vec<usize>,
+        max_key: usize,
+    ) -> Self {
+        Self {
+            top_index,
+            top_map,
+            block_map,
+            bitmap,
+            hot: HotBlock::default(),
+            max_key,
+        }
+    }
+
+    /// Split a dense top map into the logical indexes and words of its
+    /// non-zero words.
+    #[cfg(any(feature = "serde", feature = "prost"))]
+    pub(crate) fn sparse_top_map(dense: &[usize]) -> (Vec<usize>, Vec<usize>) {
+        dense
+            .iter()
+            .enumerate()
+            .filter(|(_, &w)| w != 0)
+            .map(|(i, &w)| (i, w))
+            .unzip()
+    }
+
+    /// Returns the physical position of the logical top map word
+    /// `top_map_index` in top_map (or the position at which it would be
+    /// inserted if it is not allocated), and the word, or 0 if it is not
+    /// allocated.
+    #[inline(always)]
+    fn top_word(&self, top_map_index: usize) -> (usize, usize) {
+        match self.top_index.binary_search(&top_map_index) {
+            Ok(pos) => (pos, self.top_map[pos]),
+            Err(pos) => (pos, 0),
+        }
+    }
+
+    /// Returns the number of bytes [`CompressedBitmap::used_bytes()`] would
+    /// grow by if all `keys` were set to `true`.
+    pub(crate) fn bytes_to_set(&self, keys: &[usize]) -> usize {
+        let mut blocks = keys.iter().map(|&v| index_for_key(v)).collect::<Vec<_>>();
+        blocks.sort_unstable();
+        blocks.dedup();
+
+        let mut words = 0;
+        let mut last_block_map_index = None;
+        let mut last_top_map_index = None;
+        for block_index in blocks {
+            let slot = Slot::locate(self, block_index);
+            if slot.is_allocated() {
+                continue;
+            }
+
+            // A new block is required.
+            words += 1;
+
+            // And a new block map word, if not already allocated (or
+            // allocated for a preceding key).
+            let block_map_index = index_for_key(block_index);
+            if slot.top_map_hit == 0 && last_block_map_index != Some(block_map_index) {
+                words += 1;
+                last_block_map_index = Some(block_map_index);
+
+                // And a new top map word and its index, if not already
+                // allocated.
+                if !slot.is_top_word_allocated(self)
+                    && last_top_map_index != Some(slot.top_map_index)
+                {
+                    words += 2;
+                    last_top_map_index = Some(slot.top_map_index);
+                }
+            }
+        }
+
+        words * std::mem::size_of::<usize>()
+    }
+
+    /// Fold the bitmap `factor` times, returning a bitmap of `max_key /
+    /// factor` bits with each bit `n` OR-ed into bit `n % (max_key / factor)`.
+    ///
+    /// Folding by a factor of 2 ORs the upper half of the bit space onto the
+    /// lower half, halving the maximum size of the bitmap while retaining
+    /// every set bit (modulo the new size):
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1000, true);
+    ///
+    /// let folded = b.fold(4);
+    /// assert!(folded.get(1000 % 256));
+    /// ```
+    ///
+    /// See [`Bloom2::fold_to()`](crate::Bloom2::fold_to) to fold a filter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not a power of 2, or the folded size is not a
+    /// non-zero multiple of 64 bits.
+    pub fn fold(&self, factor: usize) -> Self {
+        assert!(factor.is_power_of_two(), "fold factor must be a power of 2");
+        assert!(
+            self.max_key.is_multiple_of(factor),
+            "max key {} is not divisible by fold factor {}",
+            self.max_key,
+            factor
+        );
+        self.fold_to_bits(self.max_key / factor)
+    }
+
+    /// Fold the bitmap into a bitmap holding `max_key` bits, OR-ing each
+    /// block `n` into block `n % (max_key / 64)`.
+    ///
+    /// Setting `key` in the original bitmap is equivalent to setting
+    /// `key % max_key` in the folded bitmap.
+    ///
+    /// # Panics
+    ///
+    /// `max_key` MUST be a non-zero multiple of 64.
+    pub(crate) fn fold_to_bits(&self, max_key: usize) -> Self {
+        assert!(max_key > 0 && max_key.is_multiple_of(u64::BITS as usize));
+        let len = index_for_key(max_key);
+
+        let mut blocks = BlockIter::new(self)
+            .map(|(idx, block)| (idx % len, block))
+            .collect::<Vec<_>>();
+        blocks.sort_unstable_by_key(|(idx, _)| *idx);
+
+        let mut out = CompressedBitmap::new(max_key);
+        let mut blocks = blocks.into_iter().peekable();
+        while let Some((idx, mut block)) = blocks.next() {
+            while let Some((_, next)) = blocks.next_if(|(next, _)| *next == idx) {
+                block |= next;
+            }
+            out.push_block(idx, block);
+        }
+
+        out
+    }
+
+    /// Decompress this bitmap into a [`VecBitmap`] with capacity for
+    /// `max_key` number of bits.
+    pub(crate) fn decompress(&self, max_key: usize) -> VecBitmap {
+        debug_assert_eq!(self.max_key, max_key);
+
+        let mut out = VecBitmap::new_with_capacity(max_key);
+        for (block_index, block) in BlockIter::new(self) {
+            out.set_block(block_index, block);
+        }
+
+        out
+    }
+
+    /// Returns an iterator of the `(logical block index, block)` pairs of
+    /// every allocated block, in ascending block index order.
+    ///
+    /// Block `n` holds the bits of keys `n * 64..(n + 1) * 64`, with the
+    /// lowest key in the least significant bit:
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1, true);
+    /// b.set(130, true);
+    ///
+    /// let blocks = b.blocks().collect::<Vec<_>>();
+    /// assert_eq!(blocks, [(0, 1 << 1), (2, 1 << 2)]);
+    /// ```
+    ///
+    /// Unsetting every bit of a block does not free it, so an allocated block
+    /// may be 0.
+    pub fn blocks(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        BlockIter::new(self).map(|(block_index, block)| (block_index, block as u64))
+    }
+
+    /// Returns an iterator of the keys set to `true`, in ascending order.
+    pub(crate) fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        BlockIter::new(self).flat_map(|(block_index, mut block)| {
+            std::iter::from_fn(move || {
+                if block == 0 {
+                    return None;
+                }
+                let bit = block.trailing_zeros() as usize;
+                block &= block - 1;
+                Some(block_index * u64::BITS as usize + bit)
+            })
+        })
+    }
+
+    /// Returns the number of bits set to `true` in the bitmap.
+    pub fn count_ones(&self) -> usize {
+        simd::count_ones(&self.bitmap)
+    }
+
+    /// Returns the distribution of set bits across the allocated blocks.
+    ///
+    /// The value at index `n` of the returned array is the number of
+    /// allocated blocks with exactly `n` bits set (from 0 to 64 inclusive).
+    ///
+    /// For a bloom filter populated using a well distributed hash, the set
+    /// bits are spread evenly across the blocks. A skewed distribution (such
+    /// as many near-full blocks while the filter is lightly loaded) indicates
+    /// a poorly distributed hash, or that a different [`FilterSize`] may be
+    /// more appropriate.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1, true);
+    /// b.set(2, true);
+    /// b.set(1000, true);
+    ///
+    /// let histogram = b.occupancy_histogram();
+    /// assert_eq!(histogram[1], 1);
+    /// assert_eq!(histogram[2], 1);
+    /// ```
+    ///
+    /// Allocated blocks that no longer contain any set bits (after setting
+    /// them to `false`) are counted at index 0.
+    ///
+    /// [`FilterSize`]: crate::FilterSize
+    pub fn occupancy_histogram(&self) -> [usize; u64::BITS as usize + 1] {
+        let mut histogram = [0; u64::BITS as usize + 1];
+        for block in &self.bitmap {
+            histogram[block.count_ones() as usize] += 1;
+        }
+        histogram
+    }
+
+    /// Returns the fraction of the addressable bits (`max_key`) that are set
+    /// to `true`, from 0 to 1.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1, true);
+    /// b.set(1000, true);
+    ///
+    /// assert_eq!(b.density(), 2.0 / 1024.0);
+    /// ```
+    ///
+    /// For a bloom filter, this is the load factor that determines the false
+    /// positive probability.
+    pub fn density(&self) -> f64 {
+        if self.max_key == 0 {
+            return 0.0;
+        }
+        self.count_ones() as f64 / self.max_key as f64
+    }
+
+    /// Returns the fraction of the blocks covering the addressable bits that
+    /// are allocated, from 0 to 1.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1, true);
+    /// b.set(2, true);
+    /// b.set(1000, true);
+    ///
+    /// // 2 of the 16 blocks are allocated.
+    /// assert_eq!(b.allocated_fraction(), 2.0 / 16.0);
+    /// ```
+    ///
+    /// Each allocated block costs a word of memory, plus the block map
+    /// overhead. Once most blocks are allocated, a [`VecBitmap`] holding every
+    /// block uses less memory and is faster to query than the compressed
+    /// representation.
+    ///
+    /// [`VecBitmap`]: crate::VecBitmap
+    pub fn allocated_fraction(&self) -> f64 {
+        let blocks = self.max_key.div_ceil(u64::BITS as usize).max(1);
+        self.bitmap.len() as f64 / blocks as f64
+    }
+
+    /// Reserves capacity for at least `additional` more blocks (of 64 bits)
+    /// to be allocated, returning [`Error::AllocationFailed`] instead of
+    /// aborting the process if the memory cannot be allocated.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1 << 32);
+    /// b.try_reserve(1024).expect("allocation failed");
+    ///
+    /// assert!(b.try_reserve(usize::MAX).is_err());
+    /// ```
+    ///
+    /// Reserving capacity ahead of a bulk insert allows an allocation failure
+    /// to be handled gracefully - subsequent inserts that fit within the
+    /// reserved capacity do not reallocate the blocks or block map, leaving
+    /// only the comparatively tiny top map to grow.
+    ///
+    /// See [`Vec::try_reserve`](std::vec::Vec::try_reserve).
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), Error> {
+        let failed = |words: usize| Error::AllocationFailed {
+            bytes: words.saturating_mul(std::mem::size_of::<usize>()),
+        };
+
+        // In the worst case, each new block requires a new block map word, up
+        // to the number of block map words addressable by max_key.
+        let block_map_words = index_for_key(index_for_key(self.max_key)) + 1;
+        let block_map_additional =
+            additional.min(block_map_words.saturating_sub(self.block_map.len()));
+
+        self.bitmap
+            .try_reserve(additional)
+            .map_err(|_| failed(additional))?;
+        self.block_map
+            .try_reserve(block_map_additional)
+            .map_err(|_| failed(block_map_additional))?;
+
+        Ok(())
+    }
+
     /// Reduces the allocated memory usage of the bitmap to the minimum required
     /// for the current bitmap contents.
     ///
@@ -97,6 +875,8 @@ impl CompressedBitmap {
     pub fn shrink_to_fit(&mut self) {
         self.bitmap.shrink_to_fit();
         self.block_map.shrink_to_fit();
+        self.top_map.shrink_to_fit();
+        self.top_index.shrink_to_fit();
         // TODO: remove 0 blocks
     }
 
@@ -105,10 +885,17 @@ impl CompressedBitmap {
     /// An efficient way to remove all elements in the bitmap to allow it to be
     /// reused. Does not shrink the allocated backing memory, instead retaining
     /// the capacity to avoid reallocations.
+    ///
+    /// Because the blocks, and the block maps that describe them, are all
+    /// lazily allocated, clearing the bitmap only resets the length of each
+    /// level - no memory is zeroed, making this an `O(1)` operation regardless
+    /// of the size of the bitmap. Stale blocks are overwritten as the bitmap
+    /// is repopulated.
     pub fn clear(&mut self) {
-        for block in self.block_map.iter_mut() {
-            *block = 0;
-        }
+        self.hot = HotBlock::default();
+        self.top_index.truncate(0);
+        self.top_map.truncate(0);
+        self.block_map.truncate(0);
         self.bitmap.truncate(0);
     }
 
@@ -123,8 +910,13 @@ impl CompressedBitmap {
     /// `key > max` will always panic. In release builds, this may not panic for
     /// values of `key` that are only slightly larger than `max_key` for
     /// performance reasons.
+    ///
+    /// If the `saturating` feature is enabled, a `key` more than `max_key` is
+    /// clamped to `max_key` and this method never panics.
+    ///
+    /// Use [`CompressedBitmap::try_set()`] for keys that may be out of range.
     pub fn set(&mut self, key: usize, value: bool) {
-        #[cfg(debug_assertions)]
+        let key = saturate_key(key, self.max_key);
         debug_assert!(key <= self.max_key, "key {} > {} max", key, self.max_key);
 
         // First compute the index of the bit in the bitmap if it was fully
@@ -150,6 +942,18 @@ impl CompressedBitmap {
         //
         let block_index = index_for_key(key);
 
+        // Sequential writes frequently land in the same block as the previous
+        // write, in which case the physical offset of the block is already
+        // known.
+        if let Some(offset) = self.hot.get(block_index) {
+            if value {
+                self.bitmap[offset] |= bitmask_for_key(key);
+            } else {
+                self.bitmap[offset] &= !bitmask_for_key(key);
+            }
+            return;
+        }
+
         // Because the blocks are initialised lazily to provide the sparse
         // bitmap behaviour, there may be no block yet allocated for this bitmap
         // index. The block_map data structure is itself bitmap with a 1 bit
@@ -172,16 +976,16 @@ impl CompressedBitmap {
         //                   2: │ 0 │ 0 │ 1 │ 1 │
         //                      └───┴───┴───┴───┘
         //
-        let block_map_index = index_for_key(block_index);
-        let block_map_bitmask = bitmask_for_key(block_index);
-
-        // The block has been allocated if the block usize contains a 1 bit.
+        //
+        // The block map is itself sparse, with the top map marking which words
+        // of the logical block map are allocated, exactly as the block map
+        // marks allocated blocks.
         //
         // Because blocks are lazily initialised, block n may not be at
-        // block_map[n] if prior blocks have not been initialised. To
-        // calculate the offset of block n, the number of 1's in the
-        // block_map before bit n. This operation is very fast on modern
-        // hardware thanks to the POPCNT instruction.
+        // bitmap[n] if prior blocks have not been initialised. To calculate
+        // the offset of block n, count the number of 1's in the block_map
+        // before bit n. This operation is very fast on modern hardware thanks
+        // to the POPCNT instruction.
         //
         //            Block Map:
         //
@@ -198,418 +1002,2027 @@ impl CompressedBitmap {
         // In the above example, the popcount() is 3, and the block is the
         // 3+1=4th block in bitmap. However as the arrays are zero-indexed,
         // the +1 is omitted to adjust from the position 4, to index 3.
-
-        // Count the ones in the full blocks.
-        //
-        // This could chain() the final masked count_ones() call below using
-        // once_with, and while more readable, it is unfortunately measurably
-        // slower in practice.
-        let offset: usize = (0..block_map_index)
-            .map(|i| self.block_map[i].count_ones() as usize)
-            .sum();
-
-        // Mask out the higher bits in the block map to count the populated
-        // blocks before block_index
-        let mask = block_map_bitmask - 1;
-        let offset = offset + (self.block_map[block_map_index] & mask).count_ones() as usize;
-
-        // Offset now contains the index in bitmap at which block_index can
-        // be found.
         //
-        // Because the blocks are lazily initialised, there may not yet be a
-        // block for block_map_index.
-        //
-        // Read the usize at block_map_index, and check the bit for
-        // block_index.
-        if self.block_map[block_map_index] & block_map_bitmask == 0 {
-            // If the value to be set is false, there's nothing to do.
-            if !value {
-                return;
-            }
-
-            // The block does not exist, insert it into the bitmap at
-            // block_index.
-            if offset >= self.bitmap.len() {
-                self.bitmap.push(bitmask_for_key(key));
+        // See Slot::locate() for the details.
+        let slot = Slot::locate(self, block_index);
+
+        // If the block is allocated, set the bit within it.
+        if slot.is_allocated() {
+            self.hot = HotBlock::new(block_index, slot.offset);
+            if value {
+                self.bitmap[slot.offset] |= bitmask_for_key(key);
             } else {
-                // If offset is < bitmap.len() this will require moving all
-                // the elements at offset+1 one slot to the right to make
-                // room for the new element.
-                //
-                // For bitmaps with large numbers of elements to the right
-                // of offset, this can become expensive.
-                self.bitmap.insert(offset, bitmask_for_key(key));
-            }
-            self.block_map[block_map_index] |= block_map_bitmask;
+                self.bitmap[slot.offset] &= !bitmask_for_key(key);
+            }
             return;
         }
 
-        // Otherwise the block map indicates the block is already allocated
-        if value {
-            self.bitmap[offset] |= bitmask_for_key(key);
-        } else {
-            self.bitmap[offset] &= !bitmask_for_key(key);
+        // Otherwise the block does not exist, and if the value to be set is
+        // false, there's nothing to do.
+        if !value {
+            return;
         }
+
+        self.insert_block(&slot, block_index, bitmask_for_key(key));
     }
 
-    /// Returns the value at `key`.
-    ///
+    /// Allocate the unallocated block at `block_index`, located by `slot`,
+    /// initialising it to `block`.
+    fn insert_block(&mut self, slot: &Slot, block_index: usize, block: usize) {
+        debug_assert!(!slot.is_allocated());
+
+        if slot.top_map_hit == 0 {
+            // There is no block map word for block_index either.
+            //
+            // The top map is sparse, and may not hold the word marking it.
+            if !slot.is_top_word_allocated(self) {
+                self.top_index
+                    .insert(slot.top_map_offset, slot.top_map_index);
+                self.top_map.insert(slot.top_map_offset, 0);
+            }
+
+            // If the block_map_offset is < len() this will require moving all
+            // the elements one slot to the right to make room for the new
+            // element.
+            self.block_map.insert(slot.block_map_offset, 0);
+            self.top_map[slot.top_map_offset] |= slot.top_map_bitmask;
+        }
+
+        // Insert the block into the bitmap at the offset computed above.
+        //
+        // If offset is < bitmap.len() this will require moving all the
+        // elements at offset+1 one slot to the right to make room for the new
+        // element.
+        //
+        // For bitmaps with large numbers of elements to the right of offset,
+        // this can become expensive.
+        self.block_map[slot.block_map_offset] |= slot.block_map_bitmask;
+        self.bitmap.insert(slot.offset, block);
+
+        // Inserting the block moves all the blocks after it, so only the
+        // offset of the new block is known to be valid.
+        self.hot = HotBlock::new(block_index, slot.offset);
+    }
+
+    /// Returns the 64 bits of the logical block `block_index`, holding the
+    /// keys `block_index * 64..(block_index + 1) * 64` with the lowest key in
+    /// the least significant bit.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(130, true);
+    ///
+    /// assert_eq!(b.get_block(2), 1 << 2);
+    /// assert_eq!(b.get_block(3), 0);
+    /// ```
+    ///
+    /// Unallocated blocks, including those beyond `max_key`, read as 0.
+    pub fn get_block(&self, block_index: usize) -> u64 {
+        self.block(block_index) as u64
+    }
+
+    /// Overwrite the 64 bits of the logical block `block_index` with `block`
+    /// (see [`CompressedBitmap::get_block()`] for the layout).
+    ///
+    /// This allows operations over the bitmap to be applied a word at a time,
+    /// such as intersecting it with an external bitmap:
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1, true);
+    /// b.set(2, true);
+    ///
+    /// let external = 0b1100_u64;
+    /// b.set_block(0, b.get_block(0) & external).unwrap();
+    ///
+    /// assert!(!b.get(1));
+    /// assert!(b.get(2));
+    /// ```
+    ///
+    /// Returns [`Error::KeyOutOfRange`] for the highest set bit of `block` if
+    /// it is more than the `max_key` value provided when initialising the
+    /// bitmap, leaving the bitmap unmodified.
+    ///
+    /// As with [`CompressedBitmap::set()`], writing 0 to an unallocated block
+    /// does not allocate it, and writing 0 to an allocated block does not free
+    /// it.
+    pub fn set_block(&mut self, block_index: usize, block: u64) -> Result<(), Error> {
+        if block != 0 {
+            let highest = u64::BITS as usize - 1 - block.leading_zeros() as usize;
+            self.check_key(
+                block_index
+                    .saturating_mul(u64::BITS as usize)
+                    .saturating_add(highest),
+            )?;
+        }
+        let block = block as usize;
+
+        if let Some(offset) = self.hot.get(block_index) {
+            self.bitmap[offset] = block;
+            return Ok(());
+        }
+
+        let slot = Slot::locate(self, block_index);
+        if slot.is_allocated() {
+            self.hot = HotBlock::new(block_index, slot.offset);
+            self.bitmap[slot.offset] = block;
+        } else if block != 0 {
+            self.insert_block(&slot, block_index, block);
+        }
+
+        Ok(())
+    }
+
+    /// Inserts `key` into the bitmap, returning an error if `key` is more than
+    /// the `max_key` value provided when initialising the bitmap.
+    ///
+    /// ```rust
+    /// use bloom2::{CompressedBitmap, Error};
+    ///
+    /// let mut b = CompressedBitmap::new(100);
+    /// assert_eq!(b.try_set(42, true), Ok(()));
+    /// assert_eq!(
+    ///     b.try_set(101, true),
+    ///     Err(Error::KeyOutOfRange { key: 101, max_key: 100 })
+    /// );
+    /// ```
+    ///
+    /// Unlike [`CompressedBitmap::set()`], this method never panics, and an
+    /// out-of-range key never modifies the bitmap.
+    pub fn try_set(&mut self, key: usize, value: bool) -> Result<(), Error> {
+        self.check_key(key)?;
+        self.set(key, value);
+        Ok(())
+    }
+
+    /// Returns the value at `key`, or an error if `key` is more than the
+    /// `max_key` value provided when initialising the bitmap.
+    ///
+    /// Unlike [`CompressedBitmap::get()`], this method never panics.
+    pub fn try_get(&self, key: usize) -> Result<bool, Error> {
+        self.check_key(key)?;
+        Ok(self.get(key))
+    }
+
+    fn check_key(&self, key: usize) -> Result<(), Error> {
+        if key > self.max_key {
+            return Err(Error::KeyOutOfRange {
+                key,
+                max_key: self.max_key,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the value at `key`.
+    ///
     /// If a value for `key` was not previously set, `false` is returned.
     ///
     /// # Panics
     ///
     /// This method MAY panic if `key` is more than the `max_key` value provided
-    /// when initialising the bitmap.
+    /// when initialising the bitmap, unless the `saturating` feature is enabled
+    /// (see [`CompressedBitmap::set()`]).
+    ///
+    /// Use [`CompressedBitmap::try_get()`] for keys that may be out of range.
     pub fn get(&self, key: usize) -> bool {
-        let block_index = index_for_key(key);
-        let block_map_index = index_for_key(block_index);
-        let block_map_bitmask = bitmask_for_key(block_index);
+        let key = saturate_key(key, self.max_key);
+        let slot = Slot::locate(self, index_for_key(key));
+
+        // Read the block without branching on whether it is allocated - an
+        // unallocated block reads as 0, as does any block past the end of the
+        // bitmap.
+        let block = self.bitmap.get(slot.offset).copied().unwrap_or_default();
+
+        block & slot.block_map_hit & bitmask_for_key(key) != 0
+    }
 
-        if self.block_map[block_map_index] & block_map_bitmask == 0 {
-            return false;
+    /// Write the value of each of `keys` into the corresponding index of
+    /// `out`.
+    ///
+    /// This is equivalent to calling [`CompressedBitmap::get()`] for each key,
+    /// but visits each run of up to 64 keys in ascending order, grouping keys
+    /// within the same block map words together and carrying forward the
+    /// counts of set bits in the preceding block map words instead of
+    /// recomputing them for each key. The keys are sorted on the stack, so a
+    /// lookup does not allocate. If the `simd` feature is enabled, the blocks
+    /// for a batch of keys are prefetched before they are read.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(42, true);
+    ///
+    /// let mut out = [false; 3];
+    /// b.get_many(&[1000, 42, 1], &mut out);
+    ///
+    /// assert_eq!(out, [false, true, false]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keys` and `out` are not of equal length.
+    pub fn get_many(&self, keys: &[usize], out: &mut [bool]) {
+        assert_eq!(keys.len(), out.len());
+
+        for (keys, out) in keys
+            .chunks(SORT_BUFFER_LEN)
+            .zip(out.chunks_mut(SORT_BUFFER_LEN))
+        {
+            // Visit the keys of this run in ascending order, recording the
+            // index of each key to write the result to.
+            let mut order = [(0, 0); SORT_BUFFER_LEN];
+            let order = &mut order[..keys.len()];
+            for (i, (slot, &key)) in order.iter_mut().zip(keys).enumerate() {
+                *slot = (saturate_key(key, self.max_key), i);
+            }
+            order.sort_unstable();
+
+            let mut cursor = OffsetCursor::default();
+            for batch in order.chunks(PREFETCH_BATCH) {
+                // Locate the physical block for each key in the batch, hinting
+                // the block should be loaded into the cache.
+                let mut offsets = [None; PREFETCH_BATCH];
+                for (offset, &(key, _)) in offsets.iter_mut().zip(batch) {
+                    *offset = cursor.offset(self, key);
+                    if let Some(offset) = *offset {
+                        simd::prefetch(&self.bitmap[offset]);
+                    }
+                }
+
+                // And then read the (hopefully now cached) blocks.
+                for (offset, &(key, i)) in offsets.iter().zip(batch) {
+                    out[i] = offset
+                        .is_some_and(|offset| self.bitmap[offset] & bitmask_for_key(key) != 0);
+                }
+            }
         }
+    }
 
-        let offset: usize = (0..block_map_index)
-            .map(|i| self.block_map[i].count_ones() as usize)
-            .sum();
+    /// Returns `true` if any of `keys` is set to `true`.
+    ///
+    /// The keys are copied to a buffer on the stack and each run of up to 64
+    /// keys is visited in ascending order, so keys within the same block map
+    /// word are checked together and the counts of set bits in the preceding
+    /// map words are carried forward between keys (as for
+    /// [`CompressedBitmap::get_many()`]). Keys in unallocated blocks are
+    /// rejected from the top map or block map alone, without reading the
+    /// block, and the search stops at the first set key.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(42, true);
+    ///
+    /// assert!(b.get_any(&[1000, 42, 1]));
+    /// assert!(!b.get_any(&[1000, 1]));
+    /// ```
+    pub fn get_any(&self, keys: &[usize]) -> bool {
+        keys.chunks(SORT_BUFFER_LEN).any(|keys| {
+            self.with_sorted_keys(keys, |keys| {
+                let mut cursor = OffsetCursor::default();
+                keys.iter().any(|&key| cursor.get(self, key))
+            })
+        })
+    }
 
-        let mask = block_map_bitmask - 1;
-        let offset: usize = offset + (self.block_map[block_map_index] & mask).count_ones() as usize;
+    /// Returns `true` if all of `keys` are set to `true`.
+    ///
+    /// The keys are visited in ascending order as for
+    /// [`CompressedBitmap::get_any()`], and the search stops at the first
+    /// unset key.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(42, true);
+    /// b.set(1000, true);
+    ///
+    /// assert!(b.get_all(&[1000, 42]));
+    /// assert!(!b.get_all(&[1000, 42, 1]));
+    /// ```
+    pub fn get_all(&self, keys: &[usize]) -> bool {
+        keys.chunks(SORT_BUFFER_LEN).all(|keys| {
+            self.with_sorted_keys(keys, |keys| {
+                let mut cursor = OffsetCursor::default();
+                keys.iter().all(|&key| cursor.get(self, key))
+            })
+        })
+    }
 
-        self.bitmap[offset] & bitmask_for_key(key) != 0
+    /// Call `f` with a copy of `keys` (of at most [`SORT_BUFFER_LEN`] keys)
+    /// saturated to `max_key` and sorted in ascending order, in a buffer on
+    /// the stack.
+    fn with_sorted_keys<R>(&self, keys: &[usize], f: impl FnOnce(&[usize]) -> R) -> R {
+        let mut buf = [0; SORT_BUFFER_LEN];
+        let buf = &mut buf[..keys.len()];
+        for (slot, &key) in buf.iter_mut().zip(keys) {
+            *slot = saturate_key(key, self.max_key);
+        }
+        buf.sort_unstable();
+        f(buf)
     }
 
     /// Perform a bitwise OR against `self` and `other`, returning the
     /// resulting merged [`CompressedBitmap`].
     ///
-    /// # Panics
+    /// The bitmaps may be configured with a different `max_key` - the result
+    /// covers the larger of the two key spaces, with the keys beyond the end
+    /// of the smaller bitmap treated as unset:
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut small = CompressedBitmap::new(64);
+    /// small.set(1, true);
+    /// let mut large = CompressedBitmap::new(1024);
+    /// large.set(1000, true);
+    ///
+    /// let union = small.or(&large);
     ///
-    /// This method panics if `other` was not configured with the same
-    /// `max_key`.
+    /// assert!(union.get(1));
+    /// assert!(union.get(1000));
+    /// ```
     pub fn or(&self, other: &Self) -> Self {
-        #[cfg(debug_assertions)]
-        debug_assert_eq!(self.max_key, other.max_key);
-
-        // Invariant: the block maps are of equal length, meaning the zipped
-        // iters yield both sides to completion.
-        assert_eq!(self.block_map.len(), other.block_map.len());
+        let max_key = self.max_key.max(other.max_key);
+
+        // If both bitmaps have the same set of allocated blocks, the physical
+        // blocks line up and can be merged directly, without walking the
+        // block maps.
+        if self.top_index == other.top_index
+            && self.top_map == other.top_map
+            && self.block_map == other.block_map
+        {
+            return Self {
+                top_index: self.top_index.clone(),
+                top_map: self.top_map.clone(),
+                block_map: self.block_map.clone(),
+                bitmap: simd::or(&self.bitmap, &other.bitmap),
+                hot: HotBlock::default(),
+
+                max_key,
+            };
+        }
 
-        let left = BlockMapIter::new(self);
-        let right = BlockMapIter::new(other);
+        let mut out = self.empty_with_capacity(self.bitmap.len().max(other.bitmap.len()));
+        out.max_key = max_key;
 
         // Construct the physical set of compressed bitmap blocks.
         //
-        // By iterating over the non-empty logical blocks and OR-ing them
-        // together (or picking one if only one is non-empty) the merged output
-        // of both compressed bitmaps is computed (itself compressed).
-        let bitmap = left
-            .zip(right)
-            .filter_map(|(l, r)| {
-                Some(match (l, r) {
-                    (None, None) => return None,
-                    (None, Some(r)) => other.bitmap[r],
-                    (Some(l), None) => self.bitmap[l],
-                    (Some(l), Some(r)) => self.bitmap[l] | other.bitmap[r],
-                })
-            })
-            .collect::<Vec<_>>();
+        // By walking the non-empty logical blocks of both sides in order and
+        // OR-ing them together (or picking one if only one is non-empty) the
+        // merged output of both compressed bitmaps is computed (itself
+        // compressed), and appended to the output without any reordering.
+        merge_blocks(&mut out, BlockIter::new(self), BlockIter::new(other));
 
-        // Then merge the two bitmap blocks, the OR of which is guaranteed to
-        // contain exactly N set bits for the N blocks in "physical".
-        let block_map = self
-            .block_map
-            .iter()
-            .zip(&other.block_map)
-            .map(|(l, r)| l | r)
-            .collect::<Vec<_>>();
+        out
+    }
+
+    /// Perform a bitwise OR against `self` and `other`, writing the resulting
+    /// merged bitmap into `out`.
+    ///
+    /// Any existing content of `out` is discarded, but its allocated capacity
+    /// is reused, avoiding allocating new buffers for each union when merging
+    /// bitmaps in a loop:
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut a = CompressedBitmap::new(1024);
+    /// a.set(1, true);
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1000, true);
+    ///
+    /// let mut out = CompressedBitmap::new(1024);
+    /// a.or_into(&b, &mut out);
+    ///
+    /// assert!(out.get(1));
+    /// assert!(out.get(1000));
+    /// ```
+    ///
+    /// As with [`CompressedBitmap::or()`], `self` and `other` may be
+    /// configured with a different `max_key`, and `out` is reconfigured to
+    /// cover the larger of the two.
+    pub fn or_into(&self, other: &Self, out: &mut Self) {
+        out.clear();
+        out.max_key = self.max_key.max(other.max_key);
+
+        // As in or(), if both bitmaps have the same set of allocated blocks,
+        // the physical blocks are merged directly.
+        if self.top_index == other.top_index
+            && self.top_map == other.top_map
+            && self.block_map == other.block_map
+        {
+            out.top_index.extend_from_slice(&self.top_index);
+            out.top_map.extend_from_slice(&self.top_map);
+            out.block_map.extend_from_slice(&self.block_map);
+            out.bitmap.resize(self.bitmap.len(), 0);
+            simd::or_into(&mut out.bitmap, &self.bitmap, &other.bitmap);
+            return;
+        }
+
+        merge_blocks(out, BlockIter::new(self), BlockIter::new(other));
+    }
+
+    /// Perform a bitwise OR against `self` and `other`, returning the
+    /// resulting merged [`CompressedBitmap`] constructed using buffers from
+    /// `pool`.
+    ///
+    /// This is the pooled equivalent of [`CompressedBitmap::or()`], and
+    /// similarly accepts bitmaps configured with a different `max_key`.
+    pub fn or_in(&self, other: &Self, pool: &mut BufferPool) -> Self {
+        let mut out = Self {
+            top_index: pool.take(),
+            top_map: pool.take(),
+            block_map: pool.take(),
+            bitmap: pool.take(),
+            hot: HotBlock::default(),
+
+            max_key: self.max_key.max(other.max_key),
+        };
+
+        merge_blocks(&mut out, BlockIter::new(self), BlockIter::new(other));
+
+        out
+    }
+
+    /// Compute the bits that differ between the `old` and `new` snapshots of
+    /// a bitmap, returning an [`XorDelta`] containing only the changed blocks.
+    ///
+    /// Applying the delta to `old` with [`CompressedBitmap::apply_xor_delta()`]
+    /// reproduces `new`, allowing the state of a bitmap to be shipped
+    /// incrementally where a full snapshot would be too large:
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut old = CompressedBitmap::new(1024);
+    /// old.set(1, true);
+    ///
+    /// let mut new = old.clone();
+    /// new.set(1, false);
+    /// new.set(1000, true);
+    ///
+    /// let delta = CompressedBitmap::xor_delta(&old, &new);
+    /// assert_eq!(delta.len(), 2);
+    ///
+    /// old.apply_xor_delta(&delta).unwrap();
+    /// assert!(!old.get(1));
+    /// assert!(old.get(1000));
+    /// ```
+    ///
+    /// As with [`CompressedBitmap::or()`], `old` and `new` may be configured
+    /// with a different `max_key`, and the delta covers the larger of the two.
+    pub fn xor_delta(old: &Self, new: &Self) -> XorDelta {
+        let mut bits = Self::new(old.max_key.max(new.max_key));
+        merge_blocks_with(
+            &mut bits,
+            BlockIter::new(old),
+            BlockIter::new(new),
+            |l, r| l ^ r,
+        );
+
+        XorDelta { bits }
+    }
+
+    /// Apply `delta` produced by [`CompressedBitmap::xor_delta()`], flipping
+    /// each bit that differs between the snapshots it was computed from.
+    ///
+    /// Applying a delta to the `old` snapshot it was computed from produces
+    /// the `new` snapshot - applying it to any other bitmap produces a bitmap
+    /// that differs from it by the same bits.
+    ///
+    /// Returns [`Error::KeyOutOfRange`] and leaves the bitmap unchanged if
+    /// `delta` covers keys outside of the key space of this bitmap.
+    pub fn apply_xor_delta(&mut self, delta: &XorDelta) -> Result<(), Error> {
+        if delta.bits.max_key > self.max_key {
+            return Err(Error::KeyOutOfRange {
+                key: delta.bits.max_key,
+                max_key: self.max_key,
+            });
+        }
 
-        // Invariant: The number of set bits in the block map must match the
-        // number of blocks in the bitmap.
-        debug_assert_eq!(
-            block_map.iter().map(|v| v.count_ones()).sum::<u32>() as usize,
-            bitmap.len()
+        let mut out = self.empty_with_capacity(self.bitmap.len() + delta.len());
+        merge_blocks_with(
+            &mut out,
+            BlockIter::new(self),
+            BlockIter::new(&delta.bits),
+            |l, r| l ^ r,
         );
 
+        *self = out;
+        Ok(())
+    }
+
+    /// Split the bitmap at the logical block `n`, returning a bitmap of the
+    /// blocks before `n` (keys `0..n * 64`), and a bitmap of the blocks from
+    /// `n` onwards (keys `n * 64..`).
+    ///
+    /// Both bitmaps retain the key space and `max_key` of `self`, so each key
+    /// is at the same index in the part containing it. The parts can be
+    /// processed independently, and recombined with
+    /// [`CompressedBitmap::concat()`] without touching individual bits.
+    ///
+    /// ```rust
+    /// use bloom2::CompressedBitmap;
+    ///
+    /// let mut b = CompressedBitmap::new(1024);
+    /// b.set(1, true);
+    /// b.set(1000, true);
+    ///
+    /// // Split at key 512.
+    /// let (left, right) = b.split_at_block(512 / 64);
+    /// assert!(left.get(1) && !left.get(1000));
+    /// assert!(!right.get(1) && right.get(1000));
+    ///
+    /// assert_eq!(CompressedBitmap::concat(&[left, right]), b);
+    /// ```
+    pub fn split_at_block(&self, n: usize) -> (Self, Self) {
+        let mut left = Self::new(self.max_key);
+        let mut right = Self::new(self.max_key);
+
+        for (block_index, block) in BlockIter::new(self) {
+            if block_index < n {
+                left.push_block(block_index, block);
+            } else {
+                right.push_block(block_index, block);
+            }
+        }
+
+        (left, right)
+    }
+
+    /// Concatenate `parts` covering ascending, non-overlapping block ranges,
+    /// such as those returned by [`CompressedBitmap::split_at_block()`], into
+    /// a single bitmap.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `parts` is empty, if the parts were not
+    /// configured with the same `max_key`, or if a block of a part does not
+    /// follow all the blocks of the preceding parts.
+    pub fn concat(parts: &[Self]) -> Self {
+        let max_key = parts.first().expect("concat of no parts").max_key;
+
+        let mut out = Self::new(max_key);
+        let mut next = 0;
+        for part in parts {
+            assert_eq!(part.max_key, max_key, "concat of mismatched max_key");
+
+            for (block_index, block) in BlockIter::new(part) {
+                assert!(block_index >= next, "concat of overlapping parts");
+                out.push_block(block_index, block);
+                next = block_index + 1;
+            }
+        }
+
+        out
+    }
+
+    /// Return an empty `CompressedBitmap` with the same configuration as
+    /// `self`, with space for `blocks` number of blocks.
+    fn empty_with_capacity(&self, blocks: usize) -> Self {
         Self {
-            block_map,
-            bitmap,
+            top_index: Vec::with_capacity(self.top_index.len()),
+            top_map: Vec::with_capacity(self.top_map.len()),
+            block_map: Vec::with_capacity(self.block_map.len()),
+            bitmap: Vec::with_capacity(blocks),
+            hot: HotBlock::default(),
 
-            #[cfg(debug_assertions)]
             max_key: self.max_key,
         }
     }
+
+    /// Compress the dense, uncompressed blocks held in `self.bitmap` in place,
+    /// populating the (empty) block maps.
+    ///
+    /// The non-zero blocks are moved towards the front of the buffer, reusing
+    /// the existing allocation rather than copying the blocks into a second
+    /// buffer.
+    fn compact_dense(&mut self) {
+        debug_assert!(self.top_index.is_empty());
+        debug_assert!(self.top_map.is_empty());
+        debug_assert!(self.block_map.is_empty());
+
+        // Shrink the bitmap into a 3-level compressed bitmap, dropping runs of
+        // 0 bits in the raw bitmap.
+        //
+        // Blocks are only ever moved to a lower (or the same) index, so a
+        // block is always read before it is overwritten.
+        let mut len = 0;
+        for idx in 0..self.bitmap.len() {
+            let block = self.bitmap[idx];
+
+            // If this block contains no set bits, it is elided from the compressed
+            // representation.
+            if block == 0 {
+                continue;
+            }
+
+            // This block contains data.
+            //
+            // Move the block to the end of the compressed blocks and mark it in
+            // the block maps.
+            self.bitmap[len] = block;
+            self.mark_block(idx);
+            len += 1;
+        }
+
+        self.bitmap.truncate(len);
+    }
+
+    /// Append `block` as the logical block `block_index`.
+    ///
+    /// # Panics
+    ///
+    /// `block_index` MUST be greater than the logical index of all blocks
+    /// currently in the bitmap.
+    fn push_block(&mut self, block_index: usize, block: usize) {
+        self.mark_block(block_index);
+        self.bitmap.push(block);
+    }
+
+    /// Mark the logical block `block_index` as allocated in the block maps,
+    /// without adding the block itself.
+    ///
+    /// # Panics
+    ///
+    /// `block_index` MUST be greater than the logical index of all blocks
+    /// currently marked in the block maps.
+    fn mark_block(&mut self, block_index: usize) {
+        let block_map_index = index_for_key(block_index);
+        let top_map_index = index_for_key(block_map_index);
+        let top_map_bitmask = bitmask_for_key(block_map_index);
+
+        // Blocks are appended in order, so if the top map word and block map
+        // word for this block are already allocated, they are the last words
+        // in top_map and block_map.
+        if self.top_index.last() != Some(&top_map_index) {
+            self.top_index.push(top_map_index);
+            self.top_map.push(0);
+        }
+
+        let top_word = self.top_map.last_mut().unwrap();
+        if *top_word & top_map_bitmask == 0 {
+            *top_word |= top_map_bitmask;
+            self.block_map.push(0);
+        }
+
+        let word = self.block_map.last_mut().unwrap();
+        debug_assert!(
+            *word < bitmask_for_key(block_index),
+            "blocks appended out of order"
+        );
+        *word |= bitmask_for_key(block_index);
+    }
+}
+
+/// The bits that differ between two snapshots of a [`CompressedBitmap`], as
+/// returned by [`CompressedBitmap::xor_delta()`].
+///
+/// A delta stores only the blocks containing a changed bit, and is itself
+/// compressed, so its size is proportional to the number of changed blocks
+/// rather than the size of either snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct XorDelta {
+    bits: CompressedBitmap,
+}
+
+impl XorDelta {
+    /// Returns the number of changed blocks in this delta.
+    pub fn len(&self) -> usize {
+        self.bits.block_count()
+    }
+
+    /// Returns `true` if this delta contains no changes.
+    pub fn is_empty(&self) -> bool {
+        self.bits.bitmap.is_empty()
+    }
+
+    /// Returns the number of bits changed by this delta.
+    pub fn count_ones(&self) -> usize {
+        self.bits.count_ones()
+    }
+}
+
+/// Append the union of two ascending sequences of `(logical block index,
+/// block)` pairs to `out`, OR-ing together blocks that appear in both.
+fn merge_blocks<L, R>(out: &mut CompressedBitmap, left: L, right: R)
+where
+    L: Iterator<Item = (usize, usize)>,
+    R: Iterator<Item = (usize, usize)>,
+{
+    merge_blocks_with(out, left, right, |l, r| l | r);
+}
+
+/// Append the blocks of two ascending sequences of `(logical block index,
+/// block)` pairs to `out`, combining blocks that appear in both with `op`.
+///
+/// Blocks appearing in only one sequence are appended unchanged, and blocks
+/// combined to 0 are omitted.
+fn merge_blocks_with<L, R, F>(out: &mut CompressedBitmap, left: L, right: R, op: F)
+where
+    L: Iterator<Item = (usize, usize)>,
+    R: Iterator<Item = (usize, usize)>,
+    F: Fn(usize, usize) -> usize,
+{
+    let mut left = left.peekable();
+    let mut right = right.peekable();
+    loop {
+        let (idx, block) = match (left.peek(), right.peek()) {
+            (None, None) => break,
+            (Some(&(l, l_block)), Some(&(r, r_block))) if l == r => {
+                left.next();
+                right.next();
+                (l, op(l_block, r_block))
+            }
+            (Some(&(l, _)), Some(&(r, _))) if l < r => left.next().unwrap(),
+            (Some(_), Some(_)) | (None, Some(_)) => right.next().unwrap(),
+            (Some(_), None) => left.next().unwrap(),
+        };
+        if block != 0 {
+            out.push_block(idx, block);
+        }
+    }
+
+    // Invariant: The number of set bits in each map must match the number of
+    // entries in the level below it.
+    debug_assert_eq!(simd::count_ones(&out.top_map), out.block_map.len());
+    debug_assert_eq!(simd::count_ones(&out.block_map), out.bitmap.len());
 }
 
-/// Yields the 0-indexed physical indexes into the sparse bitmap for non-empty
-/// blocks.
+/// The position of a logical block within the 3 levels of a
+/// [`CompressedBitmap`].
 ///
-/// If for the Nth call to `next()` the Nth sparse bitmap block is elided,
-/// [`None`] is returned. If the Nth bitmap block is non-empty, the physical
-/// index into the compressed vec is yielded.
+/// The offsets are computed without branching on whether the block (or its
+/// block map word) is allocated, sharing the same work between reads, writes to
+/// allocated blocks, and allocating writes. If the block is not allocated, the
+/// offsets are the positions at which it would be inserted.
 #[derive(Debug)]
-struct BlockMapIter<'a> {
-    bitmap: &'a CompressedBitmap,
+struct Slot {
+    top_map_index: usize,
+    /// The physical index of the top map word in top_map.
+    top_map_offset: usize,
+    top_map_bitmask: usize,
+    /// All 1s if the block map word is allocated, else 0.
+    top_map_hit: usize,
+
+    /// The physical index of the block map word in block_map.
+    block_map_offset: usize,
+    block_map_bitmask: usize,
+    /// All 1s if the block is allocated, else 0.
+    block_map_hit: usize,
+
+    /// The physical index of the block in bitmap.
+    offset: usize,
+}
 
-    /// The index into bitmap.block_map to be processed next (0 -> N).
-    block_idx: usize,
-    /// The bit in the block to be evaluated next (LSB -> MSB).
-    block_bit: u8,
-    /// The physical index to be yielded next.
-    physical_idx: usize,
+impl Slot {
+    #[inline(always)]
+    fn locate(bitmap: &CompressedBitmap, block_index: usize) -> Self {
+        let block_map_index = index_for_key(block_index);
+        let block_map_bitmask = bitmask_for_key(block_index);
+
+        let top_map_index = index_for_key(block_map_index);
+        let top_map_bitmask = bitmask_for_key(block_map_index);
+
+        // The top map is sparse, and may not hold top_map_index, in which
+        // case the word reads as 0 and every preceding word is counted.
+        let (top_map_offset, top_word) = bitmap.top_word(top_map_index);
+        let top_map_hit = hit_mask(top_word & top_map_bitmask);
+
+        // The number of block map words before block_map_index, which is the
+        // physical index of its block map word if it is allocated.
+        let block_map_offset = simd::count_ones(&bitmap.top_map[..top_map_offset])
+            + (top_word & (top_map_bitmask - 1)).count_ones() as usize;
+
+        // If the block map word is not allocated, it reads as 0, and the
+        // offset of the block is the number of blocks referenced by all the
+        // preceding block map words.
+        let block_map_word = bitmap
+            .block_map
+            .get(block_map_offset)
+            .copied()
+            .unwrap_or_default()
+            & top_map_hit;
+        let block_map_hit = hit_mask(block_map_word & block_map_bitmask);
+
+        let offset = simd::count_ones(&bitmap.block_map[..block_map_offset])
+            + (block_map_word & (block_map_bitmask - 1)).count_ones() as usize;
+
+        Self {
+            top_map_index,
+            top_map_offset,
+            top_map_bitmask,
+            top_map_hit,
+            block_map_offset,
+            block_map_bitmask,
+            block_map_hit,
+            offset,
+        }
+    }
+
+    /// Returns true if the block is allocated.
+    #[inline(always)]
+    fn is_allocated(&self) -> bool {
+        self.block_map_hit != 0
+    }
+
+    /// Returns true if the top map word marking the block map word of the
+    /// block is allocated in `bitmap`.
+    fn is_top_word_allocated(&self, bitmap: &CompressedBitmap) -> bool {
+        bitmap.top_index.get(self.top_map_offset) == Some(&self.top_map_index)
+    }
 }
 
-impl<'a> BlockMapIter<'a> {
-    /// Construct a new [`BlockMapIter`] that yields indexes into the physical
-    /// bitmap blocks in `bitmap`.
-    fn new(bitmap: &'a CompressedBitmap) -> Self {
+/// The logical index and physical offset of the most recently written block
+/// of a [`CompressedBitmap`].
+///
+/// Any change to the physical layout of the bitmap other than inserting the
+/// cached block MUST reset the cache.
+///
+/// The cache is not part of the bitmap state, and all instances compare as
+/// equal.
+#[derive(Debug, Clone)]
+struct HotBlock {
+    block_index: usize,
+    offset: usize,
+}
+
+impl HotBlock {
+    fn new(block_index: usize, offset: usize) -> Self {
         Self {
-            bitmap,
-            block_idx: 0,
-            block_bit: 0,
-            physical_idx: 0,
+            block_index,
+            offset,
         }
     }
+
+    /// Return the physical offset of `block_index`, if cached.
+    #[inline(always)]
+    fn get(&self, block_index: usize) -> Option<usize> {
+        (self.block_index == block_index).then_some(self.offset)
+    }
 }
 
-impl Iterator for BlockMapIter<'_> {
-    type Item = Option<usize>;
+impl Default for HotBlock {
+    fn default() -> Self {
+        // No logical block has this index.
+        Self::new(usize::MAX, 0)
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let block = self.bitmap.block_map.get(self.block_idx)?;
+impl PartialEq for HotBlock {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
 
-        let v = if (block & (1 << self.block_bit)) > 0 {
-            // This logical block is non-empty.
+impl Eq for HotBlock {}
 
-            // Read the physical index for the nth logical block.
-            let idx = self.physical_idx;
+/// Return a mask of all 1s if `v` is non-zero, or 0 otherwise.
+#[inline(always)]
+fn hit_mask(v: usize) -> usize {
+    0_usize.wrapping_sub((v != 0) as usize)
+}
 
-            // Increment for the next physical block.
-            self.physical_idx += 1;
+/// Locates the physical blocks for an ascending sequence of keys, carrying
+/// forward the count of set bits in the preceding map words between keys.
+#[derive(Debug, Default)]
+struct OffsetCursor {
+    /// The number of top map words counted in top_ones.
+    top_words: usize,
+    top_ones: usize,
+    /// The number of (physical) block map words counted in block_map_ones.
+    block_map_words: usize,
+    block_map_ones: usize,
+}
 
-            Some(idx)
-        } else {
-            // This logical block is empty.
-            None
-        };
+impl OffsetCursor {
+    /// Return the physical index of the block containing `key` in `bitmap`,
+    /// or [`None`] if the block is not allocated.
+    ///
+    /// `key` MUST be greater than or equal to all keys previously passed to
+    /// this cursor.
+    fn offset(&mut self, bitmap: &CompressedBitmap, key: usize) -> Option<usize> {
+        let block_index = index_for_key(key);
+        let block_map_index = index_for_key(block_index);
+        let top_map_index = index_for_key(block_map_index);
+        let top_map_bitmask = bitmask_for_key(block_map_index);
+
+        let top_map_offset = self.top_words
+            + bitmap.top_index[self.top_words..]
+                .binary_search(&top_map_index)
+                .ok()?;
+        let top_word = bitmap.top_map[top_map_offset];
+        if top_word & top_map_bitmask == 0 {
+            return None;
+        }
+
+        // Count only the top map words between the last key and this key.
+        self.top_ones += simd::count_ones(&bitmap.top_map[self.top_words..top_map_offset]);
+        self.top_words = top_map_offset;
+
+        let block_map_offset =
+            self.top_ones + (top_word & (top_map_bitmask - 1)).count_ones() as usize;
+        let block_map_bitmask = bitmask_for_key(block_index);
+        let block_map_word = bitmap.block_map[block_map_offset];
+        if block_map_word & block_map_bitmask == 0 {
+            return None;
+        }
+
+        // And the same for the block map words.
+        self.block_map_ones +=
+            simd::count_ones(&bitmap.block_map[self.block_map_words..block_map_offset]);
+        self.block_map_words = block_map_offset;
+
+        Some(self.block_map_ones + (block_map_word & (block_map_bitmask - 1)).count_ones() as usize)
+    }
+
+    /// Return the value of `key` in `bitmap`, with the same ordering
+    /// requirement as [`OffsetCursor::offset()`].
+    fn get(&mut self, bitmap: &CompressedBitmap, key: usize) -> bool {
+        self.offset(bitmap, key)
+            .is_some_and(|offset| bitmap.bitmap[offset] & bitmask_for_key(key) != 0)
+    }
+}
+
+/// Yields the `(logical block index, block)` pairs for all allocated blocks in
+/// a [`CompressedBitmap`], in ascending logical order.
+///
+/// The Nth yielded block is the Nth physical block in the compressed bitmap.
+#[derive(Debug)]
+struct BlockIter<'a> {
+    bitmap: &'a CompressedBitmap,
+
+    /// The index into bitmap.top_map being processed (0 -> N).
+    top_idx: usize,
+    /// The set bits of the top map word at top_idx yet to be processed.
+    top_word: usize,
+    /// The logical index of the block map word being processed.
+    block_map_idx: usize,
+    /// The set bits of the block map word yet to be processed.
+    block_map_word: usize,
+    /// The physical index of the block map word to be processed next.
+    block_map_physical_idx: usize,
+    /// The physical index of the block to be yielded next.
+    physical_idx: usize,
+}
 
-        // Advance the bit within the block to evaluate next.
-        self.block_bit += 1;
+impl<'a> BlockIter<'a> {
+    /// Construct a new [`BlockIter`] that yields the allocated blocks in
+    /// `bitmap`.
+    fn new(bitmap: &'a CompressedBitmap) -> Self {
+        Self {
+            bitmap,
+            top_idx: 0,
+            top_word: bitmap.top_map.first().copied().unwrap_or_default(),
+            block_map_idx: 0,
+            block_map_word: 0,
+            block_map_physical_idx: 0,
+            physical_idx: 0,
+        }
+    }
+}
+
+impl Iterator for BlockIter<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Advance to the next allocated block map word if all the blocks in
+        // the current word have been yielded.
+        //
+        // Block map words are never 0, as they are only allocated to mark a
+        // block.
+        while self.block_map_word == 0 {
+            while self.top_word == 0 {
+                self.top_idx += 1;
+                self.top_word = *self.bitmap.top_map.get(self.top_idx)?;
+            }
+
+            // Pop the lowest set bit in the top map word.
+            let bit = self.top_word.trailing_zeros() as usize;
+            self.top_word &= self.top_word - 1;
+
+            self.block_map_idx = self.bitmap.top_index[self.top_idx] * usize::BITS as usize + bit;
+            self.block_map_word = self.bitmap.block_map[self.block_map_physical_idx];
+            self.block_map_physical_idx += 1;
+        }
+
+        // Pop the lowest set bit in the block map word.
+        let bit = self.block_map_word.trailing_zeros() as usize;
+        self.block_map_word &= self.block_map_word - 1;
+
+        let block = self.bitmap.bitmap[self.physical_idx];
+        self.physical_idx += 1;
+
+        Some((self.block_map_idx * usize::BITS as usize + bit, block))
+    }
+}
+
+/// Yields the `(logical block index, block)` pairs for an ascending sequence
+/// of keys, combining the keys that fall within the same block.
+///
+/// # Panics
+///
+/// Panics if the keys are not sorted in ascending order.
+struct SortedKeyBlocks<I>
+where
+    I: Iterator<Item = usize>,
+{
+    keys: std::iter::Peekable<I>,
+    last_key: usize,
+}
+
+impl<I> SortedKeyBlocks<I>
+where
+    I: Iterator<Item = usize>,
+{
+    fn new(keys: I) -> Self {
+        Self {
+            keys: keys.peekable(),
+            last_key: 0,
+        }
+    }
+
+    fn next_key(&mut self) -> Option<usize> {
+        let key = self.keys.next()?;
+        assert!(key >= self.last_key, "keys must be sorted");
+        self.last_key = key;
+        Some(key)
+    }
+}
+
+impl<I> Iterator for SortedKeyBlocks<I>
+where
+    I: Iterator<Item = usize>,
+{
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.next_key()?;
+        let block_index = index_for_key(key);
+        let mut block = bitmask_for_key(key);
+
+        // Accumulate all subsequent keys that fall within the same block.
+        while self
+            .keys
+            .peek()
+            .is_some_and(|&next| index_for_key(next) == block_index)
+        {
+            block |= bitmask_for_key(self.next_key().unwrap());
+        }
+
+        Some((block_index, block))
+    }
+}
+
+/// An inconsistency in the internal state of a [`CompressedBitmap`], as
+/// reported by [`CompressedBitmap::verify_invariants()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantError {
+    /// The bitmap was configured with a different `max_key`.
+    MaxKeyMismatch { want: usize, got: usize },
+
+    /// The number of top map indexes does not match the number of top map
+    /// words.
+    TopMapLength { want: usize, got: usize },
+
+    /// The top map index at the physical `index` is not greater than the
+    /// preceding index, or is beyond the key space.
+    InvalidTopMapIndex { index: usize },
+
+    /// The number of block map words does not match the number of set bits
+    /// in the top map.
+    BlockMapLength { want: usize, got: usize },
+
+    /// The block map word at the physical `index` marks no blocks.
+    EmptyBlockMapWord { index: usize },
+
+    /// The number of blocks does not match the number of set bits in the
+    /// block map.
+    BitmapLength { want: usize, got: usize },
+
+    /// A bit is set for `key`, which exceeds `max_key`.
+    KeyOutOfRange { key: usize, max_key: usize },
+}
+
+impl std::fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MaxKeyMismatch { want, got } => {
+                write!(f, "bitmap max key is {}, expected {}", got, want)
+            }
+            Self::TopMapLength { want, got } => {
+                write!(f, "top map has {} words, but {} indexes", want, got)
+            }
+            Self::InvalidTopMapIndex { index } => write!(
+                f,
+                "top map index {} is out of order or beyond the key space",
+                index
+            ),
+            Self::BlockMapLength { want, got } => write!(
+                f,
+                "top map marks {} block map words, but {} are allocated",
+                want, got
+            ),
+            Self::EmptyBlockMapWord { index } => {
+                write!(f, "block map word {} marks no blocks", index)
+            }
+            Self::BitmapLength { want, got } => write!(
+                f,
+                "block map marks {} blocks, but {} are allocated",
+                want, got
+            ),
+            Self::KeyOutOfRange { key, max_key } => {
+                write!(f, "key {} is set, exceeding max key {}", key, max_key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+impl Bitmap for CompressedBitmap {
+    fn get(&self, key: usize) -> bool {
+        self.get(key)
+    }
+
+    fn verify(&self, max_key: usize) -> Result<(), InvariantError> {
+        self.verify_invariants_for(max_key)
+    }
+
+    fn set(&mut self, key: usize, value: bool) {
+        self.set(key, value)
+    }
+
+    fn set_many(&mut self, keys: &mut [usize]) {
+        self.set_many(keys)
+    }
+
+    fn get_many(&self, keys: &[usize], out: &mut [bool]) {
+        self.get_many(keys, out)
+    }
+
+    fn get_any(&self, keys: &[usize]) -> bool {
+        self.get_any(keys)
+    }
+
+    fn get_all(&self, keys: &[usize]) -> bool {
+        self.get_all(keys)
+    }
+
+    fn byte_size(&self) -> usize {
+        self.size()
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        let (top_index_bytes, top_index_spare) =
+            vec_bytes(&self.top_index, self.top_index.capacity());
+        let (top_map_bytes, top_spare) = vec_bytes(&self.top_map, self.top_map.capacity());
+        let (block_map_bytes, block_map_spare) =
+            vec_bytes(&self.block_map, self.block_map.capacity());
+        let (bitmap_bytes, bitmap_spare) = vec_bytes(&self.bitmap, self.bitmap.capacity());
+
+        MemoryBreakdown {
+            top_map_bytes: top_index_bytes + top_map_bytes,
+            block_map_bytes,
+            bitmap_bytes,
+            spare_bytes: top_index_spare + top_spare + block_map_spare + bitmap_spare,
+            overhead_bytes: std::mem::size_of_val(self),
+        }
+    }
+
+    fn warm(&self) {
+        touch_pages(&self.top_index);
+        touch_pages(&self.top_map);
+        touch_pages(&self.block_map);
+        touch_pages(&self.bitmap);
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        self.or(other)
+    }
+
+    fn new_with_capacity(max_key: usize) -> Self {
+        Self::new(max_key)
+    }
+}
+
+/// Print a summary of the bitmap contents and memory usage.
+///
+/// ```rust
+/// use bloom2::CompressedBitmap;
+///
+/// let mut b = CompressedBitmap::new(1024);
+/// b.set(42, true);
+///
+/// println!("{}", b);
+/// // CompressedBitmap { bits set: 1, blocks: 1, memory: 80 bytes }
+/// ```
+impl std::fmt::Display for CompressedBitmap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CompressedBitmap {{ bits set: {}, blocks: {}, memory: {} bytes }}",
+            self.count_ones(),
+            self.block_count(),
+            self.size()
+        )
+    }
+}
+
+impl From<VecBitmap> for CompressedBitmap {
+    fn from(bitmap: VecBitmap) -> Self {
+        let (bitmap, max_key) = bitmap.into_parts();
+
+        // Compress the blocks in place within the dense bitmap buffer.
+        let mut compressed = CompressedBitmap::new(max_key);
+        compressed.bitmap = bitmap;
+        compressed.compact_dense();
+
+        // And release the space no longer needed by the compressed blocks.
+        compressed.bitmap.shrink_to_fit();
+
+        compressed
+    }
+}
+
+// TODO(dom:test): proptest conversion
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    macro_rules! contains_only_truthy {
+		($bitmap:ident, $max:expr; $(
+            $element:expr
+        ),*) => {
+			let truthy = vec![$($element,)*];
+			for i in 0..$max {
+				assert!($bitmap.get(i) == truthy.contains(&i), "unexpected value {}", i);
+			}
+		};
+	}
+
+    #[test]
+    fn test_set_contains() {
+        let mut b = CompressedBitmap::new(100);
+        b.set(100, true);
+        b.set(0, true);
+        b.set(42, true);
+
+        contains_only_truthy!(b, 100; 100, 0, 42);
+
+        assert!(b.get(100));
+        assert!(b.get(0));
+        assert!(b.get(42));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut b = CompressedBitmap::new(100);
+        b.set(100, true);
+        b.set(0, true);
+        b.set(42, true);
+
+        contains_only_truthy!(b, 100; 100, 0, 42);
+        b.clear();
+        contains_only_truthy!(b, 100;);
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut b = CompressedBitmap::new(1 << 20);
+        b.try_reserve(100).unwrap();
+        assert!(b.bitmap.capacity() >= 100);
+        assert!(b.block_map.capacity() >= 100);
+
+        // The block map is never reserved beyond the addressable key space.
+        let mut b = CompressedBitmap::new(1 << 12);
+        b.try_reserve(100).unwrap();
+        assert!(b.block_map.capacity() < 100);
+
+        assert_eq!(
+            b.try_reserve(usize::MAX),
+            Err(Error::AllocationFailed { bytes: usize::MAX })
+        );
+        b.set(42, true);
+        assert!(b.get(42));
+    }
+
+    #[quickcheck]
+    fn test_count_ones(vals: Vec<u16>) {
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &vals {
+            b.set(*v as usize, true);
+        }
+
+        let want = vals.iter().collect::<std::collections::HashSet<_>>().len();
+        assert_eq!(b.count_ones(), want);
+    }
+
+    #[test]
+    fn test_set_true_false() {
+        let mut b = CompressedBitmap::new(100);
+        b.set(42, true);
+        assert!(b.get(42));
+        b.set(42, false);
+        assert!(!b.get(42));
+    }
+
+    #[test]
+    fn test_block_iter() {
+        let mut bitmap = CompressedBitmap::new(i16::MAX as _);
+        bitmap.set(1, true); // Block 0
+        bitmap.set(usize::BITS as usize * 4, true); // Block 4
+        bitmap.set(usize::BITS as usize * 64, true); // Block 64
+        bitmap.set(usize::BITS as usize * 65 + 3, true); // Block 65
+        bitmap.set(usize::BITS as usize * 128, true); // Block 128
+
+        // The iterator yields (logical block, block) for each allocated block.
+        let mut iter = BlockIter::new(&bitmap);
+
+        assert_eq!(iter.next().unwrap(), (0, 1 << 1)); // The 0th block is non-empty.
+        assert_eq!(iter.next().unwrap(), (4, 1)); // The 1st, 2nd and 3rd blocks are elided.
+
+        // Then the next non-empty blocks, which live in the second block map
+        // word:
+        assert_eq!(iter.next().unwrap(), (64, 1));
+        assert_eq!(iter.next().unwrap(), (65, 1 << 3));
+
+        // Finally the last bit, in the third block map word!
+        assert_eq!(iter.next().unwrap(), (128, 1));
+
+        // And the iterator should terminate.
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_block_iter_empty() {
+        let bitmap = CompressedBitmap::new(i16::MAX as _);
+        assert!(BlockIter::new(&bitmap).next().is_none());
+    }
+
+    #[test]
+    fn test_sparse_block_map() {
+        // An empty KeyBytes5-sized bitmap allocates nothing.
+        let mut b = CompressedBitmap::new(1 << 40);
+        assert!(b.top_map.is_empty());
+        assert!(b.block_map.is_empty());
+        assert!(!b.get((1 << 40) - 1));
+
+        // Unsetting a bit doesn't allocate either.
+        b.set((1 << 40) - 1, false);
+        assert!(b.top_map.is_empty());
+
+        b.set((1 << 40) - 1, true);
+        b.set(42, true);
+        b.set(1 << 39, true);
+
+        assert_eq!(b.block_map.len(), 3);
+        assert_eq!(b.bitmap.len(), 3);
+
+        assert!(b.get((1 << 40) - 1));
+        assert!(b.get(42));
+        assert!(b.get(1 << 39));
+        assert!(!b.get((1 << 39) + 1));
+        assert!(!b.get(43));
+
+        // The top map holds only the words marking a populated block map
+        // word, regardless of the highest key set.
+        assert_eq!(b.top_map.len(), 3);
+        assert_eq!(b.top_index, [0, 1 << 21, (1 << 22) - 1]);
+        assert_eq!(b.verify_invariants(), Ok(()));
+        b.clear();
+        assert!(b.top_map.is_empty());
+        assert!(!b.get(42));
+    }
+
+    #[cfg(not(feature = "saturating"))]
+    #[quickcheck]
+    #[should_panic]
+    fn test_panic_exceeds_max(max: u16) {
+        let max = max as usize;
+        let mut b = CompressedBitmap::new(max);
+        b.set(max + 1, true);
+    }
+
+    #[cfg(feature = "saturating")]
+    #[quickcheck]
+    fn test_saturating(max: u16, over: u16) {
+        let max = max as usize;
+        let mut b = CompressedBitmap::new(max);
+
+        b.set(max + 1 + over as usize, true);
+        assert!(b.get(max));
+        assert!(b.get(usize::MAX));
+        assert_eq!(b.count_ones(), 1);
+
+        let mut out = [false; 2];
+        b.get_many(&[max + 1, 0], &mut out);
+        assert_eq!(out, [true, max == 0]);
+    }
+
+    #[quickcheck]
+    fn test_try_set_get(max: u16) {
+        let max = max as usize;
+        let mut b = CompressedBitmap::new(max);
+
+        let want = Err(Error::KeyOutOfRange {
+            key: max + 1,
+            max_key: max,
+        });
+        assert_eq!(b.try_set(max + 1, true), want);
+        assert_eq!(b.try_get(max + 1), want.map(|_| false));
+        assert_eq!(b.count_ones(), 0);
+
+        assert_eq!(b.try_set(max, true), Ok(()));
+        assert_eq!(b.try_get(max), Ok(true));
+    }
+
+    #[quickcheck]
+    fn test_set_contains_prop(mut vals: Vec<u16>) {
+        vals.truncate(10);
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &vals {
+            b.set(*v as usize, true);
+        }
+
+        for i in 0..u16::MAX {
+            assert!(
+                b.get(i as usize) == vals.contains(&i),
+                "unexpected value {}",
+                i
+            );
+        }
+    }
+
+    #[quickcheck]
+    fn test_or(mut a: Vec<u16>, mut b: Vec<u16>) {
+        a.truncate(10);
+        let mut bitmap_a = CompressedBitmap::new(u16::MAX.into());
+        for v in &a {
+            bitmap_a.set(*v as usize, true);
+        }
+
+        b.truncate(10);
+        let mut bitmap_b = CompressedBitmap::new(u16::MAX.into());
+        for v in &b {
+            bitmap_b.set(*v as usize, true);
+        }
+
+        let merged = bitmap_a.or(&bitmap_b);
+
+        for i in 0..u16::MAX {
+            let want_hit = a.contains(&i) || b.contains(&i);
+            assert!(
+                merged.get(i as usize) == want_hit,
+                "unexpected value {} want={:?}",
+                i,
+                want_hit
+            );
+        }
+    }
+
+    #[quickcheck]
+    fn test_or_same_blocks(mut a: Vec<u16>) {
+        a.truncate(10);
+
+        // Populate both bitmaps with the same set of blocks, but with
+        // different bits set within them.
+        let mut bitmap_a = CompressedBitmap::new(u16::MAX.into());
+        let mut bitmap_b = CompressedBitmap::new(u16::MAX.into());
+        for v in &a {
+            bitmap_a.set(*v as usize, true);
+            bitmap_b.set(*v as usize ^ 1, true);
+        }
+        assert_eq!(bitmap_a.block_map, bitmap_b.block_map);
+
+        let merged = bitmap_a.or(&bitmap_b);
+
+        for i in 0..u16::MAX as usize {
+            assert_eq!(merged.get(i), bitmap_a.get(i) || bitmap_b.get(i));
+        }
+    }
+
+    #[quickcheck]
+    fn test_or_different_capacity(mut a: Vec<u8>, mut b: Vec<u16>) {
+        a.truncate(10);
+        let mut small = CompressedBitmap::new(u8::MAX.into());
+        for v in &a {
+            small.set(*v as usize, true);
+        }
+
+        b.truncate(10);
+        let mut large = CompressedBitmap::new(u16::MAX.into());
+        for v in &b {
+            large.set(*v as usize, true);
+        }
+
+        let merged = small.or(&large);
+        assert_eq!(merged.max_key, large.max_key);
+
+        // The union is the same regardless of the order of the operands.
+        assert_eq!(merged, large.or(&small));
+
+        let mut out = CompressedBitmap::new(1);
+        small.or_into(&large, &mut out);
+        assert_eq!(merged, out);
+
+        for i in 0..=u16::MAX {
+            let want_hit = a.iter().any(|&v| v as u16 == i) || b.contains(&i);
+            assert_eq!(merged.get(i as usize), want_hit, "unexpected value {}", i);
+        }
+    }
+
+    #[quickcheck]
+    fn test_xor_delta_round_trip(mut a: Vec<u16>, mut b: Vec<u16>) {
+        a.truncate(20);
+        let mut old = CompressedBitmap::new(u16::MAX.into());
+        for v in &a {
+            old.set(*v as usize, true);
+        }
+
+        b.truncate(20);
+        let mut new = old.clone();
+        for v in &b {
+            // Flip the bit, so the snapshots differ by both set and unset
+            // bits.
+            new.set(*v as usize, !new.get(*v as usize));
+        }
+
+        let delta = CompressedBitmap::xor_delta(&old, &new);
+
+        // Invariant: the delta contains only the blocks that changed.
+        let changed = (0..=index_for_key(u16::MAX.into()))
+            .filter(|&i| old.block(i) != new.block(i))
+            .count();
+        assert_eq!(delta.len(), changed);
+        assert_eq!(delta.is_empty(), changed == 0);
+
+        // Unsetting bits in a snapshot may leave empty blocks allocated, so
+        // the bitmaps are compared by their bits.
+        let mut got = old.clone();
+        got.apply_xor_delta(&delta).unwrap();
+        for i in 0..=u16::MAX as usize {
+            assert_eq!(got.get(i), new.get(i), "unexpected value {}", i);
+        }
+
+        // Applying the delta to the new snapshot reverts the changes.
+        got.apply_xor_delta(&delta).unwrap();
+        assert_eq!(got, old);
+    }
+
+    #[test]
+    fn test_xor_delta_out_of_range() {
+        let mut large = CompressedBitmap::new(1024);
+        large.set(1000, true);
+
+        let delta = CompressedBitmap::xor_delta(&CompressedBitmap::new(1024), &large);
+
+        let mut small = CompressedBitmap::new(64);
+        small.set(1, true);
+        let want = small.clone();
+
+        assert_eq!(
+            small.apply_xor_delta(&delta),
+            Err(Error::KeyOutOfRange {
+                key: 1024,
+                max_key: 64
+            })
+        );
+        assert_eq!(small, want);
+    }
+
+    #[quickcheck]
+    fn test_set_block(writes: Vec<(u8, u64)>) {
+        const MAX_KEY: usize = 256 * 64;
+
+        let mut b = CompressedBitmap::new(MAX_KEY);
+        let mut want = [0_u64; 256];
+        for &(idx, block) in &writes {
+            b.set_block(idx as usize, block).unwrap();
+            want[idx as usize] = block;
+            assert_eq!(b.get_block(idx as usize), block);
+        }
+
+        b.verify_invariants().unwrap();
+        for (idx, block) in want.iter().enumerate() {
+            assert_eq!(b.get_block(idx), *block);
+        }
+        for i in 0..MAX_KEY {
+            assert_eq!(b.get(i), want[index_for_key(i)] & 1 << (i % 64) != 0);
+        }
+    }
+
+    #[test]
+    fn test_set_block_out_of_range() {
+        let mut b = CompressedBitmap::new(100);
+
+        // Bits 64..=100 are addressable.
+        assert_eq!(b.set_block(1, (1 << 37) - 1), Ok(()));
+        assert_eq!(b.get_block(1), (1 << 37) - 1);
+        assert_eq!(
+            b.set_block(1, 1 << 37),
+            Err(Error::KeyOutOfRange {
+                key: 101,
+                max_key: 100
+            })
+        );
+        assert_eq!(b.get_block(1), (1 << 37) - 1);
+
+        // Zero blocks are never allocated, in or out of range.
+        assert_eq!(b.set_block(usize::MAX, 0), Ok(()));
+        assert_eq!(b.set_block(0, 0), Ok(()));
+        assert_eq!(b.block_count(), 1);
+        assert!(b.set_block(usize::MAX, 1).is_err());
+        assert_eq!(b.get_block(usize::MAX), 0);
+    }
+
+    #[quickcheck]
+    fn test_blocks(mut a: Vec<u16>) {
+        a.truncate(20);
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &a {
+            b.set(*v as usize, true);
+        }
+
+        let blocks = b.blocks().collect::<Vec<_>>();
+        assert_eq!(blocks.len(), b.block_count());
+        assert!(blocks.windows(2).all(|w| w[0].0 < w[1].0));
+
+        for i in 0..=u16::MAX as usize {
+            let block = blocks
+                .iter()
+                .find(|(idx, _)| *idx == index_for_key(i))
+                .map(|(_, block)| *block)
+                .unwrap_or_default();
+            assert_eq!(
+                block & 1 << (i % 64) != 0,
+                b.get(i),
+                "unexpected value {}",
+                i
+            );
+        }
+    }
 
-        // Advance the block index (and wrap the bit index) if the last
-        // inspected bit was the last bit in the block.
-        if self.block_bit == usize::BITS as u8 {
-            self.block_bit = 0;
-            self.block_idx += 1;
+    #[quickcheck]
+    fn test_from_dense_words(mut a: Vec<u16>) {
+        a.truncate(20);
+        let mut want = VecBitmap::new_with_capacity(u16::MAX.into());
+        for v in &a {
+            want.set(*v as usize, true);
         }
 
-        Some(v)
-    }
-}
+        let (words, max_key) = want.clone().into_parts();
+        let words = words.iter().map(|&w| w as u64).collect::<Vec<_>>();
 
-impl Bitmap for CompressedBitmap {
-    fn get(&self, key: usize) -> bool {
-        self.get(key)
+        let got = CompressedBitmap::from_dense_words(&words, max_key).unwrap();
+        assert_eq!(got, CompressedBitmap::from(want));
     }
 
-    fn set(&mut self, key: usize, value: bool) {
-        self.set(key, value)
-    }
+    #[test]
+    fn test_from_dense_words_out_of_range() {
+        assert!(CompressedBitmap::from_dense_words(&[0; 2], 127).is_ok());
+        assert_eq!(
+            CompressedBitmap::from_dense_words(&[0; 3], 127),
+            Err(Error::KeyOutOfRange {
+                key: 191,
+                max_key: 127
+            })
+        );
 
-    fn byte_size(&self) -> usize {
-        self.size()
+        // A bit of the last word above max_key is rejected.
+        let mut words = [0; 17];
+        words[16] = 1;
+        assert!(CompressedBitmap::from_dense_words(&words, 1024).is_ok());
+        words[16] = 1 << 63;
+        assert_eq!(
+            CompressedBitmap::from_dense_words(&words, 1024),
+            Err(Error::KeyOutOfRange {
+                key: 1087,
+                max_key: 1024
+            })
+        );
     }
 
-    fn or(&self, other: &Self) -> Self {
-        self.or(other)
+    #[test]
+    fn test_pooled() {
+        let mut pool = BufferPool::default();
+
+        let mut a = CompressedBitmap::new_in(100, &mut pool);
+        a.set(1, true);
+        let mut b = CompressedBitmap::new_in(100, &mut pool);
+        b.set(99, true);
+
+        let merged = a.or_in(&b, &mut pool);
+        contains_only_truthy!(merged, 100; 1, 99);
+        assert_eq!(merged, a.or(&b));
+
+        a.recycle(&mut pool);
+        b.recycle(&mut pool);
+        merged.recycle(&mut pool);
+        assert_eq!(pool.len(), 12);
+
+        // Reusing the pooled buffers yields an empty bitmap.
+        let mut dense = VecBitmap::new_in(100, &mut pool);
+        assert_eq!(pool.len(), 11);
+        dense.set(99, true);
+
+        // The blocks are compressed in place, retaining the capacity of the
+        // dense buffer.
+        let c = CompressedBitmap::from_vec_bitmap_in(dense, &mut pool);
+        contains_only_truthy!(c, 100; 99);
+        assert_eq!(c.bitmap, [bitmask_for_key(99)]);
+        assert!(c.bitmap.capacity() >= 2);
+        assert_eq!(pool.len(), 8);
     }
 
-    fn new_with_capacity(max_key: usize) -> Self {
-        Self::new(max_key)
+    #[quickcheck]
+    fn test_bytes_to_set(initial: Vec<u16>, keys: Vec<u16>) {
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &initial {
+            b.set(*v as usize, true);
+        }
+
+        let keys = keys.into_iter().map(usize::from).collect::<Vec<_>>();
+        let want = b.used_bytes() + b.bytes_to_set(&keys);
+
+        for v in &keys {
+            b.set(*v, true);
+        }
+        assert_eq!(b.used_bytes(), want);
     }
-}
 
-impl From<VecBitmap> for CompressedBitmap {
-    fn from(bitmap: VecBitmap) -> Self {
-        let (bitmap, max_key) = bitmap.into_parts();
+    #[quickcheck]
+    fn test_bytes_to_set_sparse_top_map(initial: Vec<u16>, keys: Vec<u16>) {
+        // Spread the keys across many top map words.
+        let key = |v: u16| (v as usize) << 24;
 
-        // Calculate how many instances of usize (blocks) are needed to hold
-        // max_key number of bits.
-        let num_blocks = index_for_key(max_key);
+        let mut b = CompressedBitmap::new(1 << 40);
+        for v in initial {
+            b.set(key(v), true);
+        }
 
-        // Figure out how many usize elements are needed to represent blocks
-        // number of bitmaps.
-        let num_blocks = match num_blocks % (u64::BITS as usize) {
-            0 => index_for_key(num_blocks),
-            _ => index_for_key(num_blocks) + 1, // +1 to cover the remainder
-        };
+        let keys = keys.into_iter().map(key).collect::<Vec<_>>();
+        let want = b.used_bytes() + b.bytes_to_set(&keys);
 
-        // Then shrink the bitmap into a 2-level compressed bitmap, dropping runs of
-        // 0 bits in the raw bitmap.
-        let mut block_map = vec![0; num_blocks];
-        let mut compressed = Vec::default();
-        for (idx, block) in bitmap.into_iter().enumerate() {
-            // If this block contains no set bits, it is elided from the compressed
-            // representation.
-            if block == 0 {
-                continue;
-            }
+        for v in &keys {
+            b.set(*v, true);
+        }
+        assert_eq!(b.used_bytes(), want);
+        assert_eq!(b.verify_invariants(), Ok(()));
+    }
 
-            // This block contains data.
-            //
-            // Add the block to the compressed representation and mark it in the
-            // block map.
-            compressed.push(block);
-            block_map[index_for_key(idx)] |= bitmask_for_key(idx);
+    #[quickcheck]
+    fn test_fold(vals: Vec<u16>) {
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &vals {
+            b.set(*v as usize, true);
         }
 
-        CompressedBitmap {
-            block_map,
-            bitmap: compressed,
+        const FOLDED: usize = 1024;
+        let folded = b.fold_to_bits(FOLDED);
 
-            #[cfg(debug_assertions)]
-            max_key,
+        for i in 0..FOLDED {
+            let want = vals.iter().any(|v| *v as usize % FOLDED == i);
+            assert_eq!(folded.get(i), want);
         }
     }
-}
 
-// TODO(dom:test): proptest conversion
+    #[quickcheck]
+    fn test_get_any(vals: Vec<u16>, keys: Vec<u16>) {
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &vals {
+            b.set(*v as usize, true);
+        }
 
-#[cfg(test)]
-mod tests {
-    use proptest::prelude::*;
-    use quickcheck_macros::quickcheck;
+        let want = keys.iter().any(|v| b.get(*v as usize));
+        let keys = keys.iter().map(|v| *v as usize).collect::<Vec<_>>();
+        let unsorted = keys.clone();
+        assert_eq!(b.get_any(&keys), want);
 
-    use super::*;
+        // The caller's keys are not reordered.
+        assert_eq!(keys, unsorted);
+    }
 
-    macro_rules! contains_only_truthy {
-		($bitmap:ident, $max:expr; $(
-            $element:expr
-        ),*) => {
-			let truthy = vec![$($element,)*];
-			for i in 0..$max {
-				assert!($bitmap.get(i) == truthy.contains(&i), "unexpected value {}", i);
-			}
-		};
-	}
+    #[quickcheck]
+    fn test_get_all(vals: Vec<u16>, keys: Vec<u16>) {
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &vals {
+            b.set(*v as usize, true);
+        }
+
+        // Include a subset of the set keys, so some queries hit every key.
+        let keys = keys
+            .iter()
+            .chain(vals.iter().step_by(2))
+            .collect::<Vec<_>>();
+        let want = keys.iter().all(|v| b.get(**v as usize));
+        let keys = keys.iter().map(|v| **v as usize).collect::<Vec<_>>();
+        assert_eq!(b.get_all(&keys), want);
+
+        let vals = vals.iter().map(|v| *v as usize).collect::<Vec<_>>();
+        assert!(b.get_all(&vals));
+    }
 
     #[test]
-    fn test_set_contains() {
-        let mut b = CompressedBitmap::new(100);
-        b.set(100, true);
-        b.set(0, true);
-        b.set(42, true);
+    fn test_fold_factor() {
+        let mut b = CompressedBitmap::new(1024);
+        b.set(1, true);
+        b.set(1000, true);
 
-        contains_only_truthy!(b, 100; 100, 0, 42);
+        assert_eq!(b.fold(1), b);
 
-        assert!(b.get(100));
-        assert!(b.get(0));
-        assert!(b.get(42));
+        let folded = b.fold(2);
+        assert!(folded.get(1));
+        assert!(folded.get(1000 - 512));
+        assert_eq!(folded.count_ones(), 2);
+        assert!(folded.verify_invariants().is_ok());
     }
 
     #[test]
-    fn test_clear() {
-        let mut b = CompressedBitmap::new(100);
-        b.set(100, true);
-        b.set(0, true);
-        b.set(42, true);
+    #[should_panic(expected = "power of 2")]
+    fn test_fold_factor_not_power_of_two() {
+        CompressedBitmap::new(1024).fold(3);
+    }
 
-        contains_only_truthy!(b, 100; 100, 0, 42);
-        b.clear();
-        contains_only_truthy!(b, 100;);
+    #[quickcheck]
+    fn test_split_concat(vals: Vec<u16>, splits: Vec<u16>) {
+        let mut b = CompressedBitmap::new(u16::MAX.into());
+        for v in &vals {
+            b.set(*v as usize, true);
+        }
+
+        // Split into parts at each (sorted) block boundary.
+        let mut splits = splits
+            .iter()
+            .map(|v| index_for_key(*v as usize))
+            .collect::<Vec<_>>();
+        splits.sort_unstable();
+
+        let mut parts = Vec::new();
+        let mut rest = b.clone();
+        let mut start = 0;
+        for n in splits {
+            let (left, right) = rest.split_at_block(n);
+            for key in left.ones() {
+                assert!((start * 64..n * 64).contains(&key));
+            }
+            assert_eq!(left.verify_invariants(), Ok(()));
+            parts.push(left);
+            rest = right;
+            start = n;
+        }
+        parts.push(rest);
+
+        let got = CompressedBitmap::concat(&parts);
+        assert_eq!(got.verify_invariants(), Ok(()));
+        assert_eq!(got, b);
     }
 
     #[test]
-    fn test_set_true_false() {
-        let mut b = CompressedBitmap::new(100);
-        b.set(42, true);
-        assert!(b.get(42));
-        b.set(42, false);
-        assert!(!b.get(42));
+    #[should_panic(expected = "overlapping")]
+    fn test_concat_overlapping() {
+        let mut b = CompressedBitmap::new(1024);
+        b.set(1, true);
+        b.set(1000, true);
+
+        let (left, right) = b.split_at_block(8);
+        CompressedBitmap::concat(&[right, left]);
     }
 
     #[test]
-    fn test_block_map_iter() {
-        let mut bitmap = CompressedBitmap::new(i16::MAX as _);
-        bitmap.set(1, true); // Block 0
-        bitmap.set(usize::BITS as usize * 4, true); // Block 4
-        bitmap.set(usize::BITS as usize * 64, true); // Block 64
-        bitmap.set(usize::BITS as usize * 65, true); // Block 65
-        bitmap.set(usize::BITS as usize * 128, true); // Block 128
-
-        let mut iter = BlockMapIter::new(&bitmap).enumerate();
+    fn test_verify_invariants() {
+        let mut b = CompressedBitmap::new(1024);
+        assert_eq!(b.verify_invariants(), Ok(()));
 
-        assert_eq!(iter.next().unwrap(), (0, Some(0))); // The 0th block is non-empty and at physical index 0.
-        assert_eq!(iter.next().unwrap(), (1, None)); // The 1st block is all zero and elided.
-        assert_eq!(iter.next().unwrap(), (2, None)); // The 2nd block is all zero and elided.
-        assert_eq!(iter.next().unwrap(), (3, None)); // The 3rd block is all zero and elided.
-        assert_eq!(iter.next().unwrap(), (4, Some(1))); // The 4rd block is non-empty and at physical index 1.
+        b.set(1, true);
+        b.set(1000, true);
+        b.set(1000, false);
+        assert_eq!(b.verify_invariants(), Ok(()));
+
+        let mut bad = b.clone();
+        bad.bitmap.pop();
+        assert_eq!(
+            bad.verify_invariants(),
+            Err(InvariantError::BitmapLength { want: 2, got: 1 })
+        );
 
-        // Filter out all the None entries, preserving the enumerated idx.
-        //
-        // This causes the iterator to yield (logical block, physical block).
-        let mut iter = iter.filter_map(|(idx, block)| block.map(|v| (idx, v)));
+        let mut bad = b.clone();
+        bad.block_map.push(1);
+        assert_eq!(
+            bad.verify_invariants(),
+            Err(InvariantError::BlockMapLength { want: 1, got: 2 })
+        );
 
-        // Then the next non-empty blocks and their physical indexes:
-        assert_eq!(iter.next().unwrap(), (64, 2)); // The 64th block is non-empty and at physical index 2.
-        assert_eq!(iter.next().unwrap(), (65, 3)); // The 65th block is non-empty and at physical index 3.
+        let mut bad = b.clone();
+        bad.top_index.push(1);
+        assert_eq!(
+            bad.verify_invariants(),
+            Err(InvariantError::TopMapLength { want: 1, got: 2 })
+        );
 
-        // Finally the last bit!
-        assert_eq!(iter.next().unwrap(), (128, 4)); // The 128th block is non-empty and at physical index 4.
+        let mut bad = b.clone();
+        bad.top_index[0] = 1;
+        assert_eq!(
+            bad.verify_invariants(),
+            Err(InvariantError::InvalidTopMapIndex { index: 0 })
+        );
 
-        // And the iterator should terminate.
-        assert!(iter.next().is_none());
-    }
+        let mut bad = b.clone();
+        bad.block_map[0] = 0;
+        assert_eq!(
+            bad.verify_invariants(),
+            Err(InvariantError::EmptyBlockMapWord { index: 0 })
+        );
 
-    #[quickcheck]
-    #[should_panic]
-    fn test_panic_exceeds_max(max: u16) {
-        let max = max as usize;
-        let mut b = CompressedBitmap::new(max);
-        b.set(max + 1, true);
+        let mut bad = CompressedBitmap::new(1000);
+        bad.set(999, true);
+        bad.bitmap[0] |= 1 << 63;
+        assert_eq!(
+            bad.verify_invariants(),
+            Err(InvariantError::KeyOutOfRange {
+                key: 1023,
+                max_key: 1000
+            })
+        );
     }
 
     #[quickcheck]
-    fn test_set_contains_prop(mut vals: Vec<u16>) {
-        vals.truncate(10);
+    fn test_occupancy_histogram(vals: Vec<u16>) {
         let mut b = CompressedBitmap::new(u16::MAX.into());
         for v in &vals {
             b.set(*v as usize, true);
         }
 
-        for i in 0..u16::MAX {
-            assert!(
-                b.get(i as usize) == vals.contains(&i),
-                "unexpected value {}",
-                i
-            );
-        }
+        let histogram = b.occupancy_histogram();
+
+        // Every block is counted, and has at least 1 bit set.
+        assert_eq!(histogram.iter().sum::<usize>(), b.block_count());
+        assert_eq!(histogram[0], 0);
+
+        // And the total number of set bits matches.
+        let ones = histogram
+            .iter()
+            .enumerate()
+            .map(|(n, count)| n * count)
+            .sum::<usize>();
+        assert_eq!(ones, b.count_ones());
     }
 
     #[quickcheck]
-    fn test_or(mut a: Vec<u16>, mut b: Vec<u16>) {
-        a.truncate(10);
-        let mut bitmap_a = CompressedBitmap::new(u16::MAX.into());
-        for v in &a {
-            bitmap_a.set(*v as usize, true);
+    fn test_density(vals: Vec<u16>) {
+        let max_key = u16::MAX as usize + 1;
+        let mut b = CompressedBitmap::new(max_key);
+        assert_eq!((b.density(), b.allocated_fraction()), (0.0, 0.0));
+
+        let mut v = VecBitmap::new_with_capacity(max_key);
+        for &x in &vals {
+            b.set(x as usize, true);
+            v.set(x as usize, true);
         }
 
-        b.truncate(10);
-        let mut bitmap_b = CompressedBitmap::new(u16::MAX.into());
-        for v in &b {
-            bitmap_b.set(*v as usize, true);
+        let ones = (0..max_key).filter(|&i| v.get(i)).count();
+        assert_eq!(b.density(), ones as f64 / max_key as f64);
+        assert_eq!(b.allocated_fraction(), b.block_count() as f64 / 1024.0);
+        assert!(b.allocated_fraction() >= b.density());
+
+        // Every block allocated.
+        let mut b = CompressedBitmap::new(256);
+        for i in (0..256).step_by(64) {
+            b.set(i, true);
         }
+        assert_eq!(b.allocated_fraction(), 1.0);
+        assert_eq!(CompressedBitmap::new(0).density(), 0.0);
+    }
 
-        let merged = bitmap_a.or(&bitmap_b);
+    #[test]
+    fn test_set_many() {
+        let mut b = CompressedBitmap::new(100);
+        b.set(42, true);
+        b.set(1, true);
 
-        for i in 0..u16::MAX {
-            let want_hit = a.contains(&i) || b.contains(&i);
-            assert!(
-                merged.get(i as usize) == want_hit,
-                "unexpected value {} want={:?}",
-                i,
-                want_hit
-            );
-        }
+        b.set_many(&mut [100, 0, 42, 43, 1, 99]);
+
+        contains_only_truthy!(b, 100; 0, 1, 42, 43, 99, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "keys must be sorted")]
+    fn test_from_sorted_keys_unsorted() {
+        CompressedBitmap::from_sorted_keys(100, [1, 42, 2]);
     }
 
     #[cfg(feature = "serde")]
@@ -626,9 +3039,109 @@ mod tests {
         contains_only_truthy!(decoded, 100; 1, 3);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_v0_layout() {
+        // Blocks 1 and 130 (in block map words 0 and 2) of a v0.5 bitmap.
+        let encoded = r#"{
+            "block_map": [2, 0, 4, 0],
+            "bitmap": [1, 8],
+            "max_key": 16384
+        }"#;
+
+        let decoded: CompressedBitmap = serde_json::from_str(encoded).unwrap();
+        decoded.verify_invariants().unwrap();
+        contains_only_truthy!(decoded, 16384; 64, 130 * 64 + 3);
+
+        let mut want = CompressedBitmap::new(16384);
+        want.set(64, true);
+        want.set(130 * 64 + 3, true);
+        assert_eq!(decoded, want);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_dense_top_map_layout() {
+        // Blocks 1 and 8194 (in top map words 0 and 2) of a bitmap with a
+        // dense top map.
+        let encoded = r#"{
+            "top_map": [1, 0, 1],
+            "block_map": [2, 4],
+            "bitmap": [1, 8],
+            "max_key": 1048576
+        }"#;
+
+        let decoded: CompressedBitmap = serde_json::from_str(encoded).unwrap();
+        decoded.verify_invariants().unwrap();
+
+        let mut want = CompressedBitmap::new(1 << 20);
+        want.set(64, true);
+        want.set((2 * 4096 + 2) * 64 + 3, true);
+        assert_eq!(decoded, want);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_inconsistent_levels() {
+        for (encoded, want) in [
+            (
+                r#"{"top_index": [0], "top_map": [3], "block_map": [2], "bitmap": [1], "max_key": 1024}"#,
+                InvariantError::BlockMapLength { want: 2, got: 1 },
+            ),
+            (
+                r#"{"top_index": [0], "top_map": [1], "block_map": [6], "bitmap": [1], "max_key": 1024}"#,
+                InvariantError::BitmapLength { want: 2, got: 1 },
+            ),
+            (
+                r#"{"top_index": [0, 1], "top_map": [1], "block_map": [2], "bitmap": [1], "max_key": 1024}"#,
+                InvariantError::TopMapLength { want: 1, got: 2 },
+            ),
+            (
+                r#"{"top_index": [1], "top_map": [1], "block_map": [2], "bitmap": [1], "max_key": 1024}"#,
+                InvariantError::InvalidTopMapIndex { index: 0 },
+            ),
+        ] {
+            let err = serde_json::from_str::<CompressedBitmap>(encoded).unwrap_err();
+            assert_eq!(err.to_string(), want.to_string());
+        }
+    }
+
     const MAX_KEY: usize = 1028;
 
     proptest! {
+        #[test]
+        fn prop_sparse_top_map(
+            values in prop::collection::vec((0_usize..64, 0_usize..64), 0..50),
+        ) {
+            // Spread the keys across the top map words of a 2^40 bit key
+            // space, setting them in an arbitrary order.
+            let keys = values
+                .iter()
+                .map(|&(top, low)| top << 34 | low << 12 | top)
+                .collect::<Vec<_>>();
+
+            let mut b = CompressedBitmap::new(1 << 40);
+            for &v in &keys {
+                b.set(v, true);
+            }
+            assert_eq!(b.verify_invariants(), Ok(()));
+
+            let mut sorted = keys.clone();
+            sorted.sort_unstable();
+            sorted.dedup();
+            assert_eq!(b.ones().collect::<Vec<_>>(), sorted);
+            assert_eq!(b, CompressedBitmap::from_sorted_keys(1 << 40, sorted));
+
+            // Each key, and the key after it, reads as expected.
+            let probes = keys.iter().flat_map(|&v| [v, v + 1]).collect::<Vec<_>>();
+            let mut got = vec![false; probes.len()];
+            b.get_many(&probes, &mut got);
+            for (&v, got) in probes.iter().zip(got) {
+                assert_eq!(b.get(v), keys.contains(&v));
+                assert_eq!(got, keys.contains(&v));
+            }
+        }
+
         #[test]
         fn prop_compress(
             values in prop::collection::hash_set(0..MAX_KEY, 0..20),
@@ -647,5 +3160,119 @@ mod tests {
                 assert_eq!(b.get(i), values.contains(&i));
             }
         }
+
+        #[test]
+        fn prop_hot_block(
+            values in prop::collection::vec((0..MAX_KEY, 0_usize..4, any::<bool>()), 0..50),
+        ) {
+            let mut b = CompressedBitmap::new(MAX_KEY);
+            let mut want = VecBitmap::new_with_capacity(MAX_KEY);
+
+            // Write runs of keys within the same block, interleaved with
+            // writes that allocate blocks before the hot block.
+            for &(v, run, value) in &values {
+                for key in (v..MAX_KEY).take(run + 1) {
+                    b.set(key, value);
+                    want.set(key, value);
+                }
+            }
+
+            for i in 0..MAX_KEY {
+                assert_eq!(b.get(i), want.get(i));
+            }
+
+            b.clear();
+            b.set(1, true);
+            contains_only_truthy!(b, MAX_KEY; 1);
+        }
+
+        #[test]
+        fn prop_or_into(
+            a in prop::collection::vec(0..MAX_KEY, 0..20),
+            b in prop::collection::vec(0..MAX_KEY, 0..20),
+            stale in prop::collection::vec(0..MAX_KEY, 0..20),
+        ) {
+            let mut a_bitmap = CompressedBitmap::new(MAX_KEY);
+            for v in &a {
+                a_bitmap.set(*v, true);
+            }
+
+            let mut b_bitmap = CompressedBitmap::new(MAX_KEY);
+            for v in &b {
+                b_bitmap.set(*v, true);
+            }
+
+            // The output bitmap contains existing values that must be
+            // discarded.
+            let mut out = CompressedBitmap::new(MAX_KEY);
+            for v in &stale {
+                out.set(*v, true);
+            }
+
+            a_bitmap.or_into(&b_bitmap, &mut out);
+            assert_eq!(out, a_bitmap.or(&b_bitmap));
+
+            // Merging the same blocks takes the direct path.
+            a_bitmap.or_into(&a_bitmap.clone(), &mut out);
+            assert_eq!(out, a_bitmap);
+        }
+
+        #[test]
+        fn prop_from_sorted_keys(
+            mut values in prop::collection::vec(0..MAX_KEY, 0..100),
+        ) {
+            let mut want = CompressedBitmap::new(MAX_KEY);
+            for v in &values {
+                want.set(*v, true);
+            }
+
+            values.sort_unstable();
+            let got = CompressedBitmap::from_sorted_keys(MAX_KEY, values);
+
+            // Invariant: the bulk construction is identical to setting each
+            // key individually.
+            assert_eq!(got, want);
+        }
+
+        #[test]
+        fn prop_get_many(
+            values in prop::collection::vec(0..MAX_KEY, 0..50),
+            check in prop::collection::vec(0..MAX_KEY, 0..50),
+        ) {
+            let mut b = CompressedBitmap::new(MAX_KEY);
+            for v in &values {
+                b.set(*v, true);
+            }
+
+            let keys = values.iter().chain(&check).copied().collect::<Vec<_>>();
+            let mut out = vec![false; keys.len()];
+            b.get_many(&keys, &mut out);
+
+            // Invariant: the batch lookup matches the individual lookups.
+            for (key, got) in keys.iter().zip(out) {
+                assert_eq!(got, b.get(*key));
+            }
+        }
+
+        #[test]
+        fn prop_set_many(
+            initial in prop::collection::vec(0..MAX_KEY, 0..50),
+            mut values in prop::collection::vec(0..MAX_KEY, 0..50),
+        ) {
+            let mut want = CompressedBitmap::new(MAX_KEY);
+            for v in initial.iter().chain(&values) {
+                want.set(*v, true);
+            }
+
+            let mut got = CompressedBitmap::new(MAX_KEY);
+            for v in &initial {
+                got.set(*v, true);
+            }
+            got.set_many(&mut values);
+
+            // Invariant: the batch mutation is identical to setting each key
+            // individually.
+            assert_eq!(got, want);
+        }
     }
 }
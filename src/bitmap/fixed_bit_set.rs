@@ -0,0 +1,140 @@
+#![cfg(feature = "fixedbitset")]
+
+use fixedbitset::FixedBitSet;
+
+use crate::{
+    bitmap::{index_for_key, saturate_key, touch_pages, VecBitmap},
+    Bitmap,
+};
+
+/// Use a [`FixedBitSet`] as the storage of a [`Bloom2`](crate::Bloom2).
+///
+/// The set is sized in whole 64 bit blocks to hold at least `max_key` bits,
+/// matching the layout of a [`VecBitmap`] so the two convert between each
+/// other by copying the blocks. An existing set can be used as filter storage
+/// with [`BloomFilterBuilder::with_bitmap_data()`] if it holds at least
+/// [`FilterSize::max_bits()`] bits - setting a bit beyond the end of the set
+/// panics.
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, FilterSize};
+/// use fixedbitset::FixedBitSet;
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .with_bitmap::<FixedBitSet>()
+///     .size(FilterSize::KeyBytes2)
+///     .build();
+///
+/// filter.insert(&"bananas");
+/// assert!(filter.contains(&"bananas"));
+/// ```
+///
+/// [`BloomFilterBuilder::with_bitmap_data()`]: crate::BloomFilterBuilder::with_bitmap_data
+/// [`FilterSize::max_bits()`]: crate::FilterSize::max_bits
+impl Bitmap for FixedBitSet {
+    fn new_with_capacity(max_key: usize) -> Self {
+        Self::with_capacity((index_for_key(max_key) + 1) * u64::BITS as usize)
+    }
+
+    fn set(&mut self, key: usize, value: bool) {
+        let key = saturate_key(key, self.len().saturating_sub(1));
+        FixedBitSet::set(self, key, value)
+    }
+
+    fn get(&self, key: usize) -> bool {
+        let key = saturate_key(key, self.len().saturating_sub(1));
+        self.contains(key)
+    }
+
+    fn byte_size(&self) -> usize {
+        std::mem::size_of_val(self.as_slice())
+    }
+
+    fn warm(&self) {
+        touch_pages(self.as_slice());
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        assert_eq!(self.len(), other.len());
+
+        let mut out = self.clone();
+        out.union_with(other);
+        out
+    }
+}
+
+impl From<VecBitmap> for FixedBitSet {
+    fn from(v: VecBitmap) -> Self {
+        let (bitmap, _) = v.into_parts();
+        Self::with_capacity_and_blocks(bitmap.len() * u64::BITS as usize, bitmap)
+    }
+}
+
+/// Copy the blocks of a [`FixedBitSet`] into a [`VecBitmap`] with a `max_key`
+/// of the last bit of the set.
+impl From<FixedBitSet> for VecBitmap {
+    fn from(v: FixedBitSet) -> Self {
+        let max_key = v.len().saturating_sub(1);
+
+        let mut bitmap = v.as_slice().to_vec();
+        bitmap.resize(index_for_key(max_key) + 1, 0);
+
+        Self::from_parts(bitmap, max_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const MAX_KEY: usize = 1028;
+
+    proptest! {
+        #[test]
+        fn prop_matches_vec_bitmap(
+            values in prop::collection::vec(0..MAX_KEY, 0..20),
+            other in prop::collection::vec(0..MAX_KEY, 0..20),
+        ) {
+            let mut a = FixedBitSet::new_with_capacity(MAX_KEY);
+            let mut b = FixedBitSet::new_with_capacity(MAX_KEY);
+            let mut want = VecBitmap::new_with_capacity(MAX_KEY);
+            for v in &values {
+                a.set(*v, true);
+                want.set(*v, true);
+            }
+            for v in &other {
+                b.set(*v, true);
+                want.set(*v, true);
+            }
+
+            let merged = Bitmap::or(&a, &b);
+            assert_eq!(merged.byte_size(), want.byte_size());
+            for i in 0..MAX_KEY {
+                assert_eq!(Bitmap::get(&merged, i), want.get(i));
+            }
+
+            // Round-trip the merged set through a VecBitmap.
+            let v = VecBitmap::from(merged.clone());
+            assert_eq!(v.byte_size(), want.byte_size());
+            for i in 0..MAX_KEY {
+                assert_eq!(v.get(i), want.get(i));
+            }
+            assert_eq!(FixedBitSet::from(want), merged);
+        }
+    }
+
+    #[test]
+    fn test_from_unaligned_set() {
+        let mut set = FixedBitSet::with_capacity(100);
+        set.insert(99);
+
+        let v = VecBitmap::from(set);
+        assert!(v.get(99));
+        assert!(!v.get(98));
+        assert_eq!(v.byte_size(), 16);
+
+        assert_eq!(VecBitmap::from(FixedBitSet::new()).byte_size(), 8);
+    }
+}
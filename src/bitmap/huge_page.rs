@@ -0,0 +1,329 @@
+use std::ptr::NonNull;
+
+use crate::{Bitmap, Error};
+
+use super::{
+    bitmask_for_key, index_for_key, memory::MemoryBreakdown, saturate_key, simd, touch_pages,
+};
+
+/// The size of a (2MiB) transparent huge page.
+const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// The `mbind()` policy restricting allocations to a set of NUMA nodes.
+const MPOL_BIND: libc::c_int = 2;
+
+/// A plain, `O(1)` indexed bitmap allocated from huge pages, optionally bound
+/// to a NUMA node.
+///
+/// This bitmap behaves identically to a [`VecBitmap`](crate::VecBitmap), but
+/// maps its storage directly from the kernel in multiples of 2MiB, advising
+/// the kernel to back it with transparent huge pages. A
+/// [`FilterSize::KeyBytes4`](crate::FilterSize::KeyBytes4) filter spans
+/// 512MB, so with 4KiB pages nearly every random lookup misses the TLB - huge
+/// pages cover the same bitmap with 512 times fewer TLB entries.
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, FilterSize, HugePageBitmap};
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .with_bitmap::<HugePageBitmap>()
+///     .size(FilterSize::KeyBytes3)
+///     .build();
+///
+/// filter.insert(&"bananas");
+/// assert!(filter.contains(&"bananas"));
+/// ```
+///
+/// Use [`HugePageBitmap::new_on_node()`] to bind the storage to the memory of
+/// a NUMA node, keeping lookups from threads pinned to that node local:
+///
+/// ```rust,no_run
+/// use bloom2::{BloomFilterBuilder, FilterSize, HugePageBitmap};
+///
+/// let size = FilterSize::KeyBytes4;
+/// let bitmap = HugePageBitmap::new_on_node(1 << 32, 0)?;
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .with_bitmap::<HugePageBitmap>()
+///     .with_bitmap_data(bitmap, size)
+///     .size(size)
+///     .build();
+/// # filter.insert(&42);
+/// # Ok::<_, std::io::Error>(())
+/// ```
+///
+/// Huge pages are only used if transparent huge pages are enabled (in
+/// `/sys/kernel/mm/transparent_hugepage/enabled`), otherwise the bitmap is
+/// backed by regular pages. This type is only available on Linux, with the
+/// `huge-pages` feature enabled.
+pub struct HugePageBitmap {
+    ptr: NonNull<usize>,
+
+    /// The number of words in the bitmap.
+    len: usize,
+
+    /// The number of bytes mapped, a multiple of [`HUGE_PAGE_SIZE`].
+    mapped: usize,
+
+    max_key: usize,
+    node: Option<u32>,
+}
+
+// SAFETY: the mapping is exclusively owned, as for a Vec.
+unsafe impl Send for HugePageBitmap {}
+// SAFETY: shared references only permit reads of the mapping.
+unsafe impl Sync for HugePageBitmap {}
+
+impl HugePageBitmap {
+    /// Construct a `HugePageBitmap` with capacity to hold at least `max_key`
+    /// number of bits, allocated from the memory of NUMA `node`.
+    ///
+    /// Returns an error if the memory cannot be mapped, or bound to `node`
+    /// (such as if `node` does not exist).
+    pub fn new_on_node(max_key: usize, node: u32) -> std::io::Result<Self> {
+        Self::map(max_key, Some(node))
+    }
+
+    /// Returns the NUMA node the bitmap is bound to, if any.
+    pub fn numa_node(&self) -> Option<u32> {
+        self.node
+    }
+
+    fn map(max_key: usize, node: Option<u32>) -> std::io::Result<Self> {
+        let len = index_for_key(max_key) + 1;
+        let mapped = (len * std::mem::size_of::<usize>()).div_ceil(HUGE_PAGE_SIZE) * HUGE_PAGE_SIZE;
+
+        // SAFETY: an anonymous private mapping does not alias any memory.
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                mapped,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // Unmapped on drop from here on, including on error.
+        let bitmap = Self {
+            ptr: NonNull::new(addr.cast()).expect("mmap returned null"),
+            len,
+            mapped,
+            max_key,
+            node,
+        };
+
+        // Advisory only - if transparent huge pages are disabled, the mapping
+        // is backed by regular pages.
+        //
+        // SAFETY: the range is exactly the mapping created above.
+        unsafe { libc::madvise(addr, mapped, libc::MADV_HUGEPAGE) };
+
+        if let Some(node) = node {
+            // The kernel reads one less bit than maxnode, so the mask is
+            // padded by a word.
+            let bits = libc::c_ulong::BITS as usize;
+            let mut mask = vec![0 as libc::c_ulong; node as usize / bits + 2];
+            mask[node as usize / bits] |= 1 << (node as usize % bits);
+
+            // SAFETY: the range is exactly the mapping created above, and the
+            // mask is valid for maxnode bits. No pages have been faulted in
+            // yet, so all pages are allocated according to the policy.
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_mbind,
+                    addr,
+                    mapped,
+                    MPOL_BIND,
+                    mask.as_ptr(),
+                    (mask.len() * bits) as libc::c_ulong,
+                    0 as libc::c_uint,
+                )
+            };
+            if ret != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        Ok(bitmap)
+    }
+
+    fn words(&self) -> &[usize] {
+        // SAFETY: the mapping holds len zero-initialised words, and lives as
+        // long as self.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    fn words_mut(&mut self) -> &mut [usize] {
+        // SAFETY: as above, and the mutable borrow of self is exclusive.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Map a new, empty bitmap of the same size on the same NUMA node.
+    fn new_like(&self) -> Self {
+        Self::map(self.max_key, self.node).expect("failed to map huge page bitmap")
+    }
+}
+
+impl Drop for HugePageBitmap {
+    fn drop(&mut self) {
+        // SAFETY: the range is exactly the mapping created in map(), and no
+        // references to it outlive self.
+        unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.mapped) };
+    }
+}
+
+impl Clone for HugePageBitmap {
+    fn clone(&self) -> Self {
+        let mut out = self.new_like();
+        out.words_mut().copy_from_slice(self.words());
+        out
+    }
+}
+
+impl PartialEq for HugePageBitmap {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_key == other.max_key && self.words() == other.words()
+    }
+}
+
+impl Eq for HugePageBitmap {}
+
+impl std::fmt::Debug for HugePageBitmap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HugePageBitmap")
+            .field("max_key", &self.max_key)
+            .field("mapped", &self.mapped)
+            .field("node", &self.node)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Bitmap for HugePageBitmap {
+    fn set(&mut self, key: usize, value: bool) {
+        let key = saturate_key(key, self.max_key);
+        let offset = index_for_key(key);
+
+        if value {
+            self.words_mut()[offset] |= bitmask_for_key(key);
+        } else {
+            self.words_mut()[offset] &= !bitmask_for_key(key);
+        }
+    }
+
+    fn get(&self, key: usize) -> bool {
+        let key = saturate_key(key, self.max_key);
+        let offset = index_for_key(key);
+
+        self.words()[offset] & bitmask_for_key(key) != 0
+    }
+
+    fn byte_size(&self) -> usize {
+        self.len * std::mem::size_of::<usize>()
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        let bitmap_bytes = self.byte_size();
+
+        MemoryBreakdown {
+            bitmap_bytes,
+            spare_bytes: self.mapped - bitmap_bytes,
+            overhead_bytes: std::mem::size_of_val(self),
+            ..Default::default()
+        }
+    }
+
+    fn warm(&self) {
+        touch_pages(self.words());
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        assert_eq!(self.len, other.len);
+
+        let mut out = self.new_like();
+        simd::or_into(out.words_mut(), self.words(), other.words());
+        out
+    }
+
+    fn clone_empty(&self, max_key: usize) -> Self {
+        Self::map(max_key, self.node).expect("failed to map huge page bitmap")
+    }
+
+    fn new_with_capacity(max_key: usize) -> Self {
+        Self::map(max_key, None).expect("failed to map huge page bitmap")
+    }
+
+    fn try_new_with_capacity(max_key: usize) -> Result<Self, Error> {
+        Self::map(max_key, None).map_err(|_| Error::AllocationFailed {
+            bytes: (index_for_key(max_key).saturating_add(1))
+                .saturating_mul(std::mem::size_of::<usize>()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::VecBitmap;
+
+    const MAX_KEY: usize = 1028;
+
+    proptest! {
+        #[test]
+        fn prop_matches_vec_bitmap(
+            a in prop::collection::hash_set(0..MAX_KEY, 0..20),
+            b in prop::collection::hash_set(0..MAX_KEY, 0..20),
+        ) {
+            let mut huge_a = HugePageBitmap::new_with_capacity(MAX_KEY);
+            let mut huge_b = HugePageBitmap::new_with_capacity(MAX_KEY);
+            let mut vec_a = VecBitmap::new_with_capacity(MAX_KEY);
+            let mut vec_b = VecBitmap::new_with_capacity(MAX_KEY);
+
+            for &v in &a {
+                huge_a.set(v, true);
+                vec_a.set(v, true);
+            }
+            for &v in &b {
+                huge_b.set(v, true);
+                vec_b.set(v, true);
+            }
+
+            let huge = huge_a.or(&huge_b);
+            let vec = vec_a.or(&vec_b);
+            for i in 0..MAX_KEY {
+                assert_eq!(huge.get(i), vec.get(i));
+            }
+
+            assert_eq!(huge_a.clone(), huge_a);
+            assert_eq!(huge_a.byte_size(), vec_a.byte_size());
+        }
+    }
+
+    #[test]
+    fn test_mapped_size() {
+        let b = HugePageBitmap::new_with_capacity(MAX_KEY);
+        let usage = b.memory_breakdown();
+        assert_eq!(usage.bitmap_bytes + usage.spare_bytes, HUGE_PAGE_SIZE);
+        assert_eq!(b.numa_node(), None);
+    }
+
+    #[test]
+    fn test_numa_node() {
+        // Node 0 exists on every NUMA system, but binding may not be
+        // permitted.
+        if let Ok(mut b) = HugePageBitmap::new_on_node(MAX_KEY, 0) {
+            assert_eq!(b.numa_node(), Some(0));
+            b.set(42, true);
+            assert!(b.get(42));
+            assert_eq!(b.clone().numa_node(), Some(0));
+        }
+
+        assert!(HugePageBitmap::new_on_node(MAX_KEY, 1 << 20).is_err());
+    }
+}
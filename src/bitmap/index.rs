@@ -0,0 +1,94 @@
+//! [`Index`] implementations for the bitmap types, allowing bits to be read
+//! with `bitmap[key]` as with other bitset crates.
+
+use std::ops::Index;
+
+use crate::Bitmap;
+
+use super::{
+    AdaptiveBitmap, AlignedBitmap, CompressedBitmap, InlineBitmap, ObservedBitmap, Observer,
+    SparseBitmap, StagedBitmap, VecBitmap,
+};
+
+/// Return a `'static` reference to `value`, as the bits of a bitmap are not
+/// stored as addressable `bool` values.
+fn bool_ref(value: bool) -> &'static bool {
+    if value {
+        &true
+    } else {
+        &false
+    }
+}
+
+macro_rules! impl_index {
+    ($($(#[$meta:meta])* $ty:ty),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            impl Index<usize> for $ty {
+                type Output = bool;
+
+                /// Return the value of the bit indexed by `key`, as
+                /// [`Bitmap::get()`] does.
+                fn index(&self, key: usize) -> &bool {
+                    bool_ref(Bitmap::get(self, key))
+                }
+            }
+        )*
+    };
+}
+
+impl_index!(
+    AdaptiveBitmap,
+    AlignedBitmap,
+    CompressedBitmap,
+    SparseBitmap,
+    StagedBitmap,
+    VecBitmap,
+    #[cfg(feature = "bytes")]
+    super::BytesBitmap,
+    #[cfg(feature = "bytes")]
+    super::FrozenBytesBitmap,
+    #[cfg(all(feature = "huge-pages", target_os = "linux"))]
+    super::HugePageBitmap,
+);
+
+impl<const WORDS: usize> Index<usize> for InlineBitmap<WORDS> {
+    type Output = bool;
+
+    fn index(&self, key: usize) -> &bool {
+        bool_ref(self.get(key))
+    }
+}
+
+impl<O> Index<usize> for ObservedBitmap<O>
+where
+    O: Observer + Default + Clone,
+{
+    type Output = bool;
+
+    fn index(&self, key: usize) -> &bool {
+        bool_ref(self.get(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        let mut b = CompressedBitmap::new(1024);
+        b.set_bit(42);
+        b.set_bit(43);
+        b.clear_bit(43);
+
+        assert!(b[42]);
+        assert!(!b[43]);
+        assert!(!b[1000]);
+
+        let mut b = InlineBitmap::<4>::new_with_capacity(255);
+        b.set_bit(255);
+        assert!(b[255]);
+        assert!(!b[0]);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{Bitmap, FilterSize};
+
+use super::{bitmask_for_key, index_for_key, memory::MemoryBreakdown, saturate_key, simd};
+
+/// A fixed-capacity, `O(1)` indexed bitmap stored inline without any heap
+/// allocations.
+///
+/// An `InlineBitmap` holds `WORDS` number of `usize` blocks (`WORDS * 64` bits)
+/// in an array, making it suitable for small, short-lived filters (such as
+/// per-request filters) where the cost of allocating is significant compared
+/// to the cost of using the filter:
+///
+/// * A [`FilterSize::KeyBytes1`] filter requires an `InlineBitmap<4>` (32
+///   bytes)
+/// * A [`FilterSize::KeyBytes2`] filter requires an `InlineBitmap<1024>` (8KB)
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, FilterSize, InlineBitmap};
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .size(FilterSize::KeyBytes1)
+///     .with_bitmap::<InlineBitmap<4>>()
+///     .build();
+///
+/// filter.insert(&"no allocations here");
+/// assert!(filter.contains(&"no allocations here"));
+/// ```
+///
+/// The number of words can be derived from the filter size with
+/// [`inline_words()`], and a configuration can be checked at compile time with
+/// [`InlineBitmap::fits()`]:
+///
+/// ```rust
+/// use bloom2::{inline_words, BloomFilterBuilder, FilterSize, InlineBitmap};
+///
+/// const SIZE: FilterSize = FilterSize::KeyBits(12);
+/// type Storage = InlineBitmap<{ inline_words(SIZE) }>;
+/// const _: () = assert!(Storage::fits(SIZE));
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .size(SIZE)
+///     .with_bitmap::<Storage>()
+///     .build();
+/// # filter.insert(&42);
+/// ```
+///
+/// A bitmap too small for the filter fails to compile, rather than panicking
+/// when building the filter:
+///
+/// ```rust,compile_fail
+/// use bloom2::{FilterSize, InlineBitmap};
+///
+/// const _: () = assert!(InlineBitmap::<1024>::fits(FilterSize::KeyBytes4));
+/// ```
+///
+/// [`FilterSize::KeyBytes1`]: crate::FilterSize::KeyBytes1
+/// [`FilterSize::KeyBytes2`]: crate::FilterSize::KeyBytes2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineBitmap<const WORDS: usize> {
+    bitmap: [usize; WORDS],
+}
+
+/// An [`InlineBitmap`] sized to hold exactly the 256 bits of a
+/// [`FilterSize::KeyBytes1`] filter in 4 words (32 bytes).
+///
+/// A `KeyBytes1` filter fits entirely within a single cache line, and every
+/// `get` / `set` is a single mask operation against one word, with none of the
+/// block map indirection of a [`CompressedBitmap`]. This makes it well suited
+/// to micro-filters constructed per-connection or per-request.
+///
+/// See [`BloomFilterBuilder::small()`].
+///
+/// [`FilterSize::KeyBytes1`]: crate::FilterSize::KeyBytes1
+/// [`CompressedBitmap`]: crate::CompressedBitmap
+/// [`BloomFilterBuilder::small()`]: crate::BloomFilterBuilder::small
+pub type SmallBitmap = InlineBitmap<4>;
+
+/// Return the number of words an [`InlineBitmap`] requires to hold a filter
+/// of `size`.
+pub const fn inline_words(size: FilterSize) -> usize {
+    size.max_bits().div_ceil(u64::BITS as usize)
+}
+
+impl<const WORDS: usize> InlineBitmap<WORDS> {
+    /// The number of bits held by the bitmap storage.
+    pub const CAPACITY: usize = WORDS * u64::BITS as usize;
+
+    /// The highest key addressable by the bitmap storage.
+    const LAST_KEY: usize = Self::CAPACITY - 1;
+
+    /// Returns `true` if the bitmap holds every bit of a filter of `size`.
+    pub const fn fits(size: FilterSize) -> bool {
+        size.max_bits() <= Self::CAPACITY
+    }
+}
+
+impl<const WORDS: usize> Bitmap for InlineBitmap<WORDS> {
+    /// Construct a new, empty `InlineBitmap`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_key` exceeds the fixed capacity of `WORDS * 64` bits.
+    fn new_with_capacity(max_key: usize) -> Self {
+        assert!(
+            max_key <= Self::CAPACITY,
+            "max key {} exceeds inline bitmap capacity of {} bits",
+            max_key,
+            Self::CAPACITY
+        );
+
+        Self { bitmap: [0; WORDS] }
+    }
+
+    fn set(&mut self, key: usize, value: bool) {
+        let key = saturate_key(key, Self::LAST_KEY);
+        let offset = index_for_key(key);
+
+        if value {
+            self.bitmap[offset] |= bitmask_for_key(key);
+        } else {
+            self.bitmap[offset] &= !bitmask_for_key(key);
+        }
+    }
+
+    fn get(&self, key: usize) -> bool {
+        let key = saturate_key(key, Self::LAST_KEY);
+        let offset = index_for_key(key);
+
+        self.bitmap[offset] & bitmask_for_key(key) != 0
+    }
+
+    fn byte_size(&self) -> usize {
+        std::mem::size_of_val(&self.bitmap)
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        // The bitmap is stored inline, and the type has no other fields.
+        MemoryBreakdown {
+            bitmap_bytes: std::mem::size_of_val(&self.bitmap),
+            ..Default::default()
+        }
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        let mut bitmap = [0; WORDS];
+        simd::or_into(&mut bitmap, &self.bitmap, &other.bitmap);
+
+        Self { bitmap }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const MAX_KEY: usize = 256;
+
+    #[test]
+    #[should_panic(expected = "exceeds inline bitmap capacity")]
+    fn test_capacity_exceeded() {
+        InlineBitmap::<4>::new_with_capacity(257);
+    }
+
+    #[test]
+    fn test_fits() {
+        assert!(SmallBitmap::fits(FilterSize::KeyBytes1));
+        assert!(!SmallBitmap::fits(FilterSize::KeyBytes2));
+        assert!(InlineBitmap::<1024>::fits(FilterSize::KeyBytes2));
+
+        for bits in 1..=20 {
+            let size = FilterSize::from_key_bits(bits).unwrap();
+            let words = inline_words(size);
+            assert!(words * 64 >= size.max_bits());
+            assert!((words - 1) * 64 < size.max_bits());
+        }
+        assert_eq!(inline_words(FilterSize::KeyBits(1)), 1);
+    }
+
+    #[cfg(feature = "saturating")]
+    #[test]
+    fn test_saturating() {
+        let mut b = SmallBitmap::new_with_capacity(MAX_KEY);
+
+        b.set(MAX_KEY * 2, true);
+        assert!(b.get(MAX_KEY - 1));
+        assert!(b.get(usize::MAX));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_insert_contains(
+            values in prop::collection::hash_set(0..MAX_KEY, 0..20),
+        ) {
+            let mut b = InlineBitmap::<4>::new_with_capacity(MAX_KEY);
+
+            for v in &values {
+                b.set(*v, true);
+            }
+
+            // Ensure all values are equal in the test range.
+            for i in 0..MAX_KEY {
+                assert_eq!(b.get(i), values.contains(&i));
+            }
+        }
+
+        #[test]
+        fn prop_or(
+            a in prop::collection::vec(0..MAX_KEY, 0..20),
+            b in prop::collection::vec(0..MAX_KEY, 0..20),
+        ) {
+            let mut a_bitmap = InlineBitmap::<4>::new_with_capacity(MAX_KEY);
+            let mut b_bitmap = InlineBitmap::<4>::new_with_capacity(MAX_KEY);
+            let mut combined_bitmap = InlineBitmap::<4>::new_with_capacity(MAX_KEY);
+
+            for v in a.iter() {
+                a_bitmap.set(*v, true);
+                combined_bitmap.set(*v, true);
+            }
+
+            for v in b.iter() {
+                b_bitmap.set(*v, true);
+                combined_bitmap.set(*v, true);
+            }
+
+            let union = a_bitmap.or(&b_bitmap);
+
+            // Invariant: the union and the combined construction must be equal.
+            assert_eq!(union, combined_bitmap);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+/// A breakdown of the memory used by a [`Bitmap`](crate::Bitmap)
+/// implementation, in bytes.
+///
+/// ```rust
+/// use bloom2::{Bitmap, CompressedBitmap};
+///
+/// let mut b = CompressedBitmap::new(1024);
+/// b.set(42, true);
+///
+/// let usage = b.memory_breakdown();
+/// assert_eq!(usage.bitmap_bytes, 8);
+/// assert_eq!(usage.total(), b.size());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryBreakdown {
+    /// The bytes used by the top map of a multi-level bitmap.
+    pub top_map_bytes: usize,
+
+    /// The bytes used by the block map of a multi-level bitmap.
+    pub block_map_bytes: usize,
+
+    /// The bytes used by the bitmap blocks.
+    pub bitmap_bytes: usize,
+
+    /// The bytes allocated but not yet used (spare capacity).
+    pub spare_bytes: usize,
+
+    /// The size of the bitmap type itself, excluding any heap allocations.
+    pub overhead_bytes: usize,
+}
+
+impl MemoryBreakdown {
+    /// Return the total number of bytes used.
+    pub fn total(&self) -> usize {
+        self.top_map_bytes
+            + self.block_map_bytes
+            + self.bitmap_bytes
+            + self.spare_bytes
+            + self.overhead_bytes
+    }
+
+    /// Return the bytes used by the populated entries, excluding spare
+    /// capacity and overhead.
+    pub fn used(&self) -> usize {
+        self.top_map_bytes + self.block_map_bytes + self.bitmap_bytes
+    }
+}
+
+impl std::ops::Add for MemoryBreakdown {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            top_map_bytes: self.top_map_bytes + rhs.top_map_bytes,
+            block_map_bytes: self.block_map_bytes + rhs.block_map_bytes,
+            bitmap_bytes: self.bitmap_bytes + rhs.bitmap_bytes,
+            spare_bytes: self.spare_bytes + rhs.spare_bytes,
+            overhead_bytes: self.overhead_bytes + rhs.overhead_bytes,
+        }
+    }
+}
+
+/// Return the number of bytes used by, and the spare capacity of, `v`.
+pub(crate) fn vec_bytes<T>(v: &[T], capacity: usize) -> (usize, usize) {
+    let spare = (capacity - v.len()) * std::mem::size_of::<T>();
+    (std::mem::size_of_val(v), spare)
+}
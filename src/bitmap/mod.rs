@@ -1,15 +1,55 @@
 //! Bitmap implementations for the backing storage of a [`Bloom2`](crate::Bloom2).
 
+use std::alloc::{alloc_zeroed, Layout};
+
+use crate::Error;
+
+mod adaptive;
+mod aligned;
 mod bytes;
 mod compressed_bitmap;
+mod fixed_bit_set;
+#[cfg(all(feature = "huge-pages", target_os = "linux"))]
+mod huge_page;
+mod index;
+mod inline;
+mod memory;
+mod observed;
+mod pool;
+#[cfg(feature = "serde")]
+pub(crate) mod serde_words;
+mod simd;
+mod sparse;
+mod staged;
 mod vec;
 
+pub use adaptive::*;
+pub use aligned::*;
 pub use compressed_bitmap::*;
+pub use inline::*;
+pub use memory::*;
+pub use observed::*;
+pub use pool::*;
+pub use sparse::*;
+pub use staged::*;
 pub use vec::*;
 
 #[cfg(feature = "bytes")]
 pub use bytes::*;
 
+#[cfg(all(feature = "huge-pages", target_os = "linux"))]
+pub use huge_page::*;
+
+/// Clamp `key` to `max_key` if the `saturating` feature is enabled, otherwise
+/// return `key` unchanged.
+#[inline(always)]
+#[cfg_attr(not(feature = "saturating"), allow(unused_variables))]
+pub(crate) fn saturate_key(key: usize, max_key: usize) -> usize {
+    #[cfg(feature = "saturating")]
+    let key = key.min(max_key);
+    key
+}
+
 #[inline(always)]
 pub(crate) fn bitmask_for_key(key: usize) -> usize {
     1 << (key % (u64::BITS as usize))
@@ -19,3 +59,48 @@ pub(crate) fn bitmask_for_key(key: usize) -> usize {
 pub(crate) fn index_for_key(key: usize) -> usize {
     key / (u64::BITS as usize)
 }
+
+/// Allocate a `Vec` of `len` zeroed values, returning
+/// [`Error::AllocationFailed`] instead of aborting the process if the memory
+/// cannot be allocated.
+///
+/// As with `vec![0; len]`, the memory is requested pre-zeroed from the
+/// allocator, so large allocations are lazily backed by the OS rather than
+/// written to.
+///
+/// # Safety
+///
+/// An all-zero bit pattern MUST be a valid `T`, and `T` MUST NOT be zero
+/// sized.
+pub(crate) unsafe fn try_zeroed_vec<T>(len: usize) -> Result<Vec<T>, Error> {
+    debug_assert_ne!(std::mem::size_of::<T>(), 0);
+
+    let failed = || Error::AllocationFailed {
+        bytes: len.saturating_mul(std::mem::size_of::<T>()),
+    };
+
+    let layout = Layout::array::<T>(len).map_err(|_| failed())?;
+    if layout.size() == 0 {
+        return Ok(Vec::new());
+    }
+
+    let ptr = alloc_zeroed(layout);
+    if ptr.is_null() {
+        return Err(failed());
+    }
+
+    // SAFETY: ptr was allocated by the global allocator with the layout of
+    // exactly len values of T, all of which are initialised (zeroed).
+    Ok(Vec::from_raw_parts(ptr.cast(), len, len))
+}
+
+/// The smallest page size of the supported platforms.
+const PAGE_SIZE: usize = 4096;
+
+/// Read one value from each page spanned by `values`, faulting the pages in.
+pub(crate) fn touch_pages<V: Copy>(values: &[V]) {
+    let stride = (PAGE_SIZE / std::mem::size_of::<V>().max(1)).max(1);
+    for v in values.iter().step_by(stride).chain(values.last()) {
+        std::hint::black_box(*v);
+    }
+}
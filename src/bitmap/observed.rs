@@ -0,0 +1,289 @@
+use crate::Bitmap;
+
+use super::{compressed_bitmap::CompressedBitmap, memory::MemoryBreakdown};
+
+/// The default fraction of bits set at which
+/// [`Observer::on_saturation_threshold()`] is called.
+pub const DEFAULT_SATURATION_THRESHOLD: f64 = 0.5;
+
+/// Hooks called by an [`ObservedBitmap`] as it is modified.
+///
+/// All methods default to a no-op, allowing implementations to observe only
+/// the events they are interested in, such as to emit metrics.
+pub trait Observer {
+    /// Called when the bit for `key` changes from `false` to `true`.
+    fn on_insert(&mut self, key: usize) {
+        let _ = key;
+    }
+
+    /// Called when a new block is allocated, with the total number of
+    /// allocated `blocks` and the number of bytes used by the bitmap.
+    fn on_block_allocated(&mut self, blocks: usize, bytes: usize) {
+        let _ = (blocks, bytes);
+    }
+
+    /// Called once, when the fraction of bits set (the `load`) first reaches
+    /// the saturation threshold of the [`ObservedBitmap`].
+    ///
+    /// As the load of a bloom filter increases, so does the probability of a
+    /// false positive.
+    fn on_saturation_threshold(&mut self, load: f64) {
+        let _ = load;
+    }
+}
+
+/// A [`CompressedBitmap`] that calls the hooks of an [`Observer`] as it is
+/// modified.
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, FilterSize, ObservedBitmap, Observer};
+///
+/// #[derive(Debug, Default, Clone)]
+/// struct Metrics {
+///     blocks: usize,
+/// }
+///
+/// impl Observer for Metrics {
+///     fn on_block_allocated(&mut self, blocks: usize, _bytes: usize) {
+///         self.blocks = blocks;
+///     }
+/// }
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .with_bitmap::<ObservedBitmap<Metrics>>()
+///     .size(FilterSize::KeyBytes2)
+///     .build();
+///
+/// filter.insert(&"hello");
+/// assert!(filter.bitmap().observer().blocks > 0);
+/// ```
+///
+/// Constructing an `ObservedBitmap` through the [`Bitmap`] trait uses the
+/// [`Default`] observer and the [`DEFAULT_SATURATION_THRESHOLD`]. Use
+/// [`ObservedBitmap::new()`] and
+/// [`BloomFilterBuilder::with_bitmap_data()`](crate::BloomFilterBuilder::with_bitmap_data)
+/// to provide an observer instance or threshold.
+///
+/// The union of two `ObservedBitmap` instances uses a clone of the observer of
+/// `self`.
+#[derive(Debug, Clone)]
+pub struct ObservedBitmap<O> {
+    bitmap: CompressedBitmap,
+    observer: O,
+
+    max_key: usize,
+    threshold: f64,
+
+    /// The number of bits set to `true`.
+    ones: usize,
+    /// True if on_saturation_threshold() has been called.
+    saturated: bool,
+}
+
+impl<O> ObservedBitmap<O>
+where
+    O: Observer,
+{
+    /// Construct an `ObservedBitmap` for space to hold up to `max_key` number
+    /// of bits, calling the hooks of `observer` as it is modified.
+    ///
+    /// [`Observer::on_saturation_threshold()`] is called when the fraction of
+    /// bits set reaches `threshold`.
+    pub fn new(max_key: usize, observer: O, threshold: f64) -> Self {
+        Self {
+            bitmap: CompressedBitmap::new(max_key),
+            observer,
+            max_key,
+            threshold,
+            ones: 0,
+            saturated: false,
+        }
+    }
+
+    /// Return a reference to the observer.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Return a mutable reference to the observer.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Return the underlying [`CompressedBitmap`] and observer.
+    pub fn into_parts(self) -> (CompressedBitmap, O) {
+        (self.bitmap, self.observer)
+    }
+
+    /// Return the fraction of bits set.
+    pub fn load(&self) -> f64 {
+        self.ones as f64 / self.max_key as f64
+    }
+
+    fn check_saturation(&mut self) {
+        if !self.saturated && self.load() >= self.threshold {
+            self.saturated = true;
+            self.observer.on_saturation_threshold(self.load());
+        }
+    }
+}
+
+impl<O> Bitmap for ObservedBitmap<O>
+where
+    O: Observer + Default + Clone,
+{
+    fn new_with_capacity(max_key: usize) -> Self {
+        Self::new(max_key, O::default(), DEFAULT_SATURATION_THRESHOLD)
+    }
+
+    fn set(&mut self, key: usize, value: bool) {
+        let was = self.bitmap.get(key);
+        if was == value {
+            return;
+        }
+
+        let blocks = self.bitmap.block_count();
+        self.bitmap.set(key, value);
+
+        if !value {
+            self.ones -= 1;
+            return;
+        }
+
+        self.ones += 1;
+        self.observer.on_insert(key);
+
+        if self.bitmap.block_count() > blocks {
+            self.observer
+                .on_block_allocated(self.bitmap.block_count(), self.bitmap.used_bytes());
+        }
+
+        self.check_saturation();
+    }
+
+    fn get(&self, key: usize) -> bool {
+        self.bitmap.get(key)
+    }
+
+    fn byte_size(&self) -> usize {
+        self.bitmap.size()
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        MemoryBreakdown {
+            overhead_bytes: std::mem::size_of_val(self),
+            ..self.bitmap.memory_breakdown()
+        }
+    }
+
+    fn warm(&self) {
+        self.bitmap.warm();
+    }
+
+    fn clone_empty(&self, max_key: usize) -> Self {
+        Self::new(max_key, self.observer.clone(), self.threshold)
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        let bitmap = self.bitmap.or(&other.bitmap);
+
+        let mut out = Self {
+            ones: bitmap.count_ones(),
+            bitmap,
+            observer: self.observer.clone(),
+            max_key: self.max_key,
+            threshold: self.threshold,
+            saturated: self.saturated,
+        };
+        out.check_saturation();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Clone)]
+    struct Recorder {
+        inserts: Vec<usize>,
+        blocks: Vec<usize>,
+        saturated: Vec<f64>,
+    }
+
+    impl Observer for Recorder {
+        fn on_insert(&mut self, key: usize) {
+            self.inserts.push(key);
+        }
+
+        fn on_block_allocated(&mut self, blocks: usize, _bytes: usize) {
+            self.blocks.push(blocks);
+        }
+
+        fn on_saturation_threshold(&mut self, load: f64) {
+            self.saturated.push(load);
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let mut b = ObservedBitmap::new(128, Recorder::default(), 0.25);
+
+        b.set(1, true);
+        b.set(1, true);
+        b.set(2, true);
+        b.set(100, true);
+        assert_eq!(b.observer().inserts, [1, 2, 100]);
+        assert_eq!(b.observer().blocks, [1, 2]);
+        assert!(b.observer().saturated.is_empty());
+
+        // Clearing a bit is not an insert.
+        b.set(2, false);
+        assert_eq!(b.observer().inserts, [1, 2, 100]);
+
+        // Setting a quarter of the bits crosses the saturation threshold
+        // once.
+        for i in 0..64 {
+            b.set(i, true);
+        }
+        assert_eq!(b.observer().saturated, [0.25]);
+        assert!(b.load() > 0.25);
+
+        let (bitmap, _) = b.into_parts();
+        assert_eq!(bitmap.count_ones(), 65);
+    }
+
+    #[test]
+    fn test_or_saturation() {
+        let mut a = ObservedBitmap::new(128, Recorder::default(), 0.5);
+        let mut b = ObservedBitmap::new(128, Recorder::default(), 0.5);
+
+        for i in 0..40 {
+            a.set(i, true);
+            b.set(i + 40, true);
+        }
+        assert!(a.observer().saturated.is_empty());
+
+        let union = a.or(&b);
+        assert_eq!(union.observer().saturated, [0.625]);
+        assert_eq!(union.load(), 0.625);
+    }
+
+    #[test]
+    fn test_clone_empty() {
+        let mut b = ObservedBitmap::new(128, Recorder::default(), 0.25);
+        for i in 0..40 {
+            b.set(i, true);
+        }
+
+        // The observer and threshold are retained.
+        let mut empty = b.clone_empty(128);
+        assert_eq!(empty.load(), 0.0);
+        assert_eq!(empty.observer().inserts, b.observer().inserts);
+
+        for i in 0..32 {
+            empty.set(i, true);
+        }
+        assert_eq!(empty.observer().saturated, [0.25, 0.25]);
+    }
+}
@@ -0,0 +1,106 @@
+/// A pool of reusable bitmap buffers.
+///
+/// Constructing, compressing and merging bitmaps allocates fresh buffers, and
+/// dropping a bitmap returns them to the allocator. For workloads that
+/// repeatedly build and discard filters, a `BufferPool` allows the buffers of
+/// discarded bitmaps to be recycled, and their capacity reused by subsequent
+/// bitmaps instead.
+///
+/// ```rust
+/// use bloom2::{BufferPool, CompressedBitmap};
+///
+/// let mut pool = BufferPool::default();
+///
+/// for _ in 0..10 {
+///     // Construct a bitmap using buffers from the pool.
+///     let mut b = CompressedBitmap::new_in(1024, &mut pool);
+///     b.set(42, true);
+///
+///     // And return the buffers to the pool once done.
+///     b.recycle(&mut pool);
+/// }
+/// ```
+///
+/// A `BufferPool` retains the capacity of all the buffers returned to it until
+/// the pool is dropped, or [`BufferPool::clear()`] is called.
+#[derive(Debug, Default)]
+pub struct BufferPool {
+    buffers: Vec<Vec<usize>>,
+}
+
+impl BufferPool {
+    /// Return the number of buffers held in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Returns `true` if the pool holds no buffers.
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// Return the total capacity of the pooled buffers in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.buffers
+            .iter()
+            .map(|v| v.capacity() * std::mem::size_of::<usize>())
+            .sum()
+    }
+
+    /// Drop all the buffers held in the pool, returning their memory to the
+    /// allocator.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+
+    /// Take an empty buffer from the pool, or a new, unallocated buffer if the
+    /// pool is empty.
+    pub(crate) fn take(&mut self) -> Vec<usize> {
+        self.buffers.pop().unwrap_or_default()
+    }
+
+    /// Return `buf` to the pool for later reuse.
+    pub(crate) fn put(&mut self, mut buf: Vec<usize>) {
+        // There's no point holding onto buffers without any capacity.
+        if buf.capacity() == 0 {
+            return;
+        }
+
+        buf.clear();
+        self.buffers.push(buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_put() {
+        let mut pool = BufferPool::default();
+        assert!(pool.is_empty());
+
+        // An empty pool allocates nothing.
+        assert_eq!(pool.take().capacity(), 0);
+
+        // Buffers without capacity are not retained.
+        pool.put(Vec::new());
+        assert!(pool.is_empty());
+
+        let buf = vec![1, 2, 3];
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.byte_size(), 3 * std::mem::size_of::<usize>());
+
+        // The same (now empty) buffer is returned.
+        let buf = pool.take();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        assert!(pool.is_empty());
+
+        pool.put(buf);
+        pool.clear();
+        assert!(pool.is_empty());
+    }
+}
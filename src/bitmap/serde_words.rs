@@ -0,0 +1,105 @@
+//! (De)serialisation of bitmap words for use with `#[serde(with = "...")]`.
+//!
+//! Binary formats encode the words as a single contiguous byte slice of
+//! little-endian `u64` values rather than element-by-element, while
+//! human-readable formats (such as JSON) continue to use a sequence of
+//! integers.
+
+use std::convert::TryFrom;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use serde_bytes::{ByteBuf, Bytes};
+
+const WORD_BYTES: usize = std::mem::size_of::<u64>();
+
+pub(crate) fn serialize<S>(words: &[usize], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    if serializer.is_human_readable() {
+        return words.serialize(serializer);
+    }
+
+    let mut buf = Vec::with_capacity(words.len() * WORD_BYTES);
+    for &w in words {
+        buf.extend_from_slice(&(w as u64).to_le_bytes());
+    }
+
+    Bytes::new(&buf).serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Vec<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        return Vec::<usize>::deserialize(deserializer);
+    }
+
+    let buf = ByteBuf::deserialize(deserializer)?;
+    if buf.len() % WORD_BYTES != 0 {
+        return Err(D::Error::invalid_length(
+            buf.len(),
+            &"a multiple of 8 bytes",
+        ));
+    }
+
+    buf.chunks_exact(WORD_BYTES)
+        .map(|c| {
+            let mut word = [0; WORD_BYTES];
+            word.copy_from_slice(c);
+            usize::try_from(u64::from_le_bytes(word))
+                .map_err(|_| D::Error::custom("bitmap word exceeds usize::MAX"))
+        })
+        .collect()
+}
+
+/// Deserialise words as [`deserialize()`] does, for an optional field.
+pub(crate) fn deserialize_some<'de, D>(deserializer: D) -> Result<Option<Vec<usize>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CompressedBitmap;
+    use quickcheck_macros::quickcheck;
+
+    #[quickcheck]
+    fn test_bincode_round_trip(keys: Vec<u16>) {
+        let mut b = CompressedBitmap::new(u16::MAX as usize);
+        for k in &keys {
+            b.set(*k as usize, true);
+        }
+
+        let encoded = bincode::serialize(&b).unwrap();
+        let decoded: CompressedBitmap = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(b, decoded);
+    }
+
+    #[test]
+    fn test_bincode_compact() {
+        let mut b = CompressedBitmap::new(1024);
+        b.set(1, true);
+        b.set(1000, true);
+
+        // One top_index word, one top_map word, one block_map word and two
+        // bitmap words, each field prefixed with a u64 length, followed by the
+        // u64 max_key.
+        let encoded = bincode::serialize(&b).unwrap();
+        assert_eq!(encoded.len(), 4 * 8 + 5 * 8 + 8);
+    }
+
+    #[test]
+    fn test_bincode_invalid_length() {
+        let mut encoded = bincode::serialize(&CompressedBitmap::new(64)).unwrap();
+
+        // Corrupt the top_index length prefix so it is not a whole number of
+        // words.
+        encoded[0] = 3;
+        encoded.truncate(8 + 3);
+        assert!(bincode::deserialize::<CompressedBitmap>(&encoded).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Bulk word kernels for combining and counting bitmap storage.
+//!
+//! The kernels are written as simple loops that the compiler auto-vectorises
+//! for the baseline target features (SSE2 on `x86_64`, NEON on `aarch64`).
+//!
+//! If the `simd` feature is enabled, the kernels are additionally compiled with
+//! AVX2 (and POPCNT) enabled on `x86_64`, and the widest implementation
+//! supported by the CPU is selected once at runtime.
+
+use std::ops::BitOr;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+use std::sync::OnceLock;
+
+/// The minimum number of words counted by the runtime-selected
+/// [`count_ones()`] kernel - shorter slices (such as the block map prefixes of
+/// small bitmaps) are counted by the inlined scalar kernel, avoiding the cost
+/// of an indirect call on the lookup path.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+const DISPATCH_MIN_WORDS: usize = 8;
+
+/// Write the element-wise OR of `a` and `b` into `out`.
+///
+/// # Panics
+///
+/// Panics if `a`, `b` and `out` are not of equal length.
+pub(crate) fn or_into<T>(out: &mut [T], a: &[T], b: &[T])
+where
+    T: Copy + BitOr<Output = T>,
+{
+    // Invariant: all slices are of equal length, allowing the bounds checks
+    // to be elided from the loop.
+    assert_eq!(a.len(), b.len());
+    assert_eq!(out.len(), a.len());
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2, as checked above.
+            return unsafe { or_into_avx2(out, a, b) };
+        }
+    }
+
+    or_into_kernel(out, a, b)
+}
+
+/// Return the element-wise OR of `a` and `b`.
+///
+/// # Panics
+///
+/// Panics if `a` and `b` are not of equal length.
+pub(crate) fn or<T>(a: &[T], b: &[T]) -> Vec<T>
+where
+    T: Copy + Default + BitOr<Output = T>,
+{
+    let mut out = vec![T::default(); a.len()];
+    or_into(&mut out, a, b);
+    out
+}
+
+/// Return the total number of set bits in `words`.
+#[inline]
+pub(crate) fn count_ones(words: &[usize]) -> usize {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if words.len() >= DISPATCH_MIN_WORDS {
+            return count_ones_selected()(words);
+        }
+    }
+
+    count_ones_kernel(words)
+}
+
+/// Return the widest [`count_ones()`] kernel supported by the CPU, detected
+/// once and cached for subsequent calls.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+fn count_ones_selected() -> fn(&[usize]) -> usize {
+    static KERNEL: OnceLock<fn(&[usize]) -> usize> = OnceLock::new();
+
+    *KERNEL.get_or_init(|| {
+        if std::is_x86_feature_detected!("avx2") && std::is_x86_feature_detected!("popcnt") {
+            // SAFETY: the CPU supports AVX2 and POPCNT, as checked above.
+            |words| unsafe { count_ones_avx2(words) }
+        } else {
+            count_ones_kernel
+        }
+    })
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2,popcnt")]
+unsafe fn count_ones_avx2(words: &[usize]) -> usize {
+    count_ones_kernel(words)
+}
+
+#[inline(always)]
+fn count_ones_kernel(words: &[usize]) -> usize {
+    // Sum the set bits of 4 words per iteration into independent accumulators,
+    // breaking the dependency chain on a single running total and allowing
+    // the compiler to vectorise the popcount across the lanes.
+    let mut chunks = words.chunks_exact(4);
+    let mut acc = [0_usize; 4];
+    for chunk in &mut chunks {
+        for (acc, word) in acc.iter_mut().zip(chunk) {
+            *acc += word.count_ones() as usize;
+        }
+    }
+
+    let tail = chunks
+        .remainder()
+        .iter()
+        .map(|v| v.count_ones() as usize)
+        .sum::<usize>();
+
+    acc.iter().sum::<usize>() + tail
+}
+
+/// Hint to the CPU that `v` will be read soon, loading it into the cache.
+///
+/// This is a no-op unless the `simd` feature is enabled on `x86_64`.
+#[inline(always)]
+pub(crate) fn prefetch<T>(v: &T) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+        // SAFETY: SSE is a baseline feature of x86_64, and prefetching is a
+        // hint that never faults, even for an invalid address.
+        unsafe { _mm_prefetch::<_MM_HINT_T0>(v as *const T as *const i8) };
+    }
+
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    let _ = v;
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn or_into_avx2<T>(out: &mut [T], a: &[T], b: &[T])
+where
+    T: Copy + BitOr<Output = T>,
+{
+    or_into_kernel(out, a, b)
+}
+
+#[inline(always)]
+fn or_into_kernel<T>(out: &mut [T], a: &[T], b: &[T])
+where
+    T: Copy + BitOr<Output = T>,
+{
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = *a | *b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn prop_or(
+            (a, b) in (0_usize..100).prop_flat_map(|len| (
+                prop::collection::vec(any::<usize>(), len),
+                prop::collection::vec(any::<usize>(), len),
+            )),
+        ) {
+            let got = or(&a, &b);
+            let want = a.iter().zip(&b).map(|(a, b)| a | b).collect::<Vec<_>>();
+            assert_eq!(got, want);
+        }
+
+        #[test]
+        fn prop_count_ones(words in prop::collection::vec(any::<usize>(), 0..200)) {
+            let want = words.iter().map(|v| v.count_ones() as usize).sum::<usize>();
+            assert_eq!(count_ones(&words), want);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_or_unequal_lengths() {
+        or(&[1_u8, 2], &[1_u8]);
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use crate::Bitmap;
+
+use super::{bitmask_for_key, index_for_key, memory::MemoryBreakdown, saturate_key};
+
+/// A sparse bitmap storing only the populated blocks, keyed by block index.
+///
+/// The memory used by a `SparseBitmap` is proportional to the number of
+/// populated `usize` blocks only, and is independent of the size of the key
+/// space. Unlike a [`CompressedBitmap`], inserting a new block does not shift
+/// the blocks after it, making it suitable for write-heavy use of the very
+/// large key spaces of [`FilterSize::KeyBytes6`] and above.
+///
+/// Reads and writes take `O(log n)` time in the number of populated blocks.
+///
+/// [`CompressedBitmap`]: crate::CompressedBitmap
+/// [`FilterSize::KeyBytes6`]: crate::FilterSize::KeyBytes6
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "allocative", derive(allocative::Allocative))]
+pub struct SparseBitmap {
+    blocks: BTreeMap<usize, usize>,
+    max_key: usize,
+}
+
+impl SparseBitmap {
+    /// Construct an empty `SparseBitmap` for keys up to and including
+    /// `max_key`.
+    ///
+    /// An empty `SparseBitmap` does not allocate.
+    pub fn new(max_key: usize) -> Self {
+        Self {
+            blocks: BTreeMap::new(),
+            max_key,
+        }
+    }
+
+    /// Return an iterator of the set keys, in ascending order.
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.blocks.iter().flat_map(|(&index, &block)| {
+            (0..usize::BITS as usize)
+                .filter(move |bit| block & (1 << bit) != 0)
+                .map(move |bit| index * usize::BITS as usize + bit)
+        })
+    }
+
+    /// Returns the number of set bits in the bitmap.
+    pub fn count_ones(&self) -> usize {
+        self.blocks.values().map(|b| b.count_ones() as usize).sum()
+    }
+}
+
+impl Bitmap for SparseBitmap {
+    fn set(&mut self, key: usize, value: bool) {
+        let key = saturate_key(key, self.max_key);
+        let index = index_for_key(key);
+
+        if value {
+            *self.blocks.entry(index).or_default() |= bitmask_for_key(key);
+        } else if let Some(block) = self.blocks.get_mut(&index) {
+            *block &= !bitmask_for_key(key);
+            if *block == 0 {
+                self.blocks.remove(&index);
+            }
+        }
+    }
+
+    fn get(&self, key: usize) -> bool {
+        let key = saturate_key(key, self.max_key);
+
+        self.blocks
+            .get(&index_for_key(key))
+            .map(|block| block & bitmask_for_key(key) != 0)
+            .unwrap_or_default()
+    }
+
+    fn byte_size(&self) -> usize {
+        self.blocks.len() * 2 * std::mem::size_of::<usize>()
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        MemoryBreakdown {
+            block_map_bytes: self.blocks.len() * std::mem::size_of::<usize>(),
+            bitmap_bytes: self.blocks.len() * std::mem::size_of::<usize>(),
+            overhead_bytes: std::mem::size_of_val(self),
+            ..Default::default()
+        }
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        let mut blocks = self.blocks.clone();
+        for (&index, &block) in &other.blocks {
+            *blocks.entry(index).or_default() |= block;
+        }
+
+        Self {
+            blocks,
+            max_key: self.max_key,
+        }
+    }
+
+    fn new_with_capacity(max_key: usize) -> Self {
+        Self::new(max_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_huge_key_space() {
+        let mut b = SparseBitmap::new(usize::MAX);
+        assert_eq!(b.byte_size(), 0);
+
+        b.set(usize::MAX, true);
+        b.set(1 << 50, true);
+        b.set(0, true);
+
+        assert!(b.get(usize::MAX));
+        assert!(b.get(1 << 50));
+        assert!(!b.get((1 << 50) + 1));
+        assert_eq!(b.byte_size(), 3 * 2 * 8);
+        assert_eq!(b.ones().collect::<Vec<_>>(), vec![0, 1 << 50, usize::MAX]);
+
+        b.set(1 << 50, false);
+        assert!(!b.get(1 << 50));
+        assert_eq!(b.byte_size(), 2 * 2 * 8);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_or(
+            a in prop::collection::vec(any::<usize>(), 0..20),
+            b in prop::collection::vec(any::<usize>(), 0..20),
+        ) {
+            let mut a_bitmap = SparseBitmap::new(usize::MAX);
+            let mut b_bitmap = SparseBitmap::new(usize::MAX);
+            let mut combined = SparseBitmap::new(usize::MAX);
+
+            for &v in &a {
+                a_bitmap.set(v, true);
+                combined.set(v, true);
+            }
+            for &v in &b {
+                b_bitmap.set(v, true);
+                combined.set(v, true);
+            }
+
+            let union = a_bitmap.or(&b_bitmap);
+            assert_eq!(union, combined);
+
+            let mut want = a.iter().chain(&b).copied().collect::<Vec<_>>();
+            want.sort_unstable();
+            want.dedup();
+            assert_eq!(union.ones().collect::<Vec<_>>(), want);
+            assert_eq!(union.count_ones(), want.len());
+        }
+    }
+}
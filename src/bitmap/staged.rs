@@ -0,0 +1,240 @@
+use crate::Bitmap;
+
+use super::{
+    compressed_bitmap::CompressedBitmap,
+    memory::{vec_bytes, MemoryBreakdown},
+    saturate_key,
+};
+
+/// The default number of keys buffered by a [`StagedBitmap`] before they are
+/// merged into the compressed bitmap.
+pub const DEFAULT_STAGING_CAPACITY: usize = 64;
+
+/// A [`CompressedBitmap`] that buffers writes, merging them into the
+/// compressed representation in sorted batches.
+///
+/// Inserting a key into a `CompressedBitmap` that requires a new block to be
+/// allocated moves all the blocks after it, making random inserts into a
+/// populated bitmap the worst-case write pattern. A `StagedBitmap` appends the
+/// set keys to a small pending buffer instead, and once full, sorts and merges
+/// them into the bitmap in a single pass using
+/// [`CompressedBitmap::set_many()`].
+///
+/// Reads check both the compressed bitmap and the (small) pending buffer, so
+/// buffered keys are always visible.
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, FilterSize, StagedBitmap};
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .with_bitmap::<StagedBitmap>()
+///     .size(FilterSize::KeyBytes3)
+///     .build();
+///
+/// filter.insert(&"batched");
+/// assert!(filter.contains(&"batched"));
+/// ```
+///
+/// Setting a key to `false` merges any pending keys before clearing it.
+#[derive(Debug, Clone)]
+pub struct StagedBitmap {
+    bitmap: CompressedBitmap,
+    pending: Vec<usize>,
+    capacity: usize,
+}
+
+impl StagedBitmap {
+    /// Construct a `StagedBitmap` for space to hold up to `max_key` number of
+    /// bits, buffering up to `capacity` keys before merging them into the
+    /// compressed bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_staging_capacity(max_key: usize, capacity: usize) -> Self {
+        assert!(capacity > 0, "staging capacity must be non-zero");
+
+        Self {
+            bitmap: CompressedBitmap::new(max_key),
+            pending: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Merge all pending keys into the compressed bitmap.
+    pub fn flush(&mut self) {
+        self.bitmap.set_many(&mut self.pending);
+        self.pending.clear();
+    }
+
+    /// Return the number of keys pending a merge into the compressed bitmap.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Merge any pending keys, and return the underlying
+    /// [`CompressedBitmap`].
+    pub fn into_inner(mut self) -> CompressedBitmap {
+        self.flush();
+        self.bitmap
+    }
+}
+
+impl Bitmap for StagedBitmap {
+    fn new_with_capacity(max_key: usize) -> Self {
+        Self::with_staging_capacity(max_key, DEFAULT_STAGING_CAPACITY)
+    }
+
+    fn set(&mut self, key: usize, value: bool) {
+        if !value {
+            self.flush();
+            self.bitmap.set(key, false);
+            return;
+        }
+
+        self.pending.push(saturate_key(key, self.bitmap.max_key()));
+        if self.pending.len() >= self.capacity {
+            self.flush();
+        }
+    }
+
+    fn set_many(&mut self, keys: &mut [usize]) {
+        // The keys are merged in a single pass, so merge the pending keys
+        // alongside them rather than buffering.
+        self.flush();
+        self.bitmap.set_many(keys);
+    }
+
+    fn get(&self, key: usize) -> bool {
+        self.bitmap.get(key)
+            || self
+                .pending
+                .contains(&saturate_key(key, self.bitmap.max_key()))
+    }
+
+    fn byte_size(&self) -> usize {
+        self.bitmap.size() + self.pending.capacity() * std::mem::size_of::<usize>()
+    }
+
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        let usage = self.bitmap.memory_breakdown();
+
+        // The pending keys are counted as bitmap storage.
+        let (pending_bytes, pending_spare) = vec_bytes(&self.pending, self.pending.capacity());
+
+        // The overhead of the inner bitmap is included in the size of self.
+        MemoryBreakdown {
+            bitmap_bytes: usage.bitmap_bytes + pending_bytes,
+            spare_bytes: usage.spare_bytes + pending_spare,
+            overhead_bytes: std::mem::size_of_val(self),
+            ..usage
+        }
+    }
+
+    fn warm(&self) {
+        self.bitmap.warm();
+    }
+
+    fn clone_empty(&self, max_key: usize) -> Self {
+        Self::with_staging_capacity(max_key, self.capacity)
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        let mut bitmap = self.bitmap.or(&other.bitmap);
+
+        let mut pending = self.pending.clone();
+        pending.extend_from_slice(&other.pending);
+        bitmap.set_many(&mut pending);
+
+        Self {
+            bitmap,
+            pending: Vec::new(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::VecBitmap;
+
+    const MAX_KEY: usize = 1028;
+
+    #[test]
+    fn test_flush() {
+        let mut b = StagedBitmap::with_staging_capacity(MAX_KEY, 2);
+
+        b.set(42, true);
+        assert_eq!(b.pending(), 1);
+        assert!(b.get(42));
+
+        // Filling the buffer merges the keys.
+        b.set(1, true);
+        assert_eq!(b.pending(), 0);
+        assert!(b.get(1));
+        assert!(b.get(42));
+
+        // Clearing a bit merges any pending keys first.
+        b.set(7, true);
+        b.set(7, false);
+        assert_eq!(b.pending(), 0);
+        assert!(!b.get(7));
+
+        let b = b.into_inner();
+        assert!(b.get(1));
+        assert!(b.get(42));
+    }
+
+    #[test]
+    #[should_panic(expected = "staging capacity must be non-zero")]
+    fn test_zero_capacity() {
+        StagedBitmap::with_staging_capacity(MAX_KEY, 0);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_insert_contains(
+            values in prop::collection::vec((0..MAX_KEY, any::<bool>()), 0..200),
+            capacity in 1_usize..20,
+        ) {
+            let mut b = StagedBitmap::with_staging_capacity(MAX_KEY, capacity);
+            let mut want = VecBitmap::new_with_capacity(MAX_KEY);
+
+            for &(v, value) in &values {
+                b.set(v, value);
+                want.set(v, value);
+            }
+
+            // Ensure all values are equal in the test range.
+            for i in 0..MAX_KEY {
+                assert_eq!(b.get(i), want.get(i));
+            }
+        }
+
+        #[test]
+        fn prop_or(
+            a in prop::collection::vec(0..MAX_KEY, 0..20),
+            b in prop::collection::vec(0..MAX_KEY, 0..20),
+        ) {
+            let mut a_bitmap = StagedBitmap::with_staging_capacity(MAX_KEY, 8);
+            let mut b_bitmap = StagedBitmap::with_staging_capacity(MAX_KEY, 8);
+
+            for v in a.iter() {
+                a_bitmap.set(*v, true);
+            }
+
+            for v in b.iter() {
+                b_bitmap.set(*v, true);
+            }
+
+            let union = a_bitmap.or(&b_bitmap);
+
+            for i in 0..MAX_KEY {
+                assert_eq!(union.get(i), a_bitmap.get(i) || b_bitmap.get(i));
+            }
+        }
+    }
+}
@@ -1,6 +1,11 @@
-use crate::Bitmap;
+use crate::{Bitmap, Error};
 
-use super::{bitmask_for_key, index_for_key};
+use super::{
+    bitmask_for_key, index_for_key,
+    memory::{vec_bytes, MemoryBreakdown},
+    pool::BufferPool,
+    saturate_key, simd, touch_pages, try_zeroed_vec,
+};
 
 /// A plain, heap-allocated, `O(1)` indexed bitmap.
 ///
@@ -10,19 +15,58 @@ use super::{bitmask_for_key, index_for_key};
 /// This type is fast for both read and writes, but trades additional space for
 /// the additional performance.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "allocative", derive(allocative::Allocative))]
 pub struct VecBitmap {
     bitmap: Vec<usize>,
     max_key: usize,
 }
 
 impl VecBitmap {
+    /// Construct a `VecBitmap` with capacity to hold at least `max_key` number
+    /// of bits, reusing a buffer from `pool`.
+    ///
+    /// See [`BufferPool`].
+    pub fn new_in(max_key: usize, pool: &mut BufferPool) -> Self {
+        let mut bitmap = pool.take();
+        bitmap.resize(index_for_key(max_key) + 1, 0);
+        Self { bitmap, max_key }
+    }
+
+    /// Return the buffer of this bitmap to `pool` for later reuse.
+    ///
+    /// See [`BufferPool`].
+    pub fn recycle(self, pool: &mut BufferPool) {
+        pool.put(self.bitmap);
+    }
+
+    #[cfg(feature = "fixedbitset")]
+    pub(crate) fn from_parts(bitmap: Vec<usize>, max_key: usize) -> Self {
+        Self { bitmap, max_key }
+    }
+
     pub(crate) fn into_parts(self) -> (Vec<usize>, usize) {
         (self.bitmap, self.max_key)
     }
+
+    /// Returns the number of non-zero blocks in the bitmap.
+    pub(crate) fn block_count(&self) -> usize {
+        self.bitmap.iter().filter(|&&v| v != 0).count()
+    }
+
+    /// Overwrite the block (word) at `block_index` with `block`.
+    pub(crate) fn set_block(&mut self, block_index: usize, block: usize) {
+        self.bitmap[block_index] = block;
+    }
+
+    /// Return the block (word) containing `key`.
+    pub(crate) fn block_for_key(&self, key: usize) -> usize {
+        self.bitmap[index_for_key(key)]
+    }
 }
 
 impl Bitmap for VecBitmap {
     fn set(&mut self, key: usize, value: bool) {
+        let key = saturate_key(key, self.max_key);
         let offset = index_for_key(key);
 
         if value {
@@ -33,6 +77,7 @@ impl Bitmap for VecBitmap {
     }
 
     fn get(&self, key: usize) -> bool {
+        let key = saturate_key(key, self.max_key);
         let offset = index_for_key(key);
 
         self.bitmap[offset] & bitmask_for_key(key) != 0
@@ -42,17 +87,27 @@ impl Bitmap for VecBitmap {
         self.bitmap.len() * std::mem::size_of::<usize>()
     }
 
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        let (bitmap_bytes, spare_bytes) = vec_bytes(&self.bitmap, self.bitmap.capacity());
+
+        MemoryBreakdown {
+            bitmap_bytes,
+            spare_bytes,
+            overhead_bytes: std::mem::size_of_val(self),
+            ..Default::default()
+        }
+    }
+
+    fn warm(&self) {
+        touch_pages(&self.bitmap);
+    }
+
     fn or(&self, other: &Self) -> Self {
         // Invariant: the block maps are of equal length, meaning the zipped
         // iters yield both sides to completion.
         assert_eq!(self.bitmap.len(), other.bitmap.len());
 
-        let bitmap = self
-            .bitmap
-            .iter()
-            .zip(&other.bitmap)
-            .map(|(a, b)| a | b)
-            .collect();
+        let bitmap = simd::or(&self.bitmap, &other.bitmap);
 
         Self {
             bitmap,
@@ -64,6 +119,12 @@ impl Bitmap for VecBitmap {
         let bitmap = vec![0; index_for_key(max_key) + 1];
         Self { bitmap, max_key }
     }
+
+    fn try_new_with_capacity(max_key: usize) -> Result<Self, Error> {
+        // SAFETY: a zeroed usize is valid.
+        let bitmap = unsafe { try_zeroed_vec(index_for_key(max_key).saturating_add(1))? };
+        Ok(Self { bitmap, max_key })
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,11 @@
-use crate::{bitmap::CompressedBitmap, FilterSize, VecBitmap};
+use crate::{
+    bitmap::CompressedBitmap, math, BudgetPolicy, BudgetedBloom2, Error, FilterSize,
+    InvariantError, MatchMode, MemoryBreakdown, ProbeScheme, SmallBitmap, VecBitmap,
+};
+use std::borrow::Borrow;
 use std::collections::hash_map::RandomState;
 use std::hash::{BuildHasher, Hash};
+use std::io::BufRead;
 use std::marker::PhantomData;
 // TODO(dom): AND, XOR, NOT + examples
 
@@ -16,15 +21,131 @@ pub trait Bitmap {
     /// number of bits.
     fn new_with_capacity(max_key: usize) -> Self;
 
+    /// Construct a new [`Bitmap`] impl as [`Bitmap::new_with_capacity()`]
+    /// does, returning [`Error::AllocationFailed`] instead of aborting the
+    /// process if the memory for the bitmap cannot be allocated.
+    ///
+    /// Implementations that allocate their storage when constructed override
+    /// this, while the default implementation calls
+    /// [`Bitmap::new_with_capacity()`].
+    fn try_new_with_capacity(max_key: usize) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self::new_with_capacity(max_key))
+    }
+
     /// Set bit indexed by `key` to `value`.
     fn set(&mut self, key: usize, value: bool);
 
     /// Return `true` if the given bit index was previously set to `true`.
     fn get(&self, key: usize) -> bool;
 
+    /// Set bit indexed by `key` to `true`.
+    ///
+    /// This is a convenience for [`Bitmap::set()`]; the bitmap types also
+    /// implement [`Index<usize>`](std::ops::Index) to read a bit with
+    /// `bitmap[key]`.
+    fn set_bit(&mut self, key: usize) {
+        self.set(key, true)
+    }
+
+    /// Set bit indexed by `key` to `false`.
+    ///
+    /// This is a convenience for [`Bitmap::set()`].
+    fn clear_bit(&mut self, key: usize) {
+        self.set(key, false)
+    }
+
+    /// Set each bit indexed by `keys` to `true`, reordering `keys` as
+    /// necessary.
+    ///
+    /// Implementations may override this to merge the keys into the bitmap
+    /// in a single pass.
+    fn set_many(&mut self, keys: &mut [usize]) {
+        for &key in keys.iter() {
+            self.set(key, true);
+        }
+    }
+
+    /// Write the result of [`Bitmap::get()`] for each of `keys` into the
+    /// corresponding index of `out`.
+    ///
+    /// Implementations may override this to provide a more efficient batch
+    /// lookup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keys` and `out` are not of equal length.
+    fn get_many(&self, keys: &[usize], out: &mut [bool]) {
+        assert_eq!(keys.len(), out.len());
+        for (key, out) in keys.iter().zip(out) {
+            *out = self.get(*key);
+        }
+    }
+
+    /// Return `true` if any of `keys` is set to `true`.
+    ///
+    /// Implementations may override this to visit the keys in an order that
+    /// minimises cache misses, returning as soon as a set key is found.
+    fn get_any(&self, keys: &[usize]) -> bool {
+        keys.iter().any(|&key| self.get(key))
+    }
+
+    /// Return `true` if all of `keys` are set to `true`.
+    ///
+    /// Implementations may override this to visit the keys in an order that
+    /// minimises cache misses, returning as soon as an unset key is found.
+    fn get_all(&self, keys: &[usize]) -> bool {
+        keys.iter().all(|&key| self.get(key))
+    }
+
     /// Return the size of the bitmap in bytes.
     fn byte_size(&self) -> usize;
 
+    /// Return a breakdown of the memory used by the bitmap.
+    ///
+    /// The default implementation reports [`Bitmap::byte_size()`] as the
+    /// size of the bitmap blocks.
+    fn memory_breakdown(&self) -> MemoryBreakdown {
+        MemoryBreakdown {
+            bitmap_bytes: self.byte_size(),
+            ..Default::default()
+        }
+    }
+
+    /// Read the memory backing the bitmap, so that subsequent reads do not
+    /// stall on page faults.
+    ///
+    /// The default implementation does nothing.
+    fn warm(&self) {}
+
+    /// Return a new, empty bitmap with the same configuration as `self`,
+    /// without copying any set bits.
+    ///
+    /// `max_key` is the capacity `self` was constructed with, as passed to
+    /// [`Bitmap::new_with_capacity()`]. The default implementation constructs
+    /// a new bitmap with [`Bitmap::new_with_capacity()`] - implementations
+    /// configured with more than their capacity (such as an observer) override
+    /// this to retain their configuration.
+    fn clone_empty(&self, max_key: usize) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new_with_capacity(max_key)
+    }
+
+    /// Verify the internal consistency of a bitmap restored from outside this
+    /// crate (such as by deserialising it), and that it was constructed to
+    /// hold `max_key` number of bits.
+    ///
+    /// The default implementation performs no checks, and is overridden by
+    /// implementations that cannot safely be read when inconsistent.
+    fn verify(&self, max_key: usize) -> Result<(), InvariantError> {
+        let _ = max_key;
+        Ok(())
+    }
+
     /// Return the bitwise OR of both `self` and `other`.`
     fn or(&self, other: &Self) -> Self;
 }
@@ -41,14 +162,41 @@ pub trait Bitmap {
 ///
 /// filter.insert(&"success!");
 /// ```
+///
+/// The hasher, bitmap backend and size are independent settings, and can be
+/// configured in any order - the bitmap is constructed once, when building
+/// the filter:
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::{BloomFilterBuilder, FilterSize, VecBitmap};
+///
+/// let filter = BloomFilterBuilder::default()
+///     .size(FilterSize::KeyBytes1)
+///     .with_bitmap::<VecBitmap>()
+///     .with_hasher(RandomState::default())
+///     .build::<u32>();
+///
+/// assert_eq!(filter.key_size(), FilterSize::KeyBytes1);
+/// ```
 pub struct BloomFilterBuilder<H, B>
 where
     H: BuildHasher,
     B: Bitmap,
 {
     hasher: H,
-    bitmap: B,
+    /// The bitmap data provided by the user, and the key size it was provided
+    /// for.
+    ///
+    /// If `None`, a new bitmap for `key_size` is constructed when building.
+    bitmap: Option<(B, FilterSize)>,
     key_size: FilterSize,
+    probe_scheme: ProbeScheme,
+
+    /// The expected number of items, from which the number of probes is
+    /// derived when set (see [`BloomFilterBuilder::expected_items()`]).
+    expected_items: Option<usize>,
+    match_mode: MatchMode,
 }
 
 /// Initialise a `BloomFilterBuilder` that unless changed, will construct a
@@ -60,11 +208,13 @@ where
 /// [SipHash]: https://131002.net/siphash/
 impl std::default::Default for BloomFilterBuilder<RandomState, CompressedBitmap> {
     fn default() -> BloomFilterBuilder<RandomState, CompressedBitmap> {
-        let size = FilterSize::KeyBytes2;
         BloomFilterBuilder {
             hasher: RandomState::default(),
-            bitmap: CompressedBitmap::new(key_size_to_bits(size)),
-            key_size: size,
+            bitmap: None,
+            key_size: FilterSize::KeyBytes2,
+            probe_scheme: ProbeScheme::Split,
+            expected_items: None,
+            match_mode: MatchMode::Any,
         }
     }
 }
@@ -82,54 +232,254 @@ where
     /// range produced by the [key size](FilterSize).
     ///
     /// Providing a `bitmap` instance that is non-empty can be used to restore
-    /// the state of a [`Bloom2`] instance (although using `serde` can achieve
-    /// this safely too).
+    /// the state of a [`Bloom2`] instance (although using `serde` or
+    /// [`Bloom2::from_parts()`] can achieve this safely too).
+    ///
+    /// The `bitmap` is retained regardless of the order of subsequent calls
+    /// to [`BloomFilterBuilder::size()`] - configuring a size that differs
+    /// from `key_size` is an error when building the filter (see
+    /// [`BloomFilterBuilder::try_build()`]).
     pub fn with_bitmap_data(self, bitmap: B, key_size: FilterSize) -> Self {
         // Invariant: reading the last bit succeeds, ensuring it has sufficient
         // capacity.
-        let _ = bitmap.get(key_size as usize);
+        if let Some(bits) = key_size.checked_max_bits() {
+            let _ = bitmap.get(bits - 1);
+        }
 
         Self {
-            bitmap,
+            bitmap: Some((bitmap, key_size)),
             key_size,
             ..self
         }
     }
 
+    /// Use a new, empty bitmap of type `U` as the bit storage for the bloom
+    /// filter, discarding any bitmap data previously provided with
+    /// [`BloomFilterBuilder::with_bitmap_data()`].
     pub fn with_bitmap<U>(self) -> BloomFilterBuilder<H, U>
     where
         U: Bitmap,
     {
         BloomFilterBuilder {
             hasher: self.hasher,
-            bitmap: U::new_with_capacity(key_size_to_bits(self.key_size)),
+            bitmap: None,
             key_size: self.key_size,
+            probe_scheme: self.probe_scheme,
+            expected_items: self.expected_items,
+            match_mode: self.match_mode,
         }
     }
 
-    /// Initialise the [`Bloom2`] instance with the provided parameters.
-    pub fn build<T: Hash>(self) -> Bloom2<H, B, T> {
-        Bloom2 {
-            hasher: self.hasher,
+    /// Use `hasher` to hash the items of the bloom filter, retaining all
+    /// other configuration (including any bitmap data provided with
+    /// [`BloomFilterBuilder::with_bitmap_data()`]).
+    ///
+    /// A filter restored from bitmap data MUST use a hasher producing the same
+    /// hashes as the hasher of the original filter.
+    pub fn with_hasher<U>(self, hasher: U) -> BloomFilterBuilder<U, B>
+    where
+        U: BuildHasher,
+    {
+        BloomFilterBuilder {
+            hasher,
             bitmap: self.bitmap,
             key_size: self.key_size,
-            _key_type: PhantomData,
+            probe_scheme: self.probe_scheme,
+            expected_items: self.expected_items,
+            match_mode: self.match_mode,
         }
     }
 
+    /// Initialise the [`Bloom2`] instance with the provided parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is inconsistent, or the bitmap cannot be
+    /// allocated, as described in [`BloomFilterBuilder::try_build()`].
+    pub fn build<T: Hash>(self) -> Bloom2<H, B, T> {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Initialise the [`Bloom2`] instance with the provided parameters,
+    /// returning an error if they are inconsistent.
+    ///
+    /// If the memory for the bitmap cannot be allocated (such as a dense
+    /// bitmap for a large [`FilterSize`]), [`Error::AllocationFailed`] is
+    /// returned rather than aborting the process (see
+    /// [`Bitmap::try_new_with_capacity()`]).
+    ///
+    /// If bitmap data was provided with
+    /// [`BloomFilterBuilder::with_bitmap_data()`] for a different
+    /// [`FilterSize`] than the one configured with
+    /// [`BloomFilterBuilder::size()`], [`Error::SizeMismatch`] is returned.
+    ///
+    /// ```rust
+    /// use bloom2::{BloomFilterBuilder, CompressedBitmap, Error, FilterSize};
+    ///
+    /// let bitmap = CompressedBitmap::new(256);
+    ///
+    /// let err = BloomFilterBuilder::default()
+    ///     .with_bitmap_data(bitmap, FilterSize::KeyBytes1)
+    ///     .size(FilterSize::KeyBytes2)
+    ///     .try_build::<u32>()
+    ///     .unwrap_err();
+    ///
+    /// assert_eq!(
+    ///     err,
+    ///     Error::SizeMismatch {
+    ///         bitmap: FilterSize::KeyBytes1,
+    ///         requested: FilterSize::KeyBytes2,
+    ///     }
+    /// );
+    /// ```
+    pub fn try_build<T: Hash>(self) -> Result<Bloom2<H, B, T>, Error> {
+        let probe_scheme = self.resolved_probe_scheme()?;
+        let match_mode = self.match_mode;
+        let (hasher, bitmap, key_size) = self.into_parts()?;
+
+        Ok(Bloom2 {
+            hasher,
+            bitmap,
+            key_size,
+            probe_scheme,
+            match_mode,
+            _key_type: PhantomData,
+        })
+    }
+
     /// Control the in-memory size and false-positive probability of the filter.
     ///
-    /// Setting the bitmap size replaces the current `Bitmap` instance with a
-    /// new `CompressedBitmap` of the appropriate size.
+    /// An empty bitmap of the appropriate size is constructed when building
+    /// the filter, unless bitmap data was provided with
+    /// [`BloomFilterBuilder::with_bitmap_data()`].
     ///
     /// See [`FilterSize`].
     pub fn size(self, size: FilterSize) -> Self {
         Self {
             key_size: size,
-            bitmap: B::new_with_capacity(key_size_to_bits(size)),
             ..self
         }
     }
+
+    /// Set the [`ProbeScheme`] used to map the hash of each item to the bits
+    /// of the filter, defaulting to [`ProbeScheme::Split`].
+    ///
+    /// ```rust
+    /// use bloom2::{BloomFilterBuilder, FilterSize, ProbeScheme};
+    ///
+    /// let mut filter = BloomFilterBuilder::default()
+    ///     .size(FilterSize::KeyBytes3)
+    ///     .probe_scheme(ProbeScheme::DoubleHashing { probes: 7 })
+    ///     .build();
+    ///
+    /// filter.insert(&"success!");
+    /// assert!(filter.contains(&"success!"));
+    /// ```
+    ///
+    /// A filter restored from bitmap data with
+    /// [`BloomFilterBuilder::with_bitmap_data()`] MUST use the scheme of the
+    /// original filter.
+    ///
+    /// Setting a scheme discards any previously configured
+    /// [`BloomFilterBuilder::expected_items()`].
+    pub fn probe_scheme(self, probe_scheme: ProbeScheme) -> Self {
+        Self {
+            probe_scheme,
+            expected_items: None,
+            ..self
+        }
+    }
+
+    /// Set the number of distinct items the filter is expected to hold,
+    /// selecting the number of probes that minimises the false positive
+    /// probability for the configured [`FilterSize`].
+    ///
+    /// The filter uses [`ProbeScheme::DoubleHashing`] with
+    /// [`math::optimal_k()`](crate::math::optimal_k) probes (at most 255),
+    /// rather than the number fixed by the key size for
+    /// [`ProbeScheme::Split`], and [`MatchMode::All`] - the optimal probe count
+    /// assumes a lookup only matches if all of its probed bits are set:
+    ///
+    /// ```rust
+    /// use bloom2::{BloomFilterBuilder, FilterSize, MatchMode, ProbeScheme};
+    ///
+    /// let filter = BloomFilterBuilder::default()
+    ///     .size(FilterSize::KeyBits(20))
+    ///     .expected_items(100_000)
+    ///     .build::<u64>();
+    ///
+    /// assert_eq!(filter.probe_scheme(), ProbeScheme::DoubleHashing { probes: 7 });
+    /// assert_eq!(filter.match_mode(), MatchMode::All);
+    /// ```
+    ///
+    /// The probe count is computed when building, so the order of this call
+    /// and [`BloomFilterBuilder::size()`] does not matter. A subsequent call
+    /// to [`BloomFilterBuilder::probe_scheme()`] replaces the derived scheme,
+    /// and a subsequent call to [`BloomFilterBuilder::match_mode()`] with
+    /// [`MatchMode::Any`] discards the expected items.
+    pub fn expected_items(self, n: usize) -> Self {
+        Self {
+            expected_items: Some(n),
+            match_mode: MatchMode::All,
+            ..self
+        }
+    }
+
+    /// Set the [`MatchMode`] used to answer membership queries, defaulting to
+    /// [`MatchMode::Any`].
+    ///
+    /// [`MatchMode::All`] requires every probed bit of an item to be set, as
+    /// in a classical bloom filter - see [`MatchMode`] for migrating an
+    /// existing filter.
+    ///
+    /// Setting [`MatchMode::Any`] discards any previously configured
+    /// [`BloomFilterBuilder::expected_items()`], as more probes increase the
+    /// false positive probability when any probe matches.
+    pub fn match_mode(self, match_mode: MatchMode) -> Self {
+        let expected_items = match match_mode {
+            MatchMode::Any => None,
+            MatchMode::All => self.expected_items,
+        };
+
+        Self {
+            match_mode,
+            expected_items,
+            ..self
+        }
+    }
+
+    /// Return the configured [`ProbeScheme`], or the optimal scheme for the
+    /// [expected items](BloomFilterBuilder::expected_items) if set.
+    fn resolved_probe_scheme(&self) -> Result<ProbeScheme, Error> {
+        Ok(match self.expected_items {
+            Some(n) => {
+                let probes = math::optimal_k(try_key_size_to_bits(self.key_size)?, n);
+                ProbeScheme::DoubleHashing {
+                    probes: probes.min(u8::MAX as usize) as u8,
+                }
+            }
+            None => self.probe_scheme,
+        })
+    }
+
+    /// Validate the configuration, returning the hasher, bitmap and key size
+    /// of the filter.
+    fn into_parts(self) -> Result<(H, B, FilterSize), Error> {
+        let max_key = try_key_size_to_bits(self.key_size)?;
+
+        let bitmap = match self.bitmap {
+            Some((_, size)) if size != self.key_size => {
+                return Err(Error::SizeMismatch {
+                    bitmap: size,
+                    requested: self.key_size,
+                })
+            }
+            Some((bitmap, _)) => bitmap,
+            None => B::try_new_with_capacity(max_key)?,
+        };
+
+        Ok((self.hasher, bitmap, self.key_size))
+    }
 }
 
 impl<H> BloomFilterBuilder<H, CompressedBitmap>
@@ -141,17 +491,207 @@ where
     ///
     /// [2 byte key]: crate::FilterSize::KeyBytes2
     pub fn hasher(hasher: H) -> Self {
-        let size = FilterSize::KeyBytes2;
         Self {
             hasher,
-            bitmap: CompressedBitmap::new(key_size_to_bits(size)),
-            key_size: size,
+            bitmap: None,
+            key_size: FilterSize::KeyBytes2,
+            probe_scheme: ProbeScheme::Split,
+            expected_items: None,
+            match_mode: MatchMode::Any,
+        }
+    }
+
+    /// Initialise a [`BudgetedBloom2`] instance with the provided parameters,
+    /// limiting the memory used by the bitmap to `budget` bytes.
+    ///
+    /// When an insert would exceed the budget, `policy` is applied. See
+    /// [`BudgetPolicy`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is inconsistent, as described in
+    /// [`BloomFilterBuilder::try_build()`], or if a [`ProbeScheme`] other than
+    /// [`ProbeScheme::Split`] is configured.
+    pub fn build_with_budget<T: Hash>(
+        self,
+        budget: usize,
+        policy: BudgetPolicy,
+    ) -> BudgetedBloom2<H, T> {
+        assert_eq!(
+            self.resolved_probe_scheme()
+                .unwrap_or_else(|e| panic!("{}", e)),
+            ProbeScheme::Split,
+            "budgeted filters only support the split probe scheme"
+        );
+        let (hasher, bitmap, key_size) = self.into_parts().unwrap_or_else(|e| panic!("{}", e));
+        BudgetedBloom2::new(hasher, key_size, bitmap, budget, policy)
+    }
+
+    /// Initialise the [`Bloom2`] instance with the provided parameters,
+    /// populated with the precomputed 64-bit `hashes` of its items.
+    ///
+    /// The [`Hash`] implementation of the items is never called - each of
+    /// `hashes` is used as if it were the output of the configured hasher, so
+    /// items stored as content fingerprints can be loaded directly. The
+    /// derived bitmap keys are sorted and bulk-loaded in a single pass (see
+    /// [`Bloom2::build_from_hashes()`]), honouring the configured size and
+    /// [`ProbeScheme`]:
+    ///
+    /// ```rust
+    /// use std::hash::BuildHasher;
+    /// use std::collections::hash_map::RandomState;
+    /// use bloom2::{BloomFilterBuilder, FilterSize};
+    ///
+    /// let hasher = RandomState::default();
+    /// let hashes = (0..1000).map(|v| hasher.hash_one(v)).collect::<Vec<_>>();
+    ///
+    /// let b = BloomFilterBuilder::hasher(hasher)
+    ///     .size(FilterSize::KeyBytes3)
+    ///     .build_from_hashes::<i32, _>(hashes);
+    ///
+    /// assert!(b.contains(&42));
+    /// ```
+    ///
+    /// Any bitmap data provided with
+    /// [`BloomFilterBuilder::with_bitmap_data()`] is retained, and the
+    /// `hashes` are added to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is inconsistent, as described in
+    /// [`BloomFilterBuilder::try_build()`].
+    pub fn build_from_hashes<T, I>(self, hashes: I) -> Bloom2<H, CompressedBitmap, T>
+    where
+        T: Hash,
+        I: IntoIterator<Item = u64>,
+    {
+        let mut filter = self.build::<T>();
+
+        let (scheme, key_size) = (filter.probe_scheme, filter.key_size);
+        let mut keys = hashes
+            .into_iter()
+            .flat_map(|hash| scheme.keys(hash, key_size))
+            .collect::<Vec<_>>();
+
+        if filter.bitmap.block_count() == 0 {
+            keys.sort_unstable();
+            filter.bitmap = CompressedBitmap::from_sorted_keys(key_size_to_bits(key_size), keys);
+        } else {
+            filter.bitmap.set_many(&mut keys);
+        }
+
+        filter
+    }
+}
+
+impl<H> BloomFilterBuilder<H, SmallBitmap>
+where
+    H: BuildHasher,
+{
+    /// Initialise a `BloomFilterBuilder` that will construct a small `Bloom2`
+    /// instance using a [1 byte key] stored in a [`SmallBitmap`], and use the
+    /// specified hasher.
+    ///
+    /// The resulting filter never allocates, and each bit is read and written
+    /// with a single mask operation, making it suitable for small, short-lived
+    /// filters.
+    ///
+    /// ```rust
+    /// use std::collections::hash_map::RandomState;
+    /// use bloom2::BloomFilterBuilder;
+    ///
+    /// let mut filter = BloomFilterBuilder::small(RandomState::default()).build();
+    ///
+    /// filter.insert(&"success!");
+    /// assert!(filter.contains(&"success!"));
+    /// ```
+    ///
+    /// Changing the [size](BloomFilterBuilder::size) of the resulting builder
+    /// to anything other than [`FilterSize::KeyBytes1`] panics when building
+    /// the filter.
+    ///
+    /// [1 byte key]: crate::FilterSize::KeyBytes1
+    pub fn small(hasher: H) -> Self {
+        Self {
+            hasher,
+            bitmap: None,
+            key_size: FilterSize::KeyBytes1,
+            probe_scheme: ProbeScheme::Split,
+            expected_items: None,
+            match_mode: MatchMode::Any,
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<H> BloomFilterBuilder<H, crate::BytesBitmap>
+where
+    H: BuildHasher,
+{
+    /// Initialise a `BloomFilterBuilder` that unless changed, will construct a
+    /// `Bloom2` instance using a [2 byte key] stored in a
+    /// [`BytesBitmap`](crate::BytesBitmap), and use the specified hasher.
+    ///
+    /// The bitmap is allocated up-front, sized by the configured
+    /// [size](BloomFilterBuilder::size) (see
+    /// [`BytesBitmap::byte_len()`](crate::BytesBitmap::byte_len)), and its
+    /// frozen bytes can be persisted or sent over the network as-is:
+    ///
+    /// ```rust
+    /// use std::collections::hash_map::RandomState;
+    /// use bloom2::{BloomFilterBuilder, BytesBitmap, FilterSize};
+    ///
+    /// let mut filter = BloomFilterBuilder::bytes(RandomState::default())
+    ///     .size(FilterSize::KeyBytes1)
+    ///     .build();
+    ///
+    /// filter.insert(&"success!");
+    /// assert!(filter.contains(&"success!"));
+    ///
+    /// let bitmap = filter.into_parts().bitmap;
+    /// assert_eq!(bitmap.freeze().len(), BytesBitmap::byte_len(FilterSize::KeyBytes1));
+    /// ```
+    ///
+    /// [2 byte key]: crate::FilterSize::KeyBytes2
+    pub fn bytes(hasher: H) -> Self {
+        Self {
+            hasher,
+            bitmap: None,
+            key_size: FilterSize::KeyBytes2,
+            probe_scheme: ProbeScheme::Split,
+            expected_items: None,
+            match_mode: MatchMode::Any,
         }
     }
 }
 
-fn key_size_to_bits(k: FilterSize) -> usize {
-    2_usize.pow(8 * k as u32)
+/// Return the number of bits addressable by a filter of size `k`, as
+/// [`FilterSize::max_bits()`] does.
+///
+/// # Panics
+///
+/// Panics if `k` is invalid or its key space is not addressable on this
+/// platform (see [`try_key_size_to_bits()`]) rather than wrapping - fallible
+/// constructors validate the size first.
+pub(crate) fn key_size_to_bits(k: FilterSize) -> usize {
+    try_key_size_to_bits(k).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Return the number of bits addressable by a filter of size `k`, or
+/// [`Error::KeySpaceTooLarge`] if the keys of `k` cannot be represented as a
+/// `usize` on this platform.
+///
+/// A [`FilterSize::KeyBits`] outside of 1 to 64 returns
+/// [`Error::InvalidKeyBits`].
+pub(crate) fn try_key_size_to_bits(k: FilterSize) -> Result<usize, Error> {
+    if let FilterSize::KeyBits(key_bits @ (0 | 65..)) = k {
+        return Err(Error::InvalidKeyBits {
+            key_bits: key_bits as u32,
+        });
+    }
+
+    k.checked_max_bits()
+        .ok_or(Error::KeySpaceTooLarge { key_size: k })
 }
 
 /// A fast, memory efficient, sparse bloom filter.
@@ -180,19 +720,33 @@ fn key_size_to_bits(k: FilterSize) -> usize {
 /// for a meaningful duration of time, this is almost always worth the
 /// marginally increased insert latency. When testing performance, be sure to
 /// use a release build - there's a significant performance difference!
+///
+/// ## Serialisation
+///
+/// If the `serde` feature is enabled, a `Bloom2` is serialised with a header
+/// recording its [`FilterSize`], the number of probes per item, and a
+/// fingerprint of the hasher (covering both the hashing algorithm and its
+/// seed). Deserialising a filter validates this header against the hasher the
+/// filter is deserialised with, returning an error rather than a filter that
+/// would silently answer lookups incorrectly.
 #[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "allocative", derive(allocative::Allocative))]
+#[cfg_attr(
+    feature = "allocative",
+    allocative(bound = "H: BuildHasher, B: Bitmap + allocative::Allocative, T")
+)]
 pub struct Bloom2<H, B, T>
 where
     H: BuildHasher,
     B: Bitmap,
 {
-    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "allocative", allocative(skip))]
     hasher: H,
     bitmap: B,
     key_size: FilterSize,
+    probe_scheme: ProbeScheme,
+    match_mode: MatchMode,
 
-    #[cfg_attr(feature = "serde", serde(skip))]
     _key_type: PhantomData<T>,
 }
 
@@ -272,11 +826,85 @@ where
     pub fn insert(&mut self, data: &'_ T) {
         // Generate a hash (u64) value for data and split the u64 hash into
         // several smaller values to use as unique indexes in the bitmap.
-        self.hasher
-            .hash_one(data)
-            .to_be_bytes()
-            .chunks(self.key_size as usize)
-            .for_each(|chunk| self.bitmap.set(bytes_to_usize_key(chunk), true));
+        self.insert_hash(self.hasher.hash_one(data));
+    }
+
+    /// Insert `v` into the filter, using `v` itself as the hash of the item
+    /// and bypassing the hasher.
+    ///
+    /// This is intended for callers that maintain their own 64-bit
+    /// fingerprints of items, and `v` SHOULD be the output of a good hash
+    /// function - the bits of `v` are used as bitmap keys directly, so
+    /// sequential or otherwise clustered values produce a much higher false
+    /// positive rate.
+    ///
+    /// ```rust
+    /// use bloom2::Bloom2;
+    ///
+    /// let mut b: Bloom2<_, _, u64> = Bloom2::default();
+    /// b.insert_u64(0x9e37_79b9_7f4a_7c15);
+    ///
+    /// assert!(b.contains_u64(0x9e37_79b9_7f4a_7c15));
+    /// ```
+    ///
+    /// Values inserted with `insert_u64` are only found by
+    /// [`Bloom2::contains_u64()`], and items inserted with
+    /// [`Bloom2::insert()`] are only found by [`Bloom2::contains()`].
+    pub fn insert_u64(&mut self, v: u64) {
+        self.insert_hash(v);
+    }
+
+    /// Set the bitmap keys for `hash`.
+    pub(crate) fn insert_hash(&mut self, hash: u64) {
+        self.probe_scheme
+            .keys(hash, self.key_size)
+            .for_each(|key| self.bitmap.set(key, true));
+    }
+
+    /// Insert the hash digest on each line read from `reader`, returning the
+    /// number of digests inserted.
+    ///
+    /// Each line MUST contain the hex encoded output of the hasher for an
+    /// item (the result of `hasher.hash_one(item)`), such as those written
+    /// by an external process, for subsequent calls to
+    /// [`Bloom2::contains()`] to return the expected result. Empty lines are
+    /// skipped.
+    ///
+    /// ```rust
+    /// use std::collections::hash_map::RandomState;
+    /// use std::hash::BuildHasher;
+    /// use bloom2::{Bloom2, BloomFilterBuilder};
+    /// # fn main() -> std::io::Result<()> {
+    ///
+    /// let hasher = RandomState::default();
+    /// let digests = format!("{:x}\n{:x}\n", hasher.hash_one(1), hasher.hash_one(2));
+    ///
+    /// let mut b: Bloom2<_, _, i32> = BloomFilterBuilder::hasher(hasher).build();
+    /// assert_eq!(b.insert_digests(digests.as_bytes())?, 2);
+    ///
+    /// assert!(b.contains(&2));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from `reader` fails, or an
+    /// [`InvalidData`](std::io::ErrorKind::InvalidData) error if a line is
+    /// not a valid hex encoded 64-bit value. Digests read before the error
+    /// remain inserted.
+    pub fn insert_digests<R: BufRead>(&mut self, reader: R) -> std::io::Result<usize> {
+        for_each_line(reader, |line| {
+            let hash = std::str::from_utf8(line)
+                .ok()
+                .and_then(|v| u64::from_str_radix(v.trim(), 16).ok())
+                .ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex digest")
+                })?;
+
+            self.insert_hash(hash);
+            Ok(())
+        })
     }
 
     /// Checks if `data` exists in the filter.
@@ -284,165 +912,2088 @@ where
     /// If `contains` returns true, `hash` has **probably** been inserted
     /// previously. If `contains` returns false, `hash` has **definitely not**
     /// been inserted into the filter.
+    ///
+    /// The probes of `data` are combined according to the configured
+    /// [`MatchMode`].
     pub fn contains(&self, data: &'_ T) -> bool {
         // Generate a hash (u64) value for data
-        self.hasher
-            .hash_one(data)
-            .to_be_bytes()
-            .chunks(self.key_size as usize)
-            .any(|chunk| self.bitmap.get(bytes_to_usize_key(chunk)))
+        self.contains_hash(self.hasher.hash_one(data))
     }
 
-    /// Union two [`Bloom2`] instances (of identical configuration), returning
-    /// the merged combination of both.
+    /// Checks if `v` exists in the filter, using `v` itself as the hash of the
+    /// item (see [`Bloom2::insert_u64()`]).
+    pub fn contains_u64(&self, v: u64) -> bool {
+        self.contains_hash(v)
+    }
+
+    /// Returns the bit positions in the bitmap that inserting `data` sets,
+    /// and that [`Bloom2::contains()`] checks for `data`, in probe order.
     ///
-    /// The returned filter will return "true" for all calls to
-    /// [`Bloom2::contains()`] for all values that would return true for one (or
-    /// both) of the inputs, and will return "false" for all values that return
-    /// false from both inputs.
+    /// ```rust
+    /// use bloom2::{Bloom2, Bitmap};
     ///
-    /// # Panics
+    /// let mut b = Bloom2::default();
+    /// b.insert(&"hello");
     ///
-    /// This method panics if the two [`Bloom2`] instances have different
-    /// configuration.
-    pub fn union(&mut self, other: &Self) {
-        assert_eq!(self.key_size, other.key_size);
-        self.bitmap = self.bitmap.or(&other.bitmap);
+    /// for bit in b.probe_indexes(&"hello") {
+    ///     assert!(b.bitmap().get(bit));
+    /// }
+    /// ```
+    ///
+    /// The positions are derived from the hash of `data` by the
+    /// [`ProbeScheme`] of the filter, and may contain duplicates. This is
+    /// useful for checking the bit layout against another implementation, or
+    /// for finding the items sharing bits with a false positive.
+    pub fn probe_indexes(&self, data: &'_ T) -> impl Iterator<Item = usize> {
+        self.probe_scheme
+            .keys(self.hasher.hash_one(data), self.key_size)
+    }
+
+    /// Checks if the bitmap keys for `hash` exist in the filter.
+    ///
+    /// The keys are collected so the bitmap can order the lookups (see
+    /// [`Bitmap::get_any()`] and [`Bitmap::get_all()`]).
+    pub(crate) fn contains_hash(&self, hash: u64) -> bool {
+        let keys = self.probe_scheme.keys(hash, self.key_size);
+
+        let mut buf = [0; PROBE_BUFFER_LEN];
+        if keys.len() > buf.len() {
+            return self.probe_bitmap(&keys.collect::<Vec<_>>());
+        }
+
+        let n = keys.len();
+        for (slot, key) in buf.iter_mut().zip(keys) {
+            *slot = key;
+        }
+        self.probe_bitmap(&buf[..n])
+    }
+
+    /// Combine the bits of `keys` according to the [`MatchMode`].
+    fn probe_bitmap(&self, keys: &[usize]) -> bool {
+        match self.match_mode {
+            MatchMode::Any => self.bitmap.get_any(keys),
+            MatchMode::All => self.bitmap.get_all(keys),
+        }
     }
 
-    /// Return the byte size of this filter.
-    pub fn byte_size(&mut self) -> usize {
-        self.bitmap.byte_size()
+    /// Checks if each of `items` exists in the filter, writing the result of
+    /// [`Bloom2::contains()`] for each item into the corresponding index of
+    /// `out`.
+    ///
+    /// The bitmap lookups for all `items` are performed as a single batch (see
+    /// [`Bitmap::get_many()`]), which for a [`CompressedBitmap`] amortises the
+    /// cost of locating blocks across the batch, raising lookup throughput.
+    ///
+    /// ```rust
+    /// use bloom2::Bloom2;
+    ///
+    /// let mut b = Bloom2::default();
+    /// b.insert(&"hello");
+    ///
+    /// let mut out = [false; 2];
+    /// b.contains_batch(&["hello", "world"], &mut out);
+    /// # assert!(out[0]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `items` and `out` are not of equal length.
+    pub fn contains_batch(&self, items: &[T], out: &mut [bool]) {
+        assert_eq!(items.len(), out.len());
+
+        let keys = items
+            .iter()
+            .flat_map(|v| {
+                self.probe_scheme
+                    .keys(self.hasher.hash_one(v), self.key_size)
+            })
+            .collect::<Vec<_>>();
+
+        let mut hits = vec![false; keys.len()];
+        self.bitmap.get_many(&keys, &mut hits);
+
+        // Each item produced the same number of keys.
+        let probes = keys.len() / items.len().max(1);
+        for (out, hits) in out.iter_mut().zip(hits.chunks(probes.max(1))) {
+            *out = match self.match_mode {
+                MatchMode::Any => hits.iter().any(|v| *v),
+                MatchMode::All => hits.iter().all(|v| *v),
+            };
+        }
+    }
+
+    /// Union two [`Bloom2`] instances (of identical configuration), returning
+    /// the merged combination of both.
+    ///
+    /// The returned filter will return "true" for all calls to
+    /// [`Bloom2::contains()`] for all values that would return true for one (or
+    /// both) of the inputs, and will return "false" for all values that return
+    /// false from both inputs.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the two [`Bloom2`] instances have different
+    /// configuration - see [`Bloom2::try_union()`].
+    pub fn union(&mut self, other: &Self) {
+        if let Err(e) = self.try_union(other) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Union two [`Bloom2`] instances as [`Bloom2::union()`] does, returning
+    /// an error and leaving `self` unchanged if the two filters have different
+    /// [`FilterSize`] values, or were constructed with a different hashing
+    /// algorithm or hasher seed.
+    pub fn try_union(&mut self, other: &Self) -> Result<(), Error> {
+        Params::new(
+            &self.hasher,
+            self.key_size,
+            self.probe_scheme,
+            self.match_mode,
+        )
+        .check(&Params::new(
+            &other.hasher,
+            other.key_size,
+            other.probe_scheme,
+            other.match_mode,
+        ))?;
+        self.bitmap = self.bitmap.or(&other.bitmap);
+        Ok(())
+    }
+
+    /// Return the byte size of this filter.
+    pub fn byte_size(&mut self) -> usize {
+        self.bitmap.byte_size()
+    }
+
+    /// Return a breakdown of the memory used by this filter.
+    ///
+    /// See [`MemoryBreakdown`].
+    pub fn memory_breakdown(&self) -> MemoryBreakdown {
+        let mut usage = self.bitmap.memory_breakdown();
+        usage.overhead_bytes += std::mem::size_of::<Self>() - std::mem::size_of::<B>();
+        usage
+    }
+
+    /// Read every page of memory backing the filter, faulting it in ahead of
+    /// the first lookups.
+    ///
+    /// The first lookups against a large filter that was freshly
+    /// deserialised, or backed by a memory-mapped file (such as a
+    /// [`FrozenBytesBitmap`](crate::FrozenBytesBitmap) wrapping an mmap), stall
+    /// on page faults as the operating system lazily loads the pages they
+    /// touch. Calling `warm()` before serving traffic takes these faults up
+    /// front:
+    ///
+    /// ```rust
+    /// use bloom2::Bloom2;
+    ///
+    /// let mut b = Bloom2::default();
+    /// b.insert(&"bananas");
+    ///
+    /// b.warm();
+    /// assert!(b.contains(&"bananas"));
+    /// ```
+    ///
+    /// This reads one word per page, and is `O(n)` in the size of the bitmap.
+    /// See [`Bitmap::warm()`].
+    pub fn warm(&self) {
+        self.bitmap.warm();
+    }
+
+    /// Return a reference to the hasher used to hash inserted values.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Return a reference to the underlying bitmap storage.
+    pub fn bitmap(&self) -> &B {
+        &self.bitmap
+    }
+
+    /// Return a mutable reference to the underlying bitmap storage.
+    ///
+    /// Clearing bits in the bitmap will cause [`Bloom2::contains()`] to
+    /// return false negatives for the affected entries.
+    pub fn bitmap_mut(&mut self) -> &mut B {
+        &mut self.bitmap
+    }
+
+    /// Return the [`FilterSize`] of this filter.
+    pub fn key_size(&self) -> FilterSize {
+        self.key_size
+    }
+
+    /// Return the [`ProbeScheme`] of this filter.
+    pub fn probe_scheme(&self) -> ProbeScheme {
+        self.probe_scheme
+    }
+
+    /// Return the [`MatchMode`] used to answer membership queries.
+    pub fn match_mode(&self) -> MatchMode {
+        self.match_mode
+    }
+
+    /// Set the [`MatchMode`] used to answer membership queries.
+    ///
+    /// The match mode does not change the bits set by inserts, so an existing
+    /// filter can be switched between modes. The match mode is recorded when
+    /// serialising a filter and restored when deserialising it, and filters
+    /// using different modes cannot be merged (see [`Bloom2::try_union()`]).
+    pub fn set_match_mode(&mut self, match_mode: MatchMode) {
+        self.match_mode = match_mode;
+    }
+
+    /// Return a new, empty filter with the same hasher, [`FilterSize`],
+    /// [`ProbeScheme`] and bitmap configuration as this filter.
+    ///
+    /// Unlike cloning the filter and clearing it, no populated blocks of the
+    /// bitmap are copied (see [`Bitmap::clone_empty()`]), making this cheap
+    /// for starting the next window of a time-windowed filter:
+    ///
+    /// ```rust
+    /// use bloom2::Bloom2;
+    ///
+    /// let mut current = Bloom2::default();
+    /// current.insert(&"bananas");
+    ///
+    /// let next = current.clone_empty();
+    /// assert!(!next.contains(&"bananas"));
+    /// assert_eq!(next.key_size(), current.key_size());
+    /// ```
+    pub fn clone_empty(&self) -> Self
+    where
+        H: Clone,
+    {
+        Self {
+            hasher: self.hasher.clone(),
+            bitmap: self.bitmap.clone_empty(key_size_to_bits(self.key_size)),
+            key_size: self.key_size,
+            probe_scheme: self.probe_scheme,
+            match_mode: self.match_mode,
+            _key_type: PhantomData,
+        }
+    }
+
+    /// Decompose the filter into the hasher, bitmap storage and configuration
+    /// it was constructed with.
+    ///
+    /// See [`Bloom2::from_parts()`] to reassemble a filter.
+    pub fn into_parts(self) -> FilterParts<H, B> {
+        FilterParts {
+            hasher: self.hasher,
+            bitmap: self.bitmap,
+            key_size: self.key_size,
+            probe_scheme: self.probe_scheme,
+            match_mode: self.match_mode,
+        }
+    }
+}
+
+impl<H, T> Bloom2<H, CompressedBitmap, T>
+where
+    H: BuildHasher,
+{
+    /// Construct a filter containing the items that produced `hashes`.
+    ///
+    /// Each of the `hashes` MUST be the output of `hasher` for an item (the
+    /// result of `hasher.hash_one(item)`) for subsequent calls to
+    /// [`Bloom2::contains()`] to return the expected result.
+    ///
+    /// The filter is built with a single append-only pass over the sorted
+    /// bitmap keys derived from `hashes` (see
+    /// [`CompressedBitmap::from_sorted_keys()`]), making offline bulk builds
+    /// significantly faster than repeated calls to [`Bloom2::insert()`]. The
+    /// `hashes` do not need to be provided in sorted order.
+    ///
+    /// The filter uses the default [`ProbeScheme::Split`].
+    ///
+    /// ```rust
+    /// use std::hash::BuildHasher;
+    /// use std::collections::hash_map::RandomState;
+    /// use bloom2::{Bloom2, CompressedBitmap, FilterSize};
+    ///
+    /// let hasher = RandomState::default();
+    /// let hashes = (0..1000).map(|v| hasher.hash_one(v)).collect::<Vec<_>>();
+    ///
+    /// let b: Bloom2<_, CompressedBitmap, i32> =
+    ///     Bloom2::build_from_hashes(hasher, FilterSize::KeyBytes3, hashes);
+    ///
+    /// assert!(b.contains(&42));
+    /// ```
+    pub fn build_from_hashes<I>(hasher: H, key_size: FilterSize, hashes: I) -> Self
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let mut keys = hashes
+            .into_iter()
+            .flat_map(|hash| ProbeScheme::Split.keys(hash, key_size))
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+
+        Self {
+            hasher,
+            bitmap: CompressedBitmap::from_sorted_keys(key_size_to_bits(key_size), keys),
+            key_size,
+            probe_scheme: ProbeScheme::Split,
+            match_mode: MatchMode::Any,
+            _key_type: PhantomData,
+        }
+    }
+
+    /// Reassemble a filter from the parts returned by
+    /// [`Bloom2::into_parts()`].
+    ///
+    /// The bitmap is checked for consistency with the key size (see
+    /// [`CompressedBitmap::verify_invariants()`]), returning
+    /// [`Error::InvalidBitmap`] if it was not constructed for a filter of that
+    /// size, or is corrupt. [`Error::KeySpaceTooLarge`] is returned if the key
+    /// size cannot be addressed on this platform.
+    ///
+    /// ```rust
+    /// use bloom2::{Bloom2, BloomFilterBuilder, CompressedBitmap, ProbeScheme};
+    ///
+    /// let mut b: Bloom2<_, CompressedBitmap, _> = BloomFilterBuilder::default()
+    ///     .probe_scheme(ProbeScheme::DoubleHashing { probes: 4 })
+    ///     .build();
+    /// b.insert(&"hello");
+    ///
+    /// let parts = b.into_parts();
+    /// let b = Bloom2::from_parts(parts).expect("valid parts");
+    ///
+    /// assert!(b.contains(&"hello"));
+    /// ```
+    ///
+    /// As with [`Bloom2::build_from_hashes()`], the hasher MUST produce the
+    /// same hashes as the hasher of the original filter for subsequent calls
+    /// to [`Bloom2::contains()`] to return the expected result.
+    pub fn from_parts(parts: FilterParts<H, CompressedBitmap>) -> Result<Self, Error> {
+        let FilterParts {
+            hasher,
+            bitmap,
+            key_size,
+            probe_scheme,
+            match_mode,
+        } = parts;
+
+        bitmap
+            .verify_invariants_for(try_key_size_to_bits(key_size)?)
+            .map_err(Error::InvalidBitmap)?;
+
+        Ok(Self {
+            hasher,
+            bitmap,
+            key_size,
+            probe_scheme,
+            match_mode,
+            _key_type: PhantomData,
+        })
+    }
+
+    /// Decompress the bitmap into a [`VecBitmap`], trading memory for faster
+    /// inserts.
+    ///
+    /// This is the inverse of [`Bloom2::compress()`], allowing a filter to
+    /// move between the write-optimised and memory-optimised representations
+    /// over its lifetime. The decompressed bitmap requires `O(n)` space for
+    /// the [`FilterSize`] of the filter, regardless of the number of entries.
+    pub fn decompress(self) -> Bloom2<H, VecBitmap, T> {
+        let bitmap = self.bitmap.decompress(key_size_to_bits(self.key_size));
+        self.with_bitmap(bitmap)
+    }
+
+    /// Shrink an over-provisioned filter to the smaller `size` without the
+    /// original items, by folding the bitmap (see [`CompressedBitmap::fold()`]).
+    ///
+    /// Folding ORs the upper half of the bit space onto the lower half (once
+    /// for each bit removed from the key size), so every inserted item remains
+    /// present, while the load of the filter increases. Folding a filter by a
+    /// factor of `f` multiplies the fraction of bits set by up to `f`, and the
+    /// false positive probability grows accordingly - the returned
+    /// [`FoldImpact`] reports the estimated probability before and after:
+    ///
+    /// ```rust
+    /// use bloom2::{BloomFilterBuilder, FilterSize, ProbeScheme};
+    ///
+    /// let mut b = BloomFilterBuilder::default()
+    ///     .size(FilterSize::KeyBytes3)
+    ///     .probe_scheme(ProbeScheme::DoubleHashing { probes: 4 })
+    ///     .build();
+    /// b.insert(&"bananas");
+    ///
+    /// // Halve the filter, and then quarter it.
+    /// let impact = b.fold_to(FilterSize::KeyBits(23)).unwrap();
+    /// assert!(impact.fpp_after >= impact.fpp_before);
+    /// b.fold_to(FilterSize::KeyBits(21)).unwrap();
+    ///
+    /// assert_eq!(b.key_size(), FilterSize::KeyBits(21));
+    /// assert!(b.contains(&"bananas"));
+    /// ```
+    ///
+    /// Only filters using [`ProbeScheme::DoubleHashing`] can be folded, as
+    /// each of its bit indexes is reduced modulo the size of the filter - the
+    /// indexes of the [`ProbeScheme::Split`] and [`ProbeScheme::SplitLsb`]
+    /// schemes are instead taken from different bits of the hash for each
+    /// size, so [`Error::FoldUnsupported`]
+    /// is returned. [`Error::InvalidFoldSize`] is returned if `size` is larger
+    /// than the filter, or smaller than 64 bits.
+    pub fn fold_to(&mut self, size: FilterSize) -> Result<FoldImpact, Error> {
+        if let ProbeScheme::Split | ProbeScheme::SplitLsb = self.probe_scheme {
+            return Err(Error::FoldUnsupported {
+                scheme: self.probe_scheme,
+            });
+        }
+
+        let (from, to) = (key_size_to_bits(self.key_size), try_key_size_to_bits(size)?);
+        if to > from || to < u64::BITS as usize {
+            return Err(Error::InvalidFoldSize {
+                from: self.key_size,
+                to: size,
+            });
+        }
+
+        let (scheme, match_mode) = (self.probe_scheme, self.match_mode);
+        let fpp = |size, bits, bitmap: &CompressedBitmap| {
+            estimated_fpp(
+                size,
+                scheme,
+                match_mode,
+                bitmap.count_ones() as f64 / bits as f64,
+            )
+        };
+        let fpp_before = fpp(self.key_size, from, &self.bitmap);
+
+        self.bitmap = self.bitmap.fold_to_bits(to);
+        self.key_size = size;
+
+        Ok(FoldImpact {
+            fpp_before,
+            fpp_after: fpp(size, to, &self.bitmap),
+        })
+    }
+
+    /// Convert the bitmap into a new instance of the bitmap type `B2`, by
+    /// setting each set bit in a new, empty `B2`.
+    ///
+    /// ```rust
+    /// use bloom2::{AlignedBitmap, Bloom2};
+    ///
+    /// let mut b = Bloom2::default();
+    /// b.insert(&"hello");
+    ///
+    /// let b = b.convert::<AlignedBitmap>();
+    /// assert!(b.contains(&"hello"));
+    /// ```
+    pub fn convert<B2>(self) -> Bloom2<H, B2, T>
+    where
+        B2: Bitmap,
+    {
+        let mut bitmap = B2::new_with_capacity(key_size_to_bits(self.key_size));
+        for key in self.bitmap.ones() {
+            bitmap.set(key, true);
+        }
+        self.with_bitmap(bitmap)
+    }
+
+    /// Replace the bitmap of this filter with `bitmap`, retaining the rest of
+    /// the configuration.
+    fn with_bitmap<B2>(self, bitmap: B2) -> Bloom2<H, B2, T>
+    where
+        B2: Bitmap,
+    {
+        Bloom2 {
+            hasher: self.hasher,
+            bitmap,
+            key_size: self.key_size,
+            probe_scheme: self.probe_scheme,
+            match_mode: self.match_mode,
+            _key_type: PhantomData,
+        }
+    }
+
+    /// Minimise the memory usage of this instance by shrinking the
+    /// underlying vectors, discarding their excess capacity.
+    pub fn shrink_to_fit(&mut self) {
+        self.bitmap.shrink_to_fit();
+    }
+
+    /// Verify the internal consistency of the underlying bitmap.
+    ///
+    /// See [`CompressedBitmap::verify_invariants()`].
+    pub fn verify_invariants(&self) -> Result<(), InvariantError> {
+        self.bitmap
+            .verify_invariants_for(key_size_to_bits(self.key_size))
+    }
+
+    /// Returns the estimated number of distinct entries this filter can hold
+    /// before the estimated false positive probability exceeds `fpp`.
+    ///
+    /// ```rust
+    /// use bloom2::{Bloom2, CompressedBitmap};
+    ///
+    /// let mut b: Bloom2<_, CompressedBitmap, _> = Bloom2::default();
+    ///
+    /// let capacity = b.capacity_for_fpp(0.01);
+    /// assert_eq!(b.remaining_capacity_for_fpp(0.01), capacity);
+    ///
+    /// b.insert(&"hello");
+    /// assert!(b.remaining_capacity_for_fpp(0.01) < capacity);
+    /// ```
+    ///
+    /// A `fpp` of 1 or more returns `usize::MAX`.
+    pub fn capacity_for_fpp(&self, fpp: f64) -> usize {
+        capacity_for_fpp(self.key_size, self.probe_scheme, self.match_mode, fpp)
+    }
+
+    /// Returns the estimated number of additional distinct entries that can
+    /// be inserted before the estimated false positive probability exceeds
+    /// `fpp`, allowing an ingestion process to roll over to a new filter
+    /// before the false positive probability becomes unacceptable.
+    ///
+    /// See [`Bloom2::capacity_for_fpp()`].
+    pub fn remaining_capacity_for_fpp(&self, fpp: f64) -> usize {
+        let used = estimated_entries(self.key_size, self.probe_scheme, self.bitmap.count_ones());
+        self.capacity_for_fpp(fpp).saturating_sub(used)
+    }
+
+    /// Remove all entries from the filter in `O(1)` time, retaining the
+    /// allocated memory for reuse.
+    ///
+    /// See [`CompressedBitmap::clear()`].
+    pub fn clear(&mut self) {
+        self.bitmap.clear();
+    }
+}
+
+/// Print a summary of the filter configuration, contents, memory usage and the
+/// estimated false positive probability at the current load.
+///
+/// ```rust
+/// use bloom2::Bloom2;
+///
+/// let mut b = Bloom2::default();
+/// b.insert(&"hello");
+///
+/// println!("{}", b);
+/// // Bloom2 { key size: KeyBytes2, bits set: 4 / 65536, blocks: 4, memory: 104 bytes, estimated fpp: 0.0002441 }
+/// ```
+impl<H, T> std::fmt::Display for Bloom2<H, CompressedBitmap, T>
+where
+    H: BuildHasher,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits = key_size_to_bits(self.key_size);
+        let ones = self.bitmap.count_ones();
+
+        write!(
+            f,
+            "Bloom2 {{ key size: {:?}, bits set: {} / {}, blocks: {}, memory: {} bytes, estimated fpp: {:.7} }}",
+            self.key_size,
+            ones,
+            bits,
+            self.bitmap.block_count(),
+            self.bitmap.size(),
+            estimated_fpp(
+                self.key_size,
+                self.probe_scheme,
+                self.match_mode,
+                ones as f64 / bits as f64
+            )
+        )
+    }
+}
+
+impl<H, T> Bloom2<H, VecBitmap, T>
+where
+    H: BuildHasher,
+{
+    /// Compress the bitmap to reduce memory consumption.
+    ///
+    /// The compressed representation is optimised for reads, but subsequent
+    /// inserts will be slower. This reduction is `O(n)` in time, and is
+    /// performed in place, reusing the memory of the uncompressed bitmap.
+    pub fn compress(self) -> Bloom2<H, CompressedBitmap, T> {
+        Bloom2::from(self)
+    }
+}
+
+impl<H, B, T> Bloom2<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash + Borrow<str>,
+{
+    /// Insert each line read from `reader` into the filter, returning the
+    /// number of lines inserted.
+    ///
+    /// Lines are read into a single reused buffer and hashed in place, so no
+    /// allocations are made per line. The trailing newline (`\n` or `\r\n`)
+    /// is not part of the inserted value, and empty lines are skipped.
+    ///
+    /// ```rust
+    /// use bloom2::Bloom2;
+    /// # fn main() -> std::io::Result<()> {
+    ///
+    /// let mut b: Bloom2<_, _, String> = Bloom2::default();
+    ///
+    /// let input = "bananas\nplatanos\r\n";
+    /// assert_eq!(b.insert_lines(input.as_bytes())?, 2);
+    ///
+    /// assert!(b.contains(&"platanos".to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from `reader` fails, or an
+    /// [`InvalidData`](std::io::ErrorKind::InvalidData) error if a line is
+    /// not valid UTF-8. Lines read before the error remain inserted.
+    pub fn insert_lines<R: BufRead>(&mut self, reader: R) -> std::io::Result<usize> {
+        for_each_line(reader, |line| {
+            let line = std::str::from_utf8(line)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+            // T hashes identically to str, as required by Borrow.
+            self.insert_hash(self.hasher.hash_one(line));
+            Ok(())
+        })
+    }
+}
+
+/// Call `f` with each non-empty line read from `reader`, excluding the line
+/// terminator, returning the number of lines visited.
+fn for_each_line<R, F>(mut reader: R, mut f: F) -> std::io::Result<usize>
+where
+    R: BufRead,
+    F: FnMut(&[u8]) -> std::io::Result<()>,
+{
+    let mut buf = Vec::new();
+    let mut n = 0;
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(n);
+        }
+
+        let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+
+        f(line)?;
+        n += 1;
+    }
+}
+
+/// The parts of a [`Bloom2`] filter, as returned by [`Bloom2::into_parts()`].
+///
+/// A filter reassembled from its parts (see [`Bloom2::from_parts()`]) must
+/// use the same [`ProbeScheme`] and [`MatchMode`] to answer lookups for the
+/// items it holds.
+#[derive(Debug, Clone)]
+pub struct FilterParts<H, B> {
+    /// The hasher of the filter.
+    pub hasher: H,
+    /// The bitmap storage of the filter.
+    pub bitmap: B,
+    /// The size of the filter.
+    pub key_size: FilterSize,
+    /// The probe scheme mapping a hash to bitmap keys.
+    pub probe_scheme: ProbeScheme,
+    /// How the probes of an item are combined to answer a lookup.
+    pub match_mode: MatchMode,
+}
+
+/// The estimated false positive probability of a filter before and after
+/// [folding](Bloom2::fold_to).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FoldImpact {
+    /// The estimated false positive probability before folding.
+    pub fpp_before: f64,
+    /// The estimated false positive probability after folding.
+    pub fpp_after: f64,
+}
+
+/// Estimate the probability of a false positive lookup for a filter of
+/// `key_size` using `scheme` with `load` (0 to 1) of the bits set.
+///
+/// A lookup reports a hit if any of the keys for the hash are set when using
+/// [`MatchMode::Any`], or if all of them are set when using
+/// [`MatchMode::All`] (see [`Bloom2::contains()`]).
+pub(crate) fn estimated_fpp(
+    key_size: FilterSize,
+    scheme: ProbeScheme,
+    match_mode: MatchMode,
+    load: f64,
+) -> f64 {
+    let probes = scheme.probes(key_size) as i32;
+    match match_mode {
+        MatchMode::Any => 1.0 - (1.0 - load).powi(probes),
+        MatchMode::All => load.powi(probes),
+    }
+}
+
+/// Estimate the number of distinct entries a filter of `key_size` using
+/// `scheme` and `match_mode` can hold before the
+/// [estimated false positive probability](estimated_fpp) exceeds `fpp`.
+///
+/// This inverts [`estimated_fpp()`] to find the maximum load, and then
+/// applies the estimator of [`estimated_entries()`] to that load.
+pub(crate) fn capacity_for_fpp(
+    key_size: FilterSize,
+    scheme: ProbeScheme,
+    match_mode: MatchMode,
+    fpp: f64,
+) -> usize {
+    if fpp >= 1.0 {
+        return usize::MAX;
+    }
+
+    let bits = key_size_to_bits(key_size) as f64;
+    let probes = scheme.probes(key_size) as f64;
+
+    // The load at which the fpp is reached.
+    let fpp = fpp.max(0.0);
+    let load = match match_mode {
+        MatchMode::Any => 1.0 - (1.0 - fpp).powf(1.0 / probes),
+        MatchMode::All => fpp.powf(1.0 / probes),
+    };
+
+    (-(bits / probes) * (1.0 - load).ln()).floor() as usize
+}
+
+/// Estimate the number of distinct entries inserted into a filter of
+/// `key_size` using `scheme` with `ones` bits set.
+///
+/// Uses the estimator `n = -(m / k) * ln(1 - X / m)` for a filter of `m` bits
+/// with `k` probes and `X` bits set, saturating at `usize::MAX` when every bit
+/// is set.
+pub(crate) fn estimated_entries(key_size: FilterSize, scheme: ProbeScheme, ones: usize) -> usize {
+    let bits = key_size_to_bits(key_size) as f64;
+    let probes = scheme.probes(key_size) as f64;
+
+    (-(bits / probes) * (1.0 - ones as f64 / bits).ln()).round() as usize
+}
+
+/// The maximum number of probes collected on the stack for a lookup, with
+/// more probes collected into a [`Vec`].
+const PROBE_BUFFER_LEN: usize = 16;
+
+/// A fixed value hashed to fingerprint a hasher.
+pub(crate) const HASHER_FINGERPRINT_VALUE: &str = "bloom2";
+
+/// The parameters that must match for two filters to be merged, or for a
+/// serialised filter to be interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Params {
+    pub(crate) key_size: FilterSize,
+    pub(crate) scheme: ProbeScheme,
+    pub(crate) probes: usize,
+    /// The [`MatchMode`] of the filter, defaulting to [`MatchMode::Any`] for
+    /// filters serialised before it was recorded.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) match_mode: MatchMode,
+    /// The hash of [`HASHER_FINGERPRINT_VALUE`], identifying both the hashing
+    /// algorithm and its seed.
+    pub(crate) hasher: u64,
+}
+
+impl Params {
+    pub(crate) fn new<H: BuildHasher>(
+        hasher: &H,
+        key_size: FilterSize,
+        scheme: ProbeScheme,
+        match_mode: MatchMode,
+    ) -> Self {
+        Self {
+            key_size,
+            scheme,
+            probes: scheme.probes(key_size),
+            match_mode,
+            hasher: hasher.hash_one(HASHER_FINGERPRINT_VALUE),
+        }
+    }
+
+    /// Return an error if `theirs` is incompatible with `self`.
+    pub(crate) fn check(&self, theirs: &Self) -> Result<(), Error> {
+        if self.key_size != theirs.key_size {
+            return Err(Error::KeySizeMismatch {
+                ours: self.key_size,
+                theirs: theirs.key_size,
+            });
+        }
+        if self.scheme != theirs.scheme {
+            return Err(Error::ProbeSchemeMismatch {
+                ours: self.scheme,
+                theirs: theirs.scheme,
+            });
+        }
+        if self.probes != theirs.probes {
+            return Err(Error::ProbeCountMismatch {
+                expected: self.probes,
+                got: theirs.probes,
+            });
+        }
+        if self.match_mode != theirs.match_mode {
+            return Err(Error::MatchModeMismatch {
+                ours: self.match_mode,
+                theirs: theirs.match_mode,
+            });
+        }
+        if self.hasher != theirs.hasher {
+            return Err(Error::HasherMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// The serialised representation of a [`Bloom2`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Repr<B> {
+    header: Params,
+    bitmap: B,
+}
+
+#[cfg(feature = "serde")]
+impl<H, B, T> serde::Serialize for Bloom2<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Repr {
+            header: Params::new(
+                &self.hasher,
+                self.key_size,
+                self.probe_scheme,
+                self.match_mode,
+            ),
+            bitmap: &self.bitmap,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, H, B, T> serde::Deserialize<'de> for Bloom2<H, B, T>
+where
+    H: BuildHasher + Default,
+    B: Bitmap + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = Repr::<B>::deserialize(deserializer)?;
+        let hasher = H::default();
+
+        let header = repr.header;
+        Params::new(&hasher, header.key_size, header.scheme, header.match_mode)
+            .check(&header)
+            .map_err(serde::de::Error::custom)?;
+
+        let max_key = try_key_size_to_bits(header.key_size).map_err(serde::de::Error::custom)?;
+        repr.bitmap
+            .verify(max_key)
+            .map_err(Error::InvalidBitmap)
+            .map_err(serde::de::Error::custom)?;
+
+        Ok(Self {
+            hasher,
+            bitmap: repr.bitmap,
+            key_size: header.key_size,
+            probe_scheme: header.scheme,
+            match_mode: header.match_mode,
+            _key_type: PhantomData,
+        })
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<H, T> Bloom2<H, crate::FrozenBytesBitmap, T>
+where
+    H: BuildHasher,
+{
+    /// Construct a filter sharing the frozen bytes of a
+    /// [`BytesBitmap`](crate::BytesBitmap) without copying them, such as a
+    /// buffer received from the network.
+    ///
+    /// ```rust
+    /// use std::collections::hash_map::RandomState;
+    /// use bloom2::{Bloom2, BloomFilterBuilder, BytesBitmap, FilterParts, FilterSize};
+    ///
+    /// let mut b: Bloom2<_, BytesBitmap, _> = BloomFilterBuilder::hasher(RandomState::new())
+    ///     .with_bitmap()
+    ///     .size(FilterSize::KeyBytes2)
+    ///     .build();
+    /// b.insert(&"hello");
+    ///
+    /// let parts = b.into_parts();
+    /// let b = Bloom2::from_frozen_bytes(FilterParts {
+    ///     hasher: parts.hasher,
+    ///     bitmap: parts.bitmap.freeze(),
+    ///     key_size: parts.key_size,
+    ///     probe_scheme: parts.probe_scheme,
+    ///     match_mode: parts.match_mode,
+    /// })
+    /// .unwrap();
+    /// assert!(b.contains(&"hello"));
+    /// ```
+    ///
+    /// As with [`Bloom2::from_parts()`], the hasher, probe scheme and match
+    /// mode MUST match those of the original filter for subsequent calls to
+    /// [`Bloom2::contains()`] to return the expected result.
+    ///
+    /// Returns [`Error::ByteLength`] if the bitmap bytes are not the length of
+    /// a bitmap for a filter of `key_size`.
+    pub fn from_frozen_bytes(parts: FilterParts<H, bytes::Bytes>) -> Result<Self, Error> {
+        let FilterParts {
+            hasher,
+            bitmap,
+            key_size,
+            probe_scheme,
+            match_mode,
+        } = parts;
+
+        let want = crate::BytesBitmap::byte_len(key_size);
+        if bitmap.len() != want {
+            return Err(Error::ByteLength {
+                want,
+                got: bitmap.len(),
+            });
+        }
+
+        Ok(Self {
+            hasher,
+            bitmap: crate::FrozenBytesBitmap::from_bytes(bitmap),
+            key_size,
+            probe_scheme,
+            match_mode,
+            _key_type: PhantomData,
+        })
+    }
+}
+
+/// Union-reduce an iterator of filters (of identical configuration) into a
+/// single filter, such as when combining per-shard filters:
+///
+/// ```rust
+/// use std::collections::hash_map::DefaultHasher;
+/// use std::hash::BuildHasherDefault;
+/// use bloom2::{Bloom2, BloomFilterBuilder, CompressedBitmap};
+///
+/// let hasher = BuildHasherDefault::<DefaultHasher>::default();
+/// let shards = (0..4).map(|i| {
+///     let mut b = BloomFilterBuilder::hasher(hasher.clone()).build();
+///     b.insert(&i);
+///     b
+/// });
+///
+/// let combined: Bloom2<_, CompressedBitmap, i32> = shards.sum();
+///
+/// assert!((0..4).all(|i| combined.contains(&i)));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the iterator is empty, or if the filters have different
+/// configuration (see [`Bloom2::union()`]). Summing into a
+/// `Result<Bloom2, Error>` returns an error instead.
+impl<H, B, T> std::iter::Sum for Bloom2<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.collect()
+    }
+}
+
+/// Union-reduce an iterator of filters, as the [`Sum`](std::iter::Sum)
+/// implementation of [`Bloom2`] does.
+///
+/// # Panics
+///
+/// Panics if the iterator is empty, or if the filters have different
+/// configuration (see [`Bloom2::union()`]).
+impl<H, B, T> std::iter::FromIterator<Bloom2<H, B, T>> for Bloom2<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    fn from_iter<I: IntoIterator<Item = Bloom2<H, B, T>>>(iter: I) -> Self {
+        iter.into_iter()
+            .collect::<Result<Self, Error>>()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Union-reduce an iterator of filters (of identical configuration) into a
+/// single filter, returning [`Error::NoFilters`] if the iterator is empty, or
+/// the error of [`Bloom2::try_union()`] for the first filter with a different
+/// configuration.
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::{Bloom2, CompressedBitmap, Error};
+///
+/// let shards: Vec<Bloom2<RandomState, CompressedBitmap, i32>> = vec![];
+/// let combined: Result<Bloom2<_, _, _>, Error> = shards.into_iter().sum();
+///
+/// assert_eq!(combined.unwrap_err(), Error::NoFilters);
+/// ```
+impl<H, B, T> std::iter::Sum<Bloom2<H, B, T>> for Result<Bloom2<H, B, T>, Error>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    fn sum<I: Iterator<Item = Bloom2<H, B, T>>>(iter: I) -> Self {
+        iter.collect()
+    }
+}
+
+/// Union-reduce an iterator of filters, as the [`Sum`](std::iter::Sum)
+/// implementation of `Result<Bloom2, Error>` does.
+impl<H, B, T> std::iter::FromIterator<Bloom2<H, B, T>> for Result<Bloom2<H, B, T>, Error>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    fn from_iter<I: IntoIterator<Item = Bloom2<H, B, T>>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+        let mut out = iter.next().ok_or(Error::NoFilters)?;
+        for filter in iter {
+            out.try_union(&filter)?;
+        }
+        Ok(out)
+    }
+}
+
+impl<H, T> From<Bloom2<H, VecBitmap, T>> for Bloom2<H, CompressedBitmap, T>
+where
+    H: BuildHasher,
+{
+    fn from(v: Bloom2<H, VecBitmap, T>) -> Self {
+        Self {
+            hasher: v.hasher,
+            bitmap: CompressedBitmap::from(v.bitmap),
+            key_size: v.key_size,
+            probe_scheme: v.probe_scheme,
+            match_mode: v.match_mode,
+            _key_type: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "bytes")]
+    use crate::bitmap::BytesBitmap;
+    use crate::SparseBitmap;
+
+    use proptest::prelude::*;
+    use quickcheck_macros::quickcheck;
+
+    use std::collections::hash_map::RandomState;
+    use std::{
+        cell::RefCell,
+        collections::HashSet,
+        hash::{BuildHasherDefault, Hasher},
+    };
+
+    #[derive(Debug, Clone, Default)]
+    struct MockHasher {
+        return_hash: u64,
+    }
+
+    impl Hasher for MockHasher {
+        fn write(&mut self, _bytes: &[u8]) {}
+        fn finish(&self) -> u64 {
+            self.return_hash
+        }
+    }
+
+    impl BuildHasher for MockHasher {
+        type Hasher = Self;
+        fn build_hasher(&self) -> MockHasher {
+            self.clone()
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct MockBitmap {
+        set_calls: Vec<(usize, bool)>,
+        get_calls: RefCell<Vec<usize>>,
+    }
+    impl Bitmap for MockBitmap {
+        fn set(&mut self, key: usize, value: bool) {
+            self.set_calls.push((key, value))
+        }
+        fn get(&self, key: usize) -> bool {
+            self.get_calls.borrow_mut().push(key);
+            false
+        }
+        fn byte_size(&self) -> usize {
+            42
+        }
+
+        fn or(&self, _other: &Self) -> Self {
+            unreachable!()
+        }
+
+        fn new_with_capacity(_max_key: usize) -> Self {
+            Self::default()
+        }
+    }
+
+    fn new_test_bloom<T: Hash>() -> Bloom2<MockHasher, MockBitmap, T> {
+        Bloom2 {
+            hasher: MockHasher::default(),
+            bitmap: MockBitmap::default(),
+            key_size: FilterSize::KeyBytes1,
+            probe_scheme: ProbeScheme::Split,
+            match_mode: MatchMode::Any,
+            _key_type: PhantomData,
+        }
+    }
+
+    #[test]
+    fn test_default() {
+        let mut b = Bloom2::default();
+        assert_eq!(b.key_size, FilterSize::KeyBytes2);
+
+        b.insert(&42);
+        assert!(b.contains(&42));
+    }
+
+    #[test]
+    fn test_try_union() {
+        let mut a = BloomFilterBuilder::default()
+            .size(FilterSize::KeyBytes2)
+            .build();
+        a.insert(&1);
+
+        // Filter size mismatch.
+        let b = BloomFilterBuilder::hasher(a.hasher().clone())
+            .size(FilterSize::KeyBytes3)
+            .build();
+        assert_eq!(
+            a.try_union(&b),
+            Err(Error::KeySizeMismatch {
+                ours: FilterSize::KeyBytes2,
+                theirs: FilterSize::KeyBytes3,
+            })
+        );
+
+        // Hasher seed mismatch.
+        let mut b = BloomFilterBuilder::hasher(RandomState::new())
+            .size(FilterSize::KeyBytes2)
+            .build();
+        b.insert(&2);
+        let want = a.clone();
+        assert_eq!(a.try_union(&b), Err(Error::HasherMismatch));
+        assert_eq!(a.bitmap, want.bitmap);
+
+        // Matching configuration.
+        let mut b = BloomFilterBuilder::hasher(a.hasher().clone())
+            .size(FilterSize::KeyBytes2)
+            .build();
+        b.insert(&2);
+        assert_eq!(a.try_union(&b), Ok(()));
+        assert!(a.contains(&1));
+        assert!(a.contains(&2));
+    }
+
+    #[test]
+    fn test_probe_indexes() {
+        let mut b = new_test_bloom::<i32>();
+        b.hasher.return_hash = 0x0102_0304_0506_0708;
+
+        assert_eq!(
+            b.probe_indexes(&1).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+
+        // The positions are those set by an insert, for each scheme.
+        for scheme in [
+            ProbeScheme::Split,
+            ProbeScheme::SplitLsb,
+            ProbeScheme::DoubleHashing { probes: 5 },
+        ] {
+            b.probe_scheme = scheme;
+            b.bitmap.set_calls.clear();
+            b.insert(&1);
+
+            let want = b.probe_indexes(&1).map(|v| (v, true)).collect::<Vec<_>>();
+            assert_eq!(b.bitmap.set_calls, want, "scheme {:?}", scheme);
+        }
+    }
+
+    #[quickcheck]
+    fn test_insert_u64(values: Vec<u64>) {
+        let mut b: Bloom2<_, CompressedBitmap, u64> =
+            BloomFilterBuilder::hasher(MockHasher::default())
+                .size(FilterSize::KeyBytes2)
+                .build();
+        for &v in &values {
+            b.insert_u64(v);
+        }
+
+        for &v in &values {
+            assert!(b.contains_u64(v));
+
+            // The value is used as the hash, so matches an item that hashes
+            // to the same value.
+            let mut want: Bloom2<_, CompressedBitmap, u64> =
+                BloomFilterBuilder::hasher(MockHasher { return_hash: v })
+                    .size(FilterSize::KeyBytes2)
+                    .build();
+            want.insert(&v);
+            assert!(want.contains_u64(v));
+            assert!(ProbeScheme::Split
+                .keys(v, FilterSize::KeyBytes2)
+                .all(|key| want.bitmap.get(key) && b.bitmap.get(key)));
+        }
+    }
+
+    #[quickcheck]
+    fn test_wide_key_sizes(values: Vec<u64>) {
+        for &key_size in &[
+            FilterSize::KeyBytes6,
+            FilterSize::KeyBytes7,
+            FilterSize::KeyBytes8,
+        ] {
+            let mut b: Bloom2<_, SparseBitmap, _> = BloomFilterBuilder::default()
+                .with_bitmap::<SparseBitmap>()
+                .size(key_size)
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 7 })
+                .build();
+            assert_eq!(b.byte_size(), 0);
+
+            for v in &values {
+                b.insert(v);
+            }
+            for v in &values {
+                assert!(b.contains(v));
+            }
+
+            // Memory is proportional to the populated blocks only.
+            assert!(b.byte_size() <= values.len() * 7 * 16);
+        }
+    }
+
+    #[quickcheck]
+    fn test_wide_key_sizes_compressed(values: Vec<u64>) {
+        for &key_size in &[
+            FilterSize::KeyBytes6,
+            FilterSize::KeyBytes7,
+            FilterSize::KeyBytes8,
+        ] {
+            let mut b = BloomFilterBuilder::default()
+                .size(key_size)
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 7 })
+                .build();
+            assert_eq!(b.byte_size(), size_of::<CompressedBitmap>());
+
+            for v in &values {
+                b.insert(v);
+            }
+            for v in &values {
+                assert!(b.contains(v));
+            }
+            assert_eq!(b.verify_invariants(), Ok(()));
+
+            // Each populated block costs at most a block, a block map word, and
+            // a top map word and its index.
+            let usage = b.memory_breakdown();
+            assert!(usage.used() <= values.len() * 7 * 4 * size_of::<usize>());
+        }
+    }
+
+    #[quickcheck]
+    fn test_probe_scheme_double_hashing(values: Vec<u32>, probes: u8) {
+        let scheme = ProbeScheme::DoubleHashing { probes };
+        let mut b = BloomFilterBuilder::default()
+            .size(FilterSize::KeyBytes3)
+            .probe_scheme(scheme)
+            .build();
+        for v in &values {
+            b.insert(v);
+        }
+
+        assert_eq!(b.probe_scheme(), scheme);
+        for v in &values {
+            assert!(b.contains(v));
+        }
+
+        // The scheme is retained when changing the bitmap representation.
+        let b = b.decompress().compress();
+        assert_eq!(b.probe_scheme(), scheme);
+
+        // Filters with different schemes cannot be merged.
+        let mut other = BloomFilterBuilder::hasher(b.hasher().clone())
+            .size(FilterSize::KeyBytes3)
+            .build();
+        assert_eq!(
+            other.try_union(&b),
+            Err(Error::ProbeSchemeMismatch {
+                ours: ProbeScheme::Split,
+                theirs: scheme,
+            })
+        );
+    }
+
+    #[quickcheck]
+    fn test_expected_items(n: usize) {
+        let size = FilterSize::KeyBytes3;
+        let want = ProbeScheme::DoubleHashing {
+            probes: crate::math::optimal_k(key_size_to_bits(size), n).min(255) as u8,
+        };
+
+        // The order of size() and expected_items() does not matter.
+        let b = BloomFilterBuilder::default()
+            .expected_items(n)
+            .size(size)
+            .build::<u32>();
+        assert_eq!(b.probe_scheme(), want);
+
+        assert_eq!(b.match_mode(), MatchMode::All);
+
+        // An explicit scheme replaces the derived scheme.
+        let b = BloomFilterBuilder::default()
+            .size(size)
+            .expected_items(n)
+            .probe_scheme(ProbeScheme::Split)
+            .build::<u32>();
+        assert_eq!(b.probe_scheme(), ProbeScheme::Split);
+
+        // Matching any probe discards the expected items.
+        let b = BloomFilterBuilder::default()
+            .size(size)
+            .expected_items(n)
+            .match_mode(MatchMode::Any)
+            .build::<u32>();
+        assert_eq!(b.probe_scheme(), ProbeScheme::Split);
+        assert_eq!(b.match_mode(), MatchMode::Any);
+    }
+
+    #[test]
+    fn test_expected_items_fpp() {
+        const N: u32 = 100_000;
+
+        let b = {
+            let mut b =
+                BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                    .size(FilterSize::KeyBits(20))
+                    .expected_items(N as usize)
+                    .build::<u32>();
+            for v in 0..N {
+                b.insert(&v);
+            }
+            b
+        };
+
+        // The measured false positive rate is close to the theoretical
+        // optimum for the size and item count.
+        let fp = (N..2 * N).filter(|v| b.contains(v)).count();
+        let got = fp as f64 / N as f64;
+        let want =
+            crate::math::theoretical_fpp(key_size_to_bits(FilterSize::KeyBits(20)), 7, N as usize);
+        assert!(got < want * 1.5, "fpp {} exceeds {}", got, want);
+
+        // Matching any of the probes is far worse.
+        let mut any = b.clone();
+        any.set_match_mode(MatchMode::Any);
+        let fp = (N..2 * N).filter(|v| any.contains(v)).count();
+        assert!(fp as f64 / N as f64 > 0.9);
+    }
+
+    #[test]
+    fn test_expected_items_probes() {
+        let probes = |size, n| match BloomFilterBuilder::default()
+            .size(size)
+            .expected_items(n)
+            .build::<u32>()
+            .probe_scheme()
+        {
+            ProbeScheme::DoubleHashing { probes } => probes,
+            ProbeScheme::Split | ProbeScheme::SplitLsb => unreachable!(),
+        };
+
+        assert_eq!(probes(FilterSize::KeyBytes2, 5000), 9);
+        assert_eq!(probes(FilterSize::KeyBytes2, 100_000), 1);
+        assert_eq!(probes(FilterSize::KeyBytes8, 1), 255);
+    }
+
+    #[quickcheck]
+    fn test_fold_to(values: Vec<u32>, probes: u8) {
+        let scheme = ProbeScheme::DoubleHashing { probes };
+        let mut b =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .size(FilterSize::KeyBytes3)
+                .probe_scheme(scheme)
+                .build();
+        for v in &values {
+            b.insert(v);
+        }
+
+        let impact = b.fold_to(FilterSize::KeyBits(12)).unwrap();
+        assert!(impact.fpp_after >= impact.fpp_before);
+        assert!(b.verify_invariants().is_ok());
+
+        // The folded filter matches a filter built at the folded size.
+        let mut want =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .size(FilterSize::KeyBits(12))
+                .probe_scheme(scheme)
+                .build();
+        for v in &values {
+            want.insert(v);
+        }
+        assert_eq!(b, want);
+    }
+
+    #[test]
+    fn test_fold_to_invalid() {
+        let mut b: Bloom2<_, CompressedBitmap, u32> =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .build();
+        assert_eq!(
+            b.fold_to(FilterSize::KeyBytes1),
+            Err(Error::FoldUnsupported {
+                scheme: ProbeScheme::Split
+            })
+        );
+
+        let mut b: Bloom2<_, CompressedBitmap, u32> =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 2 })
+                .build();
+        for to in &[FilterSize::KeyBytes3, FilterSize::KeyBits(5)] {
+            assert_eq!(
+                b.fold_to(*to),
+                Err(Error::InvalidFoldSize {
+                    from: FilterSize::KeyBytes2,
+                    to: *to,
+                })
+            );
+        }
+        assert_eq!(b.key_size(), FilterSize::KeyBytes2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_probe_scheme() {
+        type MyBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+        let mut b: Bloom2<MyBuildHasher, CompressedBitmap, i32> =
+            BloomFilterBuilder::hasher(MyBuildHasher::default())
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 5 })
+                .build();
+        b.insert(&42);
+
+        let decoded: Bloom2<MyBuildHasher, CompressedBitmap, i32> =
+            bincode::deserialize(&bincode::serialize(&b).unwrap()).unwrap();
+        assert_eq!(decoded.probe_scheme(), b.probe_scheme());
+        assert!(decoded.contains(&42));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_match_mode() {
+        type MyBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+        let mut b: Bloom2<MyBuildHasher, CompressedBitmap, i32> =
+            BloomFilterBuilder::hasher(MyBuildHasher::default())
+                .match_mode(MatchMode::All)
+                .build();
+        b.insert(&42);
+
+        let decoded: Bloom2<MyBuildHasher, CompressedBitmap, i32> =
+            bincode::deserialize(&bincode::serialize(&b).unwrap()).unwrap();
+        assert_eq!(decoded.match_mode(), MatchMode::All);
+        assert_eq!(decoded, b);
+
+        // A header without a match mode predates it, and uses the default.
+        let mut encoded = serde_json::to_value(&b).unwrap();
+        encoded["header"]
+            .as_object_mut()
+            .unwrap()
+            .remove("match_mode");
+        let decoded: Bloom2<MyBuildHasher, CompressedBitmap, i32> =
+            serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded.match_mode(), MatchMode::Any);
+    }
+
+    #[test]
+    fn test_union_match_mode_mismatch() {
+        let hasher = BuildHasherDefault::<twox_hash::XxHash64>::default();
+
+        let mut any: Bloom2<_, CompressedBitmap, i32> =
+            BloomFilterBuilder::hasher(hasher.clone()).build();
+        let all = BloomFilterBuilder::hasher(hasher)
+            .match_mode(MatchMode::All)
+            .build();
+
+        assert_eq!(
+            any.try_union(&all),
+            Err(Error::MatchModeMismatch {
+                ours: MatchMode::Any,
+                theirs: MatchMode::All,
+            })
+        );
+    }
+
+    #[quickcheck]
+    fn test_match_mode_all(values: Vec<u32>, control: Vec<u32>) {
+        let hasher = BuildHasherDefault::<twox_hash::XxHash64>::default();
+
+        let mut b: Bloom2<_, CompressedBitmap, u32> = BloomFilterBuilder::hasher(hasher.clone())
+            .size(FilterSize::KeyBytes1)
+            .match_mode(MatchMode::All)
+            .build();
+        assert_eq!(b.match_mode(), MatchMode::All);
+        for v in &values {
+            b.insert(v);
+        }
+
+        let mut got = vec![false; control.len()];
+        b.contains_batch(&control, &mut got);
+
+        for (v, got) in control.iter().zip(got) {
+            let want = ProbeScheme::Split
+                .keys(hasher.hash_one(v), FilterSize::KeyBytes1)
+                .all(|key| b.bitmap().get(key));
+            assert_eq!(b.contains(v), want);
+            assert_eq!(got, want);
+
+            // The strict mode only ever removes positives.
+            let mut any = b.clone();
+            any.set_match_mode(MatchMode::Any);
+            assert!(!want || any.contains(v));
+        }
+
+        for v in &values {
+            assert!(b.contains(v));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "different hasher")]
+    fn test_union_hasher_mismatch_panics() {
+        let mut a: Bloom2<RandomState, CompressedBitmap, i32> = Bloom2::default();
+        a.union(&Bloom2::default());
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut b = BloomFilterBuilder::hasher(MockHasher { return_hash: 42 })
+            .size(FilterSize::KeyBytes1)
+            .build();
+        b.insert(&1);
+
+        assert_eq!(b.key_size(), FilterSize::KeyBytes1);
+        assert_eq!(b.hasher().return_hash, 42);
+        assert!(b.bitmap().get(0));
+        assert!(b.bitmap().get(42));
+
+        b.bitmap_mut().set(0, false);
+        b.bitmap_mut().set(42, false);
+        assert!(!b.contains(&1));
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_try_build_key_space_too_large() {
+        let got = BloomFilterBuilder::default()
+            .size(FilterSize::KeyBytes5)
+            .try_build::<u32>();
+        assert_eq!(
+            got.err(),
+            Some(Error::KeySpaceTooLarge {
+                key_size: FilterSize::KeyBytes5
+            })
+        );
+
+        // Resolving the probe count for the expected items is also checked.
+        let got = BloomFilterBuilder::default()
+            .size(FilterSize::KeyBytes5)
+            .expected_items(1000)
+            .try_build::<u32>();
+        assert_eq!(
+            got.err(),
+            Some(Error::KeySpaceTooLarge {
+                key_size: FilterSize::KeyBytes5
+            })
+        );
+
+        let got = Bloom2::<_, _, u32>::from_parts(FilterParts {
+            hasher: RandomState::default(),
+            bitmap: CompressedBitmap::new(64),
+            key_size: FilterSize::KeyBytes5,
+            probe_scheme: ProbeScheme::Split,
+            match_mode: MatchMode::Any,
+        });
+        assert_eq!(
+            got.err(),
+            Some(Error::KeySpaceTooLarge {
+                key_size: FilterSize::KeyBytes5
+            })
+        );
+
+        let mut b: Bloom2<_, CompressedBitmap, u32> = BloomFilterBuilder::default()
+            .probe_scheme(ProbeScheme::DoubleHashing { probes: 4 })
+            .build();
+        assert_eq!(
+            b.fold_to(FilterSize::KeyBytes5),
+            Err(Error::KeySpaceTooLarge {
+                key_size: FilterSize::KeyBytes5
+            })
+        );
+    }
+
+    #[test]
+    fn test_try_build_invalid_key_bits() {
+        for key_bits in [0, 65, u8::MAX] {
+            let got = BloomFilterBuilder::default()
+                .size(FilterSize::KeyBits(key_bits))
+                .try_build::<u32>();
+            assert_eq!(
+                got.err(),
+                Some(Error::InvalidKeyBits {
+                    key_bits: key_bits as u32
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_build_allocation_failed() {
+        // A dense bitmap for the full 64-bit key space cannot be allocated.
+        let got = BloomFilterBuilder::default()
+            .with_bitmap::<VecBitmap>()
+            .size(FilterSize::KeyBytes8)
+            .try_build::<u32>();
+        assert_eq!(
+            got.err(),
+            Some(Error::AllocationFailed {
+                bytes: (usize::MAX / 64 + 1) * std::mem::size_of::<usize>()
+            })
+        );
+
+        let got = BloomFilterBuilder::default()
+            .with_bitmap::<VecBitmap>()
+            .size(FilterSize::KeyBytes2)
+            .try_build::<u32>()
+            .unwrap();
+        assert_eq!(
+            got.bitmap(),
+            &VecBitmap::new_with_capacity(key_size_to_bits(FilterSize::KeyBytes2))
+        );
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let mut b = BloomFilterBuilder::hasher(MockHasher { return_hash: 42 })
+            .size(FilterSize::KeyBytes1)
+            .build();
+        b.insert(&1);
+
+        let parts = b.clone().into_parts();
+        assert_eq!(parts.key_size, FilterSize::KeyBytes1);
+
+        let got = Bloom2::from_parts(parts.clone()).unwrap();
+        assert_eq!(got.bitmap(), b.bitmap());
+        assert!(got.contains(&1));
+
+        // A bitmap constructed for a different filter size is rejected.
+        let parts = FilterParts {
+            key_size: FilterSize::KeyBytes2,
+            ..parts
+        };
+        assert_eq!(
+            Bloom2::<_, CompressedBitmap, i32>::from_parts(parts).err(),
+            Some(Error::InvalidBitmap(InvariantError::MaxKeyMismatch {
+                want: key_size_to_bits(FilterSize::KeyBytes2),
+                got: key_size_to_bits(FilterSize::KeyBytes1),
+            }))
+        );
+    }
+
+    #[quickcheck]
+    fn test_parts_round_trip(values: Vec<u32>) {
+        let mut b: Bloom2<_, CompressedBitmap, u32> =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .size(FilterSize::KeyBytes2)
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 5 })
+                .match_mode(MatchMode::All)
+                .build();
+        for v in &values {
+            b.insert(v);
+        }
+
+        let got = Bloom2::from_parts(b.clone().into_parts()).unwrap();
+        assert_eq!(got, b);
+        assert_eq!(got.probe_scheme(), ProbeScheme::DoubleHashing { probes: 5 });
+        assert_eq!(got.match_mode(), MatchMode::All);
+        for v in &values {
+            assert!(got.contains(v));
+        }
+    }
+
+    #[quickcheck]
+    fn test_clone_empty(vals: Vec<u16>) {
+        let mut b =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .size(FilterSize::KeyBytes3)
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 3 })
+                .build();
+        for v in &vals {
+            b.insert(v);
+        }
+
+        let want = BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+            .size(FilterSize::KeyBytes3)
+            .probe_scheme(ProbeScheme::DoubleHashing { probes: 3 })
+            .build();
+
+        let got = b.clone_empty();
+        assert_eq!(got, want);
+        assert_eq!(got.bitmap().size(), want.bitmap().size());
+    }
+
+    #[quickcheck]
+    fn test_decompress_convert(vals: Vec<u16>) {
+        let hasher = BuildHasherDefault::<twox_hash::XxHash64>::default();
+        let mut b = BloomFilterBuilder::hasher(hasher)
+            .size(FilterSize::KeyBytes2)
+            .build();
+        for v in &vals {
+            b.insert(v);
+        }
+
+        let vec = b.clone().decompress();
+        let aligned = b.clone().convert::<crate::AlignedBitmap>();
+        for v in &vals {
+            assert!(vec.contains(v));
+            assert!(aligned.contains(v));
+        }
+
+        // Compressing the decompressed filter round-trips.
+        assert_eq!(
+            vec.compress().bitmap().count_ones(),
+            b.bitmap().count_ones()
+        );
+        for i in 0..key_size_to_bits(FilterSize::KeyBytes2) {
+            assert_eq!(aligned.bitmap().get(i), b.bitmap().get(i));
+        }
     }
 
-    pub fn bitmap(&self) -> &B {
-        &self.bitmap
+    #[test]
+    fn test_builder_order() {
+        let mut bitmap = CompressedBitmap::new(key_size_to_bits(FilterSize::KeyBytes1));
+        bitmap.set(42, true);
+
+        // The provided bitmap is retained regardless of call order.
+        let b = BloomFilterBuilder::default()
+            .size(FilterSize::KeyBytes1)
+            .with_bitmap_data(bitmap.clone(), FilterSize::KeyBytes1)
+            .size(FilterSize::KeyBytes1)
+            .build::<u32>();
+        assert!(b.bitmap().get(42));
+
+        let err = BloomFilterBuilder::default()
+            .with_bitmap_data(bitmap, FilterSize::KeyBytes1)
+            .size(FilterSize::KeyBytes3)
+            .try_build::<u32>()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::SizeMismatch {
+                bitmap: FilterSize::KeyBytes1,
+                requested: FilterSize::KeyBytes3,
+            }
+        );
     }
-}
 
-impl<H, T> Bloom2<H, CompressedBitmap, T>
-where
-    H: BuildHasher,
-{
-    /// Minimise the memory usage of this instance by shrinking the
-    /// underlying vectors, discarding their excess capacity.
-    pub fn shrink_to_fit(&mut self) {
-        self.bitmap.shrink_to_fit();
+    #[test]
+    fn test_builder_size_any_backend() {
+        let want = VecBitmap::new_with_capacity(key_size_to_bits(FilterSize::KeyBytes1));
+
+        let a = BloomFilterBuilder::default()
+            .with_bitmap::<VecBitmap>()
+            .size(FilterSize::KeyBytes1)
+            .build::<u32>();
+        let b = BloomFilterBuilder::default()
+            .size(FilterSize::KeyBytes1)
+            .with_bitmap::<VecBitmap>()
+            .build::<u32>();
+
+        assert_eq!(a.key_size(), FilterSize::KeyBytes1);
+        assert_eq!(a.bitmap(), &want);
+        assert_eq!(b.bitmap(), &want);
     }
-}
 
-impl<H, T> Bloom2<H, VecBitmap, T>
-where
-    H: BuildHasher,
-{
-    /// Compress the bitmap to reduce memory consumption.
-    ///
-    /// The compressed representation is optimised for reads, but subsequent
-    /// inserts will be slower. This reduction is `O(n)` in time, and up to
-    /// `O(2n)` in space.
-    pub fn compress(self) -> Bloom2<H, CompressedBitmap, T> {
-        Bloom2::from(self)
+    #[test]
+    fn test_builder_hasher_any_order() {
+        let hasher = MockHasher::default();
+
+        let a = BloomFilterBuilder::hasher(hasher.clone())
+            .with_bitmap::<VecBitmap>()
+            .size(FilterSize::KeyBytes1)
+            .probe_scheme(ProbeScheme::DoubleHashing { probes: 3 })
+            .build::<u32>();
+        let b = BloomFilterBuilder::default()
+            .probe_scheme(ProbeScheme::DoubleHashing { probes: 3 })
+            .size(FilterSize::KeyBytes1)
+            .with_hasher(hasher)
+            .with_bitmap::<VecBitmap>()
+            .build::<u32>();
+
+        assert_eq!(a.key_size(), b.key_size());
+        assert_eq!(a.probe_scheme(), b.probe_scheme());
+        assert_eq!(a.bitmap(), b.bitmap());
+
+        // Bitmap data is retained when replacing the hasher.
+        let mut bitmap = CompressedBitmap::new(key_size_to_bits(FilterSize::KeyBytes1));
+        bitmap.set(42, true);
+        let c = BloomFilterBuilder::default()
+            .with_bitmap_data(bitmap, FilterSize::KeyBytes1)
+            .with_hasher(MockHasher::default())
+            .build::<u32>();
+        assert!(c.bitmap().get(42));
     }
-}
 
-fn bytes_to_usize_key<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> usize {
-    bytes
-        .into_iter()
-        .fold(0, |key, &byte| (key << 8) | byte as usize)
-}
+    #[cfg(not(feature = "saturating"))]
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn test_with_bitmap_data_undersized_panics() {
+        // A bitmap holding 64 bits is too small for the 256 bits of KeyBytes1.
+        let _ = BloomFilterBuilder::hasher(RandomState::default())
+            .with_bitmap::<VecBitmap>()
+            .with_bitmap_data(VecBitmap::new_with_capacity(64), FilterSize::KeyBytes1);
+    }
 
-impl<H, T> From<Bloom2<H, VecBitmap, T>> for Bloom2<H, CompressedBitmap, T>
-where
-    H: BuildHasher,
-{
-    fn from(v: Bloom2<H, VecBitmap, T>) -> Self {
-        Self {
-            hasher: v.hasher,
-            bitmap: CompressedBitmap::from(v.bitmap),
-            key_size: v.key_size,
-            _key_type: PhantomData,
-        }
+    #[test]
+    #[should_panic(expected = "conflicts with requested size")]
+    fn test_builder_size_mismatch_panics() {
+        BloomFilterBuilder::default()
+            .with_bitmap_data(CompressedBitmap::new(256), FilterSize::KeyBytes1)
+            .size(FilterSize::KeyBytes2)
+            .build::<u32>();
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[quickcheck]
+    fn test_sum(shards: Vec<Vec<u32>>) {
+        let hasher = MockHasher::default();
+        let new_filter = || -> Bloom2<_, CompressedBitmap, u32> {
+            BloomFilterBuilder::hasher(hasher.clone())
+                .size(FilterSize::KeyBytes2)
+                .build()
+        };
+
+        let filters = shards
+            .iter()
+            .map(|values| {
+                let mut b = new_filter();
+                values.iter().for_each(|v| b.insert(v));
+                b
+            })
+            .collect::<Vec<_>>();
+
+        let got: Result<Bloom2<_, _, _>, Error> = filters.clone().into_iter().sum();
+        if shards.is_empty() {
+            assert_eq!(got.unwrap_err(), Error::NoFilters);
+            return;
+        }
 
-    #[cfg(feature = "bytes")]
-    use crate::bitmap::BytesBitmap;
+        let mut want = new_filter();
+        shards.iter().flatten().for_each(|v| want.insert(v));
 
-    use proptest::prelude::*;
-    use quickcheck_macros::quickcheck;
+        // Invariant: the sum is the union of all the filters.
+        assert_eq!(got.unwrap().bitmap(), want.bitmap());
+        let got: Bloom2<_, _, _> = filters.into_iter().collect();
+        assert_eq!(got.bitmap(), want.bitmap());
+    }
 
-    use std::collections::hash_map::RandomState;
-    use std::{
-        cell::RefCell,
-        collections::HashSet,
-        hash::{BuildHasherDefault, Hasher},
-    };
+    #[test]
+    fn test_sum_mismatch() {
+        let filters: Vec<Bloom2<_, CompressedBitmap, u32>> = vec![
+            BloomFilterBuilder::hasher(MockHasher::default())
+                .size(FilterSize::KeyBytes1)
+                .build(),
+            BloomFilterBuilder::hasher(MockHasher::default())
+                .size(FilterSize::KeyBytes2)
+                .build(),
+        ];
 
-    #[derive(Debug, Clone, Default)]
-    struct MockHasher {
-        return_hash: u64,
+        let got: Result<Bloom2<_, _, _>, Error> = filters.into_iter().sum();
+        assert_eq!(
+            got.unwrap_err(),
+            Error::KeySizeMismatch {
+                ours: FilterSize::KeyBytes1,
+                theirs: FilterSize::KeyBytes2,
+            }
+        );
     }
 
-    impl Hasher for MockHasher {
-        fn write(&mut self, _bytes: &[u8]) {}
-        fn finish(&self) -> u64 {
-            self.return_hash
-        }
-    }
+    #[test]
+    fn test_insert_lines() {
+        let mut b: Bloom2<_, CompressedBitmap, String> =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .size(FilterSize::KeyBytes3)
+                .build();
 
-    impl BuildHasher for MockHasher {
-        type Hasher = Self;
-        fn build_hasher(&self) -> MockHasher {
-            self.clone()
+        let input = "a\n\nb\r\nc";
+        assert_eq!(b.insert_lines(input.as_bytes()).unwrap(), 3);
+        for v in ["a", "b", "c"] {
+            assert!(b.contains(&v.to_string()));
         }
+
+        let err = b.insert_lines(&b"d\n\xff\n"[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(b.contains(&"d".to_string()));
     }
 
-    #[derive(Debug, Default)]
-    struct MockBitmap {
-        set_calls: Vec<(usize, bool)>,
-        get_calls: RefCell<Vec<usize>>,
+    #[test]
+    fn test_insert_digests() {
+        let mut b = BloomFilterBuilder::hasher(MockHasher::default())
+            .size(FilterSize::KeyBytes1)
+            .build::<u32>();
+
+        assert_eq!(b.insert_digests(&b"0102\n\n"[..]).unwrap(), 1);
+        assert!(b.bitmap().get(0x01));
+        assert!(b.bitmap().get(0x02));
+
+        let err = b.insert_digests(&b"zz\n"[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
     }
-    impl Bitmap for MockBitmap {
-        fn set(&mut self, key: usize, value: bool) {
-            self.set_calls.push((key, value))
-        }
-        fn get(&self, key: usize) -> bool {
-            self.get_calls.borrow_mut().push(key);
-            false
-        }
-        fn byte_size(&self) -> usize {
-            42
-        }
 
-        fn or(&self, _other: &Self) -> Self {
-            unreachable!()
-        }
+    #[test]
+    fn test_estimated_fpp() {
+        let scheme = ProbeScheme::DoubleHashing { probes: 4 };
+        let size = FilterSize::KeyBytes2;
 
-        fn new_with_capacity(_max_key: usize) -> Self {
-            Self::default()
-        }
+        let any = estimated_fpp(size, scheme, MatchMode::Any, 0.5);
+        assert!((any - (1.0 - 0.5_f64.powi(4))).abs() < f64::EPSILON);
+
+        let all = estimated_fpp(size, scheme, MatchMode::All, 0.5);
+        assert!((all - 0.5_f64.powi(4)).abs() < f64::EPSILON);
+
+        // More probes increase the fpp of matching any probe, and decrease
+        // the fpp of matching all of them.
+        let more = ProbeScheme::DoubleHashing { probes: 8 };
+        assert!(estimated_fpp(size, more, MatchMode::Any, 0.5) > any);
+        assert!(estimated_fpp(size, more, MatchMode::All, 0.5) < all);
     }
 
-    fn new_test_bloom<T: Hash>() -> Bloom2<MockHasher, MockBitmap, T> {
-        Bloom2 {
-            hasher: MockHasher::default(),
-            bitmap: MockBitmap::default(),
-            key_size: FilterSize::KeyBytes1,
-            _key_type: PhantomData,
+    #[test]
+    fn test_capacity_for_fpp() {
+        for match_mode in [MatchMode::Any, MatchMode::All] {
+            let mut b =
+                BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                    .size(FilterSize::KeyBytes2)
+                    .match_mode(match_mode)
+                    .build();
+
+            assert_eq!(b.capacity_for_fpp(0.0), 0);
+            assert_eq!(b.capacity_for_fpp(1.0), usize::MAX);
+
+            let capacity = b.capacity_for_fpp(0.1);
+            assert!(capacity > 0);
+
+            // Fill the filter to capacity, at which point the estimated fpp
+            // is close to the target.
+            for v in 0..capacity {
+                b.insert(&v);
+            }
+            let load = b.bitmap().count_ones() as f64 / key_size_to_bits(b.key_size()) as f64;
+            let fpp = estimated_fpp(b.key_size(), b.probe_scheme(), match_mode, load);
+            assert!((fpp - 0.1).abs() < 0.01, "{:?} fpp {}", match_mode, fpp);
+
+            // As does the measured fpp.
+            let fp = (capacity..capacity + 10_000)
+                .filter(|v| b.contains(v))
+                .count();
+            let got = fp as f64 / 10_000.0;
+            assert!(
+                (got - 0.1).abs() < 0.02,
+                "{:?} measured fpp {}",
+                match_mode,
+                got
+            );
+
+            assert!(b.remaining_capacity_for_fpp(0.1) < capacity / 50);
+            assert_eq!(b.remaining_capacity_for_fpp(0.01), 0);
         }
+
+        // Matching all probes holds far more entries for the same fpp.
+        let b = |match_mode| {
+            BloomFilterBuilder::default()
+                .size(FilterSize::KeyBytes2)
+                .match_mode(match_mode)
+                .build::<u32>()
+                .capacity_for_fpp(0.01)
+        };
+        assert!(b(MatchMode::All) > b(MatchMode::Any) * 10);
     }
 
+    #[cfg(feature = "bytes")]
     #[test]
-    fn test_default() {
-        let mut b = Bloom2::default();
-        assert_eq!(b.key_size, FilterSize::KeyBytes2);
+    fn test_from_frozen_bytes() {
+        let mut b: Bloom2<RandomState, BytesBitmap, i32> = BloomFilterBuilder::default()
+            .with_bitmap()
+            .size(FilterSize::KeyBytes2)
+            .probe_scheme(ProbeScheme::DoubleHashing { probes: 3 })
+            .match_mode(MatchMode::All)
+            .build();
+        for i in 0..100 {
+            b.insert(&i);
+        }
 
-        b.insert(&42);
-        assert!(b.contains(&42));
+        let FilterParts {
+            hasher,
+            bitmap,
+            key_size,
+            probe_scheme,
+            match_mode,
+        } = b.into_parts();
+        let bytes = bitmap.freeze();
+        let parts = |bitmap| FilterParts {
+            hasher: hasher.clone(),
+            bitmap,
+            key_size,
+            probe_scheme,
+            match_mode,
+        };
+
+        let frozen = Bloom2::from_frozen_bytes(parts(bytes.clone())).unwrap();
+        assert_eq!(
+            frozen.bitmap().clone().into_bytes().as_ptr(),
+            bytes.as_ptr()
+        );
+        assert_eq!(frozen.probe_scheme(), probe_scheme);
+        assert_eq!(frozen.match_mode(), MatchMode::All);
+        for i in 0..100 {
+            assert!(frozen.contains(&i));
+        }
+
+        let err = Bloom2::<_, _, i32>::from_frozen_bytes(parts(bytes.slice(1..))).unwrap_err();
+        assert_eq!(
+            err,
+            Error::ByteLength {
+                want: bytes.len(),
+                got: bytes.len() - 1
+            }
+        );
     }
 
     #[cfg(feature = "bytes")]
@@ -455,6 +3006,61 @@ mod tests {
         assert!(b.contains(&42));
     }
 
+    #[test]
+    fn test_with_inline_bitmap() {
+        let mut b: Bloom2<RandomState, crate::InlineBitmap<4>, i32> = BloomFilterBuilder::default()
+            .size(FilterSize::KeyBytes1)
+            .with_bitmap()
+            .build();
+        b.insert(&42);
+        assert!(b.contains(&42));
+        assert_eq!(b.byte_size(), 32);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut b = Bloom2 {
+            hasher: MockHasher::default(),
+            bitmap: CompressedBitmap::new(key_size_to_bits(FilterSize::KeyBytes1)),
+            key_size: FilterSize::KeyBytes1,
+            probe_scheme: ProbeScheme::Split,
+            match_mode: MatchMode::Any,
+            _key_type: PhantomData,
+        };
+        b.hasher.return_hash = 12345678901234567890;
+        b.insert(&42);
+
+        assert_eq!(
+            b.to_string(),
+            format!(
+                "Bloom2 {{ key size: KeyBytes1, bits set: 8 / 256, blocks: 4, memory: {} bytes, \
+                 estimated fpp: 0.2243001 }}",
+                b.bitmap.size()
+            )
+        );
+    }
+
+    #[quickcheck]
+    fn test_small_prop(vals: Vec<u16>) {
+        let hasher = BuildHasherDefault::<twox_hash::XxHash64>::default();
+        let mut small = BloomFilterBuilder::small(hasher.clone()).build();
+        let mut compressed = BloomFilterBuilder::hasher(hasher)
+            .size(FilterSize::KeyBytes1)
+            .build();
+
+        for v in &vals {
+            small.insert(v);
+            compressed.insert(v);
+        }
+
+        // The small filter must behave identically to the equivalent
+        // compressed filter.
+        for v in 0..=u16::MAX {
+            assert_eq!(small.contains(&v), compressed.contains(&v));
+        }
+        assert_eq!(small.byte_size(), 32);
+    }
+
     #[quickcheck]
     fn test_default_prop(vals: Vec<u16>) {
         let mut b = Bloom2::default();
@@ -522,20 +3128,58 @@ mod tests {
         bloom_filter.insert(&"d");
     }
 
+    #[cfg(feature = "allocative")]
+    #[test]
+    fn test_allocative() {
+        let mut b =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
+                .size(FilterSize::KeyBytes2)
+                .build();
+        for i in 0..100 {
+            b.insert(&i);
+        }
+
+        assert_eq!(allocative::size_of_unique(&b), b.memory_breakdown().total());
+    }
+
     #[test]
     fn test_size_shrink() {
-        let mut bloom_filter: Bloom2<RandomState, CompressedBitmap, _> =
-            BloomFilterBuilder::default()
+        let mut bloom_filter: Bloom2<_, CompressedBitmap, _> =
+            BloomFilterBuilder::hasher(BuildHasherDefault::<twox_hash::XxHash64>::default())
                 .size(FilterSize::KeyBytes4)
                 .build();
 
+        // An empty filter does not allocate.
+        assert_eq!(
+            bloom_filter.byte_size(),
+            std::mem::size_of::<CompressedBitmap>()
+        );
+
         for i in 0..10 {
             bloom_filter.insert(&i);
         }
 
-        assert_eq!(bloom_filter.byte_size(), 8388920);
+        assert_eq!(bloom_filter.byte_size(), 1144);
         bloom_filter.shrink_to_fit();
-        assert_eq!(bloom_filter.byte_size(), 8388824);
+        assert_eq!(bloom_filter.byte_size(), 760);
+
+        assert_eq!(bloom_filter.verify_invariants(), Ok(()));
+
+        let usage = bloom_filter.memory_breakdown();
+        assert_eq!(usage.spare_bytes, 0);
+        assert_eq!(usage.used() + size_of::<CompressedBitmap>(), 760);
+        assert_eq!(usage.overhead_bytes, size_of_val(&bloom_filter));
+
+        // Clearing the filter retains the allocated capacity.
+        bloom_filter.clear();
+        assert_eq!(bloom_filter.byte_size(), 760);
+        for i in 0..10 {
+            assert!(!bloom_filter.contains(&i));
+        }
+
+        bloom_filter.insert(&42);
+        assert!(bloom_filter.contains(&42));
+        assert_eq!(bloom_filter.byte_size(), 760);
     }
 
     #[test]
@@ -603,6 +3247,100 @@ mod tests {
         }
     }
 
+    #[quickcheck]
+    fn test_build_from_hashes(values: Vec<u32>, control: Vec<u32>) {
+        let hasher = BuildHasherDefault::<twox_hash::XxHash64>::default();
+
+        let mut want: Bloom2<_, CompressedBitmap, u32> = BloomFilterBuilder::hasher(hasher.clone())
+            .size(FilterSize::KeyBytes3)
+            .build();
+        for v in &values {
+            want.insert(v);
+        }
+
+        let got: Bloom2<_, CompressedBitmap, u32> = Bloom2::build_from_hashes(
+            hasher.clone(),
+            FilterSize::KeyBytes3,
+            values.iter().map(|v| hasher.hash_one(v)),
+        );
+
+        // Invariant: the bulk build produces an identical filter to inserting
+        // each value.
+        assert_eq!(got.bitmap, want.bitmap);
+
+        for v in values.iter().chain(&control) {
+            assert_eq!(got.contains(v), want.contains(v));
+        }
+    }
+
+    #[quickcheck]
+    fn test_builder_build_from_hashes(values: Vec<u32>, control: Vec<u32>) {
+        let hasher = BuildHasherDefault::<twox_hash::XxHash64>::default();
+        let scheme = ProbeScheme::DoubleHashing { probes: 5 };
+
+        let mut want: Bloom2<_, CompressedBitmap, u32> = BloomFilterBuilder::hasher(hasher.clone())
+            .size(FilterSize::KeyBytes2)
+            .probe_scheme(scheme)
+            .build();
+        for v in &values {
+            want.insert(v);
+        }
+
+        let got = BloomFilterBuilder::hasher(hasher.clone())
+            .probe_scheme(scheme)
+            .size(FilterSize::KeyBytes2)
+            .build_from_hashes::<u32, _>(values.iter().map(|v| hasher.hash_one(v)));
+
+        // Invariant: the bulk build produces an identical filter to inserting
+        // each value, using the configured probe scheme.
+        assert_eq!(got.probe_scheme(), scheme);
+        assert_eq!(got.bitmap, want.bitmap);
+
+        for v in values.iter().chain(&control) {
+            assert_eq!(got.contains(v), want.contains(v));
+        }
+
+        // Hashes are added to any provided bitmap data.
+        let mut bitmap = CompressedBitmap::new(key_size_to_bits(FilterSize::KeyBytes2));
+        bitmap.set(42, true);
+        let got = BloomFilterBuilder::hasher(hasher.clone())
+            .with_bitmap_data(bitmap, FilterSize::KeyBytes2)
+            .build_from_hashes::<u32, _>(values.iter().map(|v| hasher.hash_one(v)));
+        assert!(got.bitmap().get(42));
+        for v in &values {
+            assert!(got.contains(v));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_contains_batch(
+            values in prop::collection::vec(arbitrary_value(), 0..50),
+            check in prop::collection::vec(arbitrary_value(), 0..50),
+            size in prop_oneof![
+                Just(FilterSize::KeyBytes1),
+                Just(FilterSize::KeyBytes2),
+                Just(FilterSize::KeyBytes3),
+            ],
+        ) {
+            let mut b: Bloom2<_, CompressedBitmap, usize> = BloomFilterBuilder::default()
+                .size(size)
+                .build();
+            for v in &values {
+                b.insert(v);
+            }
+
+            let items = values.iter().chain(&check).copied().collect::<Vec<_>>();
+            let mut out = vec![false; items.len()];
+            b.contains_batch(&items, &mut out);
+
+            // Invariant: the batch lookup matches the individual lookups.
+            for (v, got) in items.iter().zip(out) {
+                assert_eq!(got, b.contains(v));
+            }
+        }
+    }
+
     #[cfg(feature = "serde")]
     #[test]
     fn serde() {
@@ -655,6 +3393,55 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_header_mismatch() {
+        let mut b: Bloom2<RandomState, CompressedBitmap, i32> = Bloom2::default();
+        b.insert(&42);
+
+        // A RandomState filter cannot be interpreted with a new RandomState.
+        let encoded = serde_json::to_string(&b).unwrap();
+        let err = serde_json::from_str::<Bloom2<RandomState, CompressedBitmap, i32>>(&encoded)
+            .unwrap_err();
+        assert!(err.to_string().contains("different hasher"), "{}", err);
+
+        // A probe count inconsistent with the filter size is rejected.
+        type MyBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+        let b: Bloom2<MyBuildHasher, CompressedBitmap, i32> =
+            BloomFilterBuilder::hasher(MyBuildHasher::default()).build();
+        let encoded = serde_json::to_string(&b)
+            .unwrap()
+            .replace("\"probes\":4", "\"probes\":3");
+        let err = serde_json::from_str::<Bloom2<MyBuildHasher, CompressedBitmap, i32>>(&encoded)
+            .unwrap_err();
+        assert!(err.to_string().contains("3 probes"), "{}", err);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_invalid_bitmap() {
+        type MyBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+        type Filter = Bloom2<MyBuildHasher, CompressedBitmap, i32>;
+
+        let mut b: Filter = BloomFilterBuilder::hasher(MyBuildHasher::default())
+            .size(FilterSize::KeyBytes1)
+            .build();
+        b.insert(&42);
+        let encoded = serde_json::to_value(&b).unwrap();
+
+        // A bitmap sized for a different key space is rejected.
+        let mut v = encoded.clone();
+        v["bitmap"]["max_key"] = 1024.into();
+        let err = serde_json::from_value::<Filter>(v).unwrap_err();
+        assert!(err.to_string().contains("max key is 1024"), "{}", err);
+
+        // As is a bitmap with a block missing.
+        let mut v = encoded;
+        v["bitmap"]["bitmap"].as_array_mut().unwrap().pop();
+        let err = serde_json::from_value::<Filter>(v).unwrap_err();
+        assert!(err.to_string().contains("blocks"), "{}", err);
+    }
+
     /// Generate an arbitrary `usize` value.
     ///
     /// Prefers generating values from a small range to encourage collisions.
@@ -692,6 +3479,13 @@ mod tests {
             run_ops_fuzz::<VecBitmap>(ops);
         }
 
+        #[test]
+        fn prop_ops_aligned_bitmap(
+            ops in prop::collection::vec(arbitrary_op(arbitrary_value()), 1..100),
+        ) {
+            run_ops_fuzz::<crate::AlignedBitmap>(ops);
+        }
+
         #[test]
         fn prop_ops_compress(
             values in prop::collection::vec(arbitrary_value(), 1..100),
@@ -0,0 +1,336 @@
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use crate::{bloom::key_size_to_bits, CompressedBitmap, FilterSize, ProbeScheme};
+
+/// The maximum number of keys generated for a single hash.
+const MAX_KEYS: usize = 8;
+
+/// The action taken by a [`BudgetedBloom2`] when an insert would exceed the
+/// memory budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPolicy {
+    /// Reject the insert, returning a [`BudgetExceeded`] error and leaving the
+    /// filter unchanged.
+    Reject,
+
+    /// Fold the bitmap in half (repeatedly, if necessary) until the insert
+    /// fits within the budget.
+    ///
+    /// Folding halves the number of bits in the filter by OR-ing the upper
+    /// half of the bitmap into the lower half, retaining all existing entries
+    /// at the cost of an increased false positive probability. The bitmap can
+    /// be folded down to a minimum of 64 bits, after which further inserts
+    /// that exceed the budget are rejected.
+    Fold,
+
+    /// Rotate the filter generation, moving the current filter to become the
+    /// previous generation (dropping the existing previous generation, if
+    /// any) and starting a new, empty current generation.
+    ///
+    /// Lookups check both generations, so entries are retained for at least
+    /// one full generation before they are evicted.
+    Rotate,
+}
+
+/// The error returned when an insert into a [`BudgetedBloom2`] would exceed
+/// the memory budget, and the [`BudgetPolicy`] is unable to make space for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    /// The configured memory budget in bytes.
+    pub budget: usize,
+    /// The number of bytes required to complete the insert.
+    pub required: usize,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "insert requires {} bytes, exceeding the memory budget of {} bytes",
+            self.required, self.budget
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// A sparse bloom filter with a hard memory budget.
+///
+/// A `BudgetedBloom2` never grows the bitmap storage beyond the configured
+/// number of bytes. When an insert would exceed the budget, the configured
+/// [`BudgetPolicy`] is applied, allowing multi-tenant services to
+/// deterministically cap the memory used by each filter.
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, BudgetPolicy, FilterSize};
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .size(FilterSize::KeyBytes3)
+///     .build_with_budget(1024, BudgetPolicy::Reject);
+///
+/// filter.insert(&"hello").expect("within budget");
+/// assert!(filter.contains(&"hello"));
+/// ```
+///
+/// The budget applies to the bytes used by the populated entries of the
+/// underlying [`CompressedBitmap`] (of both generations for
+/// [`BudgetPolicy::Rotate`]), excluding any excess capacity held by the
+/// allocator.
+#[derive(Debug, Clone)]
+pub struct BudgetedBloom2<H, T>
+where
+    H: BuildHasher,
+{
+    hasher: H,
+    key_size: FilterSize,
+
+    /// The number of bits in the (possibly folded) bitmap.
+    bits: usize,
+    bitmap: CompressedBitmap,
+
+    /// The previous generation, if rotated.
+    previous: Option<CompressedBitmap>,
+
+    budget: usize,
+    policy: BudgetPolicy,
+
+    _key_type: PhantomData<T>,
+}
+
+impl<H, T> BudgetedBloom2<H, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    pub(crate) fn new(
+        hasher: H,
+        key_size: FilterSize,
+        bitmap: CompressedBitmap,
+        budget: usize,
+        policy: BudgetPolicy,
+    ) -> Self {
+        Self {
+            hasher,
+            key_size,
+            bits: key_size_to_bits(key_size),
+            bitmap,
+            previous: None,
+            budget,
+            policy,
+            _key_type: PhantomData,
+        }
+    }
+
+    /// Insert `data` into the filter, applying the [`BudgetPolicy`] if the
+    /// insert would exceed the memory budget.
+    ///
+    /// If the policy is unable to make space for the insert, a
+    /// [`BudgetExceeded`] error is returned and `data` is not inserted. Any
+    /// folds or rotations performed while attempting to make space are
+    /// retained.
+    pub fn insert(&mut self, data: &'_ T) -> Result<(), BudgetExceeded> {
+        let hash = self.hasher.hash_one(data);
+
+        loop {
+            let mut keys = [0; MAX_KEYS];
+            let n = self.keys(hash, &mut keys);
+            let keys = &keys[..n];
+
+            let required = self.used_bytes() + self.bitmap.bytes_to_set(keys);
+            if required <= self.budget {
+                for &key in keys {
+                    self.bitmap.set(key, true);
+                }
+                return Ok(());
+            }
+
+            let err = BudgetExceeded {
+                budget: self.budget,
+                required,
+            };
+
+            match self.policy {
+                BudgetPolicy::Reject => return Err(err),
+                BudgetPolicy::Fold => {
+                    // Stop folding at a single block.
+                    if self.bits <= u64::BITS as usize {
+                        return Err(err);
+                    }
+                    self.bits /= 2;
+                    self.bitmap = self.bitmap.fold_to_bits(self.bits);
+                }
+                BudgetPolicy::Rotate => {
+                    // If there is nothing left to evict, the insert can never
+                    // fit.
+                    if self.used_bytes() == 0 {
+                        return Err(err);
+                    }
+                    let next = CompressedBitmap::new(self.bits);
+                    self.previous = Some(std::mem::replace(&mut self.bitmap, next));
+                }
+            }
+        }
+    }
+
+    /// Checks if `data` exists in the filter.
+    ///
+    /// If `contains` returns true, `hash` has **probably** been inserted
+    /// previously. If `contains` returns false, `hash` has **definitely not**
+    /// been inserted into the filter (or was evicted by a generation
+    /// rotation).
+    pub fn contains(&self, data: &'_ T) -> bool {
+        let mut keys = [0; MAX_KEYS];
+        let n = self.keys(self.hasher.hash_one(data), &mut keys);
+        let keys = &keys[..n];
+
+        let contains = |b: &CompressedBitmap| keys.iter().all(|&key| b.get(key));
+
+        contains(&self.bitmap) || self.previous.as_ref().is_some_and(contains)
+    }
+
+    /// Returns the number of bytes counted against the memory budget.
+    pub fn used_bytes(&self) -> usize {
+        self.bitmap.used_bytes() + self.previous.as_ref().map_or(0, |v| v.used_bytes())
+    }
+
+    /// Returns the configured memory budget in bytes.
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Returns the number of bits in the bitmap, which is halved each time the
+    /// bitmap is folded.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Write the (possibly folded) bitmap keys for `hash` into `out`,
+    /// returning the number of keys.
+    fn keys(&self, hash: u64, out: &mut [usize; MAX_KEYS]) -> usize {
+        let mut n = 0;
+        for key in ProbeScheme::Split.keys(hash, self.key_size) {
+            // The number of bits is always a power of 2.
+            out[n] = key & (self.bits - 1);
+            n += 1;
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use proptest::prelude::*;
+    use twox_hash::XxHash64;
+
+    use super::*;
+    use crate::BloomFilterBuilder;
+
+    fn new_filter(
+        budget: usize,
+        policy: BudgetPolicy,
+    ) -> BudgetedBloom2<BuildHasherDefault<XxHash64>, u32> {
+        BloomFilterBuilder::hasher(BuildHasherDefault::<XxHash64>::default())
+            .size(FilterSize::KeyBytes3)
+            .build_with_budget(budget, policy)
+    }
+
+    #[test]
+    fn test_reject() {
+        let mut b = new_filter(1024, BudgetPolicy::Reject);
+
+        let mut inserted = Vec::new();
+        let err = loop {
+            let v = inserted.len() as u32;
+            match b.insert(&v) {
+                Ok(()) => inserted.push(v),
+                Err(e) => break e,
+            }
+        };
+
+        assert_eq!(err.budget, 1024);
+        assert!(err.required > 1024);
+        assert!(b.used_bytes() <= 1024);
+
+        for v in &inserted {
+            assert!(b.contains(v));
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut b = new_filter(1024, BudgetPolicy::Fold);
+
+        for v in 0..1000 {
+            b.insert(&v).unwrap();
+            assert!(b.used_bytes() <= 1024);
+        }
+        assert!(b.bits() < key_size_to_bits(FilterSize::KeyBytes3));
+
+        // Folding retains all entries.
+        for v in 0..1000 {
+            assert!(b.contains(&v));
+        }
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut b = new_filter(1024, BudgetPolicy::Rotate);
+
+        for v in 0..1000 {
+            b.insert(&v).unwrap();
+            assert!(b.used_bytes() <= 1024);
+
+            // The most recent entry is always present.
+            assert!(b.contains(&v));
+        }
+
+        // Older generations have been evicted.
+        assert!((0..1000).any(|v| !b.contains(&v)));
+    }
+
+    #[test]
+    fn test_budget_too_small() {
+        for policy in [
+            BudgetPolicy::Reject,
+            BudgetPolicy::Fold,
+            BudgetPolicy::Rotate,
+        ] {
+            let mut b = new_filter(0, policy);
+            assert!(b.insert(&42).is_err());
+            assert!(!b.contains(&42));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_within_budget(
+            values in prop::collection::vec(any::<u32>(), 0..200),
+            budget in 0_usize..1024,
+            policy in prop_oneof![
+                Just(BudgetPolicy::Reject),
+                Just(BudgetPolicy::Fold),
+                Just(BudgetPolicy::Rotate),
+            ],
+        ) {
+            let mut b = new_filter(budget, policy);
+
+            for v in &values {
+                let before = b.used_bytes();
+                if b.insert(v).is_err() {
+                    // A rejected insert leaves the filter unchanged.
+                    if policy == BudgetPolicy::Reject {
+                        assert_eq!(b.used_bytes(), before);
+                    }
+                    continue;
+                }
+
+                assert!(b.contains(v));
+            }
+
+            assert!(b.used_bytes() <= budget);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    bloom::capacity_for_fpp, Bloom2, BloomFilterBuilder, CompressedBitmap, Error, FilterSize,
+    MatchMode, ProbeScheme,
+};
+
+/// The maximum number of levels in a [`FilterCascade`].
+const MAX_LEVELS: usize = 64;
+
+/// The false positive probability each level is sized for.
+const LEVEL_FPP: f64 = 0.5;
+
+/// The probe scheme of every level.
+const LEVEL_SCHEME: ProbeScheme = ProbeScheme::DoubleHashing { probes: 1 };
+
+/// An exact encoding of a static set, with no false positives for any item in
+/// a known universe of members and non-members.
+///
+/// A `FilterCascade` is a sequence of [`Bloom2`] levels, as used to
+/// distribute certificate revocation lists (see [CRLite]). The first level
+/// contains the members, the second level contains the non-members that are
+/// false positives of the first level, the third level contains the members
+/// that are false positives of the second level, and so on, until a level has
+/// no false positives.
+///
+/// ```rust
+/// use std::hash::BuildHasherDefault;
+/// use std::collections::hash_map::DefaultHasher;
+/// use bloom2::FilterCascade;
+///
+/// let revoked = (0..1000_u32).collect::<Vec<_>>();
+/// let valid = (1000..100_000_u32).collect::<Vec<_>>();
+///
+/// let cascade =
+///     FilterCascade::build(BuildHasherDefault::<DefaultHasher>::default(), &revoked, &valid)
+///         .unwrap();
+///
+/// assert!(cascade.contains(&42));
+/// assert!(!cascade.contains(&4242));
+/// ```
+///
+/// A lookup for an item outside of the universe the cascade was built from is
+/// subject to the false positive probability of the first level.
+///
+/// Each level hashes items with the level number as a salt, so the false
+/// positives of each level are independent. All levels use the same hasher,
+/// which must hash identically wherever the cascade is queried - such as a
+/// [`BuildHasherDefault`](std::hash::BuildHasherDefault) of a fixed hasher.
+///
+/// [CRLite]: https://obj.umiacs.umd.edu/papers_for_stories/crlite_oakland17.pdf
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterCascade<H, T>
+where
+    H: BuildHasher,
+{
+    levels: Vec<Bloom2<H, CompressedBitmap, T>>,
+}
+
+impl<H, T> FilterCascade<H, T>
+where
+    H: BuildHasher + Clone,
+    T: Hash,
+{
+    /// Build a cascade containing `members`, with no false positives for any
+    /// of `non_members`.
+    ///
+    /// Returns [`Error::CascadeDiverged`] if the false positives are not
+    /// eliminated within the maximum number of levels, which happens if an
+    /// item is in both `members` and `non_members`.
+    pub fn build(hasher: H, members: &[T], non_members: &[T]) -> Result<Self, Error> {
+        let mut include = members.iter().collect::<Vec<_>>();
+        let mut exclude = non_members.iter().collect::<Vec<_>>();
+
+        let mut levels = Vec::new();
+        while !include.is_empty() {
+            if levels.len() == MAX_LEVELS {
+                return Err(Error::CascadeDiverged);
+            }
+
+            let level = levels.len();
+            let mut filter: Bloom2<H, CompressedBitmap, T> =
+                BloomFilterBuilder::hasher(hasher.clone())
+                    .size(level_size(include.len()))
+                    .probe_scheme(LEVEL_SCHEME)
+                    .build();
+            for v in &include {
+                filter.insert_hash(level_hash(&hasher, level, v));
+            }
+
+            // The false positives of this level are the items of the next.
+            let false_positives = exclude
+                .into_iter()
+                .filter(|v| filter.contains_hash(level_hash(&hasher, level, v)))
+                .collect();
+
+            levels.push(filter);
+            exclude = include;
+            include = false_positives;
+        }
+
+        Ok(Self { levels })
+    }
+}
+
+impl<H, T> FilterCascade<H, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    /// Returns `true` if `data` is a member of the set.
+    ///
+    /// The result is exact for all the members and non-members the cascade
+    /// was built from.
+    pub fn contains(&self, data: &'_ T) -> bool {
+        for (level, filter) in self.levels.iter().enumerate() {
+            if !filter.contains_hash(level_hash(filter.hasher(), level, data)) {
+                // Absent from a member level means a non-member, and absent
+                // from a non-member level means a member.
+                return level % 2 == 1;
+            }
+        }
+
+        // Present in every level - the last level decides.
+        self.levels.len() % 2 == 1
+    }
+
+    /// Returns the number of levels in the cascade.
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the total number of bytes used by the bitmaps of all levels.
+    pub fn byte_size(&self) -> usize {
+        self.levels.iter().map(|l| l.bitmap().size()).sum()
+    }
+}
+
+/// Return the hash of `data` for `level`.
+fn level_hash<H, T>(hasher: &H, level: usize, data: &T) -> u64
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    hasher.hash_one((level as u32, data))
+}
+
+/// Return the smallest [`FilterSize`] holding `n` items within the
+/// [`LEVEL_FPP`].
+fn level_size(n: usize) -> FilterSize {
+    [
+        FilterSize::KeyBytes1,
+        FilterSize::KeyBytes2,
+        FilterSize::KeyBytes3,
+        FilterSize::KeyBytes4,
+    ]
+    .iter()
+    .copied()
+    .find(|&size| capacity_for_fpp(size, LEVEL_SCHEME, MatchMode::Any, LEVEL_FPP) >= n)
+    .unwrap_or(FilterSize::KeyBytes5)
+}
+
+#[cfg(feature = "serde")]
+impl<H, T> serde::Serialize for FilterCascade<H, T>
+where
+    H: BuildHasher,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.levels.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, H, T> serde::Deserialize<'de> for FilterCascade<H, T>
+where
+    H: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self {
+            levels: Vec::deserialize(deserializer)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    proptest! {
+        #[test]
+        fn prop_exact(
+            universe in prop::collection::hash_set(any::<u64>(), 0..2000),
+            ratio in 0_usize..10,
+        ) {
+            let universe = universe.into_iter().collect::<Vec<_>>();
+            let (members, non_members) = universe.split_at(universe.len() * ratio / 10);
+
+            let cascade =
+                FilterCascade::build(StableBuildHasher::default(), members, non_members).unwrap();
+
+            for v in members {
+                assert!(cascade.contains(v));
+            }
+            for v in non_members {
+                assert!(!cascade.contains(v));
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let cascade =
+            FilterCascade::<_, u32>::build(StableBuildHasher::default(), &[], &[1, 2]).unwrap();
+        assert_eq!(cascade.levels(), 0);
+        assert!(!cascade.contains(&1));
+    }
+
+    #[test]
+    fn test_levels_shrink() {
+        let members = (0..1000_u32).collect::<Vec<_>>();
+        let non_members = (1000..50_000_u32).collect::<Vec<_>>();
+
+        let cascade =
+            FilterCascade::build(StableBuildHasher::default(), &members, &non_members).unwrap();
+        assert!(cascade.levels() > 1);
+
+        // The first level is sized for the members only.
+        assert_eq!(cascade.levels[0].key_size(), FilterSize::KeyBytes2);
+        assert!(non_members.iter().all(|v| !cascade.contains(v)));
+    }
+
+    #[test]
+    fn test_diverged() {
+        assert_eq!(
+            FilterCascade::build(StableBuildHasher::default(), &[1, 2, 3], &[3]),
+            Err(Error::CascadeDiverged)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let members = (0..100_u32).collect::<Vec<_>>();
+        let non_members = (100..1000_u32).collect::<Vec<_>>();
+        let cascade =
+            FilterCascade::build(StableBuildHasher::default(), &members, &non_members).unwrap();
+
+        let encoded = bincode::serialize(&cascade).unwrap();
+        let got: FilterCascade<StableBuildHasher, u32> = bincode::deserialize(&encoded).unwrap();
+
+        assert_eq!(got, cascade);
+        for v in &members {
+            assert!(got.contains(v));
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::{Bitmap, Bloom2, FilterSize, ProbeScheme};
+
+/// The default number of bitmap keys buffered by a [`ConcurrentWriter`]
+/// before they are merged into the shared filter.
+pub const DEFAULT_WRITE_BUFFER_CAPACITY: usize = 1024;
+
+/// A per-thread writer to a shared [`Bloom2`] filter that buffers inserts,
+/// merging them into the filter in batches.
+///
+/// Inserting into a filter shared between threads requires acquiring its
+/// lock for every insert, serialising writers and contending with readers. A
+/// `ConcurrentWriter` hashes each item without holding the lock, and appends
+/// its bitmap keys to a private buffer. Once full, the write lock is acquired
+/// briefly to merge the buffered keys in a single pass (see
+/// [`Bitmap::set_many()`]):
+///
+/// ```rust
+/// use std::sync::{Arc, RwLock};
+/// use bloom2::{Bloom2, ConcurrentWriter};
+///
+/// let filter = Arc::new(RwLock::new(Bloom2::default()));
+///
+/// let handles = (0..4_u64)
+///     .map(|t| {
+///         let mut writer = ConcurrentWriter::new(&filter);
+///         std::thread::spawn(move || {
+///             for v in (t * 1000)..((t + 1) * 1000) {
+///                 writer.insert(&v);
+///             }
+///             // Dropping the writer flushes any buffered keys.
+///         })
+///     })
+///     .collect::<Vec<_>>();
+///
+/// for h in handles {
+///     h.join().unwrap();
+/// }
+///
+/// assert!(filter.read().unwrap().contains(&3999));
+/// ```
+///
+/// Items are not visible to readers of the shared filter until they are
+/// [flushed](ConcurrentWriter::flush), either explicitly, when the buffer is
+/// full, or when the writer is dropped.
+///
+/// The writer hashes items with a clone of the hasher, [`FilterSize`] and
+/// [`ProbeScheme`] of the shared filter at the time the writer was
+/// constructed.
+#[derive(Debug)]
+pub struct ConcurrentWriter<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    filter: Arc<RwLock<Bloom2<H, B, T>>>,
+
+    hasher: H,
+    key_size: FilterSize,
+    probe_scheme: ProbeScheme,
+
+    /// The bitmap keys pending a merge into the shared filter.
+    pending: Vec<usize>,
+    capacity: usize,
+}
+
+impl<H, B, T> ConcurrentWriter<H, B, T>
+where
+    H: BuildHasher + Clone,
+    B: Bitmap,
+    T: Hash,
+{
+    /// Construct a `ConcurrentWriter` to `filter`, buffering up to
+    /// [`DEFAULT_WRITE_BUFFER_CAPACITY`] bitmap keys.
+    pub fn new(filter: &Arc<RwLock<Bloom2<H, B, T>>>) -> Self {
+        Self::with_capacity(filter, DEFAULT_WRITE_BUFFER_CAPACITY)
+    }
+
+    /// Construct a `ConcurrentWriter` to `filter`, buffering up to `capacity`
+    /// bitmap keys before merging them into `filter`.
+    ///
+    /// Each insert buffers one key per probe (see [`ProbeScheme`]).
+    pub fn with_capacity(filter: &Arc<RwLock<Bloom2<H, B, T>>>, capacity: usize) -> Self {
+        let (hasher, key_size, probe_scheme) = {
+            let f = filter.read().unwrap_or_else(PoisonError::into_inner);
+            (f.hasher().clone(), f.key_size(), f.probe_scheme())
+        };
+
+        Self {
+            filter: Arc::clone(filter),
+            hasher,
+            key_size,
+            probe_scheme,
+            pending: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Buffer `data` for insertion into the shared filter, merging the
+    /// buffer into the filter if it is full.
+    pub fn insert(&mut self, data: &'_ T) {
+        let hash = self.hasher.hash_one(data);
+        self.pending
+            .extend(self.probe_scheme.keys(hash, self.key_size));
+
+        if self.pending.len() >= self.capacity {
+            self.flush();
+        }
+    }
+}
+
+impl<H, B, T> ConcurrentWriter<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    /// Merge all buffered keys into the shared filter, making the buffered
+    /// items visible to readers.
+    pub fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        self.filter
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .bitmap_mut()
+            .set_many(&mut self.pending);
+        self.pending.clear();
+    }
+
+    /// Return the number of bitmap keys pending a merge into the shared
+    /// filter.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl<H, B, T> Drop for ConcurrentWriter<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use super::*;
+    use crate::{BloomFilterBuilder, CompressedBitmap, StagedBitmap, VecBitmap};
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    fn build<B: Bitmap>() -> Bloom2<StableBuildHasher, B, u32> {
+        BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .with_bitmap::<B>()
+            .size(FilterSize::KeyBytes3)
+            .probe_scheme(ProbeScheme::DoubleHashing { probes: 5 })
+            .build()
+    }
+
+    #[test]
+    fn test_flush() {
+        let filter = Arc::new(RwLock::new(build::<CompressedBitmap>()));
+
+        let mut w = ConcurrentWriter::with_capacity(&filter, 10);
+        w.insert(&42);
+        assert_eq!(w.pending(), 5);
+        assert!(!filter.read().unwrap().contains(&42));
+
+        // Filling the buffer merges the keys.
+        w.insert(&1);
+        assert_eq!(w.pending(), 0);
+        assert!(filter.read().unwrap().contains(&42));
+        assert!(filter.read().unwrap().contains(&1));
+
+        w.insert(&7);
+        w.flush();
+        assert_eq!(w.pending(), 0);
+        assert!(filter.read().unwrap().contains(&7));
+
+        // Dropping the writer merges the keys.
+        w.insert(&8);
+        drop(w);
+        assert!(filter.read().unwrap().contains(&8));
+    }
+
+    fn assert_threads<B>()
+    where
+        B: Bitmap + PartialEq + std::fmt::Debug + Send + Sync + 'static,
+    {
+        let filter = Arc::new(RwLock::new(build::<B>()));
+
+        let handles = (0..4_u32)
+            .map(|t| {
+                let mut w = ConcurrentWriter::with_capacity(&filter, 64);
+                std::thread::spawn(move || {
+                    for v in (t * 500)..((t + 1) * 500) {
+                        w.insert(&v);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        // The result matches inserting every item in turn.
+        let mut want = build::<B>();
+        for v in 0..2000 {
+            want.insert(&v);
+        }
+        assert_eq!(*filter.read().unwrap(), want);
+    }
+
+    #[test]
+    fn test_threads() {
+        assert_threads::<VecBitmap>();
+        assert_threads::<CompressedBitmap>();
+    }
+
+    #[test]
+    fn test_staged_set_many() {
+        let mut got = build::<StagedBitmap>();
+        got.insert(&1);
+
+        let mut w = ConcurrentWriter::new(&Arc::new(RwLock::new(got.clone())));
+        w.insert(&2);
+        w.flush();
+
+        got.insert(&2);
+        let got = got.bitmap().clone().into_inner();
+        let want = w.filter.read().unwrap().bitmap().clone().into_inner();
+        assert_eq!(got, want);
+    }
+}
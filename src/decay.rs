@@ -0,0 +1,219 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+/// The number of counters updated for each key.
+const PROBES: u64 = 4;
+
+/// An exponentially-decaying counting filter, approximating the recent
+/// activity of each key within a fixed amount of memory.
+///
+/// Each [observation](DecayingFilter::observe) of a key increments a small
+/// set of counters selected by the hash of the key, and the
+/// [rate](DecayingFilter::estimate_rate) of a key is estimated as the smallest
+/// of its counters. Periodically calling [`DecayingFilter::decay()`] halves
+/// every counter, so an observation contributes `2^-n` to the estimate after
+/// `n` decays and the estimates reflect recent activity:
+///
+/// ```rust
+/// use bloom2::DecayingFilter;
+///
+/// let mut filter = DecayingFilter::new(1024);
+///
+/// for _ in 0..8 {
+///     filter.observe(&"10.0.0.1");
+/// }
+/// filter.observe(&"10.0.0.2");
+///
+/// assert_eq!(filter.estimate_rate(&"10.0.0.1"), 8);
+///
+/// // One decay period later, the activity of each key has halved.
+/// filter.decay();
+/// assert_eq!(filter.estimate_rate(&"10.0.0.1"), 4);
+/// assert_eq!(filter.estimate_rate(&"10.0.0.2"), 0);
+/// ```
+///
+/// A key observed `r` times in every decay period converges to an estimate of
+/// approximately `2r` immediately before each decay.
+///
+/// As with a count-min sketch, the estimate may exceed the true (decayed)
+/// number of observations when keys share counters, but never underestimates
+/// it. The probability of an overestimate grows with the number of distinct
+/// active keys relative to the number of counters.
+#[derive(Debug, Clone)]
+pub struct DecayingFilter<T, H = RandomState>
+where
+    H: BuildHasher,
+{
+    hasher: H,
+
+    /// The counters, a power of 2 in length.
+    counters: Vec<u32>,
+
+    _key_type: PhantomData<T>,
+}
+
+impl<T> DecayingFilter<T, RandomState>
+where
+    T: Hash,
+{
+    /// Construct a `DecayingFilter` of at least `counters` number of 32-bit
+    /// counters.
+    ///
+    /// The number of counters is rounded up to the next power of 2, and
+    /// should be several times the number of keys expected to be active
+    /// within a few decay periods.
+    pub fn new(counters: usize) -> Self {
+        Self::with_hasher(RandomState::default(), counters)
+    }
+}
+
+impl<T, H> DecayingFilter<T, H>
+where
+    T: Hash,
+    H: BuildHasher,
+{
+    /// Construct a `DecayingFilter` using `hasher` to hash keys, as described
+    /// in [`DecayingFilter::new()`].
+    pub fn with_hasher(hasher: H, counters: usize) -> Self {
+        Self {
+            hasher,
+            counters: vec![0; counters.max(PROBES as usize).next_power_of_two()],
+            _key_type: PhantomData,
+        }
+    }
+
+    /// Record an observation of `key`.
+    pub fn observe(&mut self, key: &T) {
+        let hash = self.hasher.hash_one(key);
+        for slot in slots(hash, self.counters.len()) {
+            self.counters[slot] = self.counters[slot].saturating_add(1);
+        }
+    }
+
+    /// Returns the estimated number of recent observations of `key`, each
+    /// weighted by `2^-n` after `n` [decays](DecayingFilter::decay).
+    pub fn estimate_rate(&self, key: &T) -> u32 {
+        let hash = self.hasher.hash_one(key);
+        slots(hash, self.counters.len())
+            .map(|slot| self.counters[slot])
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Halve every counter, decaying the estimated rate of every key.
+    ///
+    /// This is typically called at a fixed interval, which determines the
+    /// half-life of each observation.
+    pub fn decay(&mut self) {
+        for v in &mut self.counters {
+            *v >>= 1;
+        }
+    }
+
+    /// Forget all observations, retaining the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.counters.iter_mut().for_each(|v| *v = 0);
+    }
+
+    /// Returns the number of counters in the filter.
+    pub fn counters(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Returns the hasher used to hash keys.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+}
+
+/// Return the index of each of the [`PROBES`] counters for `hash` in a filter
+/// of `width` counters, a power of 2.
+fn slots(hash: u64, width: usize) -> impl Iterator<Item = usize> {
+    let h1 = hash;
+    let h2 = (hash >> 32) | 1;
+
+    (0..PROBES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) as usize) & (width - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    fn new_filter(counters: usize) -> DecayingFilter<u32, StableBuildHasher> {
+        DecayingFilter::with_hasher(StableBuildHasher::default(), counters)
+    }
+
+    #[quickcheck]
+    fn test_never_underestimates(observations: Vec<u8>, decays: u8) {
+        let mut f = new_filter(64);
+
+        let mut want = std::collections::HashMap::new();
+        for &v in &observations {
+            f.observe(&(v as u32));
+            *want.entry(v as u32).or_insert(0_u32) += 1;
+        }
+
+        for _ in 0..decays % 8 {
+            f.decay();
+        }
+
+        for (v, n) in want {
+            assert!(f.estimate_rate(&v) >= n >> (decays % 8));
+        }
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut f = new_filter(1024);
+        assert_eq!(f.counters(), 1024);
+        assert_eq!(f.estimate_rate(&1), 0);
+
+        for _ in 0..10 {
+            f.observe(&1);
+        }
+        assert_eq!(f.estimate_rate(&1), 10);
+
+        f.decay();
+        assert_eq!(f.estimate_rate(&1), 5);
+        f.decay();
+        assert_eq!(f.estimate_rate(&1), 2);
+
+        f.clear();
+        assert_eq!(f.estimate_rate(&1), 0);
+    }
+
+    #[test]
+    fn test_steady_rate() {
+        let mut f = new_filter(1024);
+
+        // A key observed 10 times per decay period converges to
+        // approximately twice its rate.
+        for _ in 0..20 {
+            for _ in 0..10 {
+                f.observe(&1);
+            }
+            f.decay();
+        }
+        for _ in 0..10 {
+            f.observe(&1);
+        }
+
+        assert!((18..=20).contains(&f.estimate_rate(&1)));
+    }
+
+    #[test]
+    fn test_saturate() {
+        let mut f = new_filter(4);
+        f.counters.iter_mut().for_each(|v| *v = u32::MAX);
+
+        f.observe(&1);
+        assert_eq!(f.estimate_rate(&1), u32::MAX);
+    }
+}
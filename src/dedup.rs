@@ -0,0 +1,125 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Bitmap, Bloom2};
+
+/// An extension trait adding [`dedup_approx()`](DedupApproxExt::dedup_approx)
+/// to all iterators of hashable items.
+pub trait DedupApproxExt: Iterator + Sized
+where
+    Self::Item: Hash,
+{
+    /// Return an iterator yielding only the items not previously seen by
+    /// `filter`, inserting each yielded item into `filter`.
+    ///
+    /// ```rust
+    /// use bloom2::{Bloom2, DedupApproxExt};
+    ///
+    /// let mut filter = Bloom2::default();
+    ///
+    /// let unique = vec!["a", "b", "a", "c", "b"]
+    ///     .into_iter()
+    ///     .dedup_approx(&mut filter)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert_eq!(unique, ["a", "b", "c"]);
+    /// assert!(filter.contains(&"c"));
+    /// ```
+    ///
+    /// Items are checked against (and inserted into) `filter` as the
+    /// iterator is advanced, so `filter` can be reused to deduplicate across
+    /// several iterators. An item is never yielded twice, but a false
+    /// positive lookup causes an item that was never seen to be skipped.
+    fn dedup_approx<H, B>(
+        self,
+        filter: &mut Bloom2<H, B, Self::Item>,
+    ) -> DedupApprox<'_, Self, H, B>
+    where
+        H: BuildHasher,
+        B: Bitmap,
+    {
+        DedupApprox { iter: self, filter }
+    }
+}
+
+impl<I> DedupApproxExt for I
+where
+    I: Iterator,
+    I::Item: Hash,
+{
+}
+
+/// An iterator that yields only the items not previously seen by a
+/// [`Bloom2`] filter.
+///
+/// See [`DedupApproxExt::dedup_approx()`].
+#[derive(Debug)]
+pub struct DedupApprox<'a, I, H, B>
+where
+    I: Iterator,
+    H: BuildHasher,
+    B: Bitmap,
+{
+    iter: I,
+    filter: &'a mut Bloom2<H, B, I::Item>,
+}
+
+impl<I, H, B> Iterator for DedupApprox<'_, I, H, B>
+where
+    I: Iterator,
+    I::Item: Hash,
+    H: BuildHasher,
+    B: Bitmap,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let filter = &mut *self.filter;
+        self.iter.find(|v| {
+            if filter.contains(v) {
+                return false;
+            }
+            filter.insert(v);
+            true
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::hash::BuildHasherDefault;
+
+    use proptest::prelude::*;
+    use twox_hash::XxHash64;
+
+    use super::*;
+    use crate::{BloomFilterBuilder, FilterSize};
+
+    proptest! {
+        #[test]
+        fn prop_dedup(values in prop::collection::vec(0_u32..100, 0..200)) {
+            let mut filter = BloomFilterBuilder::hasher(BuildHasherDefault::<XxHash64>::default())
+                .size(FilterSize::KeyBytes3)
+                .build();
+
+            let got = values.iter().copied().dedup_approx(&mut filter).collect::<Vec<_>>();
+
+            // No item is yielded twice, and every yielded item is in the
+            // input.
+            let unique = got.iter().collect::<HashSet<_>>();
+            assert_eq!(unique.len(), got.len());
+            for v in &got {
+                assert!(values.contains(v));
+            }
+
+            // All items are in the filter afterwards.
+            for v in &values {
+                assert!(filter.contains(v));
+            }
+        }
+    }
+}
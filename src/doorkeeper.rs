@@ -0,0 +1,313 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    bloom::capacity_for_fpp, Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize, MatchMode,
+    ProbeScheme,
+};
+
+/// The number of counter rows in the frequency sketch.
+const DEPTH: u64 = 4;
+
+/// The number of 4-bit counters packed into each sketch word.
+const COUNTERS_PER_WORD: usize = 16;
+
+/// The maximum value of a 4-bit counter.
+const MAX_COUNT: u64 = 15;
+
+/// The number of accesses recorded per unit of capacity before the recorded
+/// frequencies are aged.
+const SAMPLE_FACTOR: usize = 10;
+
+/// The false positive probability of the doorkeeper filter when full.
+const DOORKEEPER_FPP: f64 = 0.01;
+
+/// The default minimum estimated frequency of an admitted key.
+const DEFAULT_THRESHOLD: u8 = 2;
+
+/// A [TinyLFU] cache admission policy, admitting keys that are accessed
+/// frequently and rejecting one-hit wonders.
+///
+/// A `Doorkeeper` records each access to a key, estimating how often each key
+/// has recently been accessed. The first access to a key is recorded in a
+/// [`Bloom2`] filter (the "doorkeeper"), and subsequent accesses are counted
+/// in a small count-min sketch of 4-bit counters, so the large number of keys
+/// only ever accessed once do not pollute the sketch.
+///
+/// [`Doorkeeper::admit()`] records an access and returns `true` if the key has
+/// been accessed at least twice (or the configured
+/// [threshold](Doorkeeper::threshold)):
+///
+/// ```rust
+/// use bloom2::Doorkeeper;
+///
+/// let mut doorkeeper = Doorkeeper::new(1000);
+///
+/// assert!(!doorkeeper.admit(&"user-42"));
+/// assert!(doorkeeper.admit(&"user-42"));
+/// ```
+///
+/// A cache implementing W-TinyLFU can instead compare the
+/// [frequency](Doorkeeper::frequency) of a candidate against that of the
+/// eviction victim, admitting the candidate only if it is accessed more often.
+///
+/// After `10 * capacity` accesses, the doorkeeper is cleared and every counter
+/// is halved, so the frequencies reflect recent accesses and keys that are no
+/// longer popular are eventually forgotten.
+///
+/// [TinyLFU]: https://arxiv.org/abs/1512.00727
+#[derive(Debug, Clone)]
+pub struct Doorkeeper<T, H = RandomState>
+where
+    H: BuildHasher,
+{
+    filter: Bloom2<H, CompressedBitmap, T>,
+
+    /// [`DEPTH`] rows of 4-bit counters, packed [`COUNTERS_PER_WORD`] to a
+    /// word.
+    counters: Vec<u64>,
+
+    /// The number of counters in each row, a power of 2.
+    width: usize,
+
+    /// The number of accesses since the frequencies were last aged.
+    accesses: usize,
+    sample_size: usize,
+    threshold: u8,
+}
+
+impl<T> Doorkeeper<T, RandomState>
+where
+    T: Hash,
+{
+    /// Construct a `Doorkeeper` tracking the frequencies of approximately
+    /// `capacity` keys, typically the maximum number of entries in the cache.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(RandomState::default(), capacity)
+    }
+}
+
+impl<T, H> Doorkeeper<T, H>
+where
+    T: Hash,
+    H: BuildHasher,
+{
+    /// Construct a `Doorkeeper` using `hasher` to hash keys, as described in
+    /// [`Doorkeeper::new()`].
+    pub fn with_hasher(hasher: H, capacity: usize) -> Self {
+        let width = capacity.max(COUNTERS_PER_WORD).next_power_of_two();
+        let sample_size = capacity.max(1).saturating_mul(SAMPLE_FACTOR);
+
+        Self {
+            filter: BloomFilterBuilder::hasher(hasher)
+                .size(doorkeeper_size(sample_size))
+                .build(),
+            counters: vec![0; width / COUNTERS_PER_WORD * DEPTH as usize],
+            width,
+            accesses: 0,
+            sample_size,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Set the minimum estimated [frequency](Doorkeeper::frequency) of a key
+    /// admitted by [`Doorkeeper::admit()`], defaulting to 2.
+    pub fn threshold(self, threshold: u8) -> Self {
+        Self { threshold, ..self }
+    }
+
+    /// Record an access to `key`, returning `true` if it has been accessed
+    /// frequently enough to be admitted to the cache.
+    pub fn admit(&mut self, key: &T) -> bool {
+        let hash = self.filter.hasher().hash_one(key);
+        self.record(hash);
+        let frequency = self.estimate(hash);
+
+        self.accesses += 1;
+        if self.accesses >= self.sample_size {
+            self.age();
+        }
+
+        frequency >= self.threshold
+    }
+
+    /// Returns the estimated number of recent accesses to `key`, between 0
+    /// and 16.
+    ///
+    /// The estimate may exceed the true number of accesses, but never
+    /// underestimates it (until the frequencies are aged).
+    pub fn frequency(&self, key: &T) -> u8 {
+        self.estimate(self.filter.hasher().hash_one(key))
+    }
+
+    /// Forget all recorded accesses, retaining the allocated memory for
+    /// reuse.
+    pub fn clear(&mut self) {
+        self.filter.clear();
+        self.counters.iter_mut().for_each(|v| *v = 0);
+        self.accesses = 0;
+    }
+
+    fn record(&mut self, hash: u64) {
+        if !self.filter.contains_hash(hash) {
+            self.filter.insert_hash(hash);
+            return;
+        }
+
+        for (word, shift) in self.slots(hash) {
+            if (self.counters[word] >> shift) & MAX_COUNT < MAX_COUNT {
+                self.counters[word] += 1 << shift;
+            }
+        }
+    }
+
+    fn estimate(&self, hash: u64) -> u8 {
+        if !self.filter.contains_hash(hash) {
+            return 0;
+        }
+
+        let count = self
+            .slots(hash)
+            .map(|(word, shift)| (self.counters[word] >> shift) & MAX_COUNT)
+            .min()
+            .unwrap_or_default();
+
+        count as u8 + 1
+    }
+
+    /// Halve every counter and clear the doorkeeper filter.
+    fn age(&mut self) {
+        for v in &mut self.counters {
+            *v = (*v >> 1) & 0x7777_7777_7777_7777;
+        }
+        self.filter.clear();
+        self.accesses = 0;
+    }
+
+    /// Return the word index and bit shift of the counter for `hash` in each
+    /// row of the sketch.
+    fn slots(&self, hash: u64) -> impl Iterator<Item = (usize, u32)> {
+        // Rehash to decorrelate the counters from the doorkeeper bits.
+        let h1 = hash.wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(32);
+        let h2 = (hash >> 32) | 1;
+        let width = self.width;
+
+        (0..DEPTH).map(move |row| {
+            let counter = (h1.wrapping_add(row.wrapping_mul(h2)) as usize) & (width - 1);
+            let index = row as usize * width + counter;
+            (
+                index / COUNTERS_PER_WORD,
+                (index % COUNTERS_PER_WORD) as u32 * 4,
+            )
+        })
+    }
+}
+
+/// Return the smallest [`FilterSize`] holding `n` keys within the
+/// [`DOORKEEPER_FPP`].
+fn doorkeeper_size(n: usize) -> FilterSize {
+    [
+        FilterSize::KeyBytes1,
+        FilterSize::KeyBytes2,
+        FilterSize::KeyBytes3,
+        FilterSize::KeyBytes4,
+    ]
+    .iter()
+    .copied()
+    .find(|&size| capacity_for_fpp(size, ProbeScheme::Split, MatchMode::Any, DOORKEEPER_FPP) >= n)
+    .unwrap_or(FilterSize::KeyBytes5)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    fn new_doorkeeper(capacity: usize) -> Doorkeeper<u32, StableBuildHasher> {
+        Doorkeeper::with_hasher(StableBuildHasher::default(), capacity)
+    }
+
+    #[quickcheck]
+    fn test_never_underestimates(accesses: Vec<u8>) {
+        // A sample size exceeding the accesses, so no aging occurs.
+        let mut d = new_doorkeeper(accesses.len() + 1);
+
+        let mut want = std::collections::HashMap::new();
+        for &v in &accesses {
+            d.admit(&(v as u32));
+            *want.entry(v as u32).or_insert(0) += 1;
+        }
+
+        for (v, n) in want {
+            assert!(d.frequency(&v) >= n.min(16) as u8);
+        }
+    }
+
+    #[test]
+    fn test_admit() {
+        let mut d = new_doorkeeper(100);
+        assert_eq!(d.frequency(&1), 0);
+
+        // One-hit wonders are rejected.
+        assert!(!d.admit(&1));
+        assert_eq!(d.frequency(&1), 1);
+        assert!(d.admit(&1));
+        assert_eq!(d.frequency(&1), 2);
+
+        let mut d = new_doorkeeper(100).threshold(4);
+        assert!(!d.admit(&1));
+        assert!(!d.admit(&1));
+        assert!(!d.admit(&1));
+        assert!(d.admit(&1));
+
+        d.clear();
+        assert_eq!(d.frequency(&1), 0);
+    }
+
+    #[test]
+    fn test_saturate() {
+        let mut d = new_doorkeeper(1000);
+        for _ in 0..100 {
+            d.admit(&1);
+        }
+        assert_eq!(d.frequency(&1), 16);
+    }
+
+    #[test]
+    fn test_aging() {
+        let mut d = new_doorkeeper(1);
+        for _ in 0..9 {
+            d.admit(&1);
+        }
+        assert_eq!(d.frequency(&1), 9);
+
+        // The 10th access ages the frequencies, halving the 9 counted
+        // accesses and clearing the doorkeeper.
+        d.admit(&1);
+        assert_eq!(d.frequency(&1), 0);
+        d.admit(&1);
+        assert_eq!(d.frequency(&1), 5);
+    }
+
+    #[test]
+    fn test_frequent_keys() {
+        let mut d = new_doorkeeper(1000);
+
+        // A few popular keys amongst many one-off accesses.
+        for i in 0..5000_u32 {
+            d.admit(&(i % 10));
+            d.admit(&(1_000_000 + i));
+        }
+
+        for i in 0..10 {
+            assert!(d.admit(&i));
+        }
+        let admitted = (0..1000).filter(|i| d.admit(&(2_000_000 + i))).count();
+        assert!(admitted < 50, "admitted {} one-hit wonders", admitted);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{FilterSize, InvariantError, MatchMode, ProbeScheme};
+
+/// Errors returned by the checked operations of this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The `key` exceeds the `max_key` the bitmap was initialised with.
+    KeyOutOfRange { key: usize, max_key: usize },
+
+    /// Bitmap data provided to a
+    /// [`BloomFilterBuilder`](crate::BloomFilterBuilder) for a filter of size
+    /// `bitmap` conflicts with the `requested` filter size.
+    SizeMismatch {
+        bitmap: FilterSize,
+        requested: FilterSize,
+    },
+
+    /// Two filters, or a filter and its serialised representation, were
+    /// constructed with different [`FilterSize`] values.
+    KeySizeMismatch {
+        ours: FilterSize,
+        theirs: FilterSize,
+    },
+
+    /// Two filters, or a filter and its serialised representation, were
+    /// constructed with different [`ProbeScheme`] values.
+    ProbeSchemeMismatch {
+        ours: ProbeScheme,
+        theirs: ProbeScheme,
+    },
+
+    /// Two filters, or a filter and its serialised representation, were
+    /// constructed with different [`MatchMode`] values.
+    MatchModeMismatch { ours: MatchMode, theirs: MatchMode },
+
+    /// An encoded filter specifies an unknown [`ProbeScheme`] identifier.
+    UnknownProbeScheme { scheme: u32 },
+
+    /// An encoded filter specifies an unknown [`MatchMode`] identifier.
+    UnknownMatchMode { mode: u32 },
+
+    /// A serialised filter records `got` probes per item, but its
+    /// [`FilterSize`] requires `expected`.
+    ProbeCountMismatch { expected: usize, got: usize },
+
+    /// Two filters, or a filter and its serialised representation, were
+    /// constructed with a different hashing algorithm or hasher seed.
+    HasherMismatch,
+
+    /// An encoded filter specifies `key_bytes` bytes per key, which is not a
+    /// valid [`FilterSize`].
+    InvalidKeySize { key_bytes: u32 },
+
+    /// The number of bits per key is not between 1 and 64.
+    InvalidKeyBits { key_bits: u32 },
+
+    /// A string could not be parsed as a [`FilterSize`].
+    ParseFilterSize,
+
+    /// An encoded filter contains an inconsistent bitmap.
+    InvalidBitmap(InvariantError),
+
+    /// The bitmap bytes of a filter are `got` bytes long, but its
+    /// [`FilterSize`] requires `want` bytes.
+    ByteLength { want: usize, got: usize },
+
+    /// Two [`ShardedFilterSet`](crate::ShardedFilterSet) instances have a
+    /// different number of shards.
+    ShardCountMismatch { ours: usize, theirs: usize },
+
+    /// A [`ShardedFilterSet`](crate::ShardedFilterSet) was constructed from
+    /// no shards.
+    NoShards,
+
+    /// A [`PartitionedFilterSet`](crate::PartitionedFilterSet) does not
+    /// contain the partition `id`.
+    UnknownPartition { id: u64 },
+
+    /// A [`FilterDelta`](crate::FilterDelta) contains the changes after
+    /// version `from`, but the filter it is applied to is at the older
+    /// `version`, so the changes in between are missing.
+    VersionGap { from: u64, version: u64 },
+
+    /// A [`FilterCascade`](crate::FilterCascade) did not eliminate all false
+    /// positives within the maximum number of levels, such as when an item
+    /// is both a member and a non-member.
+    CascadeDiverged,
+
+    /// A filter using `scheme` cannot be folded, as its bit indexes are not
+    /// preserved by folding (see [`Bloom2::fold_to()`](crate::Bloom2::fold_to)).
+    FoldUnsupported { scheme: ProbeScheme },
+
+    /// A filter of size `from` cannot be folded to the larger (or smaller than
+    /// 64 bit) size `to`.
+    InvalidFoldSize { from: FilterSize, to: FilterSize },
+
+    /// A rebuild was cancelled by its progress callback after inserting
+    /// `inserted` items (see
+    /// [`Bloom2::rebuild_with()`](crate::Bloom2::rebuild_with)).
+    RebuildCancelled { inserted: usize },
+
+    /// No filters were provided to combine into a single filter.
+    NoFilters,
+
+    /// The allocator failed to provide `bytes` bytes of memory for a bitmap.
+    AllocationFailed { bytes: usize },
+
+    /// The keys of a filter of size `key_size` cannot be represented as a
+    /// `usize` on the target platform (see
+    /// [`FilterSize::checked_max_bits()`]).
+    KeySpaceTooLarge { key_size: FilterSize },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KeyOutOfRange { key, max_key } => {
+                write!(f, "key {} exceeds max key {}", key, max_key)
+            }
+            Self::SizeMismatch { bitmap, requested } => write!(
+                f,
+                "bitmap data for filter size {:?} conflicts with requested size {:?}",
+                bitmap, requested
+            ),
+            Self::KeySizeMismatch { ours, theirs } => write!(
+                f,
+                "filter size {:?} conflicts with filter size {:?}",
+                ours, theirs
+            ),
+            Self::ProbeSchemeMismatch { ours, theirs } => write!(
+                f,
+                "probe scheme {:?} conflicts with probe scheme {:?}",
+                ours, theirs
+            ),
+            Self::MatchModeMismatch { ours, theirs } => write!(
+                f,
+                "match mode {:?} conflicts with match mode {:?}",
+                ours, theirs
+            ),
+            Self::UnknownProbeScheme { scheme } => {
+                write!(f, "unknown probe scheme {}", scheme)
+            }
+            Self::UnknownMatchMode { mode } => write!(f, "unknown match mode {}", mode),
+            Self::ProbeCountMismatch { expected, got } => write!(
+                f,
+                "filter records {} probes per item, expected {}",
+                got, expected
+            ),
+            Self::HasherMismatch => {
+                write!(f, "filters were built with a different hasher or seed")
+            }
+            Self::InvalidKeySize { key_bytes } => {
+                write!(f, "invalid filter size of {} bytes per key", key_bytes)
+            }
+            Self::InvalidKeyBits { key_bits } => {
+                write!(f, "invalid filter size of {} bits per key", key_bits)
+            }
+            Self::ParseFilterSize => write!(
+                f,
+                "invalid filter size, expected a number of bytes or a FilterSize variant"
+            ),
+            Self::InvalidBitmap(e) => write!(f, "invalid bitmap: {}", e),
+            Self::ByteLength { want, got } => {
+                write!(f, "bitmap is {} bytes, expected {}", got, want)
+            }
+            Self::ShardCountMismatch { ours, theirs } => {
+                write!(f, "{} shards conflicts with {} shards", ours, theirs)
+            }
+            Self::NoShards => write!(f, "a sharded filter set requires at least 1 shard"),
+            Self::UnknownPartition { id } => write!(f, "unknown partition {}", id),
+            Self::VersionGap { from, version } => write!(
+                f,
+                "delta from version {} cannot be applied to version {}",
+                from, version
+            ),
+            Self::CascadeDiverged => write!(
+                f,
+                "filter cascade did not converge - is an item both a member and a non-member?"
+            ),
+            Self::FoldUnsupported { scheme } => {
+                write!(
+                    f,
+                    "filters using probe scheme {:?} cannot be folded",
+                    scheme
+                )
+            }
+            Self::InvalidFoldSize { from, to } => {
+                write!(f, "filter size {} cannot be folded to size {}", from, to)
+            }
+            Self::RebuildCancelled { inserted } => {
+                write!(f, "rebuild cancelled after inserting {} items", inserted)
+            }
+            Self::NoFilters => write!(f, "at least 1 filter is required to compute a union"),
+            Self::AllocationFailed { bytes } => {
+                write!(f, "failed to allocate {} bytes for bitmap", bytes)
+            }
+            Self::KeySpaceTooLarge { key_size } => write!(
+                f,
+                "filter size {} exceeds the {} bit address space of this platform",
+                key_size,
+                usize::BITS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidBitmap(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -1,3 +1,8 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use crate::Error;
+
 // TODO: run test w/ FilterSize3 distribution, try xor with other
 
 /// FilterSize bounds the allocated size and false-positive rate of a
@@ -10,11 +15,24 @@
 ///
 /// The value of FilterSize controls the `k` property of the filter: `k =
 /// input_length_bytes / FilterSize`.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+///
+/// If the `serde` feature is enabled, a `FilterSize` is serialised as a
+/// single `u8` holding its number of bits per key ([`FilterSize::key_bits()`])
+/// rather than the variant name, so `KeyBytes2` is serialised as `16` and
+/// `KeyBits(20)` as `20`. This representation is part of the stable wire
+/// format and does not change if variants are renamed - deserialising a
+/// value outside of 1 to 64 returns an error.
+///
+/// The byte-aligned variants retain the discriminants of earlier releases
+/// (`KeyBytes1 = 1` to `KeyBytes8 = 8`), but since v0.6 [`FilterSize::KeyBits`]
+/// carries a value, so they can no longer be read with an `as` cast - use
+/// [`FilterSize::key_bytes()`] instead.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "allocative", derive(allocative::Allocative))]
+#[repr(u8)]
 pub enum FilterSize {
-    /// 1 byte / 8 bits per key results in a bloom filter with a minimum memory
-    /// usage of ~4 bytes and a maximum memory usage of 36 bytes.
+    /// 1 byte / 8 bits per key results in a bloom filter that does not
+    /// allocate when empty, and has a maximum memory usage of 48 bytes.
     ///
     /// The false positive probability using `k=1` (a single byte key per entry)
     /// grows proportionally to the number of entries in the filter:
@@ -44,15 +62,15 @@ pub enum FilterSize {
     ///
     /// The probability of false positives reaches 1-in-2 after 80 entries.
     ///
-    /// An empty sparse bloom filter would require 1x64 bit block map entries (8
-    /// bytes) to map 4 64 bit blocks, containing a total of 256 bits (memory
-    /// saving: 75%)
+    /// An empty sparse bloom filter does not allocate, lazily allocating up to
+    /// 1 top map entry (a 64 bit word and its index) and 1x64 bit block map
+    /// entry (24 bytes) to map 4 64 bit blocks, containing a total of 256 bits.
     ///
     KeyBytes1 = 1,
 
-    /// 2 bytes / 16 bits per key results in a bloom filter with a minimum
-    /// memory usage of ~1024 bytes and a maximum memory usage of ~8KB when
-    /// fully populated.
+    /// 2 bytes / 16 bits per key results in a bloom filter that does not
+    /// allocate when empty, and has a maximum memory usage of ~8KB when fully
+    /// populated.
     ///
     /// When using a 64bit hash (4x2 byte keys, `k=4`) the probability of a
     /// false positive is:
@@ -82,15 +100,15 @@ pub enum FilterSize {
     ///
     /// The probability of false positives reaches 1-in-2 after 30118 entries.
     ///
-    /// An empty sparse bloom filter would require 16x64 bit block map entries
-    /// (128 bytes) to map 1024 64 bit blocks, containing a total of 65536 bits
-    /// (memory saving: 98.4375%)
+    /// An empty sparse bloom filter does not allocate, lazily allocating up to
+    /// 1 top map entry (a 64 bit word and its index, 16 bytes) to map 1024 64
+    /// bit blocks, containing a total of 65536 bits.
     ///
     KeyBytes2 = 2,
 
-    /// 3 bytes / 24 bits per key results in a bloom filter with a minimum
-    /// memory usage of ~262KB bytes and a maximum memory usage of ~2MB when
-    /// fully populated.
+    /// 3 bytes / 24 bits per key results in a bloom filter that does not
+    /// allocate when empty, and has a maximum memory usage of ~2MB when fully
+    /// populated.
     ///
     /// When using a 64bit hash (2x3 byte keys, `k=2`) the probability of a
     /// false positive is:
@@ -121,14 +139,14 @@ pub enum FilterSize {
     /// The probability of false positives reaches 1-in-2 after 10300768
     /// entries.
     ///
-    /// An empty sparse bloom filter would require 4096x64 bit block map entries
-    /// (32768 bytes) to map 262144 64 bit blocks, containing a total of
-    /// 16777216 bits (memory saving: 98.4375%)
+    /// An empty sparse bloom filter does not allocate, lazily allocating up to
+    /// 64 top map entries (1024 bytes) to map 262144 64 bit blocks,
+    /// containing a total of 16777216 bits.
     ///
     KeyBytes3 = 3,
 
-    /// 4 bytes / 32 bits per key results in a bloom filter with a minimum
-    /// memory usage of ~67MB and a maximum memory usage of ~603MB when fully
+    /// 4 bytes / 32 bits per key results in a bloom filter that does not
+    /// allocate when empty, and has a maximum memory usage of ~603MB when fully
     /// populated.
     ///
     /// When using a 64bit hash (2x4 byte keys, `k=2`) the probability of a
@@ -160,18 +178,15 @@ pub enum FilterSize {
     /// The probability of false positives reaches 1-in-2 after 2636996484
     /// entries.
     ///
-    /// An empty sparse bloom filter would require 1048576x64 bit block map
-    /// entries (8388608 bytes) to map 67108864 64 bit blocks, containing a
-    /// total of 4294967296 bits (memory saving: 98.4375%)
+    /// An empty sparse bloom filter does not allocate, lazily allocating up to
+    /// 16384 top map entries (262144 bytes) to map 67108864 64 bit blocks,
+    /// containing a total of 4294967296 bits.
     ///
     KeyBytes4 = 4,
 
-    /// 5 bytes / 32 bits per key results in a bloom filter with a minimum
-    /// memory usage of ~17GB and a maximum memory usage of ~1117GB when fully
-    /// populated.
-    ///
-    /// If you actually need this get in touch - I have some ideas for reducing
-    /// the memory footprint even further.
+    /// 5 bytes / 40 bits per key results in a bloom filter that does not
+    /// allocate when empty, and has a maximum memory usage of ~1117GB when
+    /// fully populated.
     ///
     /// When using a 64bit hash (1x5 byte keys, `k=1`) the probability of a
     /// false positive is:
@@ -202,9 +217,529 @@ pub enum FilterSize {
     /// The probability of false positives reaches 1-in-2 after 762123384786
     /// entries.
     ///
-    /// An empty sparse bloom filter would require 268435456x64 bit block map
-    /// entries (2147483648 bytes) to map 17179869184 64 bit blocks, containing
-    /// a total of 1099511627776 bits (memory saving: 98.4375%)
+    /// An empty sparse bloom filter does not allocate, lazily allocating up to
+    /// 4194304 top map entries (67108864 bytes) to map 17179869184 64 bit
+    /// blocks, containing a total of 1099511627776 bits.
     ///
     KeyBytes5 = 5,
+
+    /// 6 bytes / 48 bits per key, with a key space of 2^48 bits.
+    ///
+    /// When using a 64bit hash with [`ProbeScheme::Split`] this yields 2 keys
+    /// per entry (one of 6 bytes, one of 2 bytes), so prefer
+    /// [`ProbeScheme::DoubleHashing`] to spread every probe over the full key
+    /// space.
+    ///
+    /// Every level of the default [`CompressedBitmap`] grows with the number of
+    /// populated blocks, so an empty filter of this size does not allocate,
+    /// and each populated block costs at most 4 words. For write-heavy
+    /// workloads, a [`SparseBitmap`] inserts new blocks in `O(log n)` time.
+    ///
+    /// [`ProbeScheme::Split`]: crate::ProbeScheme::Split
+    /// [`ProbeScheme::DoubleHashing`]: crate::ProbeScheme::DoubleHashing
+    /// [`CompressedBitmap`]: crate::CompressedBitmap
+    /// [`SparseBitmap`]: crate::SparseBitmap
+    KeyBytes6 = 6,
+
+    /// 7 bytes / 56 bits per key, with a key space of 2^56 bits.
+    ///
+    /// See [`FilterSize::KeyBytes6`] for the recommended probe scheme and
+    /// bitmap.
+    KeyBytes7 = 7,
+
+    /// 8 bytes / 64 bits per key, covering the full 64bit hash.
+    ///
+    /// [`ProbeScheme::Split`] yields a single key per entry. See
+    /// [`FilterSize::KeyBytes6`] for the recommended probe scheme and bitmap.
+    ///
+    /// [`ProbeScheme::Split`]: crate::ProbeScheme::Split
+    KeyBytes8 = 8,
+
+    /// An arbitrary number of bits per key, from 1 to 64, with a key space of
+    /// `2^n` bits.
+    ///
+    /// The byte-aligned sizes grow the key space by a factor of 256 at each
+    /// step, while bit-granular sizes fill the gaps between them - such as a
+    /// `KeyBits(20)` filter of 2^20 bits (at most 128KiB), or a `KeyBits(26)`
+    /// filter of 2^26 bits (at most 8MiB):
+    ///
+    /// ```rust
+    /// use bloom2::{BloomFilterBuilder, FilterSize};
+    ///
+    /// let mut b = BloomFilterBuilder::default()
+    ///     .size(FilterSize::KeyBits(20))
+    ///     .build();
+    ///
+    /// b.insert(&"bananas");
+    /// assert!(b.contains(&"bananas"));
+    /// ```
+    ///
+    /// [`ProbeScheme::Split`] yields `ceil(64 / n)` keys per entry, the last
+    /// of which is shorter if `n` does not divide 64.
+    ///
+    /// A multiple of 8 bits is equal to the byte-aligned variant of the same
+    /// size (`KeyBits(16) == KeyBytes2`). Building a filter with a value
+    /// outside of 1 to 64 returns [`Error::InvalidKeyBits`] - prefer
+    /// [`FilterSize::from_key_bits()`] to construct this variant.
+    ///
+    /// [`ProbeScheme::Split`]: crate::ProbeScheme::Split
+    KeyBits(u8),
+}
+
+/// Sizes are compared by their number of bits per key.
+impl PartialEq for FilterSize {
+    fn eq(&self, other: &Self) -> bool {
+        self.key_bits() == other.key_bits()
+    }
+}
+
+impl Eq for FilterSize {}
+
+impl FilterSize {
+    /// All byte-aligned `FilterSize` variants, in ascending order of key size.
+    ///
+    /// ```rust
+    /// use bloom2::FilterSize;
+    ///
+    /// for size in FilterSize::ALL.iter().take(3) {
+    ///     println!("{} bits per key", size.key_bits());
+    /// }
+    /// ```
+    pub const ALL: [FilterSize; 8] = [
+        Self::KeyBytes1,
+        Self::KeyBytes2,
+        Self::KeyBytes3,
+        Self::KeyBytes4,
+        Self::KeyBytes5,
+        Self::KeyBytes6,
+        Self::KeyBytes7,
+        Self::KeyBytes8,
+    ];
+
+    /// Return the `FilterSize` using `key_bytes` bytes per key, or [`None`] if
+    /// `key_bytes` is not between 1 and 8.
+    ///
+    /// ```rust
+    /// use bloom2::FilterSize;
+    ///
+    /// assert_eq!(FilterSize::from_key_bytes(6), Some(FilterSize::KeyBytes6));
+    /// assert_eq!(FilterSize::from_key_bytes(9), None);
+    /// ```
+    pub fn from_key_bytes(key_bytes: u32) -> Option<Self> {
+        let index = key_bytes.checked_sub(1)?;
+        Self::ALL.get(index as usize).copied()
+    }
+
+    /// Return the `FilterSize` using `key_bits` bits per key, or [`None`] if
+    /// `key_bits` is not between 1 and 64.
+    ///
+    /// Multiples of 8 return the byte-aligned variant.
+    ///
+    /// ```rust
+    /// use bloom2::FilterSize;
+    ///
+    /// assert_eq!(FilterSize::from_key_bits(20), Some(FilterSize::KeyBits(20)));
+    /// assert_eq!(FilterSize::from_key_bits(24), Some(FilterSize::KeyBytes3));
+    /// assert_eq!(FilterSize::from_key_bits(65), None);
+    /// ```
+    pub fn from_key_bits(key_bits: u32) -> Option<Self> {
+        match key_bits {
+            1..=64 if key_bits.is_multiple_of(8) => Self::from_key_bytes(key_bits / 8),
+            1..=64 => Some(Self::KeyBits(key_bits as u8)),
+            _ => None,
+        }
+    }
+
+    /// Return the number of bits of hash per key, between 1 and 64.
+    ///
+    /// The bits of an invalid [`FilterSize::KeyBits`] value are clamped to
+    /// this range, though it is rejected when building a filter.
+    pub const fn key_bits(&self) -> u32 {
+        match *self {
+            Self::KeyBytes1 => 8,
+            Self::KeyBytes2 => 16,
+            Self::KeyBytes3 => 24,
+            Self::KeyBytes4 => 32,
+            Self::KeyBytes5 => 40,
+            Self::KeyBytes6 => 48,
+            Self::KeyBytes7 => 56,
+            Self::KeyBytes8 => 64,
+            Self::KeyBits(0) => 1,
+            Self::KeyBits(bits @ 1..=64) => bits as u32,
+            Self::KeyBits(_) => 64,
+        }
+    }
+
+    /// Return the number of bits addressable by a filter of this size,
+    /// `2^key_bits`, saturating at [`usize::MAX`].
+    ///
+    /// Use [`FilterSize::checked_max_bits()`] to detect a key space that does
+    /// not fit in a `usize` on the target platform.
+    ///
+    /// This is a `const fn`, so it can be used to size storage or check a
+    /// configuration at compile time:
+    ///
+    /// ```rust
+    /// use bloom2::FilterSize;
+    ///
+    /// const BITS: usize = FilterSize::KeyBits(12).max_bits();
+    /// assert_eq!(BITS, 4096);
+    /// ```
+    pub const fn max_bits(&self) -> usize {
+        // A FilterSize::KeyBytes8 key space of 2^64 bits is not representable,
+        // and saturates to usize::MAX - the largest key remains addressable as
+        // the bitmaps accept keys up to and including this value.
+        match 2_usize.checked_pow(self.key_bits()) {
+            Some(bits) => bits,
+            None => usize::MAX,
+        }
+    }
+
+    /// Return the number of bits addressable by a filter of this size as
+    /// [`FilterSize::max_bits()`] does, or [`None`] if the keys of this size
+    /// cannot be represented as a `usize` on the target platform.
+    ///
+    /// ```rust
+    /// use bloom2::FilterSize;
+    ///
+    /// assert_eq!(FilterSize::KeyBytes2.checked_max_bits(), Some(65536));
+    ///
+    /// // A 40 bit key space is only addressable on 64-bit targets.
+    /// let addressable = FilterSize::KeyBytes5.checked_max_bits().is_some();
+    /// assert_eq!(addressable, usize::BITS >= 40);
+    /// ```
+    ///
+    /// A key space of exactly `usize::BITS` bits (such as
+    /// [`FilterSize::KeyBytes8`] on a 64-bit target) is addressable, with
+    /// the largest key being [`usize::MAX`].
+    pub const fn checked_max_bits(&self) -> Option<usize> {
+        if self.key_bits() > usize::BITS {
+            return None;
+        }
+        Some(self.max_bits())
+    }
+
+    /// Estimate the number of bytes a [`CompressedBitmap`] of this size uses
+    /// after `n_entries` distinct, uniformly hashed inserts using
+    /// [`ProbeScheme::Split`].
+    ///
+    /// Each insert sets [`ProbeScheme::probes()`] bits, and a block (or block
+    /// map word) is allocated once any bit within it is set, so after `s`
+    /// uniformly distributed bits are set, `b * (1 - e^(-s / b))` of `b`
+    /// blocks are expected to be allocated. This predicts the footprint
+    /// between the empty and fully populated extremes documented for each
+    /// size:
+    ///
+    /// ```rust
+    /// use bloom2::FilterSize;
+    ///
+    /// let size = FilterSize::KeyBytes3;
+    ///
+    /// // A lightly loaded filter allocates a block per probe.
+    /// println!("{} bytes", size.estimated_bytes_at_load(1_000));
+    ///
+    /// // A heavily loaded filter allocates every block.
+    /// assert!(size.estimated_bytes_at_load(1_000_000) > 2_000_000);
+    /// ```
+    ///
+    /// The estimate excludes any excess capacity of the underlying
+    /// allocations (see [`CompressedBitmap::shrink_to_fit()`]).
+    ///
+    /// [`CompressedBitmap`]: crate::CompressedBitmap
+    /// [`CompressedBitmap::shrink_to_fit()`]: crate::CompressedBitmap::shrink_to_fit
+    /// [`ProbeScheme::Split`]: crate::ProbeScheme::Split
+    /// [`ProbeScheme::probes()`]: crate::ProbeScheme::probes
+    pub fn estimated_bytes_at_load(&self, n_entries: usize) -> usize {
+        if n_entries == 0 {
+            return 0;
+        }
+
+        let n = n_entries as f64;
+        let bits = self.key_bits();
+
+        // Each full chunk of the hash is a key uniformly distributed over the
+        // whole bitmap, while the shorter last chunk (if any) only reaches
+        // the lowest 2^rem bits.
+        let full = (64 / bits) as f64 * n;
+        let rem = 64 % bits;
+        let short = if rem == 0 { 0.0 } else { n };
+
+        // The expected number of `slots` (of `slot_bits` bits each) with at
+        // least one bit set.
+        let populated = |slots: f64, slot_bits: f64| {
+            let short_slots = ((1_u64 << rem) as f64 / slot_bits).ceil().min(slots);
+            let p_full = -(-full / slots).exp_m1();
+            let p_both = -(-full / slots - short / short_slots).exp_m1();
+            (slots - short_slots) * p_full + short_slots * p_both
+        };
+
+        let blocks = self.max_bits().div_ceil(u64::BITS as usize) as f64;
+        let block_map_words = (blocks / u64::BITS as f64).ceil();
+        let top_map_words = (block_map_words / u64::BITS as f64).ceil();
+
+        // Each populated top map word is stored with its index.
+        let words = populated(blocks, u64::BITS as f64)
+            + populated(block_map_words, (u64::BITS * u64::BITS) as f64)
+            + 2.0 * populated(top_map_words, u64::BITS.pow(3) as f64);
+        (words * std::mem::size_of::<usize>() as f64).round() as usize
+    }
+
+    /// Return the number of bytes of hash per key, or [`None`] if the key
+    /// size is not a multiple of 8 bits.
+    pub fn key_bytes(&self) -> Option<u32> {
+        let bits = self.key_bits();
+        bits.is_multiple_of(8).then_some(bits / 8)
+    }
+}
+
+/// Convert a number of bytes per key into a `FilterSize`, returning
+/// [`Error::InvalidKeySize`] if it is not between 1 and 8.
+impl TryFrom<u8> for FilterSize {
+    type Error = Error;
+
+    fn try_from(key_bytes: u8) -> Result<Self, Self::Error> {
+        Self::from_key_bytes(key_bytes as u32).ok_or(Error::InvalidKeySize {
+            key_bytes: key_bytes as u32,
+        })
+    }
+}
+
+/// Format the variant name, such as `KeyBytes2` or `KeyBits(20)`.
+impl std::fmt::Display for FilterSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.key_bytes() {
+            Some(bytes) => write!(f, "KeyBytes{}", bytes),
+            None => write!(f, "KeyBits({})", self.key_bits()),
+        }
+    }
+}
+
+/// Parse either the number of bytes per key (`"2"`) or the variant name
+/// (`"KeyBytes2"` or `"KeyBits(20)"`, case insensitive) into a `FilterSize`.
+///
+/// ```rust
+/// use bloom2::FilterSize;
+///
+/// assert_eq!("3".parse(), Ok(FilterSize::KeyBytes3));
+/// assert_eq!("keybytes3".parse(), Ok(FilterSize::KeyBytes3));
+/// assert_eq!("KeyBits(20)".parse(), Ok(FilterSize::KeyBits(20)));
+/// assert_eq!(FilterSize::KeyBytes3.to_string().parse(), Ok(FilterSize::KeyBytes3));
+/// ```
+impl FromStr for FilterSize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(digits) = strip_prefix(s, BITS_PREFIX) {
+            let key_bits = digits
+                .strip_prefix('(')
+                .and_then(|v| v.strip_suffix(')'))
+                .and_then(|v| v.parse().ok())
+                .ok_or(Error::ParseFilterSize)?;
+            return Self::from_key_bits(key_bits).ok_or(Error::InvalidKeyBits { key_bits });
+        }
+
+        let digits = strip_prefix(s, BYTES_PREFIX).unwrap_or(s);
+        let key_bytes = digits.parse().map_err(|_| Error::ParseFilterSize)?;
+        Self::from_key_bytes(key_bytes).ok_or(Error::InvalidKeySize { key_bytes })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for FilterSize {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.key_bits() as u8)
+    }
+}
+
+/// Deserialise the number of bits per key.
+///
+/// Human-readable formats also accept the variant name (such as
+/// `"KeyBytes2"`) written by v0.5 and earlier.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FilterSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(FilterSizeVisitor)
+        } else {
+            deserializer.deserialize_u8(FilterSizeVisitor)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+struct FilterSizeVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for FilterSizeVisitor {
+    type Value = FilterSize;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a number of bits per key, or a FilterSize variant name")
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        let key_bits = u32::try_from(v).unwrap_or(u32::MAX);
+        FilterSize::from_key_bits(key_bits)
+            .ok_or(Error::InvalidKeyBits { key_bits })
+            .map_err(E::custom)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        // Only variant names are accepted - a bare number in a string is
+        // ambiguous between bits and bytes.
+        let v = v.trim();
+        if strip_prefix(v, BYTES_PREFIX).is_none() && strip_prefix(v, BITS_PREFIX).is_none() {
+            return Err(E::custom(Error::ParseFilterSize));
+        }
+        v.parse().map_err(E::custom)
+    }
+}
+
+/// The prefixes of the variant names accepted by [`FilterSize::from_str()`].
+const BYTES_PREFIX: &str = "KeyBytes";
+const BITS_PREFIX: &str = "KeyBits";
+
+/// Strip `prefix` from `s`, ignoring ASCII case.
+fn strip_prefix<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    match s.get(..prefix.len()) {
+        Some(v) if v.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasher, BuildHasherDefault};
+
+    use super::*;
+    use crate::{Bitmap, CompressedBitmap, ProbeScheme};
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    #[test]
+    fn test_byte_discriminants() {
+        for (i, size) in FilterSize::ALL.iter().enumerate() {
+            // SAFETY: a repr(u8) enum begins with its u8 discriminant.
+            let got = unsafe { *(size as *const FilterSize).cast::<u8>() };
+            assert_eq!(got as usize, i + 1);
+        }
+    }
+
+    #[test]
+    fn test_checked_max_bits() {
+        for bits in 1..=64 {
+            let size = FilterSize::from_key_bits(bits).unwrap();
+            let got = size.checked_max_bits();
+
+            if bits > usize::BITS {
+                assert_eq!(got, None, "{} bits", bits);
+            } else {
+                // Never wraps - the largest key is always addressable.
+                assert_eq!(got, Some(size.max_bits()), "{} bits", bits);
+                assert!(got.unwrap() >= usize::MAX >> (usize::BITS - bits));
+            }
+        }
+    }
+
+    #[test]
+    fn test_estimated_bytes_at_load() {
+        assert_eq!(FilterSize::KeyBytes2.estimated_bytes_at_load(0), 0);
+
+        // A fully populated filter allocates every block.
+        assert_eq!(FilterSize::KeyBytes1.estimated_bytes_at_load(10_000), 56);
+
+        let hasher = StableBuildHasher::default();
+        for &(size, n) in &[
+            (FilterSize::KeyBytes2, 100),
+            (FilterSize::KeyBytes2, 10_000),
+            (FilterSize::KeyBits(20), 5_000),
+            (FilterSize::KeyBytes3, 1_000),
+            (FilterSize::KeyBytes3, 20_000),
+            (FilterSize::KeyBytes5, 1_000),
+        ] {
+            let mut b = CompressedBitmap::new(size.max_bits());
+            for v in 0..n {
+                for key in ProbeScheme::Split.keys(hasher.hash_one(v), size) {
+                    b.set(key, true);
+                }
+            }
+            b.shrink_to_fit();
+
+            let got = b.byte_size() - std::mem::size_of_val(&b);
+            let want = size.estimated_bytes_at_load(n);
+            let error = (got as f64 - want as f64).abs() / got as f64;
+            assert!(error < 0.05, "{} x {}: got {}, want {}", size, n, got, want);
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        for (i, &size) in FilterSize::ALL.iter().enumerate() {
+            let key_bytes = i as u8 + 1;
+            assert_eq!(size.key_bytes(), Some(key_bytes as u32));
+            assert_eq!(size.key_bits(), 8 * key_bytes as u32);
+            assert_eq!(FilterSize::KeyBits(8 * key_bytes), size);
+            assert_eq!(FilterSize::try_from(key_bytes), Ok(size));
+            assert_eq!(size.to_string().parse(), Ok(size));
+            assert_eq!(key_bytes.to_string().parse(), Ok(size));
+            assert_eq!(format!("{}", size), format!("{:?}", size));
+        }
+
+        assert_eq!(
+            FilterSize::try_from(0),
+            Err(Error::InvalidKeySize { key_bytes: 0 })
+        );
+        assert_eq!(
+            "KeyBytes9".parse::<FilterSize>(),
+            Err(Error::InvalidKeySize { key_bytes: 9 })
+        );
+        for s in &["", "KeyBytes", "bananas", "-1", "2.0", "KeyByte2"] {
+            assert_eq!(
+                s.parse::<FilterSize>(),
+                Err(Error::ParseFilterSize),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        for size in FilterSize::ALL {
+            let json = serde_json::to_string(&size).unwrap();
+            assert_eq!(json, size.key_bits().to_string());
+            assert_eq!(serde_json::from_str::<FilterSize>(&json).unwrap(), size);
+        }
+
+        let size = serde_json::from_str::<FilterSize>("20").unwrap();
+        assert_eq!(size, FilterSize::KeyBits(20));
+        assert_eq!(serde_json::to_string(&size).unwrap(), "20");
+
+        // Aligned bit widths decode as the byte-sized variant.
+        assert!(matches!(
+            serde_json::from_str::<FilterSize>("24").unwrap(),
+            FilterSize::KeyBytes3
+        ));
+
+        assert!(serde_json::from_str::<FilterSize>("0").is_err());
+        assert!(serde_json::from_str::<FilterSize>("65").is_err());
+        assert!(serde_json::from_str::<FilterSize>("-1").is_err());
+
+        // The variant names written by v0.5 and earlier are still accepted.
+        assert_eq!(
+            serde_json::from_str::<FilterSize>("\"KeyBytes1\"").unwrap(),
+            FilterSize::KeyBytes1
+        );
+        assert_eq!(
+            serde_json::from_str::<FilterSize>("\"KeyBits(20)\"").unwrap(),
+            FilterSize::KeyBits(20)
+        );
+        assert!(serde_json::from_str::<FilterSize>("\"KeyBytes9\"").is_err());
+        assert!(serde_json::from_str::<FilterSize>("\"2\"").is_err());
+
+        assert_eq!(bincode::serialize(&FilterSize::KeyBytes2).unwrap(), [16]);
+    }
 }
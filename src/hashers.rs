@@ -0,0 +1,290 @@
+//! Ready-made [`BuildHasher`](std::hash::BuildHasher) implementations for
+//! faster hashing than the [SipHash] based default.
+//!
+//! [SipHash]: https://131002.net/siphash/
+
+#[cfg(feature = "xxhash")]
+use std::hash::{BuildHasher, Hash};
+use std::hash::{BuildHasherDefault, Hasher};
+
+#[cfg(feature = "xxhash")]
+use crate::{Bitmap, Bloom2};
+#[cfg(any(feature = "ahash", feature = "xxhash"))]
+use crate::{BloomFilterBuilder, CompressedBitmap};
+
+/// The number of items hashed per batch by [`Bloom2::insert_all_hashed()`].
+#[cfg(feature = "xxhash")]
+const HASH_BATCH_LEN: usize = 64;
+
+/// A [`BuildHasher`](std::hash::BuildHasher) for [`IdentityHasher`], for use with keys that are
+/// already uniformly distributed 64-bit values.
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, IdentityBuildHasher};
+///
+/// let mut filter = BloomFilterBuilder::hasher(IdentityBuildHasher::default()).build();
+///
+/// let content_hash: u64 = 0x9e3779b97f4a7c15;
+/// filter.insert(&content_hash);
+/// assert!(filter.contains(&content_hash));
+/// ```
+pub type IdentityBuildHasher = BuildHasherDefault<IdentityHasher>;
+
+/// A [`Hasher`] that returns the integer written to it, unchanged.
+///
+/// Hashing a pre-hashed key (such as a content hash or a randomly generated
+/// ID) is wasted work - the `IdentityHasher` uses the key itself as the hash,
+/// removing the hashing cost from inserts and lookups entirely.
+///
+/// # Uniformity
+///
+/// A [`Bloom2`](crate::Bloom2) splits the 64-bit hash into
+/// [`FilterSize`](crate::FilterSize) chunks, each selecting a bit in the
+/// filter. Keys MUST therefore be uniformly distributed across **all** 64
+/// bits. Keys that are not (such as sequential IDs, which vary only in their
+/// low bits) set the same few bits for every key, resulting in a very high
+/// false positive probability.
+///
+/// The `IdentityHasher` is intended to hash a single integer (such as a `u64`
+/// key). When other types are hashed, the hash is the last 8 bytes written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 << 8) | b as u64;
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.0 = i as u64;
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A deterministic [`Hasher`] and [`BuildHasher`](std::hash::BuildHasher)
+/// that returns `return_hash` for every value, for use in tests.
+///
+/// Each value inserted into a filter using a `MockHasher` sets the same,
+/// known bits, allowing tests of code embedding a [`Bloom2`](crate::Bloom2) to
+/// make stable assertions against the filter contents.
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, FilterSize, MockHasher};
+///
+/// let mut filter = BloomFilterBuilder::hasher(MockHasher { return_hash: 0x0102 })
+///     .size(FilterSize::KeyBytes1)
+///     .build();
+///
+/// filter.insert(&"anything");
+///
+/// // The key for each byte of the hash is set.
+/// assert!(filter.bitmap().get(0x01));
+/// assert!(filter.bitmap().get(0x02));
+/// assert!(filter.bitmap().get(0x00));
+/// assert_eq!(filter.bitmap().count_ones(), 3);
+/// ```
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MockHasher {
+    /// The hash returned for every value.
+    pub return_hash: u64,
+}
+
+#[cfg(feature = "test-util")]
+impl Hasher for MockHasher {
+    fn write(&mut self, _bytes: &[u8]) {}
+
+    fn finish(&self) -> u64 {
+        self.return_hash
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl std::hash::BuildHasher for MockHasher {
+    type Hasher = Self;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        *self
+    }
+}
+
+/// A randomly seeded [aHash] hasher.
+///
+/// [aHash]: https://github.com/tkaitchuck/aHash
+#[cfg(feature = "ahash")]
+pub type AHashBuildHasher = ahash::RandomState;
+
+/// A zero-seeded [xxHash] (XXH64) hasher.
+///
+/// Unlike [`AHashBuildHasher`], the hash of a value is identical across
+/// process restarts and machines, allowing a persisted filter to be restored
+/// and queried with a new instance of this hasher.
+///
+/// [xxHash]: https://github.com/Cyan4973/xxHash
+#[cfg(feature = "xxhash")]
+pub type XxHashBuildHasher = std::hash::BuildHasherDefault<twox_hash::XxHash64>;
+
+/// A zero-seeded [XXH3] (64-bit) hasher.
+///
+/// XXH3 selects an AVX2, SSE2 or NEON implementation at runtime when hashing
+/// inputs larger than 240 bytes, and is deterministic across process restarts
+/// and machines, as with [`XxHashBuildHasher`]. Filters using this hasher can
+/// insert items in bulk with [`Bloom2::insert_all_hashed()`].
+///
+/// [XXH3]: https://github.com/Cyan4973/xxHash
+#[cfg(feature = "xxhash")]
+pub type Xxh3BuildHasher = std::hash::BuildHasherDefault<twox_hash::XxHash3_64>;
+
+#[cfg(feature = "xxhash")]
+impl<B, T> Bloom2<Xxh3BuildHasher, B, T>
+where
+    B: Bitmap,
+    T: Hash,
+{
+    /// Insert all `items` into the filter, hashing them in batches.
+    ///
+    /// ```rust
+    /// use bloom2::{Bloom2, BloomFilterBuilder, CompressedBitmap, Xxh3BuildHasher};
+    ///
+    /// let mut filter: Bloom2<_, CompressedBitmap, _> =
+    ///     BloomFilterBuilder::hasher(Xxh3BuildHasher::default()).build();
+    ///
+    /// let items = (0..1_000).collect::<Vec<u32>>();
+    /// filter.insert_all_hashed(&items);
+    ///
+    /// assert!(items.iter().all(|v| filter.contains(v)));
+    /// ```
+    ///
+    /// The resulting filter is identical to calling [`Bloom2::insert()`] for
+    /// each item. Rather than interleaving hashing with bitmap writes, the
+    /// hashes of each batch of items are computed in a single tight loop
+    /// before their bits are set, keeping the vectorised XXH3 implementation
+    /// and its secret hot in cache, and allowing the CPU to overlap the
+    /// hashing of independent items. For inputs where hashing dominates the
+    /// insert cost (such as long strings), this raises insert throughput.
+    pub fn insert_all_hashed(&mut self, items: &[T]) {
+        let mut hashes = [0; HASH_BATCH_LEN];
+        for batch in items.chunks(HASH_BATCH_LEN) {
+            for (hash, item) in hashes.iter_mut().zip(batch) {
+                *hash = self.hasher().hash_one(item);
+            }
+            for &hash in &hashes[..batch.len()] {
+                self.insert_hash(hash);
+            }
+        }
+    }
+}
+
+/// The fastest hasher enabled by the crate features.
+///
+/// This is [`AHashBuildHasher`] if the `ahash` feature is enabled, otherwise
+/// [`XxHashBuildHasher`].
+#[cfg(feature = "ahash")]
+pub type FastBuildHasher = AHashBuildHasher;
+
+/// The fastest hasher enabled by the crate features.
+///
+/// This is [`AHashBuildHasher`] if the `ahash` feature is enabled, otherwise
+/// [`XxHashBuildHasher`].
+#[cfg(all(feature = "xxhash", not(feature = "ahash")))]
+pub type FastBuildHasher = XxHashBuildHasher;
+
+#[cfg(any(feature = "ahash", feature = "xxhash"))]
+impl BloomFilterBuilder<FastBuildHasher, CompressedBitmap> {
+    /// Initialise a `BloomFilterBuilder` that unless changed, will construct a
+    /// `Bloom2` instance using a [2 byte key] and the [`FastBuildHasher`].
+    ///
+    /// ```rust
+    /// use bloom2::BloomFilterBuilder;
+    ///
+    /// let mut filter = BloomFilterBuilder::fast_hasher().build();
+    ///
+    /// filter.insert(&"fast");
+    /// assert!(filter.contains(&"fast"));
+    /// ```
+    ///
+    /// [2 byte key]: crate::FilterSize::KeyBytes2
+    pub fn fast_hasher() -> Self {
+        Self::hasher(FastBuildHasher::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomFilterBuilder;
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_xxhash_deterministic() {
+        use std::hash::BuildHasher;
+
+        let a = XxHashBuildHasher::default();
+        let b = XxHashBuildHasher::default();
+        assert_eq!(a.hash_one(42), b.hash_one(42));
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_insert_all_hashed() {
+        let items = (0..1_000)
+            .map(|v| format!("item-{}", v).repeat(v % 50))
+            .collect::<Vec<_>>();
+
+        let mut got: Bloom2<_, CompressedBitmap, _> =
+            BloomFilterBuilder::hasher(Xxh3BuildHasher::default()).build();
+        got.insert_all_hashed(&items);
+
+        let mut want: Bloom2<_, CompressedBitmap, _> =
+            BloomFilterBuilder::hasher(Xxh3BuildHasher::default()).build();
+        for v in &items {
+            want.insert(v);
+        }
+
+        assert_eq!(got.bitmap(), want.bitmap());
+        assert!(items.iter().all(|v| got.contains(v)));
+
+        got.insert_all_hashed(&[]);
+        assert_eq!(got.bitmap(), want.bitmap());
+    }
+
+    #[test]
+    fn test_identity() {
+        use std::hash::BuildHasher;
+
+        let h = IdentityBuildHasher::default();
+        assert_eq!(h.hash_one(0x0102030405060708_u64), 0x0102030405060708);
+        assert_eq!(h.hash_one(42_usize), 42);
+        assert_eq!(h.hash_one(42_u8), 42);
+
+        let mut b = BloomFilterBuilder::hasher(h).build();
+        b.insert(&0x0102030405060708_u64);
+
+        // Each key is a 2 byte chunk of the value.
+        for key in [0x0102, 0x0304, 0x0506, 0x0708] {
+            assert!(b.bitmap().get(key));
+        }
+        assert_eq!(b.bitmap().count_ones(), 4);
+    }
+
+    #[cfg(any(feature = "ahash", feature = "xxhash"))]
+    #[test]
+    fn test_fast_hasher() {
+        let mut b = BloomFilterBuilder::fast_hasher().build();
+        for v in 0..100 {
+            b.insert(&v);
+        }
+        for v in 0..100 {
+            assert!(b.contains(&v));
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize};
+
+/// A set of keys that records the most recent keys exactly, and older keys
+/// in a [`Bloom2`] filter.
+///
+/// The `window` most recently inserted (or re-inserted) distinct keys are
+/// held in an exact, least-recently-used set. When a key is evicted from the
+/// window it is inserted into the filter, so membership of recent keys is
+/// exact, while older history degrades gracefully to approximate membership
+/// using a fixed amount of memory.
+///
+/// This suits event deduplication, where most duplicates arrive shortly after
+/// the original event:
+///
+/// ```rust
+/// use bloom2::{FilterSize, HybridSet};
+///
+/// let mut seen = HybridSet::new(FilterSize::KeyBytes3, 2);
+///
+/// let events = ["a", "b", "a", "c", "d", "b"];
+/// let unique = events
+///     .iter()
+///     .filter(|&&v| seen.insert(v))
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(unique, [&"a", &"b", &"c", &"d"]);
+///
+/// // "c" and "d" are exact, while the evicted "a" is probably present.
+/// assert!(seen.is_recent(&"d"));
+/// assert!(!seen.is_recent(&"a"));
+/// assert!(seen.contains(&"a"));
+/// ```
+///
+/// A key inserted within the last `window` distinct keys is never reported as
+/// absent, and a key that was never inserted is only reported as present by a
+/// false positive of the filter, which holds only the evicted keys.
+#[derive(Debug, Clone)]
+pub struct HybridSet<T, H = RandomState>
+where
+    H: BuildHasher,
+{
+    /// The keys in the window, and the stamp of their most recent insert.
+    recent: HashMap<T, u64, H>,
+
+    /// The keys in insertion order, with the stamp at insert time.
+    ///
+    /// Re-inserting a key pushes a new entry rather than moving the existing
+    /// one, so an entry is only live if its stamp matches that in `recent`.
+    /// Stale entries are skipped when evicting, and discarded when the queue
+    /// grows to more than twice the window.
+    order: VecDeque<(T, u64)>,
+
+    /// The stamp of the next insert.
+    next_stamp: u64,
+
+    window: usize,
+    filter: Bloom2<H, CompressedBitmap, T>,
+}
+
+impl<T> HybridSet<T, RandomState>
+where
+    T: Hash + Eq + Clone,
+{
+    /// Construct a `HybridSet` holding the `window` most recent keys exactly,
+    /// and older keys in a filter of `key_size`.
+    pub fn new(key_size: FilterSize, window: usize) -> Self {
+        Self::with_hasher(RandomState::default(), key_size, window)
+    }
+}
+
+impl<T, H> HybridSet<T, H>
+where
+    T: Hash + Eq + Clone,
+    H: BuildHasher + Clone,
+{
+    /// Construct a `HybridSet` using `hasher` to hash keys, as described in
+    /// [`HybridSet::new()`].
+    pub fn with_hasher(hasher: H, key_size: FilterSize, window: usize) -> Self {
+        Self {
+            recent: HashMap::with_capacity_and_hasher(window, hasher.clone()),
+            order: VecDeque::with_capacity(window),
+            next_stamp: 0,
+            window,
+            filter: BloomFilterBuilder::hasher(hasher).size(key_size).build(),
+        }
+    }
+
+    /// Insert `key` as the most recent key, returning `true` if it was
+    /// **definitely** not present, or `false` if it was (exactly, or
+    /// probably) present.
+    pub fn insert(&mut self, key: T) -> bool {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+
+        let fresh = match self.recent.insert(key.clone(), stamp) {
+            Some(_) => false,
+            None => !self.filter.contains(&key),
+        };
+        self.order.push_back((key, stamp));
+
+        self.evict();
+        fresh
+    }
+
+    /// Returns `true` if `key` is **probably** present, or `false` if it is
+    /// **definitely** not.
+    ///
+    /// Keys within the window are answered exactly.
+    pub fn contains(&self, key: &T) -> bool {
+        self.recent.contains_key(key) || self.filter.contains(key)
+    }
+
+    /// Returns `true` if `key` is one of the `window` most recent distinct
+    /// keys.
+    pub fn is_recent(&self, key: &T) -> bool {
+        self.recent.contains_key(key)
+    }
+
+    /// Returns the number of keys held exactly, at most the window size.
+    pub fn recent_len(&self) -> usize {
+        self.recent.len()
+    }
+
+    /// Returns the maximum number of keys held exactly.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Returns the filter holding the keys evicted from the window.
+    pub fn filter(&self) -> &Bloom2<H, CompressedBitmap, T> {
+        &self.filter
+    }
+
+    /// Remove all keys, retaining the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.recent.clear();
+        self.order.clear();
+        self.filter.clear();
+    }
+
+    /// Move the least recently used keys in excess of the window into the
+    /// filter.
+    fn evict(&mut self) {
+        while self.recent.len() > self.window {
+            let (key, stamp) = self
+                .order
+                .pop_front()
+                .expect("every key in the window has a queue entry");
+
+            if self.recent.get(&key) == Some(&stamp) {
+                self.recent.remove(&key);
+                self.filter.insert(&key);
+            }
+        }
+
+        // Bound the memory used by stale entries of re-inserted keys.
+        if self.order.len() > self.window.max(1) * 2 {
+            let recent = &self.recent;
+            self.order
+                .retain(|(key, stamp)| recent.get(key) == Some(stamp));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    fn new_set(window: usize) -> HybridSet<u8, StableBuildHasher> {
+        HybridSet::with_hasher(StableBuildHasher::default(), FilterSize::KeyBytes3, window)
+    }
+
+    #[quickcheck]
+    fn test_recent_exact(keys: Vec<u8>, window: u8) {
+        let window = window as usize % 16;
+        let mut set = new_set(window);
+
+        // A model of the window, most recent last.
+        let mut want: Vec<u8> = Vec::new();
+        for &k in &keys {
+            let fresh = set.insert(k);
+            if want.contains(&k) {
+                assert!(!fresh);
+            }
+
+            want.retain(|&v| v != k);
+            want.push(k);
+            if want.len() > window {
+                want.remove(0);
+            }
+
+            assert_eq!(set.recent_len(), want.len());
+            for v in 0..=u8::MAX {
+                assert_eq!(set.is_recent(&v), want.contains(&v), "key {}", v);
+            }
+            assert!(set.order.len() <= window.max(1) * 2 + 1);
+        }
+
+        // No inserted key is ever reported as absent.
+        for k in &keys {
+            assert!(set.contains(k));
+        }
+    }
+
+    #[test]
+    fn test_eviction_to_filter() {
+        let mut set = new_set(2);
+
+        assert!(set.insert(1));
+        assert!(set.insert(2));
+        assert!(!set.filter().contains(&1));
+
+        // Re-inserting 1 makes 2 the least recently used key.
+        assert!(!set.insert(1));
+        assert!(set.insert(3));
+        assert!(set.is_recent(&1));
+        assert!(set.is_recent(&3));
+        assert!(!set.is_recent(&2));
+        assert!(set.filter().contains(&2));
+
+        // The evicted key is detected by the filter.
+        assert!(set.contains(&2));
+        assert!(!set.insert(2));
+
+        set.clear();
+        assert_eq!(set.recent_len(), 0);
+        assert!(!set.contains(&1));
+        assert!(!set.contains(&2));
+        assert_eq!(set.window(), 2);
+    }
+
+    #[test]
+    fn test_zero_window() {
+        let mut set = new_set(0);
+
+        assert!(set.insert(1));
+        assert_eq!(set.recent_len(), 0);
+        assert!(set.filter().contains(&1));
+        assert!(!set.insert(1));
+    }
+}
@@ -0,0 +1,259 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    bloom::try_key_size_to_bits, Bloom2, CompressedBitmap, Error, FilterParts, FilterSize,
+    InvariantError, MatchMode, ProbeScheme,
+};
+
+/// A [`Bloom2`] filter backed by a [`CompressedBitmap`], serialised with
+/// [serde] by v0.5 (and earlier) of this crate.
+///
+/// Filters serialised by v0.5 record their [`FilterSize`] by name, and store
+/// the bitmap as a single, uncompressed block map - this layout can no longer
+/// be deserialised as a [`Bloom2`]. A `LegacyBloom2` reads the old layout with
+/// any serde format, and [converts](LegacyBloom2::into_filter) it to the
+/// current representation:
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::{Bloom2, CompressedBitmap, LegacyBloom2};
+///
+/// let legacy = r#"{
+///     "bitmap": { "block_map": [1], "bitmap": [2], "max_key": 256 },
+///     "key_size": "KeyBytes1"
+/// }"#;
+///
+/// let legacy: LegacyBloom2 = serde_json::from_str(legacy).unwrap();
+/// let filter: Bloom2<_, CompressedBitmap, u32> =
+///     legacy.into_filter(RandomState::default()).unwrap();
+///
+/// assert!(filter.bitmap().get(1));
+/// ```
+///
+/// The hasher of a v0.5 filter was not serialised - the filter MUST be
+/// converted with a hasher producing the same hashes as the hasher of the
+/// original filter for [`Bloom2::contains()`] to return the expected result.
+/// v0.5 filters split each hash into big-endian chunks, and report an item as
+/// present if any chunk is set, matching the defaults of [`Bloom2`].
+///
+/// Debug builds of v0.5 also serialised the `max_key` of the bitmap - this is
+/// ignored by self-describing formats (such as JSON), but filters serialised
+/// with a non-self-describing format (such as [bincode]) are only readable if
+/// they were written by a release build.
+///
+/// [serde]: https://github.com/serde-rs/serde
+/// [bincode]: https://github.com/bincode-org/bincode
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct LegacyBloom2 {
+    bitmap: LegacyCompressedBitmap,
+    key_size: LegacyFilterSize,
+}
+
+/// The v0.5 layout of a [`CompressedBitmap`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+struct LegacyCompressedBitmap {
+    /// A bitmap of the allocated blocks, covering every block of the key
+    /// space.
+    ///
+    /// LSB is 0.
+    block_map: Vec<usize>,
+
+    /// The allocated blocks, in ascending block index order.
+    bitmap: Vec<usize>,
+}
+
+/// The v0.5 [`FilterSize`], serialised by variant name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+enum LegacyFilterSize {
+    KeyBytes1,
+    KeyBytes2,
+    KeyBytes3,
+    KeyBytes4,
+    KeyBytes5,
+}
+
+impl From<LegacyFilterSize> for FilterSize {
+    fn from(v: LegacyFilterSize) -> Self {
+        match v {
+            LegacyFilterSize::KeyBytes1 => Self::KeyBytes1,
+            LegacyFilterSize::KeyBytes2 => Self::KeyBytes2,
+            LegacyFilterSize::KeyBytes3 => Self::KeyBytes3,
+            LegacyFilterSize::KeyBytes4 => Self::KeyBytes4,
+            LegacyFilterSize::KeyBytes5 => Self::KeyBytes5,
+        }
+    }
+}
+
+impl LegacyBloom2 {
+    /// Returns the [`FilterSize`] of the legacy filter.
+    pub fn key_size(&self) -> FilterSize {
+        self.key_size.into()
+    }
+
+    /// Convert the legacy filter into a [`Bloom2`] using `hasher` to hash
+    /// items.
+    ///
+    /// Returns [`Error::InvalidBitmap`] if the block map does not mark the
+    /// same number of blocks as are stored, or marks a block outside of the
+    /// key space of the filter.
+    pub fn into_filter<H, T>(self, hasher: H) -> Result<Bloom2<H, CompressedBitmap, T>, Error>
+    where
+        H: BuildHasher,
+        T: Hash,
+    {
+        let LegacyCompressedBitmap { block_map, bitmap } = self.bitmap;
+        let key_size = FilterSize::from(self.key_size);
+
+        let marked = block_map.iter().map(|w| w.count_ones() as usize).sum();
+        if marked != bitmap.len() {
+            return Err(Error::InvalidBitmap(InvariantError::BitmapLength {
+                want: marked,
+                got: bitmap.len(),
+            }));
+        }
+
+        // The logical index of each block marked in the block map, in
+        // ascending order.
+        let indexes = block_map.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * usize::BITS as usize + bit)
+            })
+        });
+
+        let max_key = try_key_size_to_bits(key_size)?;
+        let blocks = max_key.div_ceil(usize::BITS as usize);
+        if let Some(index) = indexes.clone().find(|&i| i >= blocks) {
+            return Err(Error::InvalidBitmap(InvariantError::KeyOutOfRange {
+                key: index * usize::BITS as usize,
+                max_key,
+            }));
+        }
+
+        let bitmap = CompressedBitmap::from_blocks(max_key, indexes.zip(bitmap.iter().copied()));
+
+        Bloom2::from_parts(FilterParts {
+            hasher,
+            bitmap,
+            key_size,
+            probe_scheme: ProbeScheme::Split,
+            match_mode: MatchMode::Any,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use super::*;
+    use crate::BloomFilterBuilder;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    /// A filter of the values 42..100 serialised by v0.5, as in the
+    /// `compressed_bitmap` serialisation fixture of that version.
+    const LEGACY_FIXTURE: &str = r#"{
+        "bitmap": {
+            "block_map": [15],
+            "bitmap": [
+                18414653452446586751,
+                9218513282017460079,
+                18302035097798045183,
+                18442222331972544511
+            ],
+            "max_key": 256
+        },
+        "key_size": "KeyBytes1"
+    }"#;
+
+    #[test]
+    fn test_legacy_fixture() {
+        let legacy: LegacyBloom2 = serde_json::from_str(LEGACY_FIXTURE).unwrap();
+        assert_eq!(legacy.key_size(), FilterSize::KeyBytes1);
+
+        let got: Bloom2<_, CompressedBitmap, usize> =
+            legacy.into_filter(StableBuildHasher::default()).unwrap();
+
+        let mut want: Bloom2<_, CompressedBitmap, usize> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default())
+                .size(FilterSize::KeyBytes1)
+                .build();
+        for i in 42..100 {
+            want.insert(&i);
+        }
+
+        // Invariant: the converted filter matches one built by the current
+        // version.
+        for i in 0..256 {
+            assert_eq!(got.bitmap().get(i), want.bitmap().get(i));
+        }
+        for i in 42..100 {
+            assert!(got.contains(&i));
+        }
+
+        // The release layout omits the max key.
+        let mut release: serde_json::Value = serde_json::from_str(LEGACY_FIXTURE).unwrap();
+        release["bitmap"]
+            .as_object_mut()
+            .unwrap()
+            .remove("max_key")
+            .unwrap();
+        assert_eq!(
+            serde_json::from_value::<LegacyBloom2>(release).unwrap(),
+            serde_json::from_str::<LegacyBloom2>(LEGACY_FIXTURE).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_legacy_sparse_block_map() {
+        // Blocks 1 and 65 of a KeyBytes2 filter, in different block map
+        // words.
+        let legacy: LegacyBloom2 = serde_json::from_str(
+            r#"{"bitmap": {"block_map": [2, 2], "bitmap": [1, 4]}, "key_size": "KeyBytes2"}"#,
+        )
+        .unwrap();
+
+        let got: Bloom2<_, CompressedBitmap, u32> =
+            legacy.into_filter(StableBuildHasher::default()).unwrap();
+
+        let ones = (0..1 << 16)
+            .filter(|&i| got.bitmap().get(i))
+            .collect::<Vec<_>>();
+        assert_eq!(ones, [64, 65 * 64 + 2]);
+    }
+
+    #[test]
+    fn test_legacy_invalid_bitmap() {
+        let legacy: LegacyBloom2 = serde_json::from_str(
+            r#"{"bitmap": {"block_map": [3], "bitmap": [1]}, "key_size": "KeyBytes1"}"#,
+        )
+        .unwrap();
+
+        let got = legacy.into_filter::<_, u32>(StableBuildHasher::default());
+        assert_eq!(
+            got.unwrap_err(),
+            Error::InvalidBitmap(InvariantError::BitmapLength { want: 2, got: 1 })
+        );
+
+        // A block beyond the 4 blocks of a KeyBytes1 filter.
+        let legacy: LegacyBloom2 = serde_json::from_str(
+            r#"{"bitmap": {"block_map": [16], "bitmap": [1]}, "key_size": "KeyBytes1"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            legacy
+                .into_filter::<_, u32>(StableBuildHasher::default())
+                .unwrap_err(),
+            Error::InvalidBitmap(InvariantError::KeyOutOfRange {
+                key: 256,
+                max_key: 256
+            })
+        );
+    }
+}
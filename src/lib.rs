@@ -1,4 +1,4 @@
-//! bloom2 implements a 2-level bitmap to provide a sparse, lazily initialised,
+//! bloom2 implements a multi-level bitmap to provide a sparse, lazily initialised,
 //! high performance bloom filter with a reduced memory footprint.
 //!
 //! The memory usage of a sparse bloom filter grows proportionally with the load
@@ -20,17 +20,156 @@
 //!
 //! ## Features
 //!
+//! * `ahash` - provide the [aHash] based `AHashBuildHasher` and use it as the
+//!   `FastBuildHasher` of `BloomFilterBuilder::fast_hasher()`, disabled by
+//!   default
+//! * `allocative` - implement [allocative]'s `Allocative` trait for the filter
+//!   and bitmap types, attributing the heap memory of embedded filters in
+//!   memory profiles, disabled by default
+//! * `arbitrary` - implement [arbitrary]'s `Arbitrary` trait for the filter
+//!   and bitmap types, for use in property tests and fuzzers, disabled by
+//!   default
+//! * `bytes` - provide the [bytes] backed `BytesBitmap` and
+//!   `FrozenBytesBitmap`, and `BloomFilterBuilder::bytes()`, disabled by
+//!   default. Enable `serde` as well to serialise a `BytesBitmap` - since
+//!   v0.6, `serde` alone no longer enables the `bytes` dependency
+//! * `cli` - build the `bloom2` command line tool to build, query, merge and
+//!   inspect filter files, disabled by default
+//! * `fixedbitset` - implement `Bitmap` for [fixedbitset]'s `FixedBitSet`,
+//!   with conversions to and from `VecBitmap`, disabled by default
+//! * `huge-pages` - provide the `HugePageBitmap` dense bitmap, allocated from
+//!   transparent huge pages and optionally bound to a NUMA node (Linux only),
+//!   disabled by default
+//! * `prost` - provide the [prost] `Bloom2Proto` protobuf message (defined in
+//!   `proto/bloom2.proto`) and `Bloom2::to_proto()` / `Bloom2::from_proto()`
+//!   conversions, disabled by default
+//! * `saturating` - clamp out-of-range bitmap keys to the highest valid key
+//!   instead of panicking or aliasing other bits, for use with untrusted keys,
+//!   disabled by default
 //! * `serde` - enable serialisation with [serde], disabled by default
+//! * `simd` - select AVX2 implementations of bitmap combination operations at
+//!   runtime when supported by the CPU, disabled by default
+//! * `test-util` - provide the deterministic `MockHasher` for writing stable
+//!   assertions against filter contents in tests, disabled by default
+//! * `test-vectors` - provide `test_vectors()` to generate canonical filters,
+//!   hashes, probe indexes and serialised bytes for verifying implementations
+//!   in other languages, disabled by default
+//! * `xxhash` - provide the [xxHash] based `XxHashBuildHasher`, used as the
+//!   `FastBuildHasher` of `BloomFilterBuilder::fast_hasher()` if `ahash`
+//!   is not enabled, and the XXH3 based `Xxh3BuildHasher` with batched
+//!   `Bloom2::insert_all_hashed()`, disabled by default
+//! * `wasm` - export a [wasm-bindgen] JavaScript `Bloom2` class to build,
+//!   query and (de)serialise filters of strings in the browser, disabled by
+//!   default
 //!
+//! [aHash]: https://github.com/tkaitchuck/aHash
+//! [allocative]: https://github.com/facebookexperimental/allocative
+//! [arbitrary]: https://github.com/rust-fuzz/arbitrary
+//! [bytes]: https://github.com/tokio-rs/bytes
+//! [fixedbitset]: https://github.com/petgraph/fixedbitset
+//! [prost]: https://github.com/tokio-rs/prost
 //! [serde]: https://github.com/serde-rs/serde
+//! [wasm-bindgen]: https://github.com/rustwasm/wasm-bindgen
+//! [xxHash]: https://github.com/Cyan4973/xxHash
 //! [`Bloom2`]: crate::Bloom2
 //! [`CompressedBitmap`]: crate::bitmap::CompressedBitmap
 
+mod analyze;
+pub use analyze::*;
+
+mod approx;
+pub use approx::*;
+
 mod bitmap;
 pub use bitmap::*;
 
 mod bloom;
 pub use bloom::*;
 
+mod budget;
+pub use budget::*;
+
+mod concurrent;
+pub use concurrent::*;
+
+mod decay;
+pub use decay::*;
+
+mod dedup;
+pub use dedup::*;
+
+mod doorkeeper;
+pub use doorkeeper::*;
+
+mod error;
+pub use error::*;
+
+mod cascade;
+pub use cascade::*;
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impls;
+
 mod filter_size;
 pub use filter_size::*;
+
+mod hybrid;
+pub use hybrid::*;
+
+#[cfg(feature = "serde")]
+mod legacy;
+#[cfg(feature = "serde")]
+pub use legacy::*;
+
+pub mod math;
+
+mod negative_cache;
+pub use negative_cache::*;
+
+mod partitioned;
+pub use partitioned::*;
+
+mod pipeline;
+pub use pipeline::*;
+
+mod probe_scheme;
+pub use probe_scheme::*;
+
+#[cfg(feature = "prost")]
+mod proto;
+#[cfg(feature = "prost")]
+pub use proto::*;
+
+mod hashers;
+pub use hashers::*;
+
+mod query_worker;
+pub use query_worker::*;
+
+mod rebuild;
+pub use rebuild::*;
+
+mod replication;
+pub use replication::*;
+
+mod ribbon;
+pub use ribbon::*;
+
+mod set;
+pub use set::*;
+
+mod sharded;
+pub use sharded::*;
+
+mod stream;
+pub use stream::*;
+
+#[cfg(feature = "test-vectors")]
+mod test_vectors;
+#[cfg(feature = "test-vectors")]
+pub use test_vectors::*;
+
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "wasm")]
+pub use wasm::*;
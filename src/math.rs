@@ -0,0 +1,129 @@
+//! Bloom filter sizing formulas.
+//!
+//! These are the standard approximations for a bloom filter of `m` bits
+//! setting `k` bits per item for `n` distinct items, where a lookup is a hit
+//! only if all `k` bits are set:
+//!
+//! ```text
+//! fpp = (1 - e^(-k * n / m))^k
+//! k   = (m / n) * ln(2)
+//! m   = -n * ln(p) / ln(2)^2
+//! ```
+//!
+//! ```rust
+//! use bloom2::math::{optimal_k, required_bits, theoretical_fpp};
+//!
+//! // Size a filter for 1000 items with a 1% false positive probability.
+//! let m = required_bits(1000, 0.01);
+//! let k = optimal_k(m, 1000);
+//! assert_eq!((m, k), (9586, 7));
+//!
+//! // Rounding k to an integer slightly exceeds the target probability.
+//! let fpp = theoretical_fpp(m, k, 1000);
+//! assert!((fpp - 0.01).abs() < 0.0001);
+//! ```
+//!
+//! [`FilterSize`](crate::FilterSize) key spaces are powers of 2, so the bits
+//! of a filter are typically rounded up from [`required_bits()`].
+
+use std::f64::consts::LN_2;
+
+/// Return the probability of a false positive lookup in a filter of `m_bits`
+/// bits with `k` bits set for each of `n` distinct items.
+///
+/// An empty filter (`m_bits` of 0) always returns 1.
+pub fn theoretical_fpp(m_bits: usize, k: usize, n: usize) -> f64 {
+    if m_bits == 0 {
+        return 1.0;
+    }
+
+    let load = 1.0 - (-(k as f64) * n as f64 / m_bits as f64).exp();
+    load.powi(k.min(i32::MAX as usize) as i32)
+}
+
+/// Return the number of bits to set per item minimising the false positive
+/// probability of a filter of `m_bits` bits holding `n` distinct items.
+///
+/// This is `(m / n) * ln(2)`, rounded to whichever of the neighbouring
+/// integers gives the lower [`theoretical_fpp()`]. The result is at least 1,
+/// and an `n` of 0 is treated as 1.
+pub fn optimal_k(m_bits: usize, n: usize) -> usize {
+    let n = n.max(1);
+    let k = m_bits as f64 / n as f64 * LN_2;
+
+    let lo = (k.floor() as usize).max(1);
+    let hi = (k.ceil() as usize).max(1);
+    if theoretical_fpp(m_bits, hi, n) < theoretical_fpp(m_bits, lo, n) {
+        hi
+    } else {
+        lo
+    }
+}
+
+/// Return the number of bits a filter requires to hold `n` distinct items
+/// within a false positive probability of `p`, when using [`optimal_k()`]
+/// bits per item.
+///
+/// A `p` of 1 or more requires no bits, and a `p` of 0 or less (or NaN)
+/// saturates to [`usize::MAX`].
+pub fn required_bits(n: usize, p: f64) -> usize {
+    if p >= 1.0 || n == 0 {
+        return 0;
+    }
+    if p.is_nan() || p <= 0.0 {
+        return usize::MAX;
+    }
+
+    (-(n as f64) * p.ln() / (LN_2 * LN_2)).ceil() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(required_bits(1_000_000, 0.001), 14_377_588);
+        assert_eq!(optimal_k(14_377_588, 1_000_000), 10);
+        assert!((theoretical_fpp(14_377_588, 10, 1_000_000) - 0.001).abs() < 1e-5);
+
+        // 8 bits per item with 3 probes.
+        assert!((theoretical_fpp(8000, 3, 1000) - 0.0306).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        assert_eq!(theoretical_fpp(0, 3, 10), 1.0);
+        assert_eq!(theoretical_fpp(1024, 3, 0), 0.0);
+        assert_eq!(optimal_k(0, 10), 1);
+        assert_eq!(optimal_k(1024, 0), optimal_k(1024, 1));
+        assert_eq!(required_bits(10, 1.0), 0);
+        assert_eq!(required_bits(0, 0.01), 0);
+        assert_eq!(required_bits(10, 0.0), usize::MAX);
+        assert_eq!(required_bits(10, f64::NAN), usize::MAX);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_required_bits_meets_fpp(n in 1_usize..1_000_000, p in 0.0001_f64..0.5) {
+            let m = required_bits(n, p);
+            let k = optimal_k(m, n);
+
+            // Rounding k to an integer costs a little accuracy.
+            prop_assert!(theoretical_fpp(m, k, n) <= p * 1.05);
+        }
+
+        #[test]
+        fn prop_optimal_k_is_minimal(m in 1_usize..1_000_000, n in 1_usize..100_000) {
+            let k = optimal_k(m, n);
+            let fpp = theoretical_fpp(m, k, n);
+
+            prop_assert!(fpp <= theoretical_fpp(m, k + 1, n) + 1e-12);
+            if k > 1 {
+                prop_assert!(fpp <= theoretical_fpp(m, k - 1, n) + 1e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use crate::{Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize};
+
+/// A cache of keys known to be missing from a backing store, shielding the
+/// store from repeated lookups of nonexistent keys.
+///
+/// A `NegativeCache` records keys in two generations of [`Bloom2`] filters,
+/// flip-flopping between them every `ttl`: the previous generation is
+/// discarded, the current generation becomes the previous, and a new, empty
+/// current generation is started. A key marked as missing is reported as
+/// missing for at least `ttl`, and at most `2 * ttl`.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use bloom2::{FilterSize, NegativeCache};
+///
+/// let mut cache = NegativeCache::new(FilterSize::KeyBytes3, Duration::from_secs(60));
+///
+/// let lookup = |key: &str| -> Option<String> { None };
+///
+/// for _ in 0..3 {
+///     if cache.probably_missing(&"user-42") {
+///         continue; // Skip the lookup.
+///     }
+///     if lookup("user-42").is_none() {
+///         cache.mark_missing(&"user-42");
+///     }
+/// }
+/// ```
+///
+/// As with any bloom filter, a key that was never marked as missing may be
+/// reported as missing (a false positive). Keys cannot be removed, so a key
+/// created in the backing store after being marked as missing continues to be
+/// reported as missing until it expires, or the cache is
+/// [cleared](NegativeCache::clear).
+#[derive(Debug, Clone)]
+pub struct NegativeCache<T, H = RandomState>
+where
+    H: BuildHasher,
+{
+    current: Bloom2<H, CompressedBitmap, T>,
+    previous: Bloom2<H, CompressedBitmap, T>,
+
+    /// The time the current generation was started.
+    rotated_at: Instant,
+    ttl: Duration,
+}
+
+impl<T> NegativeCache<T, RandomState>
+where
+    T: Hash,
+{
+    /// Construct a `NegativeCache` using filters of `key_size`, rotating
+    /// generations every `ttl`.
+    pub fn new(key_size: FilterSize, ttl: Duration) -> Self {
+        Self::with_hasher(RandomState::default(), key_size, ttl)
+    }
+}
+
+impl<T, H> NegativeCache<T, H>
+where
+    T: Hash,
+    H: BuildHasher + Clone,
+{
+    /// Construct a `NegativeCache` using `hasher` to hash keys, as described
+    /// in [`NegativeCache::new()`].
+    pub fn with_hasher(hasher: H, key_size: FilterSize, ttl: Duration) -> Self {
+        let filter = || {
+            BloomFilterBuilder::hasher(hasher.clone())
+                .size(key_size)
+                .build()
+        };
+
+        Self {
+            current: filter(),
+            previous: filter(),
+            rotated_at: Instant::now(),
+            ttl,
+        }
+    }
+
+    /// Record `key` as missing from the backing store.
+    pub fn mark_missing(&mut self, key: &T) {
+        self.mark_missing_at(key, Instant::now())
+    }
+
+    /// Returns `true` if `key` was **probably** marked as missing within the
+    /// TTL, or `false` if it **definitely** was not, and the backing store
+    /// should be consulted.
+    pub fn probably_missing(&self, key: &T) -> bool {
+        self.probably_missing_at(key, Instant::now())
+    }
+
+    /// Forget all keys marked as missing, retaining the allocated memory for
+    /// reuse.
+    pub fn clear(&mut self) {
+        self.current.clear();
+        self.previous.clear();
+        self.rotated_at = Instant::now();
+    }
+
+    fn mark_missing_at(&mut self, key: &T, now: Instant) {
+        self.rotate(now);
+        self.current.insert(key);
+    }
+
+    fn probably_missing_at(&self, key: &T, now: Instant) -> bool {
+        // The generations are rotated lazily when writing, so account for any
+        // rotations that are due when reading.
+        let elapsed = now.saturating_duration_since(self.rotated_at);
+        if elapsed >= self.ttl * 2 {
+            false
+        } else if elapsed >= self.ttl {
+            self.current.contains(key)
+        } else {
+            self.current.contains(key) || self.previous.contains(key)
+        }
+    }
+
+    /// Rotate the generations if the current generation is older than the
+    /// TTL.
+    fn rotate(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.rotated_at);
+        if elapsed >= self.ttl * 2 {
+            self.current.clear();
+            self.previous.clear();
+            self.rotated_at = now;
+        } else if elapsed >= self.ttl {
+            self.previous.clear();
+            std::mem::swap(&mut self.current, &mut self.previous);
+
+            // Advance by exactly one TTL (rather than to now) so a key is
+            // never retained for longer than 2 * ttl.
+            self.rotated_at += self.ttl;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use twox_hash::XxHash64;
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(10);
+
+    fn new_cache() -> NegativeCache<u32, BuildHasherDefault<XxHash64>> {
+        NegativeCache::with_hasher(BuildHasherDefault::default(), FilterSize::KeyBytes4, TTL)
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut c = new_cache();
+        let start = c.rotated_at;
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(!c.probably_missing_at(&1, at(0)));
+        c.mark_missing_at(&1, at(5));
+        assert!(c.probably_missing_at(&1, at(5)));
+
+        // Retained in the previous generation for the next TTL.
+        assert!(c.probably_missing_at(&1, at(15)));
+        c.mark_missing_at(&2, at(15));
+        assert!(c.probably_missing_at(&1, at(19)));
+        assert!(c.probably_missing_at(&2, at(19)));
+
+        // Evicted after the second rotation, while the newer key remains.
+        assert!(!c.probably_missing_at(&1, at(20)));
+        assert!(c.probably_missing_at(&2, at(20)));
+        c.mark_missing_at(&3, at(21));
+        assert!(!c.probably_missing_at(&1, at(21)));
+        assert!(c.probably_missing_at(&2, at(21)));
+
+        // Both generations expire after an idle period.
+        assert!(!c.probably_missing_at(&2, at(40)));
+        assert!(!c.probably_missing_at(&3, at(40)));
+        c.mark_missing_at(&4, at(45));
+        assert!(!c.probably_missing_at(&3, at(45)));
+        assert!(c.probably_missing_at(&4, at(45)));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut c = new_cache();
+        c.mark_missing(&1);
+        assert!(c.probably_missing(&1));
+
+        c.clear();
+        assert!(!c.probably_missing(&1));
+    }
+}
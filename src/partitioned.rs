@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    sharded::ROUTING_MIX, Bloom2, BloomFilterBuilder, CompressedBitmap, Error, FilterSize,
+};
+
+/// A logical filter partitioned across a changing set of [`Bloom2`]
+/// partitions by [consistent hashing].
+///
+/// Each partition is identified by a `u64` ID, and placed at `vnodes` points
+/// on a hash ring. An item is owned by the partition at the first ring point
+/// at or after the (mixed) hash of the item, so adding or removing a partition
+/// moves only the items in the ring ranges it gains or loses - approximately
+/// `1 / n` of the items for `n` partitions.
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::{FilterSize, PartitionedFilterSet};
+///
+/// let mut set = PartitionedFilterSet::new(RandomState::default(), FilterSize::KeyBytes2, 16);
+/// set.add_partition(1);
+/// set.add_partition(2);
+///
+/// set.insert(&"bananas");
+///
+/// // Rebalancing never loses an inserted item.
+/// set.add_partition(3);
+/// set.remove_partition(1);
+/// assert!(set.contains(&"bananas"));
+/// ```
+///
+/// # Rebalancing
+///
+/// A bloom filter cannot enumerate its items, so they cannot be moved between
+/// partitions individually. Instead:
+///
+/// * An added partition starts with the union of the filters of the
+///   partitions that previously owned its ring ranges.
+///
+/// * A removed partition has its filter unioned into each partition that
+///   takes over one of its ring ranges.
+///
+/// Every item remains present in its owning partition, at the cost of the
+/// merged partitions containing the bits of items they do not own, raising
+/// their false positive probability until rebuilt.
+///
+/// # Distribution
+///
+/// The ring points of a partition and the routing of an item are derived from
+/// the hasher `H`, so all participants must use the same hasher and seed, and
+/// the same `vnodes` value, to agree on the owner of an item.
+///
+/// [consistent hashing]: https://en.wikipedia.org/wiki/Consistent_hashing
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionedFilterSet<H, T>
+where
+    H: BuildHasher,
+{
+    hasher: H,
+    key_size: FilterSize,
+    vnodes: usize,
+
+    /// The ring points of all partitions, mapped to the owning partition ID.
+    ring: BTreeMap<u64, u64>,
+    partitions: BTreeMap<u64, Bloom2<H, CompressedBitmap, T>>,
+}
+
+impl<H, T> PartitionedFilterSet<H, T>
+where
+    H: BuildHasher + Clone,
+    T: Hash,
+{
+    /// Construct an empty set with no partitions, for partition filters of
+    /// `key_size`, placing each partition at `vnodes` points on the ring.
+    ///
+    /// More `vnodes` spread the items more evenly across the partitions.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `vnodes` is 0.
+    pub fn new(hasher: H, key_size: FilterSize, vnodes: usize) -> Self {
+        assert!(vnodes > 0, "vnodes must be at least 1");
+
+        Self {
+            hasher,
+            key_size,
+            vnodes,
+            ring: BTreeMap::new(),
+            partitions: BTreeMap::new(),
+        }
+    }
+
+    /// Add the partition `id`, rebalancing the items it takes ownership of
+    /// from the existing partitions.
+    ///
+    /// Returns `false` and does nothing if `id` already exists.
+    pub fn add_partition(&mut self, id: u64) -> bool {
+        if self.partitions.contains_key(&id) {
+            return false;
+        }
+
+        let mut filter: Bloom2<H, CompressedBitmap, T> =
+            BloomFilterBuilder::hasher(self.hasher.clone())
+                .size(self.key_size)
+                .build();
+
+        // Each new ring point takes the range ending at it from the partition
+        // that previously owned the point.
+        let points = self.points(id);
+        let previous = points
+            .iter()
+            .filter_map(|&p| self.owner_of_point(p))
+            .collect::<BTreeSet<_>>();
+        for owner in previous {
+            filter.union(&self.partitions[&owner]);
+        }
+
+        for p in points {
+            self.ring.insert(p, id);
+        }
+        self.partitions.insert(id, filter);
+
+        true
+    }
+
+    /// Remove the partition `id`, rebalancing its items into the partitions
+    /// that take ownership of them, and returning its filter.
+    ///
+    /// Returns [`None`] if `id` does not exist.
+    pub fn remove_partition(&mut self, id: u64) -> Option<Bloom2<H, CompressedBitmap, T>> {
+        let filter = self.partitions.remove(&id)?;
+        self.ring.retain(|_, owner| *owner != id);
+
+        // Each removed ring point hands its range to the partition owning the
+        // next point.
+        let next = self
+            .points(id)
+            .into_iter()
+            .filter_map(|p| self.owner_of_point(p))
+            .collect::<BTreeSet<_>>();
+        for owner in next {
+            self.partition_mut(owner).union(&filter);
+        }
+
+        Some(filter)
+    }
+
+    /// Insert `data` into the partition that owns it.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the set has no partitions.
+    pub fn insert(&mut self, data: &'_ T) {
+        let hash = self.hasher.hash_one(data);
+        let owner = self
+            .owner_of_hash(hash)
+            .expect("insert into a partitioned filter set with no partitions");
+        self.partition_mut(owner).insert_hash(hash);
+    }
+
+    /// Checks if `data` exists in the partition that owns it.
+    ///
+    /// As with [`Bloom2::contains()`], a `true` result means `data` has
+    /// **probably** been inserted, while `false` means it has **definitely
+    /// not** been inserted. A set with no partitions contains nothing.
+    pub fn contains(&self, data: &'_ T) -> bool {
+        let hash = self.hasher.hash_one(data);
+        self.owner_of_hash(hash)
+            .map(|owner| self.partitions[&owner].contains_hash(hash))
+            .unwrap_or_default()
+    }
+
+    /// Return the ID of the partition that owns `data`, or [`None`] if the set
+    /// has no partitions.
+    pub fn partition_for(&self, data: &'_ T) -> Option<u64> {
+        self.owner_of_hash(self.hasher.hash_one(data))
+    }
+
+    /// Return an iterator of the partition IDs, in ascending order.
+    pub fn partitions(&self) -> impl Iterator<Item = u64> + '_ {
+        self.partitions.keys().copied()
+    }
+
+    /// Return the filter of partition `id` for export to the worker that owns
+    /// it, or [`None`] if `id` does not exist.
+    pub fn export(&self, id: u64) -> Option<&Bloom2<H, CompressedBitmap, T>> {
+        self.partitions.get(&id)
+    }
+
+    /// Union `filter`, such as one populated by a remote worker, into the
+    /// partition `id`.
+    ///
+    /// Returns an error and leaves the set unchanged if `id` does not exist,
+    /// or if `filter` is incompatible with the set (see
+    /// [`Bloom2::try_union()`]).
+    pub fn import(
+        &mut self,
+        id: u64,
+        filter: &Bloom2<H, CompressedBitmap, T>,
+    ) -> Result<(), Error> {
+        self.partitions
+            .get_mut(&id)
+            .ok_or(Error::UnknownPartition { id })?
+            .try_union(filter)
+    }
+
+    /// Return the ring points of partition `id`.
+    fn points(&self, id: u64) -> Vec<u64> {
+        (0..self.vnodes as u64)
+            .map(|replica| self.hasher.hash_one((id, replica)))
+            .collect()
+    }
+
+    /// Return the partition owning the ring position of `hash`.
+    fn owner_of_hash(&self, hash: u64) -> Option<u64> {
+        // Mix the hash so the ring position is not correlated with the probes
+        // derived from it.
+        self.owner_of_point(hash.wrapping_mul(ROUTING_MIX))
+    }
+
+    /// Return the partition owning the first ring point at or after `point`,
+    /// wrapping around the ring.
+    fn owner_of_point(&self, point: u64) -> Option<u64> {
+        self.ring
+            .range(point..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, &owner)| owner)
+    }
+
+    fn partition_mut(&mut self, id: u64) -> &mut Bloom2<H, CompressedBitmap, T> {
+        // Invariant: every partition on the ring has a filter.
+        self.partitions
+            .get_mut(&id)
+            .expect("ring point for missing partition")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+    type Set = PartitionedFilterSet<StableBuildHasher, u32>;
+
+    fn set(partitions: &[u64]) -> Set {
+        let mut s = Set::new(StableBuildHasher::default(), FilterSize::KeyBytes2, 32);
+        for &id in partitions {
+            assert!(s.add_partition(id));
+        }
+        s
+    }
+
+    #[test]
+    fn test_empty() {
+        let s = set(&[]);
+        assert!(!s.contains(&42));
+        assert_eq!(s.partition_for(&42), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no partitions")]
+    fn test_insert_empty() {
+        set(&[]).insert(&42);
+    }
+
+    #[quickcheck]
+    fn test_rebalance(values: Vec<u32>) {
+        let mut s = set(&[1, 2, 3]);
+        for v in &values {
+            s.insert(v);
+        }
+
+        assert!(!s.add_partition(2));
+        assert!(s.add_partition(4));
+        for v in &values {
+            assert!(s.contains(v));
+        }
+
+        assert!(s.remove_partition(1).is_some());
+        assert!(s.remove_partition(1).is_none());
+        for v in &values {
+            assert!(s.contains(v));
+        }
+        assert_eq!(s.partitions().collect::<Vec<_>>(), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_minimal_movement() {
+        let mut s = set(&[1, 2, 3, 4]);
+        let before = (0..1000_u32)
+            .map(|v| s.partition_for(&v).unwrap())
+            .collect::<Vec<_>>();
+
+        s.add_partition(5);
+
+        // Only items moved to the new partition change owner.
+        let mut moved = 0;
+        for (v, &owner) in (0..1000_u32).zip(&before) {
+            let now = s.partition_for(&v).unwrap();
+            if now != owner {
+                assert_eq!(now, 5);
+                moved += 1;
+            }
+        }
+        assert!((100..300).contains(&moved), "moved {} of 1000", moved);
+    }
+
+    #[test]
+    fn test_export_import() {
+        let mut s = set(&[1, 2]);
+        let owner = s.partition_for(&42).unwrap();
+
+        // A remote worker populates a copy of the partition filter.
+        let mut remote = s.export(owner).unwrap().clone();
+        remote.insert(&42);
+        assert!(!s.contains(&42));
+
+        s.import(owner, &remote).unwrap();
+        assert!(s.contains(&42));
+
+        assert_eq!(s.import(7, &remote), Err(Error::UnknownPartition { id: 7 }));
+
+        let other: Bloom2<_, CompressedBitmap, u32> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default())
+                .size(FilterSize::KeyBytes1)
+                .build();
+        assert_eq!(
+            s.import(owner, &other),
+            Err(Error::KeySizeMismatch {
+                ours: FilterSize::KeyBytes2,
+                theirs: FilterSize::KeyBytes1,
+            })
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::sync_channel;
+
+use crate::{Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize, ProbeScheme, VecBitmap};
+
+/// The default number of items sent to a worker at once.
+const DEFAULT_BATCH_SIZE: usize = 4096;
+
+/// The number of batches queued for each worker before the producer blocks.
+const QUEUE_DEPTH: usize = 4;
+
+/// Build a compressed [`Bloom2`] from a large number of items in parallel.
+///
+/// Inserting into a [`CompressedBitmap`] is comparatively slow, while
+/// inserting into a dense [`VecBitmap`] is `O(1)`. A `FilterBuildPipeline`
+/// hashes and inserts batches of items into a dense per-worker filter on each
+/// of several worker threads, then unions the worker filters and compresses
+/// the result once:
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::{FilterBuildPipeline, FilterSize};
+///
+/// let filter = FilterBuildPipeline::new(RandomState::default(), FilterSize::KeyBytes3)
+///     .workers(4)
+///     .build(0..100_000_u64);
+///
+/// assert!(filter.contains(&42));
+/// ```
+///
+/// The resulting filter is identical to one built by inserting each item in
+/// turn.
+///
+/// Each worker allocates a dense bitmap of `2^(8 * n)` bits for a
+/// [`FilterSize`] of `n` bytes (2MB per worker for
+/// [`FilterSize::KeyBytes3`], 512MB for [`FilterSize::KeyBytes4`]), so this
+/// is best suited to the smaller filter sizes.
+#[derive(Debug, Clone)]
+pub struct FilterBuildPipeline<H> {
+    hasher: H,
+    key_size: FilterSize,
+    probe_scheme: ProbeScheme,
+    workers: usize,
+    batch_size: usize,
+}
+
+impl<H> FilterBuildPipeline<H>
+where
+    H: BuildHasher + Clone + Send,
+{
+    /// Construct a pipeline building a filter of `key_size`, hashing with
+    /// `hasher`, using one worker per available CPU.
+    pub fn new(hasher: H, key_size: FilterSize) -> Self {
+        let workers = std::thread::available_parallelism()
+            .map(|v| v.get())
+            .unwrap_or(1);
+
+        Self {
+            hasher,
+            key_size,
+            probe_scheme: ProbeScheme::default(),
+            workers,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Set the number of worker threads (at least 1).
+    pub fn workers(self, workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            ..self
+        }
+    }
+
+    /// Set the number of items sent to a worker at once (at least 1).
+    pub fn batch_size(self, batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            ..self
+        }
+    }
+
+    /// Set the [`ProbeScheme`] of the filter.
+    pub fn probe_scheme(self, probe_scheme: ProbeScheme) -> Self {
+        Self {
+            probe_scheme,
+            ..self
+        }
+    }
+
+    /// Insert all `items` into a new filter, returning the compressed result.
+    ///
+    /// The items are read from `items` on the calling thread, and inserted
+    /// into the filter by the worker threads.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if a worker thread panics, such as if hashing an
+    /// item panics.
+    pub fn build<I, T>(self, items: I) -> Bloom2<H, CompressedBitmap, T>
+    where
+        I: IntoIterator<Item = T>,
+        T: Hash + Send,
+    {
+        std::thread::scope(|scope| {
+            let (senders, workers): (Vec<_>, Vec<_>) = (0..self.workers)
+                .map(|_| {
+                    let (tx, rx) = sync_channel::<Vec<T>>(QUEUE_DEPTH);
+                    let mut filter = self.new_filter();
+
+                    let worker = scope.spawn(move || {
+                        for batch in rx {
+                            for item in &batch {
+                                filter.insert(item);
+                            }
+                        }
+                        filter
+                    });
+
+                    (tx, worker)
+                })
+                .unzip();
+
+            // Distribute the batches to the workers in turn.
+            let mut items = items.into_iter();
+            for tx in senders.iter().cycle() {
+                let batch = items.by_ref().take(self.batch_size).collect::<Vec<_>>();
+                if batch.is_empty() {
+                    break;
+                }
+                if tx.send(batch).is_err() {
+                    // The worker panicked, which is surfaced by join() below.
+                    break;
+                }
+            }
+
+            // Close the channels, allowing the workers to complete.
+            drop(senders);
+
+            let mut out = self.new_filter();
+            for worker in workers {
+                let filter = worker.join().expect("filter build worker panicked");
+                out.union(&filter);
+            }
+
+            out.compress()
+        })
+    }
+
+    fn new_filter<T: Hash>(&self) -> Bloom2<H, VecBitmap, T> {
+        BloomFilterBuilder::hasher(self.hasher.clone())
+            .with_bitmap::<VecBitmap>()
+            .size(self.key_size)
+            .probe_scheme(self.probe_scheme)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    #[quickcheck]
+    fn test_build(values: Vec<u32>, workers: u8, batch_size: u8) {
+        let got = FilterBuildPipeline::new(StableBuildHasher::default(), FilterSize::KeyBytes2)
+            .workers(workers as usize % 8)
+            .batch_size(batch_size as usize)
+            .build(values.iter().copied());
+
+        // The result matches inserting each item in turn.
+        let mut want: Bloom2<_, CompressedBitmap, u32> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default()).build();
+        for v in &values {
+            want.insert(v);
+        }
+
+        assert_eq!(got.bitmap(), want.bitmap());
+        assert_eq!(got.verify_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn test_probe_scheme() {
+        let scheme = ProbeScheme::DoubleHashing { probes: 5 };
+        let got = FilterBuildPipeline::new(StableBuildHasher::default(), FilterSize::KeyBytes3)
+            .probe_scheme(scheme)
+            .build(0..10_000_u64);
+
+        assert_eq!(got.probe_scheme(), scheme);
+        assert_eq!(got.key_size(), FilterSize::KeyBytes3);
+        for v in 0..10_000_u64 {
+            assert!(got.contains(&v));
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use crate::FilterSize;
+
+/// ProbeScheme controls how the 64-bit hash of an item is mapped to the bits
+/// (probes) set in a [`Bloom2`](crate::Bloom2) filter.
+///
+/// Both schemes are defined in terms of the 64-bit hash only, so a filter
+/// built by any implementation that produces the same hashes (such as
+/// [xxHash] over the same bytes) and follows the same scheme can be queried by
+/// this crate, and vice versa.
+///
+/// In both schemes, a filter of [`FilterSize`] with `b` bits per key (see
+/// [`FilterSize::key_bits()`]) contains `m = 2^b` bits.
+///
+/// [xxHash]: https://github.com/Cyan4973/xxHash
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "allocative", derive(allocative::Allocative))]
+pub enum ProbeScheme {
+    /// Split the hash into chunks of `b` bits, from the most significant bit
+    /// (the last chunk is shorter if `b` does not divide 64), each
+    /// interpreted as an integer bit index. For a byte-aligned [`FilterSize`]
+    /// this is equivalent to splitting the big-endian bytes of the hash.
+    ///
+    /// The number of probes is fixed by the [`FilterSize`]: `ceil(64 / b)`.
+    #[default]
+    Split,
+
+    /// Derive `probes` bit indexes from the hash `h` using double hashing:
+    ///
+    /// ```text
+    /// index(i) = (h + i * ((h >> 32) | 1)) mod m,   for i in 0..probes
+    /// ```
+    ///
+    /// using wrapping unsigned 64-bit arithmetic. As `m` is a power of 2, the
+    /// `mod m` is equivalent to masking the low `b` bits of the result.
+    ///
+    /// This decouples the number of probes from the [`FilterSize`], and is
+    /// simple to implement in languages without support for arbitrary byte
+    /// splitting of integers. A `probes` value of 0 is treated as 1.
+    DoubleHashing { probes: u8 },
+
+    /// Split the hash into chunks of `b` bits as [`ProbeScheme::Split`] does,
+    /// but from the least significant bit (the last chunk, taken from the
+    /// most significant bits, is shorter if `b` does not divide 64). For a
+    /// byte-aligned [`FilterSize`] this is equivalent to splitting the
+    /// little-endian bytes of the hash.
+    ///
+    /// This chunking order is provided for compatibility with filters built
+    /// by systems (or older versions of this crate) that split digests from
+    /// the least significant end, so they keep answering correctly:
+    ///
+    /// ```rust
+    /// use bloom2::{BloomFilterBuilder, FilterSize, ProbeScheme};
+    ///
+    /// let mut filter = BloomFilterBuilder::default()
+    ///     .size(FilterSize::KeyBytes2)
+    ///     .probe_scheme(ProbeScheme::SplitLsb)
+    ///     .build();
+    ///
+    /// filter.insert(&"success!");
+    /// assert!(filter.contains(&"success!"));
+    /// ```
+    ///
+    /// The number of probes is fixed by the [`FilterSize`]: `ceil(64 / b)`.
+    SplitLsb,
+}
+
+impl ProbeScheme {
+    /// Return the number of bits probed per item for a filter of `key_size`.
+    pub fn probes(&self, key_size: FilterSize) -> usize {
+        match *self {
+            Self::Split | Self::SplitLsb => 64_usize.div_ceil(key_size.key_bits() as usize),
+            Self::DoubleHashing { probes } => probes.max(1) as usize,
+        }
+    }
+
+    /// Return an iterator of the bitmap keys for `hash` in a filter of
+    /// `key_size`.
+    pub(crate) fn keys(&self, hash: u64, key_size: FilterSize) -> Keys {
+        Keys {
+            hash,
+            key_size,
+            scheme: *self,
+            i: 0,
+            n: self.probes(key_size),
+        }
+    }
+}
+
+/// MatchMode controls how the probes of an item are combined to answer a
+/// membership query of a [`Bloom2`](crate::Bloom2) filter.
+///
+/// Historically, this crate reports an item as (probably) present if **any**
+/// of its probed bits are set, which is the default for compatibility with
+/// existing users. A textbook bloom filter instead requires **all** of the
+/// probed bits to be set, yielding a far lower false positive probability for
+/// the same filter size:
+///
+/// ```rust
+/// use bloom2::{BloomFilterBuilder, MatchMode};
+///
+/// let mut filter = BloomFilterBuilder::default()
+///     .match_mode(MatchMode::All)
+///     .build();
+///
+/// filter.insert(&"success!");
+/// assert!(filter.contains(&"success!"));
+/// ```
+///
+/// Both modes never report a false negative, and the bits set by inserts are
+/// identical, so an existing filter can be queried in either mode. Migrating
+/// a filter to [`MatchMode::All`] only removes false positives - any item
+/// reported as present by the strict mode is also reported as present by the
+/// default mode - switch an existing filter to the strict mode with
+/// [`Bloom2::set_match_mode()`](crate::Bloom2::set_match_mode).
+///
+/// The match mode is serialised with a filter, and a filter serialised before
+/// the mode was recorded is restored using [`MatchMode::Any`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "allocative", derive(allocative::Allocative))]
+pub enum MatchMode {
+    /// An item matches if any of its probed bits are set.
+    #[default]
+    Any,
+
+    /// An item matches only if all of its probed bits are set, as in a
+    /// classical bloom filter.
+    All,
+}
+
+/// An iterator of the bitmap keys for a hash, as derived by a
+/// [`ProbeScheme`].
+#[derive(Debug)]
+pub(crate) struct Keys {
+    hash: u64,
+    key_size: FilterSize,
+    scheme: ProbeScheme,
+    i: usize,
+    n: usize,
+}
+
+impl Iterator for Keys {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.i == self.n {
+            return None;
+        }
+
+        let bits = self.key_size.key_bits();
+
+        // Keys wider than a usize would be truncated - filters of such sizes
+        // are rejected when constructed.
+        debug_assert!(bits <= usize::BITS, "key size {} too large", self.key_size);
+
+        let key = match self.scheme {
+            ProbeScheme::Split => {
+                // Shift the chunk to the top of the word, and then down to
+                // the bottom, discarding the bits either side of it.
+                let start = self.i as u32 * bits;
+                let len = bits.min(64 - start);
+                ((self.hash << start) >> (64 - len)) as usize
+            }
+            ProbeScheme::SplitLsb => {
+                // Shift the chunk to the bottom of the word, and mask off the
+                // bits above it.
+                let start = self.i as u32 * bits;
+                let len = bits.min(64 - start);
+                ((self.hash >> start) & (u64::MAX >> (64 - len))) as usize
+            }
+            ProbeScheme::DoubleHashing { .. } => {
+                let step = (self.hash >> 32) | 1;
+                let mask = u64::MAX >> (64 - bits);
+                (self.hash.wrapping_add((self.i as u64).wrapping_mul(step)) & mask) as usize
+            }
+        };
+
+        self.i += 1;
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.n - self.i;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Keys {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_lsb() {
+        let hash = 0x0102_0304_0506_0708;
+
+        let keys = |size| ProbeScheme::SplitLsb.keys(hash, size).collect::<Vec<_>>();
+        assert_eq!(keys(FilterSize::KeyBytes1), vec![8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(
+            keys(FilterSize::KeyBytes2),
+            vec![0x0708, 0x0506, 0x0304, 0x0102]
+        );
+        assert_eq!(
+            keys(FilterSize::KeyBytes3),
+            vec![0x060708, 0x030405, 0x0102]
+        );
+        assert_eq!(keys(FilterSize::KeyBytes8), vec![0x0102030405060708]);
+
+        assert_eq!(
+            keys(FilterSize::KeyBits(20)),
+            vec![0x60708, 0x04050, 0x10203, 0x0]
+        );
+        assert_eq!(
+            keys(FilterSize::KeyBits(28)),
+            vec![0x5060708, 0x0203040, 0x1]
+        );
+
+        // Byte-aligned sizes split the little-endian bytes of the hash.
+        let le = u64::to_le_bytes(hash);
+        let want = le
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]) as usize)
+            .collect::<Vec<_>>();
+        assert_eq!(keys(FilterSize::KeyBytes2), want);
+    }
+
+    #[test]
+    fn test_split() {
+        let hash = 0x0102_0304_0506_0708;
+
+        let keys = |size| ProbeScheme::Split.keys(hash, size).collect::<Vec<_>>();
+        assert_eq!(keys(FilterSize::KeyBytes1), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            keys(FilterSize::KeyBytes2),
+            vec![0x0102, 0x0304, 0x0506, 0x0708]
+        );
+        assert_eq!(
+            keys(FilterSize::KeyBytes3),
+            vec![0x010203, 0x040506, 0x0708]
+        );
+        assert_eq!(keys(FilterSize::KeyBytes5), vec![0x0102030405, 0x060708]);
+        assert_eq!(keys(FilterSize::KeyBytes6), vec![0x010203040506, 0x0708]);
+        assert_eq!(keys(FilterSize::KeyBytes8), vec![0x0102030405060708]);
+
+        assert_eq!(
+            keys(FilterSize::KeyBits(20)),
+            vec![0x01020, 0x30405, 0x06070, 0x8]
+        );
+        assert_eq!(
+            keys(FilterSize::KeyBits(28)),
+            vec![0x0102030, 0x4050607, 0x08]
+        );
+        assert_eq!(
+            keys(FilterSize::KeyBits(63)),
+            vec![0x0102030405060708 >> 1, 0]
+        );
+    }
+
+    #[test]
+    fn test_split_matches_bytes() {
+        let hash: u64 = 0xdead_beef_cafe_f00d;
+        for bytes in 1..=8 {
+            let size = FilterSize::from_key_bytes(bytes).unwrap();
+            let want = hash
+                .to_be_bytes()
+                .chunks(bytes as usize)
+                .map(|c| c.iter().fold(0, |key, &b| (key << 8) | b as usize))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                ProbeScheme::Split.keys(hash, size).collect::<Vec<_>>(),
+                want
+            );
+        }
+    }
+
+    #[test]
+    fn test_double_hashing() {
+        let hash = 0x0102_0304_0506_0708;
+        let scheme = ProbeScheme::DoubleHashing { probes: 3 };
+
+        // h2 = 0x01020305
+        let keys = scheme.keys(hash, FilterSize::KeyBytes2).collect::<Vec<_>>();
+        assert_eq!(keys, vec![0x0708, 0x0a0d, 0x0d12]);
+
+        // The index wraps modulo 2^64 before masking.
+        let keys = scheme
+            .keys(u64::MAX, FilterSize::KeyBytes1)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![0xff, 0xfe, 0xfd]);
+
+        // The full hash is used for the widest key size.
+        let keys = scheme
+            .keys(u64::MAX, FilterSize::KeyBytes8)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![usize::MAX, 0xffff_fffe, 0x1_ffff_fffd]);
+
+        // Bit-granular sizes mask the low bits.
+        let keys = scheme
+            .keys(hash, FilterSize::KeyBits(12))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![0x708, 0xa0d, 0xd12]);
+    }
+
+    #[test]
+    fn test_probes() {
+        assert_eq!(ProbeScheme::Split.probes(FilterSize::KeyBytes3), 3);
+        assert_eq!(ProbeScheme::Split.probes(FilterSize::KeyBits(20)), 4);
+        assert_eq!(ProbeScheme::Split.probes(FilterSize::KeyBits(1)), 64);
+        assert_eq!(ProbeScheme::SplitLsb.probes(FilterSize::KeyBits(20)), 4);
+        assert_eq!(
+            ProbeScheme::DoubleHashing { probes: 0 }.probes(FilterSize::KeyBytes3),
+            1
+        );
+        assert_eq!(
+            ProbeScheme::DoubleHashing { probes: 0 }
+                .keys(42, FilterSize::KeyBytes3)
+                .len(),
+            1
+        );
+    }
+}
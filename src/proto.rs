@@ -0,0 +1,353 @@
+//! [Protocol Buffers] encoding of a [`Bloom2`] with [prost].
+//!
+//! [Protocol Buffers]: https://protobuf.dev/
+//! [prost]: https://github.com/tokio-rs/prost
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    bloom::{try_key_size_to_bits, Params},
+    Bloom2, BloomFilterBuilder, CompressedBitmap, Error, FilterSize, MatchMode, ProbeScheme,
+};
+
+/// The protobuf message for a [`Bloom2`] backed by a [`CompressedBitmap`],
+/// as defined by `proto/bloom2.proto`.
+///
+/// The message records the parameters of the filter alongside the bitmap
+/// words, which are validated by [`Bloom2::from_proto()`].
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Bloom2Proto {
+    /// The number of hash bytes per key (the [`FilterSize`]), or 0 if the
+    /// filter uses `key_bits` bits per key.
+    #[prost(uint32, tag = "1")]
+    pub key_size: u32,
+
+    /// The number of bits set per inserted item.
+    #[prost(uint32, tag = "2")]
+    pub probes: u32,
+
+    /// A fingerprint of the hashing algorithm and seed.
+    #[prost(fixed64, tag = "3")]
+    pub hasher: u64,
+
+    /// The non-zero top map words of the [`CompressedBitmap`].
+    ///
+    /// If `top_index` is empty, this is instead the dense top map written
+    /// before the top map was made sparse.
+    #[prost(fixed64, repeated, tag = "4")]
+    pub top_map: Vec<u64>,
+
+    /// The block map words of the [`CompressedBitmap`].
+    #[prost(fixed64, repeated, tag = "5")]
+    pub block_map: Vec<u64>,
+
+    /// The bitmap words of the [`CompressedBitmap`].
+    #[prost(fixed64, repeated, tag = "6")]
+    pub bitmap: Vec<u64>,
+
+    /// The [`ProbeScheme`] of the filter: 0 for [`ProbeScheme::Split`], 1 for
+    /// [`ProbeScheme::DoubleHashing`] with `probes` probes, or 2 for
+    /// [`ProbeScheme::SplitLsb`].
+    #[prost(int32, tag = "7")]
+    pub probe_scheme: i32,
+
+    /// The number of hash bits per key for a [`FilterSize::KeyBits`] filter
+    /// that is not a whole number of bytes, or 0 otherwise.
+    #[prost(uint32, tag = "8")]
+    pub key_bits: u32,
+
+    /// The [`MatchMode`] of the filter: 0 for [`MatchMode::Any`], or 1 for
+    /// [`MatchMode::All`].
+    #[prost(int32, tag = "9")]
+    pub match_mode: i32,
+
+    /// The logical index of each of the `top_map` words.
+    #[prost(fixed64, repeated, tag = "10")]
+    pub top_index: Vec<u64>,
+}
+
+impl<H, T> Bloom2<H, CompressedBitmap, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    /// Encode this filter as a [`Bloom2Proto`] message.
+    ///
+    /// ```rust
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    /// use bloom2::{Bloom2, Bloom2Proto, BloomFilterBuilder, CompressedBitmap};
+    /// use prost::Message;
+    ///
+    /// type StableBuildHasher = BuildHasherDefault<DefaultHasher>;
+    ///
+    /// let mut b: Bloom2<_, CompressedBitmap, _> =
+    ///     BloomFilterBuilder::hasher(StableBuildHasher::default()).build();
+    /// b.insert(&"hello");
+    ///
+    /// let buf = b.to_proto().encode_to_vec();
+    ///
+    /// let msg = Bloom2Proto::decode(buf.as_slice()).unwrap();
+    /// let b: Bloom2<StableBuildHasher, CompressedBitmap, &str> =
+    ///     Bloom2::from_proto(msg).unwrap();
+    /// assert!(b.contains(&"hello"));
+    /// ```
+    pub fn to_proto(&self) -> Bloom2Proto {
+        let params = Params::new(
+            self.hasher(),
+            self.key_size(),
+            self.probe_scheme(),
+            self.match_mode(),
+        );
+        let (top_index, top_map, block_map, bitmap) = self.bitmap().raw_parts();
+        let words = |v: &[usize]| v.iter().map(|&w| w as u64).collect();
+
+        Bloom2Proto {
+            key_size: params.key_size.key_bytes().unwrap_or(0),
+            key_bits: match params.key_size.key_bytes() {
+                Some(_) => 0,
+                None => params.key_size.key_bits(),
+            },
+            probes: params.probes as u32,
+            hasher: params.hasher,
+            top_index: words(top_index),
+            top_map: words(top_map),
+            block_map: words(block_map),
+            bitmap: words(bitmap),
+            probe_scheme: match params.scheme {
+                ProbeScheme::Split => 0,
+                ProbeScheme::DoubleHashing { .. } => 1,
+                ProbeScheme::SplitLsb => 2,
+            },
+            match_mode: match params.match_mode {
+                MatchMode::Any => 0,
+                MatchMode::All => 1,
+            },
+        }
+    }
+}
+
+impl<H, T> Bloom2<H, CompressedBitmap, T>
+where
+    H: BuildHasher + Default,
+    T: Hash,
+{
+    /// Decode a filter from a [`Bloom2Proto`] message, hashing with the
+    /// default `H`.
+    ///
+    /// Returns an error if the parameters of `msg` do not match the hasher
+    /// and [`FilterSize`] of the filter, or if the bitmap is inconsistent.
+    pub fn from_proto(msg: Bloom2Proto) -> Result<Self, Error> {
+        let key_size = match msg.key_bits {
+            0 => FilterSize::from_key_bytes(msg.key_size).ok_or(Error::InvalidKeySize {
+                key_bytes: msg.key_size,
+            })?,
+            key_bits => {
+                FilterSize::from_key_bits(key_bits).ok_or(Error::InvalidKeyBits { key_bits })?
+            }
+        };
+
+        let scheme = match msg.probe_scheme {
+            0 => ProbeScheme::Split,
+            1 => ProbeScheme::DoubleHashing {
+                probes: msg.probes.min(u8::MAX as u32) as u8,
+            },
+            2 => ProbeScheme::SplitLsb,
+            scheme => {
+                return Err(Error::UnknownProbeScheme {
+                    scheme: scheme as u32,
+                })
+            }
+        };
+
+        let match_mode = match msg.match_mode {
+            0 => MatchMode::Any,
+            1 => MatchMode::All,
+            mode => return Err(Error::UnknownMatchMode { mode: mode as u32 }),
+        };
+
+        let max_key = try_key_size_to_bits(key_size)?;
+
+        let hasher = H::default();
+        Params::new(&hasher, key_size, scheme, match_mode).check(&Params {
+            key_size,
+            scheme,
+            probes: msg.probes as usize,
+            match_mode,
+            hasher: msg.hasher,
+        })?;
+
+        let words = |v: Vec<u64>| v.into_iter().map(|w| w as usize).collect::<Vec<_>>();
+        let (top_index, top_map) = if msg.top_index.is_empty() {
+            CompressedBitmap::sparse_top_map(&words(msg.top_map))
+        } else {
+            (words(msg.top_index), words(msg.top_map))
+        };
+        let bitmap = CompressedBitmap::from_raw_parts(
+            top_index,
+            top_map,
+            words(msg.block_map),
+            words(msg.bitmap),
+            max_key,
+        );
+
+        bitmap
+            .verify_invariants_for(max_key)
+            .map_err(Error::InvalidBitmap)?;
+
+        BloomFilterBuilder::hasher(hasher)
+            .with_bitmap_data(bitmap, key_size)
+            .probe_scheme(scheme)
+            .match_mode(match_mode)
+            .try_build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use prost::Message;
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::BloomFilterBuilder;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+    type Filter = Bloom2<StableBuildHasher, CompressedBitmap, u32>;
+
+    #[quickcheck]
+    fn test_proto_round_trip(values: Vec<u32>) {
+        let mut b: Filter = BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .size(FilterSize::KeyBytes2)
+            .build();
+        for v in &values {
+            b.insert(v);
+        }
+
+        let buf = b.to_proto().encode_to_vec();
+        let got = Filter::from_proto(Bloom2Proto::decode(buf.as_slice()).unwrap()).unwrap();
+
+        assert_eq!(got.key_size(), b.key_size());
+        assert_eq!(got.bitmap(), b.bitmap());
+        for v in &values {
+            assert!(got.contains(v));
+        }
+    }
+
+    #[test]
+    fn test_proto_probe_scheme() {
+        let scheme = ProbeScheme::DoubleHashing { probes: 6 };
+        let mut b: Filter = BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .probe_scheme(scheme)
+            .build();
+        b.insert(&42);
+
+        let got = Filter::from_proto(b.to_proto()).unwrap();
+        assert_eq!(got.probe_scheme(), scheme);
+        assert!(got.contains(&42));
+
+        let mut lsb: Filter = BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .probe_scheme(ProbeScheme::SplitLsb)
+            .build();
+        lsb.insert(&42);
+        let got = Filter::from_proto(lsb.to_proto()).unwrap();
+        assert_eq!(got.probe_scheme(), ProbeScheme::SplitLsb);
+        assert!(got.contains(&42));
+
+        let mut m = b.to_proto();
+        m.probe_scheme = 3;
+        assert_eq!(
+            Filter::from_proto(m),
+            Err(Error::UnknownProbeScheme { scheme: 3 })
+        );
+    }
+
+    #[test]
+    fn test_proto_match_mode() {
+        let mut b: Filter = BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .match_mode(MatchMode::All)
+            .build();
+        b.insert(&42);
+
+        let got = Filter::from_proto(b.to_proto()).unwrap();
+        assert_eq!(got.match_mode(), MatchMode::All);
+        assert_eq!(got, b);
+
+        let mut m = b.to_proto();
+        m.match_mode = 2;
+        assert_eq!(
+            Filter::from_proto(m),
+            Err(Error::UnknownMatchMode { mode: 2 })
+        );
+    }
+
+    #[test]
+    fn test_proto_key_bits() {
+        let mut b: Filter = BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .size(FilterSize::KeyBits(20))
+            .build();
+        b.insert(&42);
+
+        let msg = b.to_proto();
+        assert_eq!((msg.key_size, msg.key_bits), (0, 20));
+
+        let got = Filter::from_proto(msg.clone()).unwrap();
+        assert_eq!(got.key_size(), FilterSize::KeyBits(20));
+        assert!(got.contains(&42));
+
+        let mut m = msg;
+        m.key_bits = 65;
+        assert_eq!(
+            Filter::from_proto(m),
+            Err(Error::InvalidKeyBits { key_bits: 65 })
+        );
+    }
+
+    #[test]
+    fn test_proto_invalid() {
+        let mut b: Filter = BloomFilterBuilder::hasher(StableBuildHasher::default()).build();
+        b.insert(&42);
+        let msg = b.to_proto();
+
+        let mut m = msg.clone();
+        m.key_size = 9;
+        assert_eq!(
+            Filter::from_proto(m),
+            Err(Error::InvalidKeySize { key_bytes: 9 })
+        );
+
+        let mut m = msg.clone();
+        m.hasher ^= 1;
+        assert_eq!(Filter::from_proto(m), Err(Error::HasherMismatch));
+
+        let mut m = msg;
+        m.bitmap.pop();
+        assert!(matches!(
+            Filter::from_proto(m),
+            Err(Error::InvalidBitmap(_))
+        ));
+    }
+
+    #[test]
+    fn test_proto_dense_top_map() {
+        let mut b: Filter = BloomFilterBuilder::hasher(StableBuildHasher::default())
+            .size(FilterSize::KeyBytes4)
+            .build();
+        b.insert(&42);
+        b.insert(&4242);
+
+        // Messages written before the top map was sparse hold every top map
+        // word up to the highest populated word, and no indexes.
+        let mut m = b.to_proto();
+        let mut dense = vec![0; *m.top_index.last().unwrap() as usize + 1];
+        for (&i, &w) in m.top_index.iter().zip(&m.top_map) {
+            dense[i as usize] = w;
+        }
+        m.top_index.clear();
+        m.top_map = dense;
+
+        let got = Filter::from_proto(m).unwrap();
+        assert_eq!(got, b);
+    }
+}
@@ -0,0 +1,264 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+use crate::{Bitmap, Bloom2};
+
+/// The default number of requests queued before callers block.
+const DEFAULT_QUEUE_DEPTH: usize = 1024;
+
+/// The default maximum number of items looked up in a single batch.
+const DEFAULT_MAX_BATCH: usize = 4096;
+
+/// A request for the membership of `items`, answered on `reply`.
+#[derive(Debug)]
+struct Request<T> {
+    items: Vec<T>,
+    reply: SyncSender<Vec<bool>>,
+}
+
+/// A handle to a worker thread that owns a [`Bloom2`] filter, serving
+/// lookups from many callers in batches.
+///
+/// Funnelling a high rate of lookups through one shared filter contends on
+/// the filter's cache lines and repeats the work of locating blocks for each
+/// lookup. A `QueryWorker` instead queues lookups on a bounded channel, and
+/// the worker thread drains the queued requests into a single
+/// [`Bloom2::contains_batch()`] call, amortising the cost across the batch:
+///
+/// ```rust
+/// use bloom2::{Bloom2, QueryWorker};
+///
+/// let mut filter = Bloom2::default();
+/// filter.insert(&42_u64);
+///
+/// let worker = QueryWorker::spawn(filter);
+///
+/// let handles = (0..4)
+///     .map(|_| {
+///         let worker = worker.clone();
+///         std::thread::spawn(move || worker.contains(42))
+///     })
+///     .collect::<Vec<_>>();
+///
+/// for h in handles {
+///     assert!(h.join().unwrap());
+/// }
+/// assert_eq!(worker.contains_many(vec![42, 24]), [true, false]);
+/// ```
+///
+/// The filter is frozen once it is moved into the worker. A `QueryWorker` is
+/// cheap to clone, and the worker thread exits once every clone is dropped.
+#[derive(Debug)]
+pub struct QueryWorker<T> {
+    tx: SyncSender<Request<T>>,
+}
+
+impl<T> Clone for QueryWorker<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<T> QueryWorker<T>
+where
+    T: Hash + Send + 'static,
+{
+    /// Spawn a worker thread serving lookups against `filter`, with the
+    /// default configuration of a [`QueryWorkerBuilder`].
+    pub fn spawn<H, B>(filter: Bloom2<H, B, T>) -> Self
+    where
+        H: BuildHasher + Send + 'static,
+        B: Bitmap + Send + 'static,
+    {
+        QueryWorkerBuilder::new().spawn(filter)
+    }
+
+    /// Returns `true` if `item` **probably** exists in the filter, or `false`
+    /// if it **definitely** does not, as [`Bloom2::contains()`] does.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the worker thread panicked.
+    pub fn contains(&self, item: T) -> bool {
+        self.contains_many(vec![item])[0]
+    }
+
+    /// Returns the result of [`QueryWorker::contains()`] for each of `items`,
+    /// in order.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the worker thread panicked.
+    pub fn contains_many(&self, items: Vec<T>) -> Vec<bool> {
+        let (reply, rx) = sync_channel(1);
+        self.tx
+            .send(Request { items, reply })
+            .expect("query worker stopped");
+        rx.recv().expect("query worker stopped")
+    }
+}
+
+/// Configure and spawn a [`QueryWorker`].
+///
+/// ```rust
+/// use bloom2::{Bloom2, QueryWorkerBuilder};
+///
+/// let worker = QueryWorkerBuilder::new()
+///     .queue_depth(64)
+///     .max_batch(1024)
+///     .spawn(Bloom2::default());
+///
+/// assert!(!worker.contains("bananas"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueryWorkerBuilder {
+    queue_depth: usize,
+    max_batch: usize,
+}
+
+impl Default for QueryWorkerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueryWorkerBuilder {
+    /// Initialise a `QueryWorkerBuilder` queueing up to 1024 requests, and
+    /// looking up batches of up to 4096 items.
+    pub fn new() -> Self {
+        Self {
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            max_batch: DEFAULT_MAX_BATCH,
+        }
+    }
+
+    /// Set the number of requests queued before callers block (at least 1).
+    pub fn queue_depth(self, queue_depth: usize) -> Self {
+        Self {
+            queue_depth: queue_depth.max(1),
+            ..self
+        }
+    }
+
+    /// Set the number of items after which the worker stops draining queued
+    /// requests into the current batch (at least 1).
+    ///
+    /// A single request larger than `max_batch` is not split.
+    pub fn max_batch(self, max_batch: usize) -> Self {
+        Self {
+            max_batch: max_batch.max(1),
+            ..self
+        }
+    }
+
+    /// Spawn a worker thread serving lookups against `filter`.
+    pub fn spawn<H, B, T>(self, filter: Bloom2<H, B, T>) -> QueryWorker<T>
+    where
+        H: BuildHasher + Send + 'static,
+        B: Bitmap + Send + 'static,
+        T: Hash + Send + 'static,
+    {
+        let (tx, rx) = sync_channel(self.queue_depth);
+        std::thread::spawn(move || serve(filter, rx, self.max_batch));
+        QueryWorker { tx }
+    }
+}
+
+/// Answer requests from `rx` until every sender is dropped.
+fn serve<H, B, T>(filter: Bloom2<H, B, T>, rx: Receiver<Request<T>>, max_batch: usize)
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    let mut items = Vec::new();
+    let mut out = Vec::new();
+
+    while let Ok(first) = rx.recv() {
+        // Drain any queued requests into the batch.
+        let mut requests = vec![first];
+        let mut n = requests[0].items.len();
+        while n < max_batch {
+            match rx.try_recv() {
+                Ok(r) => {
+                    n += r.items.len();
+                    requests.push(r);
+                }
+                Err(_) => break,
+            }
+        }
+
+        let lens = requests.iter().map(|r| r.items.len()).collect::<Vec<_>>();
+        items.clear();
+        items.extend(requests.iter_mut().flat_map(|r| r.items.drain(..)));
+        out.clear();
+        out.resize(items.len(), false);
+
+        filter.contains_batch(&items, &mut out);
+
+        let mut results = out.iter().copied();
+        for (r, len) in requests.into_iter().zip(lens) {
+            // The caller may have gone away, which is not an error.
+            let _ = r.reply.send(results.by_ref().take(len).collect());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::BloomFilterBuilder;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    #[quickcheck]
+    fn test_contains_many(inserted: Vec<u32>, queries: Vec<u32>, max_batch: u8) {
+        let mut filter: Bloom2<_, _, u32> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default()).build();
+        for v in &inserted {
+            filter.insert(v);
+        }
+        let want = queries
+            .iter()
+            .map(|v| filter.contains(v))
+            .collect::<Vec<_>>();
+
+        let worker = QueryWorkerBuilder::new()
+            .max_batch(max_batch as usize)
+            .spawn(filter);
+
+        assert_eq!(worker.contains_many(queries), want);
+    }
+
+    #[test]
+    fn test_concurrent_callers() {
+        let mut filter: Bloom2<_, _, u32> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default()).build();
+        for v in (0..1000).step_by(2) {
+            filter.insert(&v);
+        }
+
+        let worker = QueryWorkerBuilder::new().queue_depth(2).spawn(filter);
+
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let worker = worker.clone();
+                s.spawn(move || {
+                    for v in (t..1000).step_by(8) {
+                        if v % 2 == 0 {
+                            assert!(worker.contains(v));
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(worker.contains_many(vec![]), Vec::<bool>::new());
+    }
+}
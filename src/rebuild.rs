@@ -0,0 +1,336 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::ops::ControlFlow;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{Bitmap, Bloom2, Error};
+
+/// The number of items inserted between each progress report of
+/// [`Bloom2::rebuild_with()`].
+const PROGRESS_INTERVAL: usize = 1 << 16;
+
+/// The progress of a [`Bloom2::rebuild_with()`] call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RebuildProgress {
+    /// The number of items inserted into the replacement filter so far.
+    pub inserted: usize,
+
+    /// The total number of items, if known from the [`Iterator::size_hint()`]
+    /// of the items.
+    pub total: Option<usize>,
+
+    /// The size of the bitmap of the replacement filter in bytes (see
+    /// [`Bitmap::byte_size()`]).
+    pub byte_size: usize,
+
+    /// The time since the rebuild started.
+    pub elapsed: Duration,
+
+    /// `true` for the final report, after all items have been inserted.
+    pub done: bool,
+}
+
+impl RebuildProgress {
+    /// Return the fraction of items inserted, between 0 and 1, if the total
+    /// number of items is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            _ if self.done => Some(1.0),
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.inserted as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
+
+impl<H, B, T> Bloom2<H, B, T>
+where
+    H: BuildHasher + Clone,
+    B: Bitmap,
+    T: Hash,
+{
+    /// Replace the contents of the filter with exactly `items`.
+    ///
+    /// Items cannot be removed from a bloom filter, so a filter of a changing
+    /// set accumulates the bits of removed items, increasing the false
+    /// positive probability over time. Periodically rebuilding the filter from
+    /// the live items discards them:
+    ///
+    /// ```rust
+    /// use bloom2::Bloom2;
+    ///
+    /// let mut b = Bloom2::default();
+    /// b.insert(&"bananas");
+    /// b.insert(&"platanos");
+    ///
+    /// // "bananas" was deleted from the source of truth.
+    /// b.rebuild_from(&["platanos"]);
+    ///
+    /// assert!(b.contains(&"platanos"));
+    /// assert!(!b.contains(&"bananas"));
+    /// ```
+    ///
+    /// A new filter with the same hasher, [`FilterSize`](crate::FilterSize)
+    /// and [`ProbeScheme`](crate::ProbeScheme) is populated from `items`
+    /// before replacing this filter, so this filter is left unchanged if
+    /// `items` panics.
+    pub fn rebuild_from<I>(&mut self, items: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<T>,
+    {
+        let mut fresh = self.clone_empty();
+        for v in items {
+            fresh.insert(v.borrow());
+        }
+
+        *self = fresh;
+    }
+
+    /// Replace the contents of the filter with exactly `items` as
+    /// [`Bloom2::rebuild_from()`] does, calling `progress` periodically while
+    /// the replacement filter is populated.
+    ///
+    /// `progress` is called after every 65536 items and once more when all
+    /// items have been inserted. Returning [`ControlFlow::Break`] from
+    /// `progress` cancels the rebuild, discarding the replacement filter and
+    /// leaving this filter unchanged.
+    ///
+    /// ```rust
+    /// use std::ops::ControlFlow;
+    /// use bloom2::Bloom2;
+    ///
+    /// let mut b = Bloom2::default();
+    /// b.insert(&0_u64);
+    ///
+    /// b.rebuild_with(1..100_000_u64, |p| {
+    ///     println!("rebuild {:.0}% complete", p.fraction().unwrap() * 100.0);
+    ///     ControlFlow::Continue(())
+    /// })
+    /// .unwrap();
+    ///
+    /// assert!(b.contains(&42));
+    ///
+    /// // Cancel a rebuild, leaving the filter unchanged.
+    /// let err = b.rebuild_with(0..0_u64, |_| ControlFlow::Break(()));
+    /// assert!(err.is_err());
+    /// assert!(b.contains(&42));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::RebuildCancelled`] if `progress` cancels the rebuild.
+    pub fn rebuild_with<I, F>(&mut self, items: I, mut progress: F) -> Result<(), Error>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<T>,
+        F: FnMut(RebuildProgress) -> ControlFlow<()>,
+    {
+        let items = items.into_iter();
+        let total = match items.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        };
+
+        let start = Instant::now();
+        let mut fresh = self.clone_empty();
+        let mut report = |fresh: &Self, inserted, done| {
+            progress(RebuildProgress {
+                inserted,
+                total,
+                byte_size: fresh.bitmap().byte_size(),
+                elapsed: start.elapsed(),
+                done,
+            })
+        };
+
+        let mut inserted = 0;
+        for v in items {
+            fresh.insert(v.borrow());
+            inserted += 1;
+
+            if inserted % PROGRESS_INTERVAL == 0 && report(&fresh, inserted, false).is_break() {
+                return Err(Error::RebuildCancelled { inserted });
+            }
+        }
+
+        if report(&fresh, inserted, true).is_break() {
+            return Err(Error::RebuildCancelled { inserted });
+        }
+
+        *self = fresh;
+        Ok(())
+    }
+
+    /// Rebuild the shared `filter` from `items` on a background thread, as
+    /// [`Bloom2::rebuild_from()`] does.
+    ///
+    /// The new filter is populated without holding the lock, so readers and
+    /// writers of `filter` are not blocked during the rebuild. The write lock
+    /// is only acquired to swap the new filter in once it is complete.
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, RwLock};
+    /// use bloom2::Bloom2;
+    ///
+    /// let filter = Arc::new(RwLock::new(Bloom2::default()));
+    /// filter.write().unwrap().insert(&1_u64);
+    ///
+    /// Bloom2::rebuild_in_background(&filter, 2..1000_u64)
+    ///     .join()
+    ///     .unwrap();
+    ///
+    /// assert!(filter.read().unwrap().contains(&2));
+    /// ```
+    ///
+    /// Items inserted into `filter` while the rebuild is in progress are
+    /// discarded when the new filter is swapped in, unless they are also
+    /// yielded by `items`.
+    pub fn rebuild_in_background<I>(filter: &Arc<RwLock<Self>>, items: I) -> JoinHandle<()>
+    where
+        I: IntoIterator + Send + 'static,
+        I::Item: Borrow<T>,
+        Self: Send + Sync + 'static,
+    {
+        let filter = Arc::clone(filter);
+        std::thread::spawn(move || {
+            let mut fresh = filter
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone_empty();
+            for v in items {
+                fresh.insert(v.borrow());
+            }
+
+            *filter.write().unwrap_or_else(PoisonError::into_inner) = fresh;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{BloomFilterBuilder, FilterSize, ProbeScheme, VecBitmap};
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    #[quickcheck]
+    fn test_rebuild_from(old: Vec<u32>, live: Vec<u32>) {
+        let mut b: Bloom2<_, VecBitmap, u32> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default())
+                .with_bitmap::<VecBitmap>()
+                .size(FilterSize::KeyBytes2)
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 3 })
+                .build();
+        for v in &old {
+            b.insert(v);
+        }
+
+        b.rebuild_from(&live);
+
+        // The result matches a filter built from only the live items.
+        let mut want = b.clone_empty();
+        for v in &live {
+            want.insert(v);
+        }
+        assert_eq!(b, want);
+    }
+
+    #[test]
+    fn test_rebuild_with() {
+        let mut b: Bloom2<_, VecBitmap, u32> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default())
+                .with_bitmap::<VecBitmap>()
+                .size(FilterSize::KeyBytes3)
+                .build();
+        b.insert(&u32::MAX);
+
+        let n = 3 * PROGRESS_INTERVAL as u32 + 42;
+
+        let mut reports = vec![];
+        b.rebuild_with(0..n, |p| {
+            reports.push(p);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+        let inserted = reports.iter().map(|p| p.inserted).collect::<Vec<_>>();
+        assert_eq!(
+            inserted,
+            [
+                PROGRESS_INTERVAL,
+                2 * PROGRESS_INTERVAL,
+                3 * PROGRESS_INTERVAL,
+                n as usize
+            ]
+        );
+        assert!(reports.iter().all(|p| p.total == Some(n as usize)));
+        assert_eq!(
+            reports.iter().map(|p| p.done).collect::<Vec<_>>(),
+            [false, false, false, true]
+        );
+        assert_eq!(reports.last().unwrap().fraction(), Some(1.0));
+        assert_eq!(
+            reports[0].fraction(),
+            Some(PROGRESS_INTERVAL as f64 / n as f64)
+        );
+        assert_eq!(reports.last().unwrap().byte_size, b.bitmap().byte_size());
+
+        // The result matches a filter built from only the new items.
+        let mut want = b.clone_empty();
+        for v in 0..n {
+            want.insert(&v);
+        }
+        assert_eq!(b, want);
+    }
+
+    #[test]
+    fn test_rebuild_with_cancel() {
+        let mut b: Bloom2<_, VecBitmap, u32> =
+            BloomFilterBuilder::hasher(StableBuildHasher::default())
+                .with_bitmap::<VecBitmap>()
+                .build();
+        b.insert(&u32::MAX);
+        let want = b.clone();
+
+        // Unknown totals are reported as such.
+        let got = b.rebuild_with((0..).take_while(|&v| v < u32::MAX), |p| {
+            assert_eq!(p.total, None);
+            assert_eq!(p.fraction(), None);
+            ControlFlow::Break(())
+        });
+
+        assert_eq!(
+            got,
+            Err(Error::RebuildCancelled {
+                inserted: PROGRESS_INTERVAL
+            })
+        );
+        assert_eq!(b, want);
+    }
+
+    #[test]
+    fn test_rebuild_in_background() {
+        let filter = Arc::new(RwLock::new(
+            BloomFilterBuilder::hasher(StableBuildHasher::default()).build(),
+        ));
+        for v in 0..100_u32 {
+            filter.write().unwrap().insert(&v);
+        }
+
+        Bloom2::rebuild_in_background(&filter, 50..150_u32)
+            .join()
+            .unwrap();
+
+        let filter = filter.read().unwrap();
+        assert!((50..150).all(|v| filter.contains(&v)));
+        assert!((0..50).filter(|v| filter.contains(v)).count() < 5);
+        assert_eq!(filter.key_size(), FilterSize::KeyBytes2);
+    }
+}
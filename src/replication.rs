@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{bitmap::index_for_key, bloom::Params, Bloom2, CompressedBitmap, Error};
+
+/// A [`Bloom2`] filter that tracks a monotonically increasing version, and
+/// the version at which each bitmap block last changed, to replicate its
+/// changes to read replicas as compact [`FilterDelta`] messages.
+///
+/// The primary filter inserts items, and periodically sends each replica the
+/// [changes](VersionedFilter::changes_since) since the version the replica
+/// last [applied](VersionedFilter::apply):
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::{BloomFilterBuilder, VersionedFilter};
+///
+/// let hasher = RandomState::default();
+/// let mut primary = VersionedFilter::new(BloomFilterBuilder::hasher(hasher.clone()).build());
+/// let mut replica = VersionedFilter::new(BloomFilterBuilder::hasher(hasher).build());
+///
+/// primary.insert(&"bananas");
+///
+/// let delta = primary.changes_since(replica.version());
+/// replica.apply(&delta).unwrap();
+///
+/// assert!(replica.contains(&"bananas"));
+/// assert_eq!(replica.version(), primary.version());
+/// ```
+///
+/// A delta carries the full value of each changed block, and applying it is
+/// idempotent - a replica may safely re-apply a delta, or apply deltas that
+/// overlap. A replica can itself serve deltas to further replicas, but should
+/// not insert items directly, as its versions would diverge from those of the
+/// primary.
+#[derive(Debug, Clone)]
+pub struct VersionedFilter<H, T>
+where
+    H: BuildHasher,
+{
+    filter: Bloom2<H, CompressedBitmap, T>,
+    version: u64,
+
+    /// The version at which each populated block last changed, by block
+    /// index.
+    block_versions: HashMap<usize, u64>,
+}
+
+/// The changed blocks of a [`VersionedFilter`] between two versions, as
+/// returned by [`VersionedFilter::changes_since()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterDelta {
+    header: Params,
+    from: u64,
+    to: u64,
+
+    /// The index and value of each changed block, in ascending index order.
+    blocks: Vec<(usize, usize)>,
+}
+
+impl FilterDelta {
+    /// Returns the version this delta applies on top of.
+    pub fn from_version(&self) -> u64 {
+        self.from
+    }
+
+    /// Returns the version of the filter after applying this delta.
+    pub fn to_version(&self) -> u64 {
+        self.to
+    }
+
+    /// Returns the number of changed blocks in this delta.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns `true` if this delta contains no changes.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl<H, T> VersionedFilter<H, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    /// Start tracking the versions of `filter`.
+    ///
+    /// If `filter` is not empty, its contents are recorded as version 1.
+    pub fn new(filter: Bloom2<H, CompressedBitmap, T>) -> Self {
+        let mut block_versions = HashMap::new();
+        for key in filter.bitmap().ones() {
+            block_versions.insert(index_for_key(key), 1);
+        }
+
+        Self {
+            version: if block_versions.is_empty() { 0 } else { 1 },
+            filter,
+            block_versions,
+        }
+    }
+
+    /// Insert `data` into the filter, incrementing the version if any bit of
+    /// the filter changes.
+    pub fn insert(&mut self, data: &'_ T) {
+        let hash = self.filter.hasher().hash_one(data);
+        let keys = self
+            .filter
+            .probe_scheme()
+            .keys(hash, self.filter.key_size())
+            .filter(|&key| !self.filter.bitmap().get(key))
+            .collect::<Vec<_>>();
+
+        if keys.is_empty() {
+            return;
+        }
+
+        self.version += 1;
+        for &key in &keys {
+            self.filter.bitmap_mut().set(key, true);
+            self.block_versions.insert(index_for_key(key), self.version);
+        }
+    }
+
+    /// Checks if `data` exists in the filter, as [`Bloom2::contains()`] does.
+    pub fn contains(&self, data: &'_ T) -> bool {
+        self.filter.contains(data)
+    }
+
+    /// Returns the current version of the filter.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &Bloom2<H, CompressedBitmap, T> {
+        &self.filter
+    }
+
+    /// Return the blocks changed after `version`, to bring a replica at
+    /// `version` up to date with this filter.
+    ///
+    /// A `version` newer than the current version is treated as the current
+    /// version, returning an empty delta.
+    pub fn changes_since(&self, version: u64) -> FilterDelta {
+        let from = version.min(self.version);
+
+        let mut blocks = self
+            .block_versions
+            .iter()
+            .filter(|(_, &v)| v > from)
+            .map(|(&index, _)| (index, self.filter.bitmap().block(index)))
+            .collect::<Vec<_>>();
+        blocks.sort_unstable();
+
+        FilterDelta {
+            header: self.params(),
+            from,
+            to: self.version,
+            blocks,
+        }
+    }
+
+    /// Apply `delta` to this filter, advancing the version to the
+    /// [`to_version`](FilterDelta::to_version) of `delta` if it is newer.
+    ///
+    /// Returns an error and leaves the filter unchanged if `delta` was
+    /// produced by an incompatible filter (see [`Bloom2::try_union()`]), if
+    /// it starts after the current version (see [`Error::VersionGap`]), or if
+    /// it contains a block outside of the key space of this filter.
+    pub fn apply(&mut self, delta: &FilterDelta) -> Result<(), Error> {
+        self.params().check(&delta.header)?;
+
+        if delta.from > self.version {
+            return Err(Error::VersionGap {
+                from: delta.from,
+                version: self.version,
+            });
+        }
+
+        let max_key = self.filter.bitmap().max_key();
+        if let Some(&(index, _)) = delta
+            .blocks
+            .iter()
+            .find(|(index, _)| index_for_key(max_key) < *index)
+        {
+            return Err(Error::KeyOutOfRange {
+                key: index * u64::BITS as usize,
+                max_key,
+            });
+        }
+
+        let version = delta.to.max(self.version);
+        let mut keys = Vec::new();
+        for &(index, block) in &delta.blocks {
+            let new = block & !self.filter.bitmap().block(index);
+            if new == 0 {
+                continue;
+            }
+
+            self.block_versions.insert(index, version);
+            keys.extend(
+                (0..u64::BITS as usize)
+                    .filter(|bit| new & (1 << bit) != 0)
+                    .map(|bit| index * u64::BITS as usize + bit),
+            );
+        }
+
+        self.filter.bitmap_mut().set_many(&mut keys);
+        self.version = version;
+
+        Ok(())
+    }
+
+    fn params(&self) -> Params {
+        Params::new(
+            self.filter.hasher(),
+            self.filter.key_size(),
+            self.filter.probe_scheme(),
+            self.filter.match_mode(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{BloomFilterBuilder, FilterSize};
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+    type Filter = VersionedFilter<StableBuildHasher, u32>;
+
+    fn new_filter(key_size: FilterSize) -> Filter {
+        VersionedFilter::new(
+            BloomFilterBuilder::hasher(StableBuildHasher::default())
+                .size(key_size)
+                .build(),
+        )
+    }
+
+    #[quickcheck]
+    fn test_replicate(a: Vec<u32>, b: Vec<u32>) {
+        let mut primary = new_filter(FilterSize::KeyBytes2);
+        let mut replica = new_filter(FilterSize::KeyBytes2);
+
+        for v in &a {
+            primary.insert(v);
+        }
+        replica.apply(&primary.changes_since(0)).unwrap();
+
+        let checkpoint = replica.version();
+        for v in &b {
+            primary.insert(v);
+        }
+        let delta = primary.changes_since(checkpoint);
+        replica.apply(&delta).unwrap();
+
+        // Re-applying a delta is a no-op.
+        replica.apply(&delta).unwrap();
+
+        assert_eq!(replica.version(), primary.version());
+        assert_eq!(replica.filter().bitmap(), primary.filter().bitmap());
+
+        // A replica can serve its changes to another replica.
+        let mut chained = new_filter(FilterSize::KeyBytes2);
+        chained.apply(&replica.changes_since(0)).unwrap();
+        assert_eq!(chained.filter().bitmap(), primary.filter().bitmap());
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut f = new_filter(FilterSize::KeyBytes2);
+        assert_eq!(f.version(), 0);
+        assert!(f.changes_since(0).is_empty());
+
+        f.insert(&1);
+        assert_eq!(f.version(), 1);
+
+        // Re-inserting an item changes no bits.
+        f.insert(&1);
+        assert_eq!(f.version(), 1);
+
+        f.insert(&2);
+        assert_eq!(f.version(), 2);
+
+        // Only the blocks changed by the second insert are included.
+        let delta = f.changes_since(1);
+        assert_eq!(delta.from_version(), 1);
+        assert_eq!(delta.to_version(), 2);
+        assert!(!delta.is_empty());
+        assert!(delta.len() <= f.changes_since(0).len());
+        assert!(f.changes_since(2).is_empty());
+        assert!(f.changes_since(42).is_empty());
+
+        // Existing contents are recorded as version 1.
+        let g = VersionedFilter::new(f.filter().clone());
+        assert_eq!(g.version(), 1);
+        assert_eq!(g.changes_since(0).len(), f.changes_since(0).len());
+    }
+
+    #[test]
+    fn test_apply_errors() {
+        let mut primary = new_filter(FilterSize::KeyBytes2);
+        primary.insert(&1);
+        primary.insert(&2);
+
+        let mut replica = new_filter(FilterSize::KeyBytes2);
+        assert_eq!(
+            replica.apply(&primary.changes_since(1)),
+            Err(Error::VersionGap {
+                from: 1,
+                version: 0
+            })
+        );
+
+        let mut other = new_filter(FilterSize::KeyBytes1);
+        assert_eq!(
+            other.apply(&primary.changes_since(0)),
+            Err(Error::KeySizeMismatch {
+                ours: FilterSize::KeyBytes1,
+                theirs: FilterSize::KeyBytes2,
+            })
+        );
+
+        let mut delta = primary.changes_since(0);
+        delta.blocks.push((1 << 20, 1));
+        assert!(matches!(
+            replica.apply(&delta),
+            Err(Error::KeyOutOfRange { .. })
+        ));
+        assert_eq!(replica.version(), 0);
+        assert!(!replica.contains(&1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let mut primary = new_filter(FilterSize::KeyBytes2);
+        primary.insert(&1);
+
+        let delta = primary.changes_since(0);
+        let encoded = serde_json::to_string(&delta).unwrap();
+        assert_eq!(
+            serde_json::from_str::<FilterDelta>(&encoded).unwrap(),
+            delta
+        );
+    }
+}
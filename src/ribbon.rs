@@ -0,0 +1,426 @@
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+#[cfg(feature = "serde")]
+use crate::bloom::HASHER_FINGERPRINT_VALUE;
+
+/// The number of slots spanned by the coefficients of each item.
+const WIDTH: usize = u64::BITS as usize;
+
+/// The default number of fingerprint bits per item.
+const DEFAULT_FINGERPRINT_BITS: u8 = 8;
+
+/// Construct a [`RibbonFilter`] from a set of items.
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::RibbonFilterBuilder;
+///
+/// let filter = RibbonFilterBuilder::hasher(RandomState::default())
+///     .fingerprint_bits(10)
+///     .build(0..10_000_u32);
+///
+/// assert!(filter.contains(&42));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RibbonFilterBuilder<H> {
+    hasher: H,
+    fingerprint_bits: u8,
+}
+
+impl<H> RibbonFilterBuilder<H>
+where
+    H: BuildHasher,
+{
+    /// Initialise a `RibbonFilterBuilder` that will construct a filter using
+    /// `hasher`, and 8 fingerprint bits per item unless changed.
+    pub fn hasher(hasher: H) -> Self {
+        Self {
+            hasher,
+            fingerprint_bits: DEFAULT_FINGERPRINT_BITS,
+        }
+    }
+
+    /// Set the number of fingerprint bits stored per item, between 1 and 32.
+    ///
+    /// The false positive probability of the filter is `2^-fingerprint_bits`,
+    /// and the filter uses approximately `1.1 * fingerprint_bits` bits per
+    /// item.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `fingerprint_bits` is not between 1 and 32.
+    pub fn fingerprint_bits(self, fingerprint_bits: u8) -> Self {
+        assert!(
+            (1..=32).contains(&fingerprint_bits),
+            "fingerprint bits must be between 1 and 32"
+        );
+        Self {
+            fingerprint_bits,
+            ..self
+        }
+    }
+
+    /// Construct a [`RibbonFilter`] containing `items`.
+    pub fn build<I, T>(self, items: I) -> RibbonFilter<H, T>
+    where
+        I: IntoIterator<Item = T>,
+        T: Hash,
+    {
+        let hashes = items
+            .into_iter()
+            .map(|v| self.hasher.hash_one(&v))
+            .collect::<Vec<_>>();
+
+        // Start with ~10% spare slots, retrying with a new seed if the items
+        // cannot be solved, and growing the filter after repeated failures.
+        let mut slots = hashes.len() + hashes.len() / 10 + WIDTH;
+        let mut seed = 0;
+        loop {
+            slots = slots.div_ceil(WIDTH) * WIDTH;
+            if let Some(columns) = solve(&hashes, seed, slots, self.fingerprint_bits) {
+                return RibbonFilter {
+                    hasher: self.hasher,
+                    seed,
+                    slots,
+                    fingerprint_bits: self.fingerprint_bits,
+                    columns,
+                    _key_type: PhantomData,
+                };
+            }
+
+            seed += 1;
+            if seed % 2 == 0 {
+                slots += slots / 16;
+            }
+        }
+    }
+}
+
+/// An immutable, static approximate set using less space than a [`Bloom2`]
+/// at an equal false positive probability.
+///
+/// A ribbon filter (see [Dillinger & Walzer]) stores a solution to a system of
+/// linear equations over the fingerprints of its items, in approximately
+/// `1.1 * r` bits per item for a false positive probability of `2^-r`.
+/// Items cannot be added after the filter is constructed by a
+/// [`RibbonFilterBuilder`].
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::RibbonFilterBuilder;
+///
+/// let filter = RibbonFilterBuilder::hasher(RandomState::default())
+///     .build(vec!["bananas", "platanos"]);
+///
+/// assert!(filter.contains(&"bananas"));
+/// assert_eq!(filter.fpp(), 1.0 / 256.0);
+/// ```
+///
+/// ## Features
+///
+/// If the `serde` feature is enabled, a `RibbonFilter` supports
+/// (de)serialisation with [serde], validated against the hasher as for a
+/// [`Bloom2`].
+///
+/// [`Bloom2`]: crate::Bloom2
+/// [Dillinger & Walzer]: https://arxiv.org/abs/2103.02515
+/// [serde]: https://github.com/serde-rs/serde
+#[derive(Debug, Clone, PartialEq)]
+pub struct RibbonFilter<H, T> {
+    hasher: H,
+    seed: u64,
+
+    /// The number of solution rows (a multiple of [`WIDTH`]).
+    slots: usize,
+    fingerprint_bits: u8,
+
+    /// The solution, interleaved by column: for each block of [`WIDTH`]
+    /// rows, one word per fingerprint bit holding that bit of each row.
+    columns: Vec<usize>,
+
+    _key_type: PhantomData<T>,
+}
+
+impl<H, T> RibbonFilter<H, T>
+where
+    H: BuildHasher,
+    T: Hash,
+{
+    /// Returns `true` if `data` was **probably** in the set the filter was
+    /// built from, or `false` if it **definitely** was not.
+    pub fn contains(&self, data: &'_ T) -> bool {
+        let (start, coeff, fingerprint) = row(
+            self.hasher.hash_one(data),
+            self.seed,
+            self.slots,
+            self.fingerprint_bits,
+        );
+
+        let block = start / WIDTH;
+        let offset = start % WIDTH;
+        let r = self.fingerprint_bits as usize;
+
+        let mut got = 0;
+        for bit in 0..r {
+            // The WIDTH solution bits for this column starting at `start`,
+            // spanning at most two blocks.
+            let lo = self.columns[block * r + bit] >> offset;
+            let hi = match offset {
+                0 => 0,
+                _ => self.columns[(block + 1) * r + bit] << (WIDTH - offset),
+            };
+
+            got |= (((lo | hi) as u64 & coeff).count_ones() & 1) << bit;
+        }
+
+        got == fingerprint
+    }
+
+    /// Returns the false positive probability of the filter.
+    pub fn fpp(&self) -> f64 {
+        0.5_f64.powi(self.fingerprint_bits as i32)
+    }
+
+    /// Returns the number of bytes used by the filter solution.
+    pub fn byte_size(&self) -> usize {
+        self.columns.len() * std::mem::size_of::<usize>()
+    }
+}
+
+/// Derive the starting slot, coefficients and fingerprint of the item with
+/// `hash`.
+fn row(hash: u64, seed: u64, slots: usize, fingerprint_bits: u8) -> (usize, u64, u32) {
+    let h = mix(hash.wrapping_add(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15)));
+
+    let start = ((h as u128 * (slots - WIDTH + 1) as u128) >> 64) as usize;
+    // The first coefficient is always set, anchoring the row at start.
+    let coeff = mix(h ^ 0x6a09_e667_f3bc_c908) | 1;
+    let fingerprint = mix(h ^ 0xbb67_ae85_84ca_a73b) & (u64::MAX >> (64 - fingerprint_bits));
+
+    (start, coeff, fingerprint as u32)
+}
+
+/// The SplitMix64 finaliser.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Solve the system of equations for `hashes`, returning the interleaved
+/// solution columns, or [`None`] if the system is inconsistent.
+fn solve(hashes: &[u64], seed: u64, slots: usize, fingerprint_bits: u8) -> Option<Vec<usize>> {
+    // Gaussian elimination into a banded matrix, with each row normalised to
+    // have its first coefficient at its own slot.
+    let mut coeffs = vec![0_u64; slots];
+    let mut results = vec![0_u32; slots];
+
+    for &hash in hashes {
+        let (mut i, mut c, mut b) = row(hash, seed, slots, fingerprint_bits);
+        loop {
+            if coeffs[i] == 0 {
+                coeffs[i] = c;
+                results[i] = b;
+                break;
+            }
+
+            c ^= coeffs[i];
+            b ^= results[i];
+            if c == 0 {
+                // A duplicate (or linearly dependent) row is only consistent
+                // if its result is too.
+                if b != 0 {
+                    return None;
+                }
+                break;
+            }
+
+            let shift = c.trailing_zeros();
+            i += shift as usize;
+            c >>= shift;
+        }
+    }
+
+    // Back substitution, from the last row to the first. Free rows are 0.
+    let mut solution = vec![0_u32; slots];
+    for i in (0..slots).rev() {
+        let mut c = coeffs[i] >> 1;
+        let mut v = results[i];
+        while c != 0 {
+            let j = c.trailing_zeros() as usize + 1;
+            v ^= solution[i + j];
+            c &= c - 1;
+        }
+        solution[i] = v;
+    }
+
+    let r = fingerprint_bits as usize;
+    let mut columns = vec![0_usize; slots / WIDTH * r];
+    for (i, v) in solution.into_iter().enumerate() {
+        for bit in 0..r {
+            columns[i / WIDTH * r + bit] |= (((v >> bit) & 1) as usize) << (i % WIDTH);
+        }
+    }
+
+    Some(columns)
+}
+
+/// The serialised representation of a [`RibbonFilter`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct ReprRef<'a> {
+    hasher: u64,
+    seed: u64,
+    slots: u64,
+    fingerprint_bits: u8,
+    #[serde(serialize_with = "crate::bitmap::serde_words::serialize")]
+    columns: &'a [usize],
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Repr {
+    hasher: u64,
+    seed: u64,
+    slots: u64,
+    fingerprint_bits: u8,
+    #[serde(deserialize_with = "crate::bitmap::serde_words::deserialize")]
+    columns: Vec<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl<H, T> serde::Serialize for RibbonFilter<H, T>
+where
+    H: BuildHasher,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ReprRef {
+            hasher: self.hasher.hash_one(HASHER_FINGERPRINT_VALUE),
+            seed: self.seed,
+            slots: self.slots as u64,
+            fingerprint_bits: self.fingerprint_bits,
+            columns: &self.columns,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, H, T> serde::Deserialize<'de> for RibbonFilter<H, T>
+where
+    H: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let repr = Repr::deserialize(deserializer)?;
+        let hasher = H::default();
+
+        if repr.hasher != hasher.hash_one(HASHER_FINGERPRINT_VALUE) {
+            return Err(D::Error::custom(crate::Error::HasherMismatch));
+        }
+
+        let slots = repr.slots as usize;
+        if !(1..=32).contains(&repr.fingerprint_bits)
+            || slots < WIDTH
+            || !slots.is_multiple_of(WIDTH)
+        {
+            return Err(D::Error::custom("invalid ribbon filter parameters"));
+        }
+
+        let want = slots / WIDTH * repr.fingerprint_bits as usize;
+        if repr.columns.len() != want {
+            return Err(D::Error::custom(crate::Error::ByteLength {
+                want: want * std::mem::size_of::<usize>(),
+                got: repr.columns.len() * std::mem::size_of::<usize>(),
+            }));
+        }
+
+        Ok(Self {
+            hasher,
+            seed: repr.seed,
+            slots,
+            fingerprint_bits: repr.fingerprint_bits,
+            columns: repr.columns,
+            _key_type: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+
+    proptest! {
+        #[test]
+        fn prop_no_false_negatives(
+            values in prop::collection::vec(any::<u64>(), 0..2000),
+            bits in 1_u8..=32,
+        ) {
+            let f = RibbonFilterBuilder::hasher(StableBuildHasher::default())
+                .fingerprint_bits(bits)
+                .build(values.iter().copied());
+
+            for v in &values {
+                assert!(f.contains(v));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fpp_and_size() {
+        let f = RibbonFilterBuilder::hasher(StableBuildHasher::default()).build(0..50_000_u64);
+
+        let false_positives = (50_000..250_000_u64).filter(|v| f.contains(v)).count();
+        let rate = false_positives as f64 / 200_000.0;
+        assert!(
+            (rate - f.fpp()).abs() < f.fpp() / 4.0,
+            "false positive rate {} for fpp {}",
+            rate,
+            f.fpp()
+        );
+
+        // Within 25% of the information theoretic 8 bits per item.
+        let bits_per_item = f.byte_size() as f64 * 8.0 / 50_000.0;
+        assert!(bits_per_item < 10.0, "{} bits per item", bits_per_item);
+    }
+
+    #[test]
+    fn test_empty() {
+        let f = RibbonFilterBuilder::hasher(StableBuildHasher::default())
+            .fingerprint_bits(32)
+            .build(Vec::<u32>::new());
+        assert!(!f.contains(&42));
+    }
+
+    #[test]
+    #[should_panic(expected = "between 1 and 32")]
+    fn test_invalid_fingerprint_bits() {
+        RibbonFilterBuilder::hasher(StableBuildHasher::default()).fingerprint_bits(33);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let f = RibbonFilterBuilder::hasher(StableBuildHasher::default()).build(0..1000_u32);
+
+        let got: RibbonFilter<StableBuildHasher, u32> =
+            bincode::deserialize(&bincode::serialize(&f).unwrap()).unwrap();
+        assert_eq!(got, f);
+
+        let got: RibbonFilter<StableBuildHasher, u32> =
+            serde_json::from_str(&serde_json::to_string(&f).unwrap()).unwrap();
+        assert_eq!(got, f);
+
+        let mut json = serde_json::to_value(&f).unwrap();
+        json["columns"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<RibbonFilter<StableBuildHasher, u32>>(json).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+
+use crate::{bloom::estimated_entries, Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize};
+
+/// An approximate set of values, offering a subset of the
+/// [`HashSet`](std::collections::HashSet) API backed by a [`Bloom2`] filter.
+///
+/// An `ApproxHashSet` can be used in place of an exact set to save memory when
+/// a small probability of a false positive lookup is acceptable - a value that
+/// was never inserted may be reported as present, but an inserted value is
+/// always reported as present.
+///
+/// ```rust
+/// use bloom2::ApproxHashSet;
+///
+/// let mut set = ApproxHashSet::new();
+///
+/// assert!(set.insert("hello"));
+/// assert!(!set.insert("hello"));
+///
+/// set.extend(["bananas", "platanos"]);
+///
+/// assert!(set.contains(&"bananas"));
+/// assert_eq!(set.len(), 3);
+/// ```
+///
+/// Unlike a `HashSet`, values are not stored and cannot be retrieved or
+/// removed, and the [length](ApproxHashSet::len) of the set is estimated from
+/// the filter contents.
+#[derive(Debug, Clone)]
+pub struct ApproxHashSet<T, S = RandomState>
+where
+    S: BuildHasher,
+{
+    filter: Bloom2<S, CompressedBitmap, T>,
+}
+
+impl<T> ApproxHashSet<T, RandomState>
+where
+    T: Hash,
+{
+    /// Construct an empty `ApproxHashSet` using the default [`Bloom2`]
+    /// configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T> Default for ApproxHashSet<T, RandomState>
+where
+    T: Hash,
+{
+    fn default() -> Self {
+        Self::from_filter(Bloom2::default())
+    }
+}
+
+impl<T, S> ApproxHashSet<T, S>
+where
+    T: Hash,
+    S: BuildHasher,
+{
+    /// Construct an empty `ApproxHashSet` using `hasher` to hash values.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_hasher_and_size(hasher, FilterSize::KeyBytes2)
+    }
+
+    /// Construct an empty `ApproxHashSet` using `hasher` to hash values, with
+    /// a filter of `size`.
+    ///
+    /// See [`FilterSize`].
+    pub fn with_hasher_and_size(hasher: S, size: FilterSize) -> Self {
+        Self::from_filter(BloomFilterBuilder::hasher(hasher).size(size).build())
+    }
+
+    /// Construct an `ApproxHashSet` containing the values in `filter`.
+    pub fn from_filter(filter: Bloom2<S, CompressedBitmap, T>) -> Self {
+        Self { filter }
+    }
+
+    /// Adds `value` to the set.
+    ///
+    /// Returns `true` if the set **probably** did not previously contain
+    /// `value`, or `false` if it **probably** did - a `false` return may be a
+    /// false positive.
+    pub fn insert(&mut self, value: T) -> bool {
+        let new = !self.filter.contains(&value);
+        self.filter.insert(&value);
+        new
+    }
+
+    /// Returns `true` if `value` was **probably** added to the set, or `false`
+    /// if it was **definitely not**.
+    pub fn contains(&self, value: &T) -> bool {
+        self.filter.contains(value)
+    }
+
+    /// Returns the estimated number of distinct values added to the set.
+    ///
+    /// The estimate is derived from the number of bits set in the filter, and
+    /// becomes less accurate as the filter fills.
+    pub fn len(&self) -> usize {
+        estimated_entries(
+            self.filter.key_size(),
+            self.filter.probe_scheme(),
+            self.filter.bitmap().count_ones(),
+        )
+    }
+
+    /// Returns `true` if no values have been added to the set.
+    pub fn is_empty(&self) -> bool {
+        self.filter.bitmap().count_ones() == 0
+    }
+
+    /// Removes all values from the set, retaining the allocated memory for
+    /// reuse.
+    pub fn clear(&mut self) {
+        self.filter.clear();
+    }
+
+    /// Return the underlying [`Bloom2`] filter.
+    pub fn into_inner(self) -> Bloom2<S, CompressedBitmap, T> {
+        self.filter
+    }
+}
+
+impl<T, S> Extend<T> for ApproxHashSet<T, S>
+where
+    T: Hash,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for v in iter {
+            self.filter.insert(&v);
+        }
+    }
+}
+
+impl<'a, T, S> Extend<&'a T> for ApproxHashSet<T, S>
+where
+    T: Hash + 'a,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        for v in iter {
+            self.filter.insert(v);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for ApproxHashSet<T, RandomState>
+where
+    T: Hash,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use proptest::prelude::*;
+    use twox_hash::XxHash64;
+
+    use super::*;
+
+    #[test]
+    fn test_insert_contains() {
+        let mut set = ApproxHashSet::new();
+        assert!(set.is_empty());
+        assert_eq!(set.len(), 0);
+
+        assert!(set.insert(42));
+        assert!(!set.insert(42));
+        assert!(set.contains(&42));
+        assert!(!set.is_empty());
+        assert_eq!(set.len(), 1);
+
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.contains(&42));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_len_estimate(values in prop::collection::hash_set(any::<u64>(), 0..500)) {
+            let mut set = ApproxHashSet::with_hasher(BuildHasherDefault::<XxHash64>::default());
+            set.extend(&values);
+
+            for v in &values {
+                assert!(set.contains(v));
+            }
+
+            // The estimate is within 5% (or 2 entries) of the true length.
+            let want = values.len() as f64;
+            let got = set.len() as f64;
+            assert!((got - want).abs() <= (want * 0.05).max(2.0), "got {}, want {}", got, want);
+        }
+    }
+}
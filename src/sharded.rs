@@ -0,0 +1,366 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    bloom::Params, Bitmap, Bloom2, BloomFilterBuilder, CompressedBitmap, Error, FilterSize,
+};
+
+/// The multiplier used to mix a hash before routing it to a shard (the 64-bit
+/// golden ratio).
+pub(crate) const ROUTING_MIX: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A single logical filter partitioned across N independent [`Bloom2`]
+/// shards.
+///
+/// Each item is hashed once, and the hash routed to exactly one shard, which
+/// allows each shard to be owned, populated and persisted by a different
+/// worker while the set as a whole answers [`contains`] for any item:
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom2::{FilterSize, ShardedFilterSet};
+///
+/// let mut set = ShardedFilterSet::new(RandomState::default(), FilterSize::KeyBytes2, 4);
+/// set.insert(&"bananas");
+///
+/// assert!(set.contains(&"bananas"));
+///
+/// // Only the owning shard contains the item.
+/// let owner = set.shard_for(&"bananas");
+/// assert!(set.shards()[owner].contains(&"bananas"));
+/// ```
+///
+/// An item with hash `h` is routed to the shard at index:
+///
+/// ```text
+/// ((h * 0x9e3779b97f4a7c15 mod 2^64) * N) >> 64
+/// ```
+///
+/// That is, the high bits of the hash after mixing it with a multiplication.
+/// Routing on the unmixed high bits would correlate the shard with the first
+/// probe of the [`ProbeScheme::Split`] scheme, concentrating the bits set in
+/// each shard in a fraction of its bitmap.
+///
+/// All shards share the same hasher, [`FilterSize`] and [`ProbeScheme`], and
+/// the number of shards is fixed at construction.
+///
+/// [`contains`]: ShardedFilterSet::contains
+/// [`ProbeScheme`]: crate::ProbeScheme
+/// [`ProbeScheme::Split`]: crate::ProbeScheme::Split
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardedFilterSet<H = RandomState, B = CompressedBitmap, T = ()>
+where
+    H: BuildHasher,
+    B: Bitmap,
+{
+    shards: Vec<Bloom2<H, B, T>>,
+}
+
+impl<H, T> ShardedFilterSet<H, CompressedBitmap, T>
+where
+    H: BuildHasher + Clone,
+    T: Hash,
+{
+    /// Construct a set of `shards` empty filters of `key_size`, each hashing
+    /// with `hasher`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `shards` is 0.
+    pub fn new(hasher: H, key_size: FilterSize, shards: usize) -> Self {
+        assert!(shards > 0, "{}", Error::NoShards);
+
+        let shards = (0..shards)
+            .map(|_| {
+                BloomFilterBuilder::hasher(hasher.clone())
+                    .size(key_size)
+                    .build()
+            })
+            .collect();
+
+        Self { shards }
+    }
+}
+
+impl<H, B, T> ShardedFilterSet<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    /// Construct a set from the given `shards`, such as those loaded from
+    /// persisted state, in shard order.
+    ///
+    /// Returns an error if `shards` is empty, or if the shards do not share
+    /// the same hasher, [`FilterSize`] and [`ProbeScheme`](crate::ProbeScheme).
+    pub fn from_shards(shards: Vec<Bloom2<H, B, T>>) -> Result<Self, Error> {
+        let first = shards.first().ok_or(Error::NoShards)?;
+
+        let want = params(first);
+        for shard in &shards[1..] {
+            want.check(&params(shard))?;
+        }
+
+        Ok(Self { shards })
+    }
+
+    /// Insert `data` into the shard that owns it.
+    pub fn insert(&mut self, data: &'_ T) {
+        let hash = self.hash(data);
+        let shard = route(hash, self.shards.len());
+        self.shards[shard].insert_hash(hash);
+    }
+
+    /// Checks if `data` exists in the shard that owns it.
+    ///
+    /// As with [`Bloom2::contains()`], a `true` result means `data` has
+    /// **probably** been inserted, while `false` means it has **definitely
+    /// not** been inserted.
+    pub fn contains(&self, data: &'_ T) -> bool {
+        let hash = self.hash(data);
+        self.shards[route(hash, self.shards.len())].contains_hash(hash)
+    }
+
+    /// Return the index of the shard that owns `data`.
+    pub fn shard_for(&self, data: &'_ T) -> usize {
+        route(self.hash(data), self.shards.len())
+    }
+
+    /// Union each shard of `other` into the corresponding shard of `self`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the two sets have a different configuration -
+    /// see [`ShardedFilterSet::try_union()`].
+    pub fn union(&mut self, other: &Self) {
+        if let Err(e) = self.try_union(other) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Union two sets as [`ShardedFilterSet::union()`] does, returning an
+    /// error and leaving `self` unchanged if the sets have a different number
+    /// of shards, or the shards are incompatible (see
+    /// [`Bloom2::try_union()`]).
+    pub fn try_union(&mut self, other: &Self) -> Result<(), Error> {
+        if self.shards.len() != other.shards.len() {
+            return Err(Error::ShardCountMismatch {
+                ours: self.shards.len(),
+                theirs: other.shards.len(),
+            });
+        }
+
+        // Invariant: the shards of each set share the same parameters, so
+        // validating the first pair validates all pairs before any shard is
+        // modified.
+        params(&self.shards[0]).check(&params(&other.shards[0]))?;
+
+        for (ours, theirs) in self.shards.iter_mut().zip(&other.shards) {
+            ours.try_union(theirs)?;
+        }
+        Ok(())
+    }
+
+    /// Return the shards of this set, in shard order.
+    pub fn shards(&self) -> &[Bloom2<H, B, T>] {
+        &self.shards
+    }
+
+    /// Return a mutable reference to the shard at `index`, such as to merge
+    /// the state of the worker that owns it.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `index` is not less than the number of shards.
+    pub fn shard_mut(&mut self, index: usize) -> &mut Bloom2<H, B, T> {
+        &mut self.shards[index]
+    }
+
+    /// Return the shards of this set, in shard order, for persisting
+    /// independently.
+    ///
+    /// The set can be reconstructed with [`ShardedFilterSet::from_shards()`].
+    pub fn into_shards(self) -> Vec<Bloom2<H, B, T>> {
+        self.shards
+    }
+
+    fn hash(&self, data: &T) -> u64 {
+        // Invariant: all shards share the same hasher.
+        self.shards[0].hasher().hash_one(data)
+    }
+}
+
+/// Return the index of the shard of `shards` that owns `hash`.
+fn route(hash: u64, shards: usize) -> usize {
+    let mixed = hash.wrapping_mul(ROUTING_MIX);
+    ((mixed as u128 * shards as u128) >> 64) as usize
+}
+
+fn params<H, B, T>(b: &Bloom2<H, B, T>) -> Params
+where
+    H: BuildHasher,
+    B: Bitmap,
+    T: Hash,
+{
+    Params::new(b.hasher(), b.key_size(), b.probe_scheme(), b.match_mode())
+}
+
+#[cfg(feature = "serde")]
+impl<H, B, T> serde::Serialize for ShardedFilterSet<H, B, T>
+where
+    H: BuildHasher,
+    B: Bitmap + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.shards.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, H, B, T> serde::Deserialize<'de> for ShardedFilterSet<H, B, T>
+where
+    H: BuildHasher + Default,
+    B: Bitmap + serde::Deserialize<'de>,
+    T: Hash,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let shards = Vec::<Bloom2<H, B, T>>::deserialize(deserializer)?;
+        Self::from_shards(shards).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::ProbeScheme;
+
+    type StableBuildHasher = BuildHasherDefault<twox_hash::XxHash64>;
+    type Set = ShardedFilterSet<StableBuildHasher, CompressedBitmap, u32>;
+
+    fn set(shards: usize) -> Set {
+        ShardedFilterSet::new(StableBuildHasher::default(), FilterSize::KeyBytes2, shards)
+    }
+
+    #[quickcheck]
+    fn test_insert_contains(values: Vec<u32>) {
+        let mut s = set(3);
+        for v in &values {
+            s.insert(v);
+        }
+
+        for v in &values {
+            assert!(s.contains(v));
+
+            // The item is present in its owning shard.
+            assert!(s.shards()[s.shard_for(v)].contains(v));
+        }
+    }
+
+    #[test]
+    fn test_route_distribution() {
+        let s = set(4);
+        let mut counts = [0_usize; 4];
+        for v in 0..4000 {
+            counts[s.shard_for(&v)] += 1;
+        }
+
+        // Each shard receives roughly a quarter of the items.
+        for c in &counts {
+            assert!((800..1200).contains(c), "uneven routing: {:?}", counts);
+        }
+    }
+
+    #[quickcheck]
+    fn test_union(a: Vec<u32>, b: Vec<u32>) {
+        let mut left = set(3);
+        let mut right = set(3);
+        for v in &a {
+            left.insert(v);
+        }
+        for v in &b {
+            right.insert(v);
+        }
+
+        left.union(&right);
+        for v in a.iter().chain(&b) {
+            assert!(left.contains(v));
+        }
+    }
+
+    #[test]
+    fn test_union_mismatch() {
+        let mut s = set(3);
+        s.insert(&42);
+        let want = s.clone();
+
+        assert_eq!(
+            s.try_union(&set(2)),
+            Err(Error::ShardCountMismatch { ours: 3, theirs: 2 })
+        );
+
+        let other = ShardedFilterSet::new(StableBuildHasher::default(), FilterSize::KeyBytes1, 3);
+        assert_eq!(
+            s.try_union(&other),
+            Err(Error::KeySizeMismatch {
+                ours: FilterSize::KeyBytes2,
+                theirs: FilterSize::KeyBytes1,
+            })
+        );
+
+        assert_eq!(s, want);
+    }
+
+    #[test]
+    fn test_from_shards() {
+        let mut s = set(2);
+        s.insert(&42);
+
+        let got = Set::from_shards(s.clone().into_shards()).unwrap();
+        assert_eq!(got, s);
+        assert!(got.contains(&42));
+
+        assert_eq!(Set::from_shards(vec![]), Err(Error::NoShards));
+
+        let mut shards = s.into_shards();
+        shards.push(
+            BloomFilterBuilder::hasher(StableBuildHasher::default())
+                .probe_scheme(ProbeScheme::DoubleHashing { probes: 3 })
+                .build(),
+        );
+        assert_eq!(
+            Set::from_shards(shards),
+            Err(Error::ProbeSchemeMismatch {
+                ours: ProbeScheme::Split,
+                theirs: ProbeScheme::DoubleHashing { probes: 3 },
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1 shard")]
+    fn test_no_shards() {
+        set(0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[quickcheck]
+    fn test_serde(values: Vec<u32>) {
+        let mut s = set(3);
+        for v in &values {
+            s.insert(v);
+        }
+
+        let encoded = serde_json::to_string(&s).unwrap();
+        let got: Set = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(got, s);
+
+        assert!(serde_json::from_str::<Set>("[]")
+            .unwrap_err()
+            .to_string()
+            .contains("at least 1 shard"));
+    }
+}
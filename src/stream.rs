@@ -0,0 +1,193 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    bloom::{estimated_fpp, key_size_to_bits},
+    Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize,
+};
+
+/// A bounded-memory deduplicator for an unbounded stream of items.
+///
+/// A `StreamDeduplicator` records items in two generations of [`Bloom2`]
+/// filters - each item is checked against both, and recorded in the current
+/// generation. Once the current generation has recorded a configured number of
+/// items (or the caller calls [`StreamDeduplicator::tick()`]), the
+/// generations are rotated: the previous generation is discarded, the current
+/// generation becomes the previous, and a new, empty current generation is
+/// started.
+///
+/// This bounds the load of each filter (and therefore the false positive
+/// probability and memory usage) regardless of the length of the stream, while
+/// remembering each item for at least one full generation.
+///
+/// ```rust
+/// use bloom2::{FilterSize, StreamDeduplicator};
+///
+/// let mut dedup = StreamDeduplicator::new(FilterSize::KeyBytes3, 10_000);
+///
+/// assert!(dedup.check_and_record(&"event-1"));
+/// assert!(!dedup.check_and_record(&"event-1"));
+///
+/// // Evict items recorded more than one generation ago.
+/// dedup.tick();
+/// assert!(!dedup.check_and_record(&"event-1"));
+///
+/// println!("estimated fpp: {}", dedup.estimated_fpp());
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamDeduplicator<T, H = RandomState>
+where
+    H: BuildHasher,
+{
+    current: Bloom2<H, CompressedBitmap, T>,
+    previous: Bloom2<H, CompressedBitmap, T>,
+
+    /// The number of items recorded in the current generation.
+    recorded: usize,
+    /// The number of items recorded before a generation is rotated.
+    generation_items: usize,
+}
+
+impl<T> StreamDeduplicator<T, RandomState>
+where
+    T: Hash,
+{
+    /// Construct a `StreamDeduplicator` using filters of `key_size`, rotating
+    /// generations after `generation_items` number of new items are recorded.
+    ///
+    /// Pass `usize::MAX` as `generation_items` to rotate only when
+    /// [`StreamDeduplicator::tick()`] is called.
+    pub fn new(key_size: FilterSize, generation_items: usize) -> Self {
+        Self::with_hasher(RandomState::default(), key_size, generation_items)
+    }
+}
+
+impl<T, H> StreamDeduplicator<T, H>
+where
+    T: Hash,
+    H: BuildHasher + Clone,
+{
+    /// Construct a `StreamDeduplicator` using `hasher` to hash items, as
+    /// described in [`StreamDeduplicator::new()`].
+    pub fn with_hasher(hasher: H, key_size: FilterSize, generation_items: usize) -> Self {
+        let filter = || {
+            BloomFilterBuilder::hasher(hasher.clone())
+                .size(key_size)
+                .build()
+        };
+
+        Self {
+            current: filter(),
+            previous: filter(),
+            recorded: 0,
+            generation_items,
+        }
+    }
+
+    /// Returns `true` if `item` has **definitely not** been seen within the
+    /// last generation, recording it in the current generation.
+    ///
+    /// Returns `false` if `item` has **probably** been seen before - this may
+    /// be a false positive (see [`StreamDeduplicator::estimated_fpp()`]).
+    pub fn check_and_record(&mut self, item: &T) -> bool {
+        if self.current.contains(item) || self.previous.contains(item) {
+            return false;
+        }
+
+        self.current.insert(item);
+        self.recorded += 1;
+
+        if self.recorded >= self.generation_items {
+            self.tick();
+        }
+
+        true
+    }
+
+    /// Rotate the generations, evicting all items recorded before the
+    /// current generation.
+    ///
+    /// The memory of the evicted generation is reused for the new current
+    /// generation.
+    pub fn tick(&mut self) {
+        self.previous.clear();
+        std::mem::swap(&mut self.current, &mut self.previous);
+        self.recorded = 0;
+    }
+
+    /// Returns the number of new items recorded in the current generation.
+    pub fn recorded(&self) -> usize {
+        self.recorded
+    }
+
+    /// Returns the estimated probability of
+    /// [`StreamDeduplicator::check_and_record()`] reporting a new item as
+    /// previously seen, at the current load of both generations.
+    pub fn estimated_fpp(&self) -> f64 {
+        let fpp = |b: &Bloom2<H, CompressedBitmap, T>| {
+            let bits = key_size_to_bits(b.key_size()) as f64;
+            estimated_fpp(
+                b.key_size(),
+                b.probe_scheme(),
+                b.match_mode(),
+                b.bitmap().count_ones() as f64 / bits,
+            )
+        };
+
+        1.0 - (1.0 - fpp(&self.current)) * (1.0 - fpp(&self.previous))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+
+    use twox_hash::XxHash64;
+
+    use super::*;
+
+    fn new_dedup(generation_items: usize) -> StreamDeduplicator<u32, BuildHasherDefault<XxHash64>> {
+        StreamDeduplicator::with_hasher(
+            BuildHasherDefault::default(),
+            FilterSize::KeyBytes4,
+            generation_items,
+        )
+    }
+
+    #[test]
+    fn test_rotate_by_count() {
+        let mut d = new_dedup(3);
+        assert_eq!(d.estimated_fpp(), 0.0);
+
+        assert!(d.check_and_record(&1));
+        assert!(d.check_and_record(&2));
+        assert!(!d.check_and_record(&1));
+        assert_eq!(d.recorded(), 2);
+        assert!(d.estimated_fpp() > 0.0);
+
+        // The third new item fills the generation, rotating it.
+        assert!(d.check_and_record(&3));
+        assert_eq!(d.recorded(), 0);
+
+        // Items in the previous generation are still deduplicated.
+        assert!(!d.check_and_record(&1));
+
+        // Until a further generation is filled.
+        for v in 10..13 {
+            assert!(d.check_and_record(&v));
+        }
+        assert!(d.check_and_record(&1));
+    }
+
+    #[test]
+    fn test_tick() {
+        let mut d = new_dedup(usize::MAX);
+
+        assert!(d.check_and_record(&1));
+        d.tick();
+        assert!(!d.check_and_record(&1));
+        d.tick();
+        d.tick();
+        assert!(d.check_and_record(&1));
+    }
+}
@@ -0,0 +1,166 @@
+//! Canonical test vectors describing the hashing scheme and wire format of a
+//! [`Bloom2`], for verifying the compatibility of implementations in other
+//! languages.
+//!
+//! Each [`TestVector`] describes a filter of strings hashed with the
+//! [`XxHashBuildHasher`] (xxHash64 with a seed of 0), and serialised with
+//! [bincode] - the same configuration used by the `bloom2` command line tool.
+//!
+//! An implementation reproduces a vector by:
+//!
+//! 1. Hashing each key with xxHash64 (seed 0) over the UTF-8 bytes of the key
+//!    followed by a single `0xFF` byte, as written by the [`Hash`]
+//!    implementation of `str`. This yields the `hashes` of the vector.
+//!
+//! 2. Deriving the bit indexes of each hash with the [`ProbeScheme`] of the
+//!    vector, as documented by each scheme. This yields the `probes` of the
+//!    vector.
+//!
+//! 3. Setting each probed bit, and serialising the filter. The result must be
+//!    byte-for-byte identical to the `serialized` field of the vector.
+//!
+//! The vectors can be emitted as JSON with [serde]:
+//!
+//! ```rust
+//! let json = serde_json::to_string_pretty(&bloom2::test_vectors()).unwrap();
+//! # assert!(json.contains("\"key_size\": 8"));
+//! ```
+//!
+//! [bincode]: https://github.com/bincode-org/bincode
+//! [serde]: https://github.com/serde-rs/serde
+//! [`Hash`]: std::hash::Hash
+
+use std::hash::BuildHasher;
+
+use crate::{
+    Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize, ProbeScheme, XxHashBuildHasher,
+};
+
+/// The keys inserted into the filter of each [`TestVector`].
+const KEYS: &[&str] = &[
+    "",
+    "a",
+    "bloom2",
+    "hello 🐐",
+    "the quick brown fox jumps over the lazy dog",
+];
+
+/// A canonical filter, the intermediate values used to construct it, and its
+/// serialised representation.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TestVector {
+    /// The [`FilterSize`] of the filter.
+    pub key_size: FilterSize,
+
+    /// The [`ProbeScheme`] of the filter.
+    pub scheme: ProbeScheme,
+
+    /// The keys inserted into the filter, in order.
+    pub keys: Vec<String>,
+
+    /// The 64-bit hash of each key.
+    pub hashes: Vec<u64>,
+
+    /// The bit indexes set for each key.
+    pub probes: Vec<Vec<usize>>,
+
+    /// The [bincode] serialised filter containing all `keys`.
+    ///
+    /// [bincode]: https://github.com/bincode-org/bincode
+    pub serialized: Vec<u8>,
+}
+
+/// Generate a [`TestVector`] for each [`FilterSize`], using the
+/// [`ProbeScheme::Split`] scheme and then the [`ProbeScheme::DoubleHashing`]
+/// scheme with 7 probes.
+pub fn test_vectors() -> Vec<TestVector> {
+    let sizes = [
+        FilterSize::KeyBytes1,
+        FilterSize::KeyBytes2,
+        FilterSize::KeyBytes3,
+        FilterSize::KeyBytes4,
+        FilterSize::KeyBytes5,
+    ];
+
+    [ProbeScheme::Split, ProbeScheme::DoubleHashing { probes: 7 }]
+        .iter()
+        .flat_map(|&scheme| {
+            sizes
+                .iter()
+                .map(move |&key_size| test_vector(key_size, scheme))
+        })
+        .collect()
+}
+
+fn test_vector(key_size: FilterSize, scheme: ProbeScheme) -> TestVector {
+    let hasher = XxHashBuildHasher::default();
+    let mut filter: Bloom2<XxHashBuildHasher, CompressedBitmap, String> =
+        BloomFilterBuilder::hasher(hasher.clone())
+            .size(key_size)
+            .probe_scheme(scheme)
+            .build();
+
+    let keys = KEYS.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+    for k in &keys {
+        filter.insert(k);
+    }
+
+    let hashes = keys.iter().map(|k| hasher.hash_one(k)).collect::<Vec<_>>();
+    let probes = hashes
+        .iter()
+        .map(|&h| scheme.keys(h, key_size).collect())
+        .collect();
+
+    TestVector {
+        key_size,
+        scheme,
+        keys,
+        hashes,
+        probes,
+        serialized: bincode::serialize(&filter).expect("serialise filter"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors_consistent() {
+        for v in test_vectors() {
+            let filter: Bloom2<XxHashBuildHasher, CompressedBitmap, String> =
+                bincode::deserialize(&v.serialized).unwrap();
+            assert_eq!(filter.key_size(), v.key_size);
+            assert_eq!(filter.probe_scheme(), v.scheme);
+
+            for (k, probes) in v.keys.iter().zip(&v.probes) {
+                assert!(filter.contains(k));
+                assert!(probes.iter().all(|&p| filter.bitmap().get(p)));
+            }
+
+            // Only the probed bits are set.
+            let mut want = v.probes.concat();
+            want.sort_unstable();
+            want.dedup();
+            assert_eq!(filter.bitmap().ones().collect::<Vec<_>>(), want);
+        }
+    }
+
+    #[test]
+    fn test_vectors_stable() {
+        // Pin the hashing scheme - a change here breaks compatibility with
+        // every other implementation.
+        let v = &test_vectors()[1];
+        assert_eq!(v.keys[2], "bloom2");
+        assert_eq!(v.hashes[2], 13349982489587326695);
+        assert_eq!(v.probes[2], vec![47428, 43607, 53641, 40679]);
+
+        // h2 = 0xb944aa57
+        let v = &test_vectors()[6];
+        assert_eq!(v.scheme, ProbeScheme::DoubleHashing { probes: 7 });
+        assert_eq!(&v.probes[2][..3], &[0x9ee7, 0x493e, 0xf395]);
+
+        // The hash of a key is the xxHash64 of its bytes and a 0xFF suffix.
+        assert_eq!(v.hashes[2], twox_hash::XxHash64::oneshot(0, b"bloom2\xff"));
+    }
+}
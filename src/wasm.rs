@@ -0,0 +1,106 @@
+//! A [wasm-bindgen] JavaScript API for [`Bloom2`].
+//!
+//! [wasm-bindgen]: https://github.com/rustwasm/wasm-bindgen
+
+use std::convert::TryFrom;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Bloom2, BloomFilterBuilder, CompressedBitmap, FilterSize, XxHashBuildHasher};
+
+type Filter = Bloom2<XxHashBuildHasher, CompressedBitmap, String>;
+
+/// A bloom filter of strings, exported to JavaScript as `Bloom2`.
+///
+/// Filters are hashed with the [`XxHashBuildHasher`] and serialised with
+/// [bincode], making them interchangeable with a
+/// `Bloom2<XxHashBuildHasher, CompressedBitmap, String>` serialised by a Rust
+/// backend, or a filter file written by the `bloom2` command line tool.
+///
+/// ```js
+/// const filter = Bloom2.deserialize(new Uint8Array(await resp.arrayBuffer()));
+/// if (filter.contains("dom")) {
+///     // "dom" is probably taken
+/// }
+/// ```
+///
+/// [bincode]: https://github.com/bincode-org/bincode
+#[wasm_bindgen(js_name = Bloom2)]
+#[derive(Debug)]
+pub struct WasmBloom2 {
+    filter: Filter,
+}
+
+#[wasm_bindgen(js_class = Bloom2)]
+impl WasmBloom2 {
+    /// Construct an empty filter using `key_bytes` (1 to 5) bytes of hash per
+    /// key, as described by [`FilterSize`].
+    #[wasm_bindgen(constructor)]
+    pub fn new(key_bytes: u8) -> Result<WasmBloom2, JsError> {
+        let size = filter_size(key_bytes)
+            .ok_or_else(|| JsError::new("key_bytes must be between 1 and 5"))?;
+
+        Ok(Self {
+            filter: BloomFilterBuilder::hasher(XxHashBuildHasher::default())
+                .size(size)
+                .build(),
+        })
+    }
+
+    /// Insert `data` into the filter.
+    pub fn insert(&mut self, data: &str) {
+        self.filter.insert(&data.to_string());
+    }
+
+    /// Return true if `data` has probably been inserted into the filter, or
+    /// false if it definitely has not.
+    pub fn contains(&self, data: &str) -> bool {
+        self.filter.contains(&data.to_string())
+    }
+
+    /// Serialise the filter, returning a `Uint8Array` in JavaScript.
+    pub fn serialize(&self) -> Result<Vec<u8>, JsError> {
+        bincode::serialize(&self.filter).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Deserialise a filter from `data` produced by [`WasmBloom2::serialize`]
+    /// or by serialising a Rust filter of the same type.
+    pub fn deserialize(data: &[u8]) -> Result<WasmBloom2, JsError> {
+        bincode::deserialize(data)
+            .map(|filter| Self { filter })
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+fn filter_size(key_bytes: u8) -> Option<FilterSize> {
+    FilterSize::try_from(key_bytes)
+        .ok()
+        .filter(|size| FilterSize::ALL[..5].contains(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_contains_round_trip() {
+        let mut filter = WasmBloom2::new(2).unwrap();
+        filter.insert("dom");
+        assert!(filter.contains("dom"));
+
+        let got = WasmBloom2::deserialize(&filter.serialize().unwrap()).unwrap();
+        assert!(got.contains("dom"));
+        assert_eq!(got.filter, filter.filter);
+    }
+
+    #[test]
+    fn test_rust_interop() {
+        let mut filter: Filter = BloomFilterBuilder::hasher(XxHashBuildHasher::default())
+            .size(FilterSize::KeyBytes3)
+            .build();
+        filter.insert(&"dom".to_string());
+
+        let got = WasmBloom2::deserialize(&bincode::serialize(&filter).unwrap()).unwrap();
+        assert!(got.contains("dom"));
+    }
+}
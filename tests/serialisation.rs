@@ -39,6 +39,24 @@ macro_rules! test_serde_fixture {
 }
 
 test_serde_fixture!(compressed_bitmap, CompressedBitmap);
+#[cfg(feature = "bytes")]
+test_serde_fixture!(bytes_bitmap, bloom2::BytesBitmap);
+
+/// Filter headers written by v0.5 record the filter size by variant name,
+/// which must still deserialise.
+#[test]
+fn test_serde_legacy_key_size_name() {
+    let fixture = fs::read_to_string("tests/fixtures/compressed_bitmap.json").unwrap();
+    let legacy = fixture.replace("\"key_size\": 8", "\"key_size\": \"KeyBytes1\"");
+    assert_ne!(fixture, legacy);
+
+    let want: Bloom2<StableBuildHasher, CompressedBitmap, usize> =
+        serde_json::from_str(&fixture).unwrap();
+    let got: Bloom2<StableBuildHasher, CompressedBitmap, usize> =
+        serde_json::from_str(&legacy).unwrap();
+    assert_eq!(got, want);
+    assert_eq!(got.key_size(), FilterSize::KeyBytes1);
+}
 
 /// Serialise `t` as JSON and assert it matches a fixture value stored in a
 /// file, and that deserialising the fixture results in the same filter state.